@@ -1,3 +1,5 @@
+// Pre-1.0 Rust, kept for reference. The functionality (Matrix3, new_by_row/new_by_col,
+// AlmostEqual, and the Vec3 tests) has been migrated to MathGLM.
 
 pub use self::vec3::Vec3;
 pub use self::vec4::Vec4;
@@ -4,8 +4,51 @@ pub struct LinearTransform1D {
     pub c: f32,
 }
 
+impl LinearTransform1D {
+    pub fn apply(&self, x: f32) -> f32 {
+        self.m * x + self.c
+    }
+
+    /// Returns the transform that first applies `inner` and then `self`.
+    pub fn compose(&self, inner: &LinearTransform1D) -> LinearTransform1D {
+        LinearTransform1D {
+            m: self.m * inner.m,
+            c: self.m * inner.c + self.c,
+        }
+    }
+
+    pub fn invert(&self) -> Option<LinearTransform1D> {
+        if self.m == 0.0 || !self.m.is_finite() || !self.c.is_finite() {
+            return None;
+        }
+        Some(LinearTransform1D {
+            m: 1.0 / self.m,
+            c: -self.c / self.m,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum PixelSemantics {
+    /// Integer coordinates refer to the centers of the pixels, i.e., pixel `i` covers
+    /// the range `[i - 0.5, i + 0.5]`.
+    #[default]
+    Center,
+    /// Integer coordinates refer to the (start) boundaries of the pixels, i.e., pixel `i`
+    /// covers the range `[i, i + 1]`.
+    Boundary,
+}
+
 pub fn get_pixel_to_ndc_transform(size: u32, invert: bool) -> LinearTransform1D {
-    // The formula here allows to operate on **pixel centers**. I.e. in a 3x3 canvas,
+    get_pixel_to_ndc_transform_with_semantics(size, invert, PixelSemantics::Center)
+}
+
+pub fn get_pixel_to_ndc_transform_with_semantics(
+    size: u32,
+    invert: bool,
+    semantics: PixelSemantics,
+) -> LinearTransform1D {
+    // The "pixel center" semantics allow to operate on **pixel centers**. I.e. in a 3x3 canvas,
     // the pixel values 0, 1, 2 map to the three centers of the pixels. The alternative
     // is to use `(x1, x2) = (0.0, size as f32)`, which maps coordinates to **pixel
     // boundaries**.
@@ -30,17 +73,16 @@ pub fn get_pixel_to_ndc_transform(size: u32, invert: bool) -> LinearTransform1D
     // a bit easier. However, it may be also a bit surprising when drawing a line from like (1, 1)
     // to (10, 10) that the end pixel (10, 10) is not covered at all?
     //
-    // For now let's go for "pixel center" semantics...
+    // For now "pixel center" semantics are the default, but both are supported...
     //
     // Note that `(x1, x2) = (0.0, (size - 1) as f32)` doesn't make much sense, because the implied
     // integer grid would simply no longer match with the underlying pixel grid, and all drawing on
     // "nice" coordinates would actually draw across pixels.
-    let (x1, x2) = (-0.5, size as f32 - 0.5);
-    let (y1, y2) = if !invert { (-1.0, 1.0) } else { (1.0, -1.0) };
-    let denom = x1 - x2;
-    let m = (y1 - y2) / denom;
-    let c = (x1 * y2 - x2 * y1) / denom;
-    LinearTransform1D { m, c }
+    let (x1, x2) = match semantics {
+        PixelSemantics::Center => (-0.5, size as f32 - 0.5),
+        PixelSemantics::Boundary => (0.0, size as f32),
+    };
+    get_transform(x1, x2, invert)
 }
 
 pub fn get_transform(from: f32, upto: f32, invert: bool) -> LinearTransform1D {
@@ -52,13 +94,279 @@ pub fn get_transform(from: f32, upto: f32, invert: bool) -> LinearTransform1D {
     LinearTransform1D { m, c }
 }
 
+/// A 2D affine transform mapping `(x, y)` to
+/// `(a * x + b * y + tx, c * x + d * y + ty)`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Affine2 {
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+    pub d: f32,
+    pub tx: f32,
+    pub ty: f32,
+}
+
+impl Affine2 {
+    pub fn identity() -> Self {
+        Self::from_axes(
+            LinearTransform1D { m: 1.0, c: 0.0 },
+            LinearTransform1D { m: 1.0, c: 0.0 },
+        )
+    }
+
+    /// Combines two independent 1D transforms for the x and y axis.
+    pub fn from_axes(x: LinearTransform1D, y: LinearTransform1D) -> Self {
+        Self {
+            a: x.m,
+            b: 0.0,
+            c: 0.0,
+            d: y.m,
+            tx: x.c,
+            ty: y.c,
+        }
+    }
+
+    pub fn apply(&self, x: f32, y: f32) -> (f32, f32) {
+        (
+            self.a * x + self.b * y + self.tx,
+            self.c * x + self.d * y + self.ty,
+        )
+    }
+
+    /// Applies only the linear part, i.e., transforms a difference vector instead of a point.
+    pub fn apply_vector(&self, dx: f32, dy: f32) -> (f32, f32) {
+        (self.a * dx + self.b * dy, self.c * dx + self.d * dy)
+    }
+
+    /// Returns the transform that first applies `inner` and then `self`.
+    pub fn compose(&self, inner: &Affine2) -> Affine2 {
+        Affine2 {
+            a: self.a * inner.a + self.b * inner.c,
+            b: self.a * inner.b + self.b * inner.d,
+            c: self.c * inner.a + self.d * inner.c,
+            d: self.c * inner.b + self.d * inner.d,
+            tx: self.a * inner.tx + self.b * inner.ty + self.tx,
+            ty: self.c * inner.tx + self.d * inner.ty + self.ty,
+        }
+    }
+
+    /// Returns `None` if the transform is singular or not finite.
+    pub fn invert(&self) -> Option<Affine2> {
+        let det = self.a * self.d - self.b * self.c;
+        if det == 0.0 || !det.is_finite() || !self.tx.is_finite() || !self.ty.is_finite() {
+            return None;
+        }
+        let a = self.d / det;
+        let b = -self.b / det;
+        let c = -self.c / det;
+        let d = self.a / det;
+        Some(Affine2 {
+            a,
+            b,
+            c,
+            d,
+            tx: -(a * self.tx + b * self.ty),
+            ty: -(c * self.tx + d * self.ty),
+        })
+    }
+
+    /// The columns of the corresponding homogeneous 3x3 matrix, i.e., the layout expected
+    /// by `cgmath::Matrix3` and the shader side.
+    pub fn to_mat3_columns(&self) -> [[f32; 3]; 3] {
+        [
+            [self.a, self.c, 0.0],
+            [self.b, self.d, 0.0],
+            [self.tx, self.ty, 1.0],
+        ]
+    }
+}
+
+/// The pixel grid of a canvas. Pixel coordinates have their origin in the top left corner,
+/// with the y axis pointing down.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PixelGrid {
+    pub width: u32,
+    pub height: u32,
+    pub semantics: PixelSemantics,
+}
+
+impl PixelGrid {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            semantics: PixelSemantics::default(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    pub fn pixel_to_ndc(&self) -> Affine2 {
+        Affine2::from_axes(
+            get_pixel_to_ndc_transform_with_semantics(self.width, false, self.semantics),
+            get_pixel_to_ndc_transform_with_semantics(self.height, true, self.semantics),
+        )
+    }
+
+    /// Returns `None` for an empty grid.
+    pub fn ndc_to_pixel(&self) -> Option<Affine2> {
+        if self.is_empty() {
+            return None;
+        }
+        self.pixel_to_ndc().invert()
+    }
+}
+
+/// The visible range in data coordinates.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Viewport {
+    pub x_from: f32,
+    pub x_upto: f32,
+    pub y_from: f32,
+    pub y_upto: f32,
+    /// If true, `x_from` is at the right instead of the left.
+    pub invert_x: bool,
+    /// If true, `y_from` is at the top instead of the bottom, i.e., the y axis points down
+    /// like pixel coordinates.
+    pub invert_y: bool,
+}
+
+impl Viewport {
+    /// Creates a viewport with the y axis pointing down (like pixel coordinates).
+    pub fn new(x_from: f32, x_upto: f32, y_from: f32, y_upto: f32) -> Self {
+        Self {
+            x_from,
+            x_upto,
+            y_from,
+            y_upto,
+            invert_x: false,
+            invert_y: true,
+        }
+    }
+
+    pub fn translate(&mut self, dx: f32, dy: f32) {
+        self.x_from += dx;
+        self.x_upto += dx;
+        self.y_from += dy;
+        self.y_upto += dy;
+    }
+
+    /// Moves the viewport such that the content follows a mouse drag of `(dx, dy)` pixels.
+    pub fn pan_by_pixels(&mut self, dx: f32, dy: f32, grid: &PixelGrid) {
+        if let Some(pixel_to_data) = self.pixel_to_data(grid) {
+            let (dx, dy) = pixel_to_data.apply_vector(dx, dy);
+            self.translate(-dx, -dy);
+        }
+    }
+
+    /// Zooms by `factor` (> 1 zooms in, < 1 zooms out) such that the data point `(x, y)`
+    /// stays at the same position on the screen.
+    pub fn zoom_around(&mut self, x: f32, y: f32, factor: f32) {
+        assert!(
+            factor > 0.0 && factor.is_finite(),
+            "Zoom factor must be positive and finite, got {factor}"
+        );
+        self.x_from = x + (self.x_from - x) / factor;
+        self.x_upto = x + (self.x_upto - x) / factor;
+        self.y_from = y + (self.y_from - y) / factor;
+        self.y_upto = y + (self.y_upto - y) / factor;
+    }
+
+    /// Zooms around the data point under the given pixel, e.g. the mouse cursor.
+    /// Does nothing if the pixel position cannot be mapped to data coordinates.
+    pub fn zoom_around_pixel(&mut self, px: f32, py: f32, grid: &PixelGrid, factor: f32) {
+        if let Some(pixel_to_data) = self.pixel_to_data(grid) {
+            let (x, y) = pixel_to_data.apply(px, py);
+            self.zoom_around(x, y, factor);
+        }
+    }
+
+    /// Data units per pixel for the x and y axis.
+    pub fn units_per_pixel(&self, grid: &PixelGrid) -> (f32, f32) {
+        (
+            (self.x_upto - self.x_from).abs() / grid.width as f32,
+            (self.y_upto - self.y_from).abs() / grid.height as f32,
+        )
+    }
+
+    /// Expands the range of one axis (around its center) so that both axes have the same
+    /// number of data units per pixel. The visible range is never reduced. Note that zooming
+    /// preserves the aspect ratio, so this only needs to be re-applied after resizes.
+    pub fn lock_aspect_ratio(&mut self, grid: &PixelGrid) {
+        if grid.is_empty() {
+            return;
+        }
+        let (units_x, units_y) = self.units_per_pixel(grid);
+        if !(units_x > 0.0 && units_y > 0.0 && units_x.is_finite() && units_y.is_finite()) {
+            return;
+        }
+        if units_x < units_y {
+            let half = units_y * grid.width as f32 / 2.0;
+            (self.x_from, self.x_upto) = expand_around_center(self.x_from, self.x_upto, half);
+        } else if units_y < units_x {
+            let half = units_x * grid.height as f32 / 2.0;
+            (self.y_from, self.y_upto) = expand_around_center(self.y_from, self.y_upto, half);
+        }
+    }
+
+    pub fn data_to_ndc(&self) -> Affine2 {
+        Affine2::from_axes(
+            get_transform(self.x_from, self.x_upto, self.invert_x),
+            get_transform(self.y_from, self.y_upto, self.invert_y),
+        )
+    }
+
+    /// Returns `None` if the viewport has an empty range on either axis.
+    pub fn ndc_to_data(&self) -> Option<Affine2> {
+        self.data_to_ndc().invert()
+    }
+
+    /// Returns `None` for an empty grid.
+    pub fn data_to_pixel(&self, grid: &PixelGrid) -> Option<Affine2> {
+        Some(grid.ndc_to_pixel()?.compose(&self.data_to_ndc()))
+    }
+
+    /// Returns `None` for an empty grid or if the viewport has an empty range on either axis.
+    pub fn pixel_to_data(&self, grid: &PixelGrid) -> Option<Affine2> {
+        if grid.is_empty() {
+            return None;
+        }
+        Some(self.ndc_to_data()?.compose(&grid.pixel_to_ndc()))
+    }
+}
+
+/// Sets the half size of the range `from..upto` while keeping its center and direction.
+fn expand_around_center(from: f32, upto: f32, half: f32) -> (f32, f32) {
+    let center = (from + upto) / 2.0;
+    if from <= upto {
+        (center - half, center + half)
+    } else {
+        (center + half, center - half)
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
 
-    impl LinearTransform1D {
-        fn apply(&self, x: f32) -> f32 {
-            self.m * x + self.c
+    fn assert_close(a: (f32, f32), b: (f32, f32)) {
+        let tol = |x: f32| 1e-4 * x.abs().max(1.0);
+        assert!(
+            (a.0 - b.0).abs() <= tol(b.0) && (a.1 - b.1).abs() <= tol(b.1),
+            "{a:?} != {b:?}"
+        );
+    }
+
+    fn example_affine() -> Affine2 {
+        Affine2 {
+            a: 0.8,
+            b: -0.6,
+            c: 1.2,
+            d: 0.5,
+            tx: 3.0,
+            ty: -7.0,
         }
     }
 
@@ -69,4 +377,259 @@ pub mod tests {
         assert_eq!(transform.apply(1.0), 0.0);
         assert_eq!(transform.apply(2.0), 2.0 / 3.0);
     }
+
+    #[test]
+    fn test_get_pixel_to_ndc_transform_semantics() {
+        let center = get_pixel_to_ndc_transform_with_semantics(3, false, PixelSemantics::Center);
+        assert_eq!(center.apply(-0.5), -1.0);
+        assert_eq!(center.apply(2.5), 1.0);
+
+        let boundary =
+            get_pixel_to_ndc_transform_with_semantics(3, false, PixelSemantics::Boundary);
+        assert_eq!(boundary.apply(0.0), -1.0);
+        assert_eq!(boundary.apply(1.5), 0.0);
+        assert_eq!(boundary.apply(3.0), 1.0);
+
+        let inverted = get_pixel_to_ndc_transform_with_semantics(4, true, PixelSemantics::Boundary);
+        assert_eq!(inverted.apply(0.0), 1.0);
+        assert_eq!(inverted.apply(4.0), -1.0);
+    }
+
+    #[test]
+    fn test_linear_transform_1d() {
+        let t = get_transform(10.0, 20.0, false);
+        let t_inv = t.invert().unwrap();
+        assert_eq!(t_inv.apply(-1.0), 10.0);
+        assert_eq!(t_inv.apply(1.0), 20.0);
+        assert_eq!(t_inv.compose(&t).apply(13.0), 13.0);
+        assert_eq!(LinearTransform1D { m: 0.0, c: 1.0 }.invert(), None);
+        // degenerate ranges result in non-finite transforms
+        assert_eq!(get_transform(1.0, 1.0, false).invert(), None);
+    }
+
+    #[test]
+    fn test_affine_compose() {
+        let t1 = example_affine();
+        let t2 = Affine2::from_axes(
+            LinearTransform1D { m: 2.0, c: 1.0 },
+            LinearTransform1D { m: -3.0, c: 0.5 },
+        );
+        let p = (1.5, -2.5);
+        let (x, y) = t2.apply(p.0, p.1);
+        assert_close(t1.compose(&t2).apply(p.0, p.1), t1.apply(x, y));
+        assert_eq!(Affine2::identity().compose(&t1), t1);
+        assert_eq!(t1.compose(&Affine2::identity()), t1);
+    }
+
+    #[test]
+    fn test_affine_invert() {
+        let t = example_affine();
+        let t_inv = t.invert().unwrap();
+        for p in [(0.0, 0.0), (1.0, 2.0), (-100.0, 3.5)] {
+            let (x, y) = t.apply(p.0, p.1);
+            assert_close(t_inv.apply(x, y), p);
+        }
+        assert_close(t.compose(&t_inv).apply(4.0, 5.0), (4.0, 5.0));
+
+        let singular = Affine2 {
+            a: 1.0,
+            b: 2.0,
+            c: 2.0,
+            d: 4.0,
+            tx: 0.0,
+            ty: 0.0,
+        };
+        assert_eq!(singular.invert(), None);
+        assert_eq!(PixelGrid::new(0, 10).ndc_to_pixel(), None);
+    }
+
+    #[test]
+    fn test_affine_to_mat3_columns() {
+        let t = example_affine();
+        let m = t.to_mat3_columns();
+        let (x, y) = (2.0, 3.0);
+        let mx = m[0][0] * x + m[1][0] * y + m[2][0];
+        let my = m[0][1] * x + m[1][1] * y + m[2][1];
+        assert_close((mx, my), t.apply(x, y));
+    }
+
+    #[test]
+    fn test_viewport_pixel_range_is_identity() {
+        // This is the setup of the canvas: A viewport covering the pixel range directly,
+        // with "pixel center" semantics.
+        let grid = PixelGrid::new(300, 200);
+        let viewport = Viewport::new(0.0, 300.0, 0.0, 200.0);
+        let data_to_pixel = viewport.data_to_pixel(&grid).unwrap();
+        assert_close(data_to_pixel.apply(0.0, 0.0), (-0.5, -0.5));
+        assert_close(data_to_pixel.apply(300.0, 200.0), (299.5, 199.5));
+
+        let viewport = Viewport::new(-0.5, 299.5, -0.5, 199.5);
+        let data_to_pixel = viewport.data_to_pixel(&grid).unwrap();
+        assert_close(data_to_pixel.apply(0.0, 0.0), (0.0, 0.0));
+        assert_close(data_to_pixel.apply(17.0, 42.0), (17.0, 42.0));
+    }
+
+    #[test]
+    fn test_viewport_corners() {
+        let mut viewport = Viewport::new(10.0, 20.0, 100.0, 200.0);
+        for semantics in [PixelSemantics::Center, PixelSemantics::Boundary] {
+            let grid = PixelGrid {
+                width: 10,
+                height: 5,
+                semantics,
+            };
+            let (left, right, top, bottom) = match semantics {
+                PixelSemantics::Center => (-0.5, 9.5, -0.5, 4.5),
+                PixelSemantics::Boundary => (0.0, 10.0, 0.0, 5.0),
+            };
+            for (invert_x, invert_y) in [(false, false), (false, true), (true, false), (true, true)]
+            {
+                viewport.invert_x = invert_x;
+                viewport.invert_y = invert_y;
+                let data_to_pixel = viewport.data_to_pixel(&grid).unwrap();
+                let expected_x = if invert_x { right } else { left };
+                let expected_y = if invert_y { top } else { bottom };
+                assert_close(data_to_pixel.apply(10.0, 100.0), (expected_x, expected_y));
+                assert_close(
+                    data_to_pixel.apply(15.0, 150.0),
+                    ((left + right) / 2.0, (top + bottom) / 2.0),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_viewport_round_trips() {
+        let mut viewport = Viewport::new(-3.0, 7.0, 1e3, -2e3);
+        for semantics in [PixelSemantics::Center, PixelSemantics::Boundary] {
+            for invert_x in [false, true] {
+                viewport.invert_x = invert_x;
+                let grid = PixelGrid {
+                    width: 640,
+                    height: 480,
+                    semantics,
+                };
+                let data_to_pixel = viewport.data_to_pixel(&grid).unwrap();
+                let pixel_to_data = viewport.pixel_to_data(&grid).unwrap();
+                let data_to_ndc = viewport.data_to_ndc();
+                let ndc_to_data = viewport.ndc_to_data().unwrap();
+                let ndc_to_pixel = grid.ndc_to_pixel().unwrap();
+                for p in [(0.0, 0.0), (-3.0, 1e3), (7.0, -2e3), (1.25, 17.0)] {
+                    let (px, py) = data_to_pixel.apply(p.0, p.1);
+                    assert_close(pixel_to_data.apply(px, py), p);
+                    let (nx, ny) = data_to_ndc.apply(p.0, p.1);
+                    assert_close(ndc_to_data.apply(nx, ny), p);
+                    assert_close(ndc_to_pixel.apply(nx, ny), (px, py));
+                    assert_close(grid.pixel_to_ndc().apply(px, py), (nx, ny));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_viewport_degenerate() {
+        let grid = PixelGrid::new(100, 100);
+        let viewport = Viewport::new(1.0, 1.0, 0.0, 1.0);
+        assert_eq!(viewport.ndc_to_data(), None);
+        assert_eq!(viewport.pixel_to_data(&grid), None);
+
+        let viewport = Viewport::new(0.0, 1.0, 0.0, 1.0);
+        let empty = PixelGrid::new(0, 100);
+        assert_eq!(viewport.data_to_pixel(&empty), None);
+        assert_eq!(viewport.pixel_to_data(&empty), None);
+
+        // operations requiring a pixel mapping are no-ops
+        let mut modified = viewport;
+        modified.zoom_around_pixel(10.0, 10.0, &empty, 2.0);
+        modified.pan_by_pixels(10.0, 10.0, &empty);
+        modified.lock_aspect_ratio(&empty);
+        assert_eq!(modified, viewport);
+    }
+
+    #[test]
+    fn test_viewport_zoom_around_pixel() {
+        let grid = PixelGrid::new(400, 300);
+        let mut viewport = Viewport::new(0.0, 4.0, 0.0, 3.0);
+        let cursor = (100.0, 250.0);
+        let data_before = viewport
+            .pixel_to_data(&grid)
+            .unwrap()
+            .apply(cursor.0, cursor.1);
+
+        viewport.zoom_around_pixel(cursor.0, cursor.1, &grid, 2.0);
+        assert_close(
+            (
+                viewport.x_upto - viewport.x_from,
+                viewport.y_upto - viewport.y_from,
+            ),
+            (2.0, 1.5),
+        );
+        let data_after = viewport
+            .pixel_to_data(&grid)
+            .unwrap()
+            .apply(cursor.0, cursor.1);
+        assert_close(data_after, data_before);
+
+        viewport.zoom_around_pixel(cursor.0, cursor.1, &grid, 0.5);
+        assert_close((viewport.x_from, viewport.x_upto), (0.0, 4.0));
+        assert_close((viewport.y_from, viewport.y_upto), (0.0, 3.0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_viewport_zoom_invalid_factor() {
+        Viewport::new(0.0, 1.0, 0.0, 1.0).zoom_around(0.5, 0.5, 0.0);
+    }
+
+    #[test]
+    fn test_viewport_pan_by_pixels() {
+        let grid = PixelGrid::new(200, 100);
+        for invert_y in [false, true] {
+            let mut viewport = Viewport::new(0.0, 2.0, 0.0, 1.0);
+            viewport.invert_y = invert_y;
+            let data = (0.5, 0.25);
+            let (px, py) = viewport.data_to_pixel(&grid).unwrap().apply(data.0, data.1);
+            viewport.pan_by_pixels(30.0, -20.0, &grid);
+            // the data point has moved along with the cursor
+            let moved = viewport.data_to_pixel(&grid).unwrap().apply(data.0, data.1);
+            assert_close(moved, (px + 30.0, py - 20.0));
+        }
+    }
+
+    #[test]
+    fn test_viewport_lock_aspect_ratio() {
+        let grid = PixelGrid::new(400, 100);
+        let mut viewport = Viewport::new(0.0, 1.0, 0.0, 1.0);
+        viewport.lock_aspect_ratio(&grid);
+        // x needs to be expanded to 4 units around its center
+        assert_close((viewport.x_from, viewport.x_upto), (-1.5, 2.5));
+        assert_close((viewport.y_from, viewport.y_upto), (0.0, 1.0));
+        let (units_x, units_y) = viewport.units_per_pixel(&grid);
+        assert_close((units_x, 0.0), (units_y, 0.0));
+
+        // y needs to be expanded, preserving the reversed direction
+        let grid = PixelGrid::new(100, 400);
+        let mut viewport = Viewport::new(0.0, 1.0, 10.0, 9.0);
+        viewport.lock_aspect_ratio(&grid);
+        assert_close((viewport.x_from, viewport.x_upto), (0.0, 1.0));
+        assert_close((viewport.y_from, viewport.y_upto), (11.5, 7.5));
+
+        // zooming preserves the locked aspect ratio, and locking again is a no-op
+        viewport.zoom_around(0.3, 8.0, 3.0);
+        let locked = viewport;
+        viewport.lock_aspect_ratio(&grid);
+        assert_close(
+            (viewport.x_from, viewport.x_upto),
+            (locked.x_from, locked.x_upto),
+        );
+        assert_close(
+            (viewport.y_from, viewport.y_upto),
+            (locked.y_from, locked.y_upto),
+        );
+
+        // degenerate ranges are left alone
+        let mut viewport = Viewport::new(0.0, 0.0, 0.0, 1.0);
+        viewport.lock_aspect_ratio(&grid);
+        assert_eq!(viewport, Viewport::new(0.0, 0.0, 0.0, 1.0));
+    }
 }
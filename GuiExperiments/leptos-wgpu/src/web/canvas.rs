@@ -7,8 +7,9 @@ use leptos::*;
 use stylers::style;
 use web_sys::{HtmlCanvasElement, MouseEvent};
 
+use crate::math_utils::Viewport;
 use crate::web::resize_observer::{use_resize_observer, ResizeEventMode};
-use crate::web::wgpu_render::{Renderer, Viewport};
+use crate::web::wgpu_render::Renderer;
 
 #[component]
 pub fn CanvasWrapper(cx: Scope) -> impl IntoView {
@@ -16,15 +17,7 @@ pub fn CanvasWrapper(cx: Scope) -> impl IntoView {
 
     let renderer_orig: Rc<RefCell<Option<Renderer>>> = Default::default();
 
-    let viewport = store_value(
-        cx,
-        Viewport {
-            x_from: 0.0,
-            x_upto: 1.0,
-            y_from: 0.0,
-            y_upto: 1.0,
-        },
-    );
+    let viewport = store_value(cx, Viewport::new(0.0, 1.0, 0.0, 1.0));
 
     let renderer = renderer_orig.clone();
     let on_mount = use_resize_observer::<Div, _>(cx, ResizeEventMode::BorderBoxSize, move |ev| {
@@ -51,12 +44,7 @@ pub fn CanvasWrapper(cx: Scope) -> impl IntoView {
                 *renderer.borrow_mut() = Some(Renderer::new(canvas.clone()).await);
             });
             // Store width, height, and client bounding box in a stored value for later access?
-            viewport.set_value(Viewport {
-                x_from: 0.0,
-                x_upto: w as f32,
-                y_from: 0.0,
-                y_upto: h as f32,
-            });
+            viewport.set_value(Viewport::new(0.0, w as f32, 0.0, h as f32));
             log!("Set viewport to: {:?}", viewport.get_value());
         }
     });
@@ -5,7 +5,7 @@ use leptos::log;
 use web_sys::HtmlCanvasElement;
 use wgpu::util::DeviceExt;
 
-use crate::math_utils::{get_pixel_to_ndc_transform, get_transform};
+use crate::math_utils::Viewport;
 
 #[repr(C)]
 #[derive(Clone, Copy, Pod, Zeroable)]
@@ -59,46 +59,19 @@ impl ProjectionUniform {
     }
 
     fn new(viewport: Viewport) -> Self {
-        //let tx = get_pixel_to_ndc_transform(w, false);
-        //let ty = get_pixel_to_ndc_transform(h, true);
-        let tx = get_transform(viewport.x_from, viewport.x_upto, false);
-        let ty = get_transform(viewport.y_from, viewport.y_upto, true);
-
-        use cgmath::SquareMatrix;
-        let mut m = cgmath::Matrix3::identity();
-        m.x[0] = tx.m;
-        m.y[1] = ty.m;
-        m.z[0] = tx.c;
-        m.z[1] = ty.c;
+        let m = viewport.data_to_ndc().to_mat3_columns();
         Self {
             // Ideally, this should work, but currently not possible due to required padding.
             // view_proj: m.into(),
             view_proj: [
-                [m.x[0], m.x[1], m.x[2], 0.0],
-                [m.y[0], m.y[1], m.y[2], 0.0],
-                [m.z[0], m.z[1], m.z[2], 0.0],
+                [m[0][0], m[0][1], m[0][2], 0.0],
+                [m[1][0], m[1][1], m[1][2], 0.0],
+                [m[2][0], m[2][1], m[2][2], 0.0],
             ],
         }
     }
 }
 
-#[derive(Copy, Clone, Debug)]
-pub struct Viewport {
-    pub x_from: f32,
-    pub x_upto: f32,
-    pub y_from: f32,
-    pub y_upto: f32,
-}
-
-impl Viewport {
-    pub fn translate(&mut self, dx: f32, dy: f32) {
-        self.x_from += dx;
-        self.x_upto += dx;
-        self.y_from += dy;
-        self.y_upto += dy;
-    }
-}
-
 struct MsaaPipeline {
     bundle: wgpu::RenderBundle,
     multisampled_framebuffer: wgpu::TextureView,
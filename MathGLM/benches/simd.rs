@@ -0,0 +1,70 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use math_glm::simd::Backend;
+use math_glm::{Mat4f, Vec3f, Vec4f};
+
+fn example_matrix() -> Mat4f {
+    &(&Mat4f::translate(1.0, -2.0, 3.0) * &Mat4f::rotate(33.0, 1.0, 2.0, 3.0)) *
+        &Mat4f::scale(0.5, 2.0, 1.5)
+}
+
+fn bench_mat4_mul(c: &mut Criterion) {
+    let a = example_matrix();
+    let b = Mat4f::rotate_yaw_pitch_roll_quaternions(10.0, 20.0, 30.0);
+    let mut group = c.benchmark_group("mat4_mul");
+    group.bench_function("operator", |bencher| bencher.iter(|| black_box(&a) * black_box(&b)));
+    for backend in Backend::available() {
+        group.bench_function(format!("{:?}", backend), |bencher| {
+            bencher.iter(|| backend.mat4_mul(black_box(&a), black_box(&b)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_mat4_mul_vec4(c: &mut Criterion) {
+    let m = example_matrix();
+    let v = Vec4f::new(1.0, 2.0, 3.0, 1.0);
+    let mut group = c.benchmark_group("mat4_mul_vec4");
+    group.bench_function("operator", |bencher| bencher.iter(|| black_box(&m) * black_box(&v)));
+    for backend in Backend::available() {
+        group.bench_function(format!("{:?}", backend), |bencher| {
+            bencher.iter(|| backend.mat4_mul_vec4(black_box(&m), black_box(&v)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_mat4_transpose(c: &mut Criterion) {
+    let m = example_matrix();
+    let mut group = c.benchmark_group("mat4_transpose");
+    for backend in Backend::available() {
+        group.bench_function(format!("{:?}", backend), |bencher| {
+            bencher.iter(|| backend.mat4_transpose(black_box(&m)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_transform_points(c: &mut Criterion) {
+    let m = example_matrix();
+    let mut group = c.benchmark_group("transform_points");
+    for n in [16, 1024, 65536] {
+        let input: Vec<Vec3f> = (0..n).map(|i| Vec3f::new(i as f32, 1.0, -(i as f32))).collect();
+        let mut output = vec![Vec3f::new(0.0, 0.0, 0.0); n];
+        group.throughput(Throughput::Elements(n as u64));
+        for backend in Backend::available() {
+            group.bench_with_input(BenchmarkId::new(format!("{:?}", backend), n), &input, |bencher, input| {
+                bencher.iter(|| backend.transform_points(black_box(&m), black_box(input), &mut output))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_mat4_mul,
+    bench_mat4_mul_vec4,
+    bench_mat4_transpose,
+    bench_transform_points
+);
+criterion_main!(benches);
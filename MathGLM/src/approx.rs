@@ -0,0 +1,166 @@
+use crate::Mat3f;
+use crate::Mat4f;
+use crate::Quaternion;
+use crate::Vec3f;
+use crate::Vec4f;
+
+// ----------------------------------------------------------------------------
+// AlmostEqual
+// ----------------------------------------------------------------------------
+
+/**
+ * Approximate floating point comparisons, see:
+ *   https://randomascii.wordpress.com/2012/02/25/comparing-floating-point-numbers-2012-edition/
+ *
+ * For composite types all components have to be almost equal.
+ */
+pub trait AlmostEqual {
+    /**
+     * True if the two values are at most `max_ulps` representable floats apart.
+     * Note that this is unsuited for comparisons against zero, e.g., the tiny
+     * result of a cancellation is millions of ULPs away from 0.0.
+     */
+    fn almost_equal_ulps(&self, that: &Self, max_ulps: u32) -> bool;
+
+    /**
+     * True if the absolute difference is at most `max_diff` (which handles values
+     * near zero), or the difference relative to the larger magnitude is at most
+     * `max_rel_diff`.
+     */
+    fn almost_equal_relative(&self, that: &Self, max_diff: f32, max_rel_diff: f32) -> bool;
+
+    fn almost_equal(&self, that: &Self) -> bool {
+        self.almost_equal_relative(that, DEFAULT_MAX_DIFF, DEFAULT_MAX_REL_DIFF)
+    }
+}
+
+pub const DEFAULT_MAX_DIFF: f32 = f32::EPSILON;
+pub const DEFAULT_MAX_REL_DIFF: f32 = 4f32 * f32::EPSILON;
+
+impl AlmostEqual for f32 {
+    fn almost_equal_ulps(&self, that: &f32, max_ulps: u32) -> bool {
+        let (a, b) = (*self, *that);
+        if a.is_nan() || b.is_nan() {
+            return false;
+        }
+        if a == b {
+            // also covers +0.0 == -0.0 and equal infinities
+            return true;
+        }
+        if a.is_sign_negative() != b.is_sign_negative() {
+            return false;
+        }
+        // For floats of the same sign, the bit patterns are ordered like the values.
+        let ulps = (a.to_bits() as i64 - b.to_bits() as i64).unsigned_abs();
+        ulps <= max_ulps as u64
+    }
+
+    fn almost_equal_relative(&self, that: &f32, max_diff: f32, max_rel_diff: f32) -> bool {
+        let diff = (self - that).abs();
+        if diff <= max_diff {
+            return true;
+        }
+        let largest = self.abs().max(that.abs());
+        diff <= largest * max_rel_diff
+    }
+}
+
+fn all_almost_equal_ulps(a: &[f32], b: &[f32], max_ulps: u32) -> bool {
+    a.iter().zip(b).all(|(a, b)| a.almost_equal_ulps(b, max_ulps))
+}
+
+fn all_almost_equal_relative(a: &[f32], b: &[f32], max_diff: f32, max_rel_diff: f32) -> bool {
+    a.iter().zip(b).all(|(a, b)| a.almost_equal_relative(b, max_diff, max_rel_diff))
+}
+
+macro_rules! impl_almost_equal {
+    ($t:ty, $components:expr) => {
+        impl AlmostEqual for $t {
+            fn almost_equal_ulps(&self, that: &$t, max_ulps: u32) -> bool {
+                let components = $components;
+                all_almost_equal_ulps(&components(self), &components(that), max_ulps)
+            }
+
+            fn almost_equal_relative(&self, that: &$t, max_diff: f32, max_rel_diff: f32) -> bool {
+                let components = $components;
+                all_almost_equal_relative(&components(self), &components(that), max_diff, max_rel_diff)
+            }
+        }
+    };
+}
+
+impl_almost_equal!(Vec3f, |v: &Vec3f| [v.x, v.y, v.z]);
+impl_almost_equal!(Vec4f, |v: &Vec4f| [v.x, v.y, v.z, v.w]);
+impl_almost_equal!(Quaternion, |q: &Quaternion| [q.x, q.y, q.z, q.w]);
+impl_almost_equal!(Mat3f, |m: &Mat3f| [
+    m.m00, m.m10, m.m20,
+    m.m01, m.m11, m.m21,
+    m.m02, m.m12, m.m22,
+]);
+impl_almost_equal!(Mat4f, |m: &Mat4f| [
+    m.m00, m.m10, m.m20, m.m30,
+    m.m01, m.m11, m.m21, m.m31,
+    m.m02, m.m12, m.m22, m.m32,
+    m.m03, m.m13, m.m23, m.m33,
+]);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn next_up(x: f32) -> f32 {
+        f32::from_bits(if x >= 0f32 { x.to_bits() + 1 } else { x.to_bits() - 1 })
+    }
+
+    #[test]
+    fn test_ulps() {
+        let x = 1f32;
+        let y = next_up(next_up(x));
+        assert!(x.almost_equal_ulps(&x, 0));
+        assert!(!x.almost_equal_ulps(&y, 1));
+        assert!(x.almost_equal_ulps(&y, 2));
+        assert!(y.almost_equal_ulps(&x, 2));
+
+        let x = -1f32;
+        let y = next_up(next_up(x));
+        assert!(!x.almost_equal_ulps(&y, 1));
+        assert!(x.almost_equal_ulps(&y, 2));
+
+        assert!(0f32.almost_equal_ulps(&-0f32, 0));
+        assert!(!f32::MIN_POSITIVE.almost_equal_ulps(&-f32::MIN_POSITIVE, 1000));
+        assert!(f32::INFINITY.almost_equal_ulps(&f32::INFINITY, 0));
+        assert!(f32::MAX.almost_equal_ulps(&f32::INFINITY, 1));
+        assert!(!f32::NAN.almost_equal_ulps(&f32::NAN, u32::MAX));
+    }
+
+    #[test]
+    fn test_relative() {
+        assert!(1000f32.almost_equal_relative(&1000.1, 0.0, 1e-4));
+        assert!(!1000f32.almost_equal_relative(&1001.0, 0.0, 1e-4));
+        // near zero only the absolute tolerance helps
+        assert!(!1e-10f32.almost_equal_relative(&0.0, 0.0, 1e-4));
+        assert!(1e-10f32.almost_equal_relative(&0.0, 1e-9, 1e-4));
+        assert!(!f32::NAN.almost_equal_relative(&f32::NAN, 1.0, 1.0));
+    }
+
+    #[test]
+    fn test_almost_equal() {
+        assert!((0.1f32 + 0.2f32).almost_equal(&0.3f32));
+        assert!(!(1f32).almost_equal(&1.001f32));
+    }
+
+    #[test]
+    fn test_composite() {
+        let a = Vec3f::new(1.0, 2.0, 3.0);
+        let b = Vec3f::new(1.0, next_up(2.0), 3.0);
+        let c = Vec3f::new(1.0, 2.0, 3.1);
+        assert!(a.almost_equal_ulps(&b, 1));
+        assert!(a.almost_equal(&b));
+        assert!(!a.almost_equal(&c));
+
+        let m = Mat4f::rotate(30.0, 1.0, 1.0, 0.0);
+        let m_roundtrip = &(&m * &m.transpose()) * &m;
+        assert!(m_roundtrip.almost_equal_relative(&m, 1e-6, 1e-6));
+        assert!(!m.almost_equal(&Mat4f::create_identity()));
+    }
+}
@@ -0,0 +1,517 @@
+use crate::Mat4f;
+use crate::Vec3f;
+
+// ----------------------------------------------------------------------------
+// Ray3f
+// ----------------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ray3f {
+    pub origin: Vec3f,
+    pub direction: Vec3f,
+}
+
+impl Ray3f {
+
+    pub fn new(origin: Vec3f, direction: Vec3f) -> Ray3f {
+        Ray3f { origin, direction }
+    }
+
+    pub fn at(&self, t: f32) -> Vec3f {
+        &self.origin + &(&self.direction * t)
+    }
+
+    /**
+     * Returns the ray parameter `t` of the intersection, i.e., `self.at(t)` is the
+     * intersection point. Rays running parallel to the plane never intersect.
+     */
+    pub fn intersect_plane(&self, plane: &Plane) -> Option<f32> {
+        let denom = plane.normal.dot(&self.direction);
+        if denom.abs() < f32::EPSILON {
+            return None;
+        }
+        let t = -plane.signed_distance(&self.origin) / denom;
+        if t >= 0f32 { Some(t) } else { None }
+    }
+
+    /**
+     * Slab method:
+     *   https://tavianator.com/2011/ray_box.html
+     *
+     * Division by a zero direction component yields +/- infinity, which makes
+     * the slab comparisons work out without special casing.
+     * If the origin is inside the box, the exit point is returned.
+     */
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32> {
+        let mut t_min = f32::NEG_INFINITY;
+        let mut t_max = f32::INFINITY;
+        let slabs = [
+            (self.origin.x, self.direction.x, aabb.min.x, aabb.max.x),
+            (self.origin.y, self.direction.y, aabb.min.y, aabb.max.y),
+            (self.origin.z, self.direction.z, aabb.min.z, aabb.max.z),
+        ];
+        for (o, d, min, max) in slabs {
+            let inv_d = 1f32 / d;
+            let t1 = (min - o) * inv_d;
+            let t2 = (max - o) * inv_d;
+            t_min = t_min.max(t1.min(t2));
+            t_max = t_max.min(t1.max(t2));
+        }
+        if t_max < t_min || t_max < 0f32 {
+            None
+        } else if t_min >= 0f32 {
+            Some(t_min)
+        } else {
+            Some(t_max)
+        }
+    }
+
+    /**
+     * Solves |o + t*d - c|^2 = r^2 for t.
+     * If the origin is inside the sphere, the exit point is returned.
+     */
+    pub fn intersect_sphere(&self, sphere: &Sphere) -> Option<f32> {
+        let oc = &self.origin - &sphere.center;
+        let a = self.direction.dot(&self.direction);
+        let b = oc.dot(&self.direction);
+        let c = oc.dot(&oc) - sphere.radius * sphere.radius;
+        let discriminant = b*b - a*c;
+        if discriminant < 0f32 {
+            return None;
+        }
+        let sqrt_discriminant = discriminant.sqrt();
+        let t1 = (-b - sqrt_discriminant) / a;
+        let t2 = (-b + sqrt_discriminant) / a;
+        if t1 >= 0f32 {
+            Some(t1)
+        } else if t2 >= 0f32 {
+            Some(t2)
+        } else {
+            None
+        }
+    }
+
+    /**
+     * Möller–Trumbore algorithm:
+     *   https://en.wikipedia.org/wiki/M%C3%B6ller%E2%80%93Trumbore_intersection_algorithm
+     *
+     * Triangles are two-sided, i.e., the winding order does not matter.
+     */
+    pub fn intersect_triangle(&self, v0: &Vec3f, v1: &Vec3f, v2: &Vec3f) -> Option<f32> {
+        let edge1 = v1 - v0;
+        let edge2 = v2 - v0;
+        let h = self.direction.cross(&edge2);
+        let a = edge1.dot(&h);
+        if a.abs() < f32::EPSILON {
+            return None;
+        }
+        let f = 1f32 / a;
+        let s = &self.origin - v0;
+        let u = f * s.dot(&h);
+        if !(0f32..=1f32).contains(&u) {
+            return None;
+        }
+        let q = s.cross(&edge1);
+        let v = f * self.direction.dot(&q);
+        if v < 0f32 || u + v > 1f32 {
+            return None;
+        }
+        let t = f * edge2.dot(&q);
+        if t >= 0f32 { Some(t) } else { None }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Plane
+// ----------------------------------------------------------------------------
+
+/**
+ * Plane in Hessian normal form, i.e., all points p with dot(normal, p) + d = 0.
+ * The normal points into the positive half space.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Plane {
+    pub normal: Vec3f,
+    pub d: f32,
+}
+
+impl Plane {
+
+    pub fn new(normal: Vec3f, d: f32) -> Plane {
+        Plane { normal, d }
+    }
+
+    pub fn from_point_normal(point: &Vec3f, normal: &Vec3f) -> Plane {
+        let normal = normal.normalized();
+        let d = -normal.dot(point);
+        Plane { normal, d }
+    }
+
+    /**
+     * Creates a plane from the coefficients of a*x + b*y + c*z + d = 0.
+     * The coefficients get normalized so that `signed_distance` returns true distances.
+     */
+    pub fn from_coefficients(a: f32, b: f32, c: f32, d: f32) -> Plane {
+        let length = Vec3f::new(a, b, c).length();
+        Plane {
+            normal: Vec3f::new(a / length, b / length, c / length),
+            d: d / length,
+        }
+    }
+
+    pub fn signed_distance(&self, point: &Vec3f) -> f32 {
+        self.normal.dot(point) + self.d
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Aabb
+// ----------------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3f,
+    pub max: Vec3f,
+}
+
+impl Aabb {
+
+    pub fn new(min: Vec3f, max: Vec3f) -> Aabb {
+        Aabb { min, max }
+    }
+
+    pub fn from_center_extents(center: &Vec3f, extents: &Vec3f) -> Aabb {
+        Aabb {
+            min: center - extents,
+            max: center + extents,
+        }
+    }
+
+    pub fn center(&self) -> Vec3f {
+        self.min.mid(&self.max)
+    }
+
+    /**
+     * Half size of the box along each axis.
+     */
+    pub fn extents(&self) -> Vec3f {
+        &(&self.max - &self.min) * 0.5f32
+    }
+
+    pub fn contains_point(&self, p: &Vec3f) -> bool {
+        p.x >= self.min.x && p.x <= self.max.x &&
+        p.y >= self.min.y && p.y <= self.max.y &&
+        p.z >= self.min.z && p.z <= self.max.z
+    }
+
+    /**
+     * Bounding box of the transformed box (assuming an affine transformation):
+     *   Arvo, "Transforming Axis-Aligned Bounding Boxes", Graphics Gems, 1990
+     *
+     * The center is transformed as a point, the extents are transformed by the
+     * absolute values of the linear part.
+     */
+    pub fn transform(&self, m: &Mat4f) -> Aabb {
+        let c = self.center();
+        let e = self.extents();
+        let center = Vec3f::new(
+            m.m00*c.x + m.m10*c.y + m.m20*c.z + m.m30,
+            m.m01*c.x + m.m11*c.y + m.m21*c.z + m.m31,
+            m.m02*c.x + m.m12*c.y + m.m22*c.z + m.m32,
+        );
+        let extents = Vec3f::new(
+            m.m00.abs()*e.x + m.m10.abs()*e.y + m.m20.abs()*e.z,
+            m.m01.abs()*e.x + m.m11.abs()*e.y + m.m21.abs()*e.z,
+            m.m02.abs()*e.x + m.m12.abs()*e.y + m.m22.abs()*e.z,
+        );
+        Aabb::from_center_extents(&center, &extents)
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Sphere
+// ----------------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sphere {
+    pub center: Vec3f,
+    pub radius: f32,
+}
+
+impl Sphere {
+
+    pub fn new(center: Vec3f, radius: f32) -> Sphere {
+        Sphere { center, radius }
+    }
+
+    pub fn contains_point(&self, p: &Vec3f) -> bool {
+        let d = p - &self.center;
+        d.dot(&d) <= self.radius * self.radius
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Frustum
+// ----------------------------------------------------------------------------
+
+/**
+ * Plane normals point to the inside of the frustum.
+ * Plane order: left, right, bottom, top, near, far.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frustum {
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+
+    /**
+     * Gribb & Hartmann, "Fast Extraction of Viewing Frustum Planes from the
+     * World-View-Projection Matrix", 2001:
+     *   https://www.gamedevs.org/uploads/fast-extraction-viewing-frustum-planes-from-world-view-projection-matrix.pdf
+     *
+     * Assumes OpenGL clip space conventions, i.e., -w <= x, y, z <= w.
+     * If `m` is only a projection matrix, the planes are in view space,
+     * for a view-projection matrix they are in world space.
+     */
+    pub fn from_view_projection(m: &Mat4f) -> Frustum {
+        let row0 = [m.m00, m.m10, m.m20, m.m30];
+        let row1 = [m.m01, m.m11, m.m21, m.m31];
+        let row2 = [m.m02, m.m12, m.m22, m.m32];
+        let row3 = [m.m03, m.m13, m.m23, m.m33];
+        let add = |a: [f32; 4], b: [f32; 4]| Plane::from_coefficients(a[0]+b[0], a[1]+b[1], a[2]+b[2], a[3]+b[3]);
+        let sub = |a: [f32; 4], b: [f32; 4]| Plane::from_coefficients(a[0]-b[0], a[1]-b[1], a[2]-b[2], a[3]-b[3]);
+        Frustum {
+            planes: [
+                add(row3, row0),
+                sub(row3, row0),
+                add(row3, row1),
+                sub(row3, row1),
+                add(row3, row2),
+                sub(row3, row2),
+            ],
+        }
+    }
+
+    pub fn contains_point(&self, p: &Vec3f) -> bool {
+        self.planes.iter().all(|plane| plane.signed_distance(p) >= 0f32)
+    }
+
+    /**
+     * Conservative test: Spheres close to the frustum corners may be reported
+     * as intersecting although they are outside.
+     */
+    pub fn intersects_sphere(&self, sphere: &Sphere) -> bool {
+        self.planes.iter().all(|plane| plane.signed_distance(&sphere.center) >= -sphere.radius)
+    }
+
+    /**
+     * Conservative test based on the "positive vertex" of the box w.r.t. each plane:
+     *   https://www.lighthouse3d.com/tutorials/view-frustum-culling/geometric-approach-testing-boxes-ii/
+     */
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            let p = Vec3f::new(
+                if plane.normal.x >= 0f32 { aabb.max.x } else { aabb.min.x },
+                if plane.normal.y >= 0f32 { aabb.max.y } else { aabb.min.y },
+                if plane.normal.z >= 0f32 { aabb.max.z } else { aabb.min.z },
+            );
+            plane.signed_distance(&p) >= 0f32
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5 * b.abs().max(1f32), "{} != {}", a, b);
+    }
+
+    fn assert_close_vec(a: &Vec3f, b: &Vec3f) {
+        assert!((a - b).length() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    /**
+     * Equivalent of glFrustum, see http://www.opengl.org/sdk/docs/man2/xhtml/glFrustum.xml
+     */
+    fn create_projection_frustum(l: f32, r: f32, b: f32, t: f32, zn: f32, zf: f32) -> Mat4f {
+        Mat4f::new(
+            2f32*zn/(r-l),          0f32,     (r+l)/(r-l),                 0f32,
+                     0f32, 2f32*zn/(t-b),     (t+b)/(t-b),                 0f32,
+                     0f32,          0f32, -(zf+zn)/(zf-zn), -2f32*zf*zn/(zf-zn),
+                     0f32,          0f32,           -1f32,                 0f32,
+        )
+    }
+
+    #[test]
+    fn test_ray_plane() {
+        let plane = Plane::from_point_normal(&Vec3f::new(0.0, 0.0, -5.0), &Vec3f::new(0.0, 0.0, 2.0));
+        let ray = Ray3f::new(Vec3f::new(1.0, 2.0, 0.0), Vec3f::new(0.0, 0.0, -1.0));
+        let t = ray.intersect_plane(&plane).unwrap();
+        assert_close(t, 5.0);
+        assert_close_vec(&ray.at(t), &Vec3f::new(1.0, 2.0, -5.0));
+
+        // pointing away
+        let ray = Ray3f::new(Vec3f::new(1.0, 2.0, 0.0), Vec3f::new(0.0, 0.0, 1.0));
+        assert_eq!(ray.intersect_plane(&plane), None);
+
+        // parallel
+        let ray = Ray3f::new(Vec3f::new(1.0, 2.0, 0.0), Vec3f::new(1.0, 0.0, 0.0));
+        assert_eq!(ray.intersect_plane(&plane), None);
+    }
+
+    #[test]
+    fn test_ray_aabb() {
+        let aabb = Aabb::new(Vec3f::new(-1.0, -1.0, -1.0), Vec3f::new(1.0, 1.0, 1.0));
+
+        let ray = Ray3f::new(Vec3f::new(-5.0, 0.0, 0.0), Vec3f::new(1.0, 0.0, 0.0));
+        assert_close(ray.intersect_aabb(&aabb).unwrap(), 4.0);
+
+        let ray = Ray3f::new(Vec3f::new(-5.0, -5.0, -5.0), Vec3f::new(1.0, 1.0, 1.0));
+        assert_close(ray.intersect_aabb(&aabb).unwrap(), 4.0);
+
+        // from inside returns the exit point
+        let ray = Ray3f::new(Vec3f::new(0.0, 0.0, 0.0), Vec3f::new(0.0, 2.0, 0.0));
+        assert_close(ray.intersect_aabb(&aabb).unwrap(), 0.5);
+
+        // miss, behind, and axis parallel miss
+        let ray = Ray3f::new(Vec3f::new(-5.0, 2.0, 0.0), Vec3f::new(1.0, 0.0, 0.0));
+        assert_eq!(ray.intersect_aabb(&aabb), None);
+        let ray = Ray3f::new(Vec3f::new(5.0, 0.0, 0.0), Vec3f::new(1.0, 0.0, 0.0));
+        assert_eq!(ray.intersect_aabb(&aabb), None);
+        let ray = Ray3f::new(Vec3f::new(-5.0, 0.0, 3.0), Vec3f::new(1.0, 0.0, 0.0));
+        assert_eq!(ray.intersect_aabb(&aabb), None);
+    }
+
+    #[test]
+    fn test_ray_sphere() {
+        let sphere = Sphere::new(Vec3f::new(0.0, 0.0, -10.0), 2.0);
+
+        let ray = Ray3f::new(Vec3f::new(0.0, 0.0, 0.0), Vec3f::new(0.0, 0.0, -1.0));
+        assert_close(ray.intersect_sphere(&sphere).unwrap(), 8.0);
+
+        // non-normalized direction
+        let ray = Ray3f::new(Vec3f::new(0.0, 0.0, 0.0), Vec3f::new(0.0, 0.0, -2.0));
+        assert_close(ray.intersect_sphere(&sphere).unwrap(), 4.0);
+
+        // tangent
+        let ray = Ray3f::new(Vec3f::new(2.0, 0.0, 0.0), Vec3f::new(0.0, 0.0, -1.0));
+        assert_close(ray.intersect_sphere(&sphere).unwrap(), 10.0);
+
+        // from inside
+        let ray = Ray3f::new(Vec3f::new(0.0, 0.0, -10.0), Vec3f::new(1.0, 0.0, 0.0));
+        assert_close(ray.intersect_sphere(&sphere).unwrap(), 2.0);
+
+        // miss and behind
+        let ray = Ray3f::new(Vec3f::new(3.0, 0.0, 0.0), Vec3f::new(0.0, 0.0, -1.0));
+        assert_eq!(ray.intersect_sphere(&sphere), None);
+        let ray = Ray3f::new(Vec3f::new(0.0, 0.0, 0.0), Vec3f::new(0.0, 0.0, 1.0));
+        assert_eq!(ray.intersect_sphere(&sphere), None);
+    }
+
+    #[test]
+    fn test_ray_triangle() {
+        let v0 = Vec3f::new(0.0, 0.0, -3.0);
+        let v1 = Vec3f::new(1.0, 0.0, -3.0);
+        let v2 = Vec3f::new(0.0, 1.0, -3.0);
+
+        let ray = Ray3f::new(Vec3f::new(0.25, 0.25, 0.0), Vec3f::new(0.0, 0.0, -1.0));
+        assert_close(ray.intersect_triangle(&v0, &v1, &v2).unwrap(), 3.0);
+        // winding order doesn't matter
+        assert_close(ray.intersect_triangle(&v0, &v2, &v1).unwrap(), 3.0);
+
+        // outside of the triangle (u + v > 1)
+        let ray = Ray3f::new(Vec3f::new(0.75, 0.75, 0.0), Vec3f::new(0.0, 0.0, -1.0));
+        assert_eq!(ray.intersect_triangle(&v0, &v1, &v2), None);
+        // outside of the triangle (u < 0)
+        let ray = Ray3f::new(Vec3f::new(-0.1, 0.5, 0.0), Vec3f::new(0.0, 0.0, -1.0));
+        assert_eq!(ray.intersect_triangle(&v0, &v1, &v2), None);
+        // parallel
+        let ray = Ray3f::new(Vec3f::new(0.25, 0.25, 0.0), Vec3f::new(1.0, 0.0, 0.0));
+        assert_eq!(ray.intersect_triangle(&v0, &v1, &v2), None);
+        // behind
+        let ray = Ray3f::new(Vec3f::new(0.25, 0.25, 0.0), Vec3f::new(0.0, 0.0, 1.0));
+        assert_eq!(ray.intersect_triangle(&v0, &v1, &v2), None);
+    }
+
+    #[test]
+    fn test_aabb_transform() {
+        let aabb = Aabb::new(Vec3f::new(-1.0, -2.0, -3.0), Vec3f::new(1.0, 2.0, 3.0));
+
+        let transformed = aabb.transform(&Mat4f::translate(10.0, 0.0, 0.0));
+        assert_close_vec(&transformed.min, &Vec3f::new(9.0, -2.0, -3.0));
+        assert_close_vec(&transformed.max, &Vec3f::new(11.0, 2.0, 3.0));
+
+        let transformed = aabb.transform(&Mat4f::scale(2.0, -1.0, 1.0));
+        assert_close_vec(&transformed.min, &Vec3f::new(-2.0, -2.0, -3.0));
+        assert_close_vec(&transformed.max, &Vec3f::new(2.0, 2.0, 3.0));
+
+        // 90 deg around z swaps the x and y extents
+        let transformed = aabb.transform(&Mat4f::rotate(90.0, 0.0, 0.0, 1.0));
+        assert_close_vec(&transformed.min, &Vec3f::new(-2.0, -1.0, -3.0));
+        assert_close_vec(&transformed.max, &Vec3f::new(2.0, 1.0, 3.0));
+
+        // 45 deg around z: the transformed box must contain all transformed corners
+        let m = &Mat4f::translate(1.0, 2.0, 3.0) * &Mat4f::rotate(45.0, 0.0, 0.0, 1.0);
+        let transformed = aabb.transform(&m);
+        let half_diagonal = (0.5f32).sqrt() * 3.0;
+        assert_close_vec(&transformed.min, &Vec3f::new(1.0 - half_diagonal, 2.0 - half_diagonal, 0.0));
+        assert_close_vec(&transformed.max, &Vec3f::new(1.0 + half_diagonal, 2.0 + half_diagonal, 6.0));
+    }
+
+    #[test]
+    fn test_frustum_planes() {
+        let m = create_projection_frustum(-1.0, 1.0, -1.0, 1.0, 1.0, 100.0);
+        let frustum = Frustum::from_view_projection(&m);
+        let [left, right, bottom, top, near, far] = &frustum.planes;
+
+        let s = (0.5f32).sqrt();
+        assert_close_vec(&left.normal, &Vec3f::new(s, 0.0, -s));
+        assert_close_vec(&right.normal, &Vec3f::new(-s, 0.0, -s));
+        assert_close_vec(&bottom.normal, &Vec3f::new(0.0, s, -s));
+        assert_close_vec(&top.normal, &Vec3f::new(0.0, -s, -s));
+        assert_close_vec(&near.normal, &Vec3f::new(0.0, 0.0, -1.0));
+        assert_close_vec(&far.normal, &Vec3f::new(0.0, 0.0, 1.0));
+        assert_close(near.d, -1.0);
+        assert_close(far.d, 100.0);
+    }
+
+    #[test]
+    fn test_frustum_culling() {
+        let m = create_projection_frustum(-1.0, 1.0, -1.0, 1.0, 1.0, 100.0);
+        let frustum = Frustum::from_view_projection(&m);
+
+        assert!(frustum.contains_point(&Vec3f::new(0.0, 0.0, -10.0)));
+        assert!(frustum.contains_point(&Vec3f::new(9.0, -9.0, -10.0)));
+        assert!(!frustum.contains_point(&Vec3f::new(11.0, 0.0, -10.0)));
+        assert!(!frustum.contains_point(&Vec3f::new(0.0, 0.0, -0.5)));
+        assert!(!frustum.contains_point(&Vec3f::new(0.0, 0.0, -101.0)));
+        assert!(!frustum.contains_point(&Vec3f::new(0.0, 0.0, 10.0)));
+
+        assert!(frustum.intersects_sphere(&Sphere::new(Vec3f::new(0.0, 0.0, -10.0), 1.0)));
+        assert!(frustum.intersects_sphere(&Sphere::new(Vec3f::new(0.0, 0.0, 1.0), 2.5)));
+        assert!(!frustum.intersects_sphere(&Sphere::new(Vec3f::new(0.0, 0.0, 1.0), 1.5)));
+        assert!(!frustum.intersects_sphere(&Sphere::new(Vec3f::new(0.0, 20.0, -10.0), 5.0)));
+
+        let unit = Vec3f::new(1.0, 1.0, 1.0);
+        assert!(frustum.intersects_aabb(&Aabb::from_center_extents(&Vec3f::new(0.0, 0.0, -10.0), &unit)));
+        assert!(frustum.intersects_aabb(&Aabb::from_center_extents(&Vec3f::new(10.5, 0.0, -10.0), &unit)));
+        assert!(!frustum.intersects_aabb(&Aabb::from_center_extents(&Vec3f::new(12.5, 0.0, -10.0), &unit)));
+        assert!(!frustum.intersects_aabb(&Aabb::from_center_extents(&Vec3f::new(0.0, 0.0, -102.0), &unit)));
+    }
+
+    #[test]
+    fn test_frustum_view_projection() {
+        // Camera moved to x = 100, so the frustum planes should move along in world space.
+        let projection = create_projection_frustum(-1.0, 1.0, -1.0, 1.0, 1.0, 100.0);
+        let view = Mat4f::translate(-100.0, 0.0, 0.0);
+        let frustum = Frustum::from_view_projection(&(&projection * &view));
+
+        assert!(frustum.contains_point(&Vec3f::new(100.0, 0.0, -10.0)));
+        assert!(!frustum.contains_point(&Vec3f::new(0.0, 0.0, -10.0)));
+        assert!(frustum.intersects_sphere(&Sphere::new(Vec3f::new(100.0, 0.0, -50.0), 1.0)));
+        assert!(!frustum.intersects_sphere(&Sphere::new(Vec3f::new(0.0, 0.0, -50.0), 1.0)));
+    }
+}
@@ -1,13 +1,26 @@
+#![cfg_attr(feature = "portable_simd", feature(portable_simd))]
+
 use std::ops::Add;
 use std::ops::Sub;
 use std::ops::Mul;
 use std::ops::Div;
 use std::f32::consts::PI;
 
+mod approx;
+mod geometry;
+pub mod simd;
+mod transform;
+
+pub use approx::{AlmostEqual, DEFAULT_MAX_DIFF, DEFAULT_MAX_REL_DIFF};
+pub use geometry::{Aabb, Frustum, Plane, Ray3f, Sphere};
+pub use transform::{NodeId, Transform, TransformTree};
+
 // ----------------------------------------------------------------------------
 // Vec3f
 // ----------------------------------------------------------------------------
 
+#[derive(Clone, Debug, PartialEq)]
+#[repr(C)]
 pub struct Vec3f {
     pub x: f32,
     pub y: f32,
@@ -51,6 +64,17 @@ impl Mul for &Vec3f {
     type Output = Vec3f;
 }
 
+impl Div for &Vec3f {
+    fn div(self, that: &Vec3f) -> Vec3f {
+        Vec3f {
+            x: self.x / that.x,
+            y: self.y / that.y,
+            z: self.z / that.z,
+        }
+    }
+    type Output = Vec3f;
+}
+
 // ----------------------------------------------------------------------------
 // Scalar operations
 // ----------------------------------------------------------------------------
@@ -116,6 +140,10 @@ impl Vec3f {
         // return self?
     }
 
+    pub fn dot(&self, that: &Vec3f) -> f32 {
+        self.x*that.x + self.y*that.y + self.z*that.z
+    }
+
     pub fn cross(&self, that: &Vec3f) -> Vec3f {
         Vec3f {
             x: self.y*that.z - self.z*that.y,
@@ -165,6 +193,8 @@ impl Vec3f {
 // Vec4f
 // ----------------------------------------------------------------------------
 
+#[derive(Clone, Debug, PartialEq)]
+#[repr(C)]
 pub struct Vec4f {
     pub x: f32,
     pub y: f32,
@@ -335,6 +365,8 @@ impl Vec4f {
 // Vec4f
 // ----------------------------------------------------------------------------
 
+#[derive(Clone, Debug, PartialEq)]
+#[repr(C)]
 pub struct Mat4f {
     pub m00: f32, pub m10: f32, pub m20: f32, pub m30: f32,
     pub m01: f32, pub m11: f32, pub m21: f32, pub m31: f32,
@@ -449,6 +481,43 @@ impl Mat4f {
         }
     }
 
+    /**
+     * Same as `new`, i.e., the arguments are the elements of the first row, second row, ...
+     */
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_by_row(
+        m00: f32, m10: f32, m20: f32, m30: f32,
+        m01: f32, m11: f32, m21: f32, m31: f32,
+        m02: f32, m12: f32, m22: f32, m32: f32,
+        m03: f32, m13: f32, m23: f32, m33: f32,
+    ) -> Mat4f {
+        Mat4f::new(
+            m00, m10, m20, m30,
+            m01, m11, m21, m31,
+            m02, m12, m22, m32,
+            m03, m13, m23, m33,
+        )
+    }
+
+    /**
+     * The arguments are the elements of the first column, second column, ...
+     * (i.e., the order of a column-major array as used by OpenGL).
+     */
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_by_col(
+        m00: f32, m01: f32, m02: f32, m03: f32,
+        m10: f32, m11: f32, m12: f32, m13: f32,
+        m20: f32, m21: f32, m22: f32, m23: f32,
+        m30: f32, m31: f32, m32: f32, m33: f32,
+    ) -> Mat4f {
+        Mat4f::new(
+            m00, m10, m20, m30,
+            m01, m11, m21, m31,
+            m02, m12, m22, m32,
+            m03, m13, m23, m33,
+        )
+    }
+
     pub fn update(&mut self, that: &Mat4f) {
         self.m00 = that.m00;
         self.m01 = that.m01;
@@ -498,6 +567,36 @@ impl Mat4f {
         ).sqrt()
     }
 
+    /**
+     * Decomposes an affine matrix M = T * R * S into translation, rotation and scale.
+     * Shear cannot be represented and gets lost. A negative determinant (mirroring)
+     * is attributed to the x scale.
+     */
+    pub fn decompose(&self) -> Transform {
+        let translation = Vec3f::new(self.m30, self.m31, self.m32);
+        let mut sx = Vec3f::new(self.m00, self.m01, self.m02).length();
+        let sy = Vec3f::new(self.m10, self.m11, self.m12).length();
+        let sz = Vec3f::new(self.m20, self.m21, self.m22).length();
+        let det =
+            self.m00 * (self.m11*self.m22 - self.m21*self.m12) -
+            self.m10 * (self.m01*self.m22 - self.m21*self.m02) +
+            self.m20 * (self.m01*self.m12 - self.m11*self.m02);
+        if det < 0f32 {
+            sx = -sx;
+        }
+        let rotation_matrix = Mat4f::new(
+            self.m00/sx, self.m10/sy, self.m20/sz, 0f32,
+            self.m01/sx, self.m11/sy, self.m21/sz, 0f32,
+            self.m02/sx, self.m12/sy, self.m22/sz, 0f32,
+                   0f32,        0f32,        0f32, 1f32,
+        );
+        Transform {
+            translation,
+            rotation: Quaternion::create_from_rotation_matrix(&rotation_matrix),
+            scale: Vec3f::new(sx, sy, sz),
+        }
+    }
+
     // --------------------------------------------------------------
     // Constructors
     // --------------------------------------------------------------
@@ -510,6 +609,14 @@ impl Mat4f {
             0f32, 0f32, 0f32, 1f32,
         )
     }
+    pub fn create_from_mat3f(that: &Mat3f) -> Mat4f {
+        Mat4f::new(
+            that.m00, that.m10, that.m20, 0f32,
+            that.m01, that.m11, that.m21, 0f32,
+            that.m02, that.m12, that.m22, 0f32,
+                0f32,     0f32,     0f32, 1f32,
+        )
+    }
     pub fn create_zero() -> Mat4f {
         Mat4f::new(
             0f32, 0f32, 0f32, 0f32,
@@ -564,6 +671,7 @@ impl Mat4f {
      * http://www.flipcode.com/documents/matrfaq.html#Q36
      * http://www.songho.ca/opengl/gl_anglestoaxes.html
      *
+     * ```text
      *       |  CE      -CF      -D   0 |
      *  M  = | -BDE+AF   BDF+AE  -BC  0 |
      *       |  ADE+BF  -ADF+BE   AC  0 |
@@ -571,6 +679,7 @@ impl Mat4f {
      *   where A,B are the cosine and sine of the X-axis rotation axis, (pitch)
      *         C,D are the cosine and sine of the Y-axis rotation axis, (yaw)
      *         E,F are the cosine and sine of the Z-axis rotation axis. (roll)
+     * ```
      *
      * Convention: angles in DEG
      */
@@ -974,6 +1083,224 @@ object Mat3f {
 }
 */
 
+// ----------------------------------------------------------------------------
+// Mat3f
+// ----------------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq)]
+#[repr(C)]
+pub struct Mat3f {
+    pub m00: f32, pub m10: f32, pub m20: f32,
+    pub m01: f32, pub m11: f32, pub m21: f32,
+    pub m02: f32, pub m12: f32, pub m22: f32,
+}
+
+// ----------------------------------------------------------------------------
+// Matrix/Matrix operations
+// ----------------------------------------------------------------------------
+
+impl Add for &Mat3f {
+    fn add(self, that: &Mat3f) -> Mat3f {
+        Mat3f::new(
+            self.m00+that.m00, self.m10+that.m10, self.m20+that.m20,
+            self.m01+that.m01, self.m11+that.m11, self.m21+that.m21,
+            self.m02+that.m02, self.m12+that.m12, self.m22+that.m22,
+        )
+    }
+    type Output = Mat3f;
+}
+
+impl Sub for &Mat3f {
+    fn sub(self, that: &Mat3f) -> Mat3f {
+        Mat3f::new(
+            self.m00-that.m00, self.m10-that.m10, self.m20-that.m20,
+            self.m01-that.m01, self.m11-that.m11, self.m21-that.m21,
+            self.m02-that.m02, self.m12-that.m12, self.m22-that.m22,
+        )
+    }
+    type Output = Mat3f;
+}
+
+impl Mul for &Mat3f {
+    fn mul(self, that: &Mat3f) -> Mat3f {
+        let nm00 = self.m00 * that.m00 + self.m10 * that.m01 + self.m20 * that.m02;
+        let nm01 = self.m01 * that.m00 + self.m11 * that.m01 + self.m21 * that.m02;
+        let nm02 = self.m02 * that.m00 + self.m12 * that.m01 + self.m22 * that.m02;
+        let nm10 = self.m00 * that.m10 + self.m10 * that.m11 + self.m20 * that.m12;
+        let nm11 = self.m01 * that.m10 + self.m11 * that.m11 + self.m21 * that.m12;
+        let nm12 = self.m02 * that.m10 + self.m12 * that.m11 + self.m22 * that.m12;
+        let nm20 = self.m00 * that.m20 + self.m10 * that.m21 + self.m20 * that.m22;
+        let nm21 = self.m01 * that.m20 + self.m11 * that.m21 + self.m21 * that.m22;
+        let nm22 = self.m02 * that.m20 + self.m12 * that.m21 + self.m22 * that.m22;
+        Mat3f::new(
+            nm00, nm10, nm20,
+            nm01, nm11, nm21,
+            nm02, nm12, nm22,
+        )
+    }
+    type Output = Mat3f;
+}
+
+// ----------------------------------------------------------------------------
+// Matrix/Vector operations
+// ----------------------------------------------------------------------------
+
+impl Mul<&Vec3f> for &Mat3f {
+    fn mul(self, v: &Vec3f) -> Vec3f {
+        Vec3f {
+            x: self.m00*v.x + self.m10*v.y + self.m20*v.z,
+            y: self.m01*v.x + self.m11*v.y + self.m21*v.z,
+            z: self.m02*v.x + self.m12*v.y + self.m22*v.z,
+        }
+    }
+    type Output = Vec3f;
+}
+
+// ----------------------------------------------------------------------------
+// Scalar operations
+// ----------------------------------------------------------------------------
+
+impl Mul<f32> for &Mat3f {
+    fn mul(self, s: f32) -> Mat3f {
+        Mat3f::new(
+            self.m00*s, self.m10*s, self.m20*s,
+            self.m01*s, self.m11*s, self.m21*s,
+            self.m02*s, self.m12*s, self.m22*s,
+        )
+    }
+    type Output = Mat3f;
+}
+
+// ----------------------------------------------------------------------------
+// Methods
+// ----------------------------------------------------------------------------
+
+impl Mat3f {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        m00: f32, m10: f32, m20: f32,
+        m01: f32, m11: f32, m21: f32,
+        m02: f32, m12: f32, m22: f32,
+    ) -> Mat3f {
+        Mat3f {
+            m00, m10, m20,
+            m01, m11, m21,
+            m02, m12, m22,
+        }
+    }
+
+    /**
+     * Same as `new`, i.e., the arguments are the elements of the first row, second row, ...
+     */
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_by_row(
+        m00: f32, m10: f32, m20: f32,
+        m01: f32, m11: f32, m21: f32,
+        m02: f32, m12: f32, m22: f32,
+    ) -> Mat3f {
+        Mat3f::new(
+            m00, m10, m20,
+            m01, m11, m21,
+            m02, m12, m22,
+        )
+    }
+
+    /**
+     * The arguments are the elements of the first column, second column, ...
+     */
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_by_col(
+        m00: f32, m01: f32, m02: f32,
+        m10: f32, m11: f32, m12: f32,
+        m20: f32, m21: f32, m22: f32,
+    ) -> Mat3f {
+        Mat3f::new(
+            m00, m10, m20,
+            m01, m11, m21,
+            m02, m12, m22,
+        )
+    }
+
+    pub fn transpose(&self) -> Mat3f {
+        Mat3f::new(
+            self.m00, self.m01, self.m02,
+            self.m10, self.m11, self.m12,
+            self.m20, self.m21, self.m22,
+        )
+    }
+
+    pub fn determinant(&self) -> f32 {
+        self.m00 * (self.m11*self.m22 - self.m21*self.m12) -
+        self.m10 * (self.m01*self.m22 - self.m21*self.m02) +
+        self.m20 * (self.m01*self.m12 - self.m11*self.m02)
+    }
+
+    /**
+     * https://github.com/LWJGL/lwjgl/blob/master/src/java/org/lwjgl/util/vector/Matrix3f.java
+     * http://ardoris.wordpress.com/2008/07/18/general-formula-for-the-inverse-of-a-3x3-matrix/
+     *
+     * Returns None for singular matrices.
+     */
+    pub fn inverse(&self) -> Option<Mat3f> {
+        let a = self.m00;
+        let b = self.m10;
+        let c = self.m20;
+        let d = self.m01;
+        let e = self.m11;
+        let f = self.m21;
+        let g = self.m02;
+        let h = self.m12;
+        let i = self.m22;
+        let det = a*(e*i-f*h) - b*(d*i-f*g) + c*(d*h-e*g);
+        if det == 0f32 {
+            return None;
+        }
+        let det_inv = 1f32 / det;
+        Some(Mat3f::new(
+            det_inv*(e*i-f*h), det_inv*(c*h-b*i), det_inv*(b*f-c*e),
+            det_inv*(f*g-d*i), det_inv*(a*i-c*g), det_inv*(c*d-a*f),
+            det_inv*(d*h-e*g), det_inv*(b*g-a*h), det_inv*(a*e-b*d),
+        ))
+    }
+
+    pub fn frobenius_distance(&self, that: &Mat3f) -> f32 {
+        (
+            (self.m00-that.m00)*(self.m00-that.m00) +
+            (self.m01-that.m01)*(self.m01-that.m01) +
+            (self.m02-that.m02)*(self.m02-that.m02) +
+            (self.m10-that.m10)*(self.m10-that.m10) +
+            (self.m11-that.m11)*(self.m11-that.m11) +
+            (self.m12-that.m12)*(self.m12-that.m12) +
+            (self.m20-that.m20)*(self.m20-that.m20) +
+            (self.m21-that.m21)*(self.m21-that.m21) +
+            (self.m22-that.m22)*(self.m22-that.m22)
+        ).sqrt()
+    }
+
+    // --------------------------------------------------------------
+    // Constructors
+    // --------------------------------------------------------------
+
+    pub fn create_identity() -> Mat3f {
+        Mat3f::new(
+            1f32, 0f32, 0f32,
+            0f32, 1f32, 0f32,
+            0f32, 0f32, 1f32,
+        )
+    }
+
+    /**
+     * Extracts the upper left 3x3 block, i.e., the linear part of an affine transformation.
+     */
+    pub fn create_from_mat4f(that: &Mat4f) -> Mat3f {
+        Mat3f::new(
+            that.m00, that.m10, that.m20,
+            that.m01, that.m11, that.m21,
+            that.m02, that.m12, that.m22,
+        )
+    }
+}
+
 // ----------------------------------------------------------------------------
 // HandedSystem
 // ----------------------------------------------------------------------------
@@ -988,6 +1315,7 @@ pub enum HandedSystem {
 // ----------------------------------------------------------------------------
 
 
+#[derive(Clone, Debug, PartialEq)]
 pub struct Quaternion {
     pub x: f32,
     pub y: f32,
@@ -1150,6 +1478,93 @@ impl Quaternion {
         self.w =  self.w;
     }
 
+    /**
+     * m must be a pure rotation (orthogonal), no scale/shear allowed
+     *
+     * http://www.cs.princeton.edu/~gewang/projects/darth/stuff/quat_faq.html#Q55
+     */
+    pub fn create_from_rotation_matrix(m: &Mat4f) -> Quaternion {
+        let trace = m.m00 + m.m11 + m.m22 + 1f32;
+        if trace > 0.0000001f32 {
+            let s = trace.sqrt() * 2f32;
+            Quaternion::new(
+                (m.m12 - m.m21) / s,
+                (m.m20 - m.m02) / s,
+                (m.m01 - m.m10) / s,
+                0.25f32 * s,
+            )
+        } else if m.m00 > m.m11 && m.m00 > m.m22 {
+            let s = (1f32 + m.m00 - m.m11 - m.m22).sqrt() * 2f32;
+            Quaternion::new(
+                0.25f32 * s,
+                (m.m01 + m.m10) / s,
+                (m.m20 + m.m02) / s,
+                (m.m12 - m.m21) / s,
+            )
+        } else if m.m11 > m.m22 {
+            let s = (1f32 + m.m11 - m.m00 - m.m22).sqrt() * 2f32;
+            Quaternion::new(
+                (m.m01 + m.m10) / s,
+                0.25f32 * s,
+                (m.m12 + m.m21) / s,
+                (m.m20 - m.m02) / s,
+            )
+        } else {
+            let s = (1f32 + m.m22 - m.m00 - m.m11).sqrt() * 2f32;
+            Quaternion::new(
+                (m.m20 + m.m02) / s,
+                (m.m12 + m.m21) / s,
+                0.25f32 * s,
+                (m.m01 - m.m10) / s,
+            )
+        }
+    }
+
+    pub fn create_identity() -> Quaternion {
+        Quaternion::new(0f32, 0f32, 0f32, 1f32)
+    }
+
+    pub fn dot(&self, that: &Quaternion) -> f32 {
+        self.x*that.x + self.y*that.y + self.z*that.z + self.w*that.w
+    }
+
+    /**
+     * Rotates v by the (unit) quaternion, i.e., q * v * q^-1.
+     */
+    pub fn rotate_vector(&self, v: &Vec3f) -> Vec3f {
+        let u = Vec3f::new(self.x, self.y, self.z);
+        let t = &u.cross(v) * 2f32;
+        &(v + &(&t * self.w)) + &u.cross(&t)
+    }
+
+    /**
+     * Spherical linear interpolation along the shorter arc:
+     *   https://en.wikipedia.org/wiki/Slerp
+     * Falls back to normalized linear interpolation for nearly identical rotations.
+     */
+    pub fn slerp(&self, that: &Quaternion, alpha: f32) -> Quaternion {
+        let mut cos_theta = self.dot(that);
+        let that = if cos_theta < 0f32 {
+            cos_theta = -cos_theta;
+            that * -1f32
+        } else {
+            that.clone()
+        };
+        let (a, b) = if cos_theta > 0.9995f32 {
+            (1f32 - alpha, alpha)
+        } else {
+            let theta = cos_theta.acos();
+            let sin_theta = theta.sin();
+            (((1f32 - alpha) * theta).sin() / sin_theta, (alpha * theta).sin() / sin_theta)
+        };
+        Quaternion::new(
+            a*self.x + b*that.x,
+            a*self.y + b*that.y,
+            a*self.z + b*that.z,
+            a*self.w + b*that.w,
+        ).normalized()
+    }
+
     pub fn cast_to_orientation_matrix(&self) -> Mat4f {
         let x = self.x;
         let y = self.y;
@@ -1461,4 +1876,129 @@ case class EulerAngles(yaw: Float, pitch: Float, roll: Float) {
 }
 
 
-*/
\ No newline at end of file
+*/
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Vec3f tests ported from FirstSteps/src/glmath
+
+    #[test]
+    fn test_vec3f_add() {
+        let v1 = Vec3f::new(1., 2., 3.);
+        let v2 = Vec3f::new(4., 5., 6.);
+        assert_eq!(&v1 + &v2, Vec3f::new(5., 7., 9.));
+    }
+
+    #[test]
+    fn test_vec3f_sub() {
+        let v1 = Vec3f::new(1., 2., 3.);
+        let v2 = Vec3f::new(4., 5., 6.);
+        assert_eq!(&v1 - &v2, Vec3f::new(-3., -3., -3.));
+    }
+
+    #[test]
+    fn test_vec3f_mul() {
+        let v1 = Vec3f::new(1., 2., 3.);
+        let v2 = Vec3f::new(4., 5., 6.);
+        assert_eq!(&v1 * &v2, Vec3f::new(4., 10., 18.));
+    }
+
+    #[test]
+    fn test_vec3f_div() {
+        let v1 = Vec3f::new(1., 2., 3.);
+        let v2 = Vec3f::new(4., 5., 6.);
+        assert_eq!(&v1 / &v2, Vec3f::new(1./4., 2./5., 3./6.));
+    }
+
+    #[test]
+    fn test_vec3f_normalize() {
+        let v1 = Vec3f::new(1., 2., 3.);
+        let v2 = Vec3f::new(4., 5., 6.);
+        assert!(v1.normalized().length().almost_equal(&1f32));
+        assert!(v2.normalized().length().almost_equal(&1f32));
+        assert!(v1.normalized_with_length(3.).length().almost_equal(&3f32));
+    }
+
+    #[test]
+    fn test_mat4f_new_by_row_and_col() {
+        let by_row = Mat4f::new_by_row(
+             1.,  2.,  3.,  4.,
+             5.,  6.,  7.,  8.,
+             9., 10., 11., 12.,
+            13., 14., 15., 16.,
+        );
+        let by_col = Mat4f::new_by_col(
+             1.,  5.,  9., 13.,
+             2.,  6., 10., 14.,
+             3.,  7., 11., 15.,
+             4.,  8., 12., 16.,
+        );
+        assert_eq!(by_row, by_col);
+        assert_eq!(by_row.m30, 4.);
+        assert_eq!(by_row.m03, 13.);
+        assert_eq!(Mat4f::new_by_col(
+            1., 0., 0., 0.,
+            0., 1., 0., 0.,
+            0., 0., 1., 0.,
+            1., 2., 3., 1.,
+        ), Mat4f::translate(1., 2., 3.));
+    }
+
+    #[test]
+    fn test_mat3f_new_by_row_and_col() {
+        let by_row = Mat3f::new_by_row(
+            1., 2., 3.,
+            4., 5., 6.,
+            7., 8., 9.,
+        );
+        let by_col = Mat3f::new_by_col(
+            1., 4., 7.,
+            2., 5., 8.,
+            3., 6., 9.,
+        );
+        assert_eq!(by_row, by_col);
+        assert_eq!(by_row.transpose(), Mat3f::new_by_col(
+            1., 2., 3.,
+            4., 5., 6.,
+            7., 8., 9.,
+        ));
+    }
+
+    #[test]
+    fn test_mat3f_inverse() {
+        let m = Mat3f::new(
+            2., 0., 1.,
+            1., 3., 0.,
+            0., 1., 4.,
+        );
+        assert_eq!(m.determinant(), 25.);
+        let m_inv = m.inverse().unwrap();
+        assert!((&m * &m_inv).almost_equal_relative(&Mat3f::create_identity(), 1e-6, 1e-6));
+        assert!((&m_inv * &m).almost_equal_relative(&Mat3f::create_identity(), 1e-6, 1e-6));
+
+        let singular = Mat3f::new(
+            1., 2., 3.,
+            2., 4., 6.,
+            0., 1., 4.,
+        );
+        assert_eq!(singular.inverse(), None);
+    }
+
+    #[test]
+    fn test_mat3f_matches_mat4f() {
+        let m4 = &Mat4f::rotate(33.0, 1.0, 2.0, 3.0) * &Mat4f::scale(0.5, 2.0, 3.0);
+        let m3 = Mat3f::create_from_mat4f(&m4);
+        assert_eq!(Mat4f::create_from_mat3f(&m3), m4);
+
+        let v = Vec3f::new(1.5, -2.5, 3.5);
+        let v3 = &m3 * &v;
+        let v4 = &m4 * &Vec4f::new(v.x, v.y, v.z, 0.);
+        assert!(v3.almost_equal(&Vec3f::new(v4.x, v4.y, v4.z)));
+
+        let m3_sq = Mat3f::create_from_mat4f(&(&m4 * &m4));
+        assert!((&m3 * &m3).almost_equal_relative(&m3_sq, 1e-6, 1e-6));
+        assert_eq!((&m3 * 2.).frobenius_distance(&(&m3 + &m3)), 0.);
+        assert_eq!((&m3 - &m3).frobenius_distance(&(&m3 * 0.)), 0.);
+    }
+}
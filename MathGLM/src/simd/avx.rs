@@ -0,0 +1,63 @@
+#[cfg(target_arch = "x86")]
+use std::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+use crate::Mat4f;
+use crate::Vec3f;
+
+use super::as_array;
+use super::sse2;
+
+#[target_feature(enable = "avx")]
+pub unsafe fn mat4_mul(a: &Mat4f, b: &Mat4f) -> Mat4f {
+    // Processes two rows of the result at once: Each 128 bit lane holds one row of `a`,
+    // `_mm256_permute_ps` broadcasts a[r][k] within its lane, and the rows of `b` are
+    // duplicated into both lanes. The summation order matches the scalar implementation.
+    let a = as_array(a).as_ptr();
+    let [b0, b1, b2, b3] = sse2::load_rows(b);
+    let bb0 = _mm256_broadcast_ps(&b0);
+    let bb1 = _mm256_broadcast_ps(&b1);
+    let bb2 = _mm256_broadcast_ps(&b2);
+    let bb3 = _mm256_broadcast_ps(&b3);
+    let mut out = [0f32; 16];
+    for half in 0..2 {
+        let rows = _mm256_loadu_ps(a.add(8 * half));
+        let mut acc = _mm256_mul_ps(_mm256_permute_ps::<0x00>(rows), bb0);
+        acc = _mm256_add_ps(acc, _mm256_mul_ps(_mm256_permute_ps::<0x55>(rows), bb1));
+        acc = _mm256_add_ps(acc, _mm256_mul_ps(_mm256_permute_ps::<0xAA>(rows), bb2));
+        acc = _mm256_add_ps(acc, _mm256_mul_ps(_mm256_permute_ps::<0xFF>(rows), bb3));
+        _mm256_storeu_ps(out.as_mut_ptr().add(8 * half), acc);
+    }
+    super::from_array(out)
+}
+
+#[target_feature(enable = "avx")]
+pub unsafe fn transform_points(m: &Mat4f, input: &[Vec3f], output: &mut [Vec3f]) {
+    // Two points per iteration, one in each 128 bit lane.
+    let cols = sse2::transpose_rows(sse2::load_rows(m));
+    let cc0 = _mm256_broadcast_ps(&cols[0]);
+    let cc1 = _mm256_broadcast_ps(&cols[1]);
+    let cc2 = _mm256_broadcast_ps(&cols[2]);
+    let cc3 = _mm256_broadcast_ps(&cols[3]);
+    let mut buf = [0f32; 8];
+
+    let mut input_pairs = input.chunks_exact(2);
+    let mut output_pairs = output.chunks_exact_mut(2);
+    for (p, out) in (&mut input_pairs).zip(&mut output_pairs) {
+        let x = _mm256_setr_ps(p[0].x, p[0].x, p[0].x, p[0].x, p[1].x, p[1].x, p[1].x, p[1].x);
+        let y = _mm256_setr_ps(p[0].y, p[0].y, p[0].y, p[0].y, p[1].y, p[1].y, p[1].y, p[1].y);
+        let z = _mm256_setr_ps(p[0].z, p[0].z, p[0].z, p[0].z, p[1].z, p[1].z, p[1].z, p[1].z);
+        let mut acc = _mm256_mul_ps(cc0, x);
+        acc = _mm256_add_ps(acc, _mm256_mul_ps(cc1, y));
+        acc = _mm256_add_ps(acc, _mm256_mul_ps(cc2, z));
+        acc = _mm256_add_ps(acc, cc3);
+        _mm256_storeu_ps(buf.as_mut_ptr(), acc);
+        out[0] = Vec3f::new(buf[0], buf[1], buf[2]);
+        out[1] = Vec3f::new(buf[4], buf[5], buf[6]);
+    }
+
+    for (p, out) in input_pairs.remainder().iter().zip(output_pairs.into_remainder()) {
+        sse2::transform_point(&cols, p, out);
+    }
+}
@@ -0,0 +1,332 @@
+//! SIMD kernels for the hot matrix operations.
+//!
+//! The scalar implementations in `scalar` are the reference: All other backends perform
+//! exactly the same floating point operations in exactly the same order (no FMA, no
+//! re-association), so their results are bitwise identical to the scalar code.
+//!
+//! Mat4f is laid out row by row in memory (`#[repr(C)]` with fields m00, m10, m20, m30,
+//! m01, ...), i.e., `as_array(m)[4*r + c]` is the element in row r and column c.
+
+use std::sync::OnceLock;
+
+use crate::Mat4f;
+use crate::Vec3f;
+use crate::Vec4f;
+
+mod scalar;
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod sse2;
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod avx;
+
+#[cfg(feature = "portable_simd")]
+mod portable;
+
+// ----------------------------------------------------------------------------
+// Backend
+// ----------------------------------------------------------------------------
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Scalar,
+    Sse2,
+    Avx,
+    Portable,
+}
+
+impl Backend {
+
+    /**
+     * Returns the fastest backend supported by the CPU at runtime.
+     */
+    pub fn detect() -> Backend {
+        [Backend::Avx, Backend::Sse2, Backend::Portable]
+            .iter()
+            .copied()
+            .find(|backend| backend.is_available())
+            .unwrap_or(Backend::Scalar)
+    }
+
+    /**
+     * Returns the backend of `detect`, which is detected on the first call only.
+     */
+    pub fn detected() -> Backend {
+        static DETECTED: OnceLock<Backend> = OnceLock::new();
+        *DETECTED.get_or_init(Backend::detect)
+    }
+
+    pub fn available() -> Vec<Backend> {
+        [Backend::Scalar, Backend::Sse2, Backend::Avx, Backend::Portable]
+            .iter()
+            .copied()
+            .filter(|backend| backend.is_available())
+            .collect()
+    }
+
+    pub fn is_available(self) -> bool {
+        match self {
+            Backend::Scalar => true,
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Backend::Sse2 => is_x86_feature_detected!("sse2"),
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Backend::Avx => is_x86_feature_detected!("avx"),
+            #[cfg(feature = "portable_simd")]
+            Backend::Portable => true,
+            #[allow(unreachable_patterns)]
+            _ => false,
+        }
+    }
+
+    fn assert_available(self) {
+        assert!(self.is_available(), "SIMD backend {:?} is not available", self);
+    }
+
+    pub fn mat4_mul(self, a: &Mat4f, b: &Mat4f) -> Mat4f {
+        self.assert_available();
+        // Safety: checked by assert_available, here and below.
+        unsafe { self.mat4_mul_unchecked(a, b) }
+    }
+
+    pub fn mat4_mul_vec4(self, m: &Mat4f, v: &Vec4f) -> Vec4f {
+        self.assert_available();
+        unsafe { self.mat4_mul_vec4_unchecked(m, v) }
+    }
+
+    pub fn mat4_transpose(self, m: &Mat4f) -> Mat4f {
+        self.assert_available();
+        unsafe { self.mat4_transpose_unchecked(m) }
+    }
+
+    /**
+     * Panics if `input` and `output` have different lengths.
+     */
+    pub fn transform_points(self, m: &Mat4f, input: &[Vec3f], output: &mut [Vec3f]) {
+        self.assert_available();
+        unsafe { self.transform_points_unchecked(m, input, output) }
+    }
+
+    // The unchecked variants require that the backend is available, which makes the unsafe
+    // target feature functions safe to call.
+
+    unsafe fn mat4_mul_unchecked(self, a: &Mat4f, b: &Mat4f) -> Mat4f {
+        match self {
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Backend::Sse2 => sse2::mat4_mul(a, b),
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Backend::Avx => avx::mat4_mul(a, b),
+            #[cfg(feature = "portable_simd")]
+            Backend::Portable => portable::mat4_mul(a, b),
+            _ => scalar::mat4_mul(a, b),
+        }
+    }
+
+    unsafe fn mat4_mul_vec4_unchecked(self, m: &Mat4f, v: &Vec4f) -> Vec4f {
+        match self {
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Backend::Sse2 | Backend::Avx => sse2::mat4_mul_vec4(m, v),
+            #[cfg(feature = "portable_simd")]
+            Backend::Portable => portable::mat4_mul_vec4(m, v),
+            _ => scalar::mat4_mul_vec4(m, v),
+        }
+    }
+
+    unsafe fn mat4_transpose_unchecked(self, m: &Mat4f) -> Mat4f {
+        match self {
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Backend::Sse2 | Backend::Avx => sse2::mat4_transpose(m),
+            #[cfg(feature = "portable_simd")]
+            Backend::Portable => portable::mat4_transpose(m),
+            _ => scalar::mat4_transpose(m),
+        }
+    }
+
+    unsafe fn transform_points_unchecked(self, m: &Mat4f, input: &[Vec3f], output: &mut [Vec3f]) {
+        assert_eq!(input.len(), output.len(), "input and output must have the same length");
+        match self {
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Backend::Sse2 => sse2::transform_points(m, input, output),
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Backend::Avx => avx::transform_points(m, input, output),
+            #[cfg(feature = "portable_simd")]
+            Backend::Portable => portable::transform_points(m, input, output),
+            _ => scalar::transform_points(m, input, output),
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Convenience functions using the detected backend
+// ----------------------------------------------------------------------------
+
+// Safety: the detected backend is available, here and below.
+
+pub fn mat4_mul(a: &Mat4f, b: &Mat4f) -> Mat4f {
+    unsafe { Backend::detected().mat4_mul_unchecked(a, b) }
+}
+
+pub fn mat4_mul_vec4(m: &Mat4f, v: &Vec4f) -> Vec4f {
+    unsafe { Backend::detected().mat4_mul_vec4_unchecked(m, v) }
+}
+
+pub fn mat4_transpose(m: &Mat4f) -> Mat4f {
+    unsafe { Backend::detected().mat4_transpose_unchecked(m) }
+}
+
+impl Mat4f {
+
+    /**
+     * Transforms points as (x, y, z, 1), i.e., the matrix is assumed to be affine
+     * and no perspective division is performed.
+     */
+    pub fn transform_points(&self, input: &[Vec3f], output: &mut [Vec3f]) {
+        unsafe { Backend::detected().transform_points_unchecked(self, input, output) }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Memory access helpers
+// ----------------------------------------------------------------------------
+
+pub(crate) fn as_array(m: &Mat4f) -> &[f32; 16] {
+    // Safety: Mat4f is #[repr(C)] and consists of exactly 16 f32.
+    unsafe { &*(m as *const Mat4f as *const [f32; 16]) }
+}
+
+pub(crate) fn from_array(a: [f32; 16]) -> Mat4f {
+    Mat4f::new(
+        a[ 0], a[ 1], a[ 2], a[ 3],
+        a[ 4], a[ 5], a[ 6], a[ 7],
+        a[ 8], a[ 9], a[10], a[11],
+        a[12], a[13], a[14], a[15],
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn example_matrices() -> Vec<Mat4f> {
+        vec![
+            Mat4f::create_identity(),
+            Mat4f::translate(1.0, -2.0, 3.0),
+            &Mat4f::rotate(33.0, 1.0, 2.0, 3.0) * &Mat4f::scale(0.1, 7.0, -3.0),
+            Mat4f::rotate_yaw_pitch_roll_quaternions(10.0, 20.0, 30.0),
+            Mat4f::new(
+                 0.1,  1.7, -2.3,  3.1,
+                 4.9, -5.3,  6.7,  7.3,
+                -8.1,  9.9, 10.3, 11.7,
+                12.1, 13.3, 14.7, -15.9,
+            ),
+        ]
+    }
+
+    fn example_points(n: usize) -> Vec<Vec3f> {
+        (0..n)
+            .map(|i| {
+                let i = i as f32;
+                Vec3f::new(i * 0.37 - 1.0, (i * 1.3).sin() * 10.0, 1.0 / (i + 0.5))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_as_array_layout() {
+        let m = Mat4f::translate(1.0, 2.0, 3.0);
+        assert_eq!(as_array(&m)[3], 1.0);
+        assert_eq!(as_array(&m)[7], 2.0);
+        assert_eq!(as_array(&m)[11], 3.0);
+        assert_eq!(from_array(*as_array(&m)), m);
+    }
+
+    #[test]
+    fn test_detect() {
+        let backend = Backend::detect();
+        assert!(backend.is_available());
+        assert!(Backend::available().contains(&backend));
+        assert!(Backend::available().contains(&Backend::Scalar));
+        assert_eq!(Backend::detected(), backend);
+    }
+
+    #[test]
+    fn test_scalar_matches_operators() {
+        for a in example_matrices() {
+            for b in example_matrices() {
+                assert_eq!(scalar::mat4_mul(&a, &b), &a * &b);
+            }
+            let v = Vec4f::new(1.5, -2.5, 3.5, 1.0);
+            assert_eq!(scalar::mat4_mul_vec4(&a, &v), &a * &v);
+            assert_eq!(scalar::mat4_transpose(&a), a.transpose());
+        }
+    }
+
+    #[test]
+    fn test_mat4_mul_exact() {
+        for backend in Backend::available() {
+            for a in example_matrices() {
+                for b in example_matrices() {
+                    assert_eq!(backend.mat4_mul(&a, &b), &a * &b, "backend: {:?}", backend);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_mat4_mul_vec4_exact() {
+        let vectors = [
+            Vec4f::new(0.0, 0.0, 0.0, 1.0),
+            Vec4f::new(1.5, -2.5, 3.5, 1.0),
+            Vec4f::new(-0.3, 1e7, 1e-7, -2.0),
+        ];
+        for backend in Backend::available() {
+            for m in example_matrices() {
+                for v in &vectors {
+                    assert_eq!(backend.mat4_mul_vec4(&m, v), &m * v, "backend: {:?}", backend);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_mat4_transpose_exact() {
+        for backend in Backend::available() {
+            for m in example_matrices() {
+                assert_eq!(backend.mat4_transpose(&m), m.transpose(), "backend: {:?}", backend);
+            }
+        }
+    }
+
+    #[test]
+    fn test_transform_points_exact() {
+        // odd lengths to cover the remainder handling of the multi-point kernels
+        for n in [0, 1, 2, 3, 7, 100] {
+            let input = example_points(n);
+            for m in example_matrices() {
+                let expected: Vec<Vec3f> = input
+                    .iter()
+                    .map(|p| {
+                        let v = &m * &Vec4f::new(p.x, p.y, p.z, 1.0);
+                        Vec3f::new(v.x, v.y, v.z)
+                    })
+                    .collect();
+                for backend in Backend::available() {
+                    let mut output = vec![Vec3f::new(0.0, 0.0, 0.0); n];
+                    backend.transform_points(&m, &input, &mut output);
+                    assert_eq!(output, expected, "backend: {:?}", backend);
+                }
+                let mut output = vec![Vec3f::new(0.0, 0.0, 0.0); n];
+                m.transform_points(&input, &mut output);
+                assert_eq!(output, expected);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_transform_points_length_mismatch() {
+        let input = example_points(3);
+        let mut output = vec![Vec3f::new(0.0, 0.0, 0.0); 2];
+        Mat4f::create_identity().transform_points(&input, &mut output);
+    }
+}
@@ -0,0 +1,71 @@
+use std::simd::f32x4;
+
+use crate::Mat4f;
+use crate::Vec3f;
+use crate::Vec4f;
+
+use super::as_array;
+
+fn load_rows(m: &Mat4f) -> [f32x4; 4] {
+    let a = as_array(m);
+    [
+        f32x4::from_slice(&a[0..4]),
+        f32x4::from_slice(&a[4..8]),
+        f32x4::from_slice(&a[8..12]),
+        f32x4::from_slice(&a[12..16]),
+    ]
+}
+
+fn load_cols(m: &Mat4f) -> [f32x4; 4] {
+    let a = as_array(m);
+    [
+        f32x4::from_array([a[0], a[4], a[8], a[12]]),
+        f32x4::from_array([a[1], a[5], a[9], a[13]]),
+        f32x4::from_array([a[2], a[6], a[10], a[14]]),
+        f32x4::from_array([a[3], a[7], a[11], a[15]]),
+    ]
+}
+
+fn store_rows(rows: [f32x4; 4]) -> Mat4f {
+    let mut out = [0f32; 16];
+    for (r, row) in rows.iter().enumerate() {
+        out[4 * r..4 * r + 4].copy_from_slice(row.as_array());
+    }
+    super::from_array(out)
+}
+
+pub fn mat4_mul(a: &Mat4f, b: &Mat4f) -> Mat4f {
+    let a = as_array(a);
+    let [b0, b1, b2, b3] = load_rows(b);
+    let mut rows = [f32x4::splat(0f32); 4];
+    for (r, row) in rows.iter_mut().enumerate() {
+        *row = f32x4::splat(a[4 * r]) * b0
+            + f32x4::splat(a[4 * r + 1]) * b1
+            + f32x4::splat(a[4 * r + 2]) * b2
+            + f32x4::splat(a[4 * r + 3]) * b3;
+    }
+    store_rows(rows)
+}
+
+pub fn mat4_mul_vec4(m: &Mat4f, v: &Vec4f) -> Vec4f {
+    let [c0, c1, c2, c3] = load_cols(m);
+    let r = c0 * f32x4::splat(v.x)
+        + c1 * f32x4::splat(v.y)
+        + c2 * f32x4::splat(v.z)
+        + c3 * f32x4::splat(v.w);
+    let [x, y, z, w] = r.to_array();
+    Vec4f::new(x, y, z, w)
+}
+
+pub fn mat4_transpose(m: &Mat4f) -> Mat4f {
+    store_rows(load_cols(m))
+}
+
+pub fn transform_points(m: &Mat4f, input: &[Vec3f], output: &mut [Vec3f]) {
+    let [c0, c1, c2, c3] = load_cols(m);
+    for (p, out) in input.iter().zip(output.iter_mut()) {
+        let r = c0 * f32x4::splat(p.x) + c1 * f32x4::splat(p.y) + c2 * f32x4::splat(p.z) + c3;
+        let [x, y, z, _] = r.to_array();
+        *out = Vec3f::new(x, y, z);
+    }
+}
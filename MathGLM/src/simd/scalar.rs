@@ -0,0 +1,23 @@
+use crate::Mat4f;
+use crate::Vec3f;
+use crate::Vec4f;
+
+pub fn mat4_mul(a: &Mat4f, b: &Mat4f) -> Mat4f {
+    a * b
+}
+
+pub fn mat4_mul_vec4(m: &Mat4f, v: &Vec4f) -> Vec4f {
+    m * v
+}
+
+pub fn mat4_transpose(m: &Mat4f) -> Mat4f {
+    m.transpose()
+}
+
+pub fn transform_points(m: &Mat4f, input: &[Vec3f], output: &mut [Vec3f]) {
+    for (p, out) in input.iter().zip(output.iter_mut()) {
+        out.x = m.m00*p.x + m.m10*p.y + m.m20*p.z + m.m30;
+        out.y = m.m01*p.x + m.m11*p.y + m.m21*p.z + m.m31;
+        out.z = m.m02*p.x + m.m12*p.y + m.m22*p.z + m.m32;
+    }
+}
@@ -0,0 +1,103 @@
+#[cfg(target_arch = "x86")]
+use std::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+use crate::Mat4f;
+use crate::Vec3f;
+use crate::Vec4f;
+
+use super::as_array;
+
+#[inline]
+#[target_feature(enable = "sse2")]
+pub(super) unsafe fn load_rows(m: &Mat4f) -> [__m128; 4] {
+    let a = as_array(m).as_ptr();
+    [
+        _mm_loadu_ps(a),
+        _mm_loadu_ps(a.add(4)),
+        _mm_loadu_ps(a.add(8)),
+        _mm_loadu_ps(a.add(12)),
+    ]
+}
+
+#[inline]
+#[target_feature(enable = "sse2")]
+pub(super) unsafe fn transpose_rows([r0, r1, r2, r3]: [__m128; 4]) -> [__m128; 4] {
+    let t0 = _mm_unpacklo_ps(r0, r1);
+    let t1 = _mm_unpacklo_ps(r2, r3);
+    let t2 = _mm_unpackhi_ps(r0, r1);
+    let t3 = _mm_unpackhi_ps(r2, r3);
+    [
+        _mm_movelh_ps(t0, t1),
+        _mm_movehl_ps(t1, t0),
+        _mm_movelh_ps(t2, t3),
+        _mm_movehl_ps(t3, t2),
+    ]
+}
+
+#[inline]
+#[target_feature(enable = "sse2")]
+unsafe fn store_rows(rows: [__m128; 4]) -> Mat4f {
+    let mut out = [0f32; 16];
+    for (r, row) in rows.iter().enumerate() {
+        _mm_storeu_ps(out.as_mut_ptr().add(4 * r), *row);
+    }
+    super::from_array(out)
+}
+
+#[target_feature(enable = "sse2")]
+pub unsafe fn mat4_mul(a: &Mat4f, b: &Mat4f) -> Mat4f {
+    // Row r of the result is sum_k a[r][k] * (row k of b). Summing k in ascending
+    // order matches the evaluation order of the scalar implementation.
+    let a = as_array(a);
+    let [b0, b1, b2, b3] = load_rows(b);
+    let mut rows = [_mm_setzero_ps(); 4];
+    for (r, row) in rows.iter_mut().enumerate() {
+        let mut acc = _mm_mul_ps(_mm_set1_ps(a[4 * r]), b0);
+        acc = _mm_add_ps(acc, _mm_mul_ps(_mm_set1_ps(a[4 * r + 1]), b1));
+        acc = _mm_add_ps(acc, _mm_mul_ps(_mm_set1_ps(a[4 * r + 2]), b2));
+        acc = _mm_add_ps(acc, _mm_mul_ps(_mm_set1_ps(a[4 * r + 3]), b3));
+        *row = acc;
+    }
+    store_rows(rows)
+}
+
+#[target_feature(enable = "sse2")]
+pub unsafe fn mat4_mul_vec4(m: &Mat4f, v: &Vec4f) -> Vec4f {
+    let [c0, c1, c2, c3] = transpose_rows(load_rows(m));
+    let mut acc = _mm_mul_ps(c0, _mm_set1_ps(v.x));
+    acc = _mm_add_ps(acc, _mm_mul_ps(c1, _mm_set1_ps(v.y)));
+    acc = _mm_add_ps(acc, _mm_mul_ps(c2, _mm_set1_ps(v.z)));
+    acc = _mm_add_ps(acc, _mm_mul_ps(c3, _mm_set1_ps(v.w)));
+    let mut out = [0f32; 4];
+    _mm_storeu_ps(out.as_mut_ptr(), acc);
+    Vec4f::new(out[0], out[1], out[2], out[3])
+}
+
+#[target_feature(enable = "sse2")]
+pub unsafe fn mat4_transpose(m: &Mat4f) -> Mat4f {
+    store_rows(transpose_rows(load_rows(m)))
+}
+
+#[inline]
+#[target_feature(enable = "sse2")]
+pub(super) unsafe fn transform_point(cols: &[__m128; 4], p: &Vec3f, out: &mut Vec3f) {
+    let mut acc = _mm_mul_ps(cols[0], _mm_set1_ps(p.x));
+    acc = _mm_add_ps(acc, _mm_mul_ps(cols[1], _mm_set1_ps(p.y)));
+    acc = _mm_add_ps(acc, _mm_mul_ps(cols[2], _mm_set1_ps(p.z)));
+    acc = _mm_add_ps(acc, cols[3]);
+    let mut buf = [0f32; 4];
+    _mm_storeu_ps(buf.as_mut_ptr(), acc);
+    out.x = buf[0];
+    out.y = buf[1];
+    out.z = buf[2];
+}
+
+#[target_feature(enable = "sse2")]
+pub unsafe fn transform_points(m: &Mat4f, input: &[Vec3f], output: &mut [Vec3f]) {
+    let cols = transpose_rows(load_rows(m));
+    for (p, out) in input.iter().zip(output.iter_mut()) {
+        transform_point(&cols, p, out);
+    }
+}
@@ -0,0 +1,449 @@
+use crate::Mat4f;
+use crate::Quaternion;
+use crate::Vec3f;
+
+// ----------------------------------------------------------------------------
+// Transform
+// ----------------------------------------------------------------------------
+
+/**
+ * Translation/rotation/scale representation of an affine transformation,
+ * corresponding to the matrix M = T * R * S.
+ *
+ * Note that composition and inversion are only exact for uniform scales.
+ * With non-uniform scales the product of two transforms can contain shear,
+ * which a TRS representation cannot express.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3f,
+    pub rotation: Quaternion,
+    pub scale: Vec3f,
+}
+
+impl Transform {
+
+    pub fn new(translation: Vec3f, rotation: Quaternion, scale: Vec3f) -> Transform {
+        Transform { translation, rotation, scale }
+    }
+
+    pub fn create_identity() -> Transform {
+        Transform {
+            translation: Vec3f::new(0f32, 0f32, 0f32),
+            rotation: Quaternion::create_identity(),
+            scale: Vec3f::new(1f32, 1f32, 1f32),
+        }
+    }
+
+    pub fn to_matrix(&self) -> Mat4f {
+        let t = &self.translation;
+        let s = &self.scale;
+        &(&Mat4f::translate(t.x, t.y, t.z) * &self.rotation.cast_to_orientation_matrix()) *
+            &Mat4f::scale(s.x, s.y, s.z)
+    }
+
+    pub fn transform_point(&self, p: &Vec3f) -> Vec3f {
+        &self.rotation.rotate_vector(&(&self.scale * p)) + &self.translation
+    }
+
+    /**
+     * Returns the transform corresponding to `self.to_matrix() * that.to_matrix()`,
+     * i.e., `that` is applied first.
+     */
+    pub fn compose(&self, that: &Transform) -> Transform {
+        Transform {
+            translation: self.transform_point(&that.translation),
+            rotation: (&self.rotation * &that.rotation).normalized(),
+            scale: &self.scale * &that.scale,
+        }
+    }
+
+    pub fn inverse(&self) -> Transform {
+        let scale = Vec3f::new(1f32 / self.scale.x, 1f32 / self.scale.y, 1f32 / self.scale.z);
+        let rotation = self.rotation.inverse();
+        let translation = (&scale * &rotation.rotate_vector(&self.translation)).negate();
+        Transform { translation, rotation, scale }
+    }
+
+    /**
+     * Linear interpolation of translation and scale, spherical linear interpolation
+     * of the rotation. `alpha = 0` corresponds to `self`, `alpha = 1` to `that`.
+     */
+    pub fn interpolate(&self, that: &Transform, alpha: f32) -> Transform {
+        let lerp = |a: &Vec3f, b: &Vec3f| a + &(&(b - a) * alpha);
+        Transform {
+            translation: lerp(&self.translation, &that.translation),
+            rotation: self.rotation.slerp(&that.rotation, alpha),
+            scale: lerp(&self.scale, &that.scale),
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// TransformTree
+// ----------------------------------------------------------------------------
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+struct Node {
+    local: Transform,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    world: Mat4f,
+    dirty: bool,
+}
+
+/**
+ * Parent/child hierarchy of transforms. World matrices are cached and only
+ * recomputed lazily for nodes that have been marked dirty, either because
+ * their own local transform or one of their ancestors has changed.
+ *
+ * Invariant: If a node is dirty, all its descendants are dirty as well.
+ */
+pub struct TransformTree {
+    nodes: Vec<Node>,
+}
+
+impl Default for TransformTree {
+    fn default() -> Self {
+        TransformTree::new()
+    }
+}
+
+impl TransformTree {
+
+    pub fn new() -> TransformTree {
+        TransformTree { nodes: Vec::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn add_root(&mut self, local: Transform) -> NodeId {
+        self.add_node(local, None)
+    }
+
+    pub fn add_child(&mut self, parent: NodeId, local: Transform) -> NodeId {
+        let id = self.add_node(local, Some(parent));
+        self.nodes[parent.0].children.push(id);
+        id
+    }
+
+    fn add_node(&mut self, local: Transform, parent: Option<NodeId>) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(Node {
+            local,
+            parent,
+            children: Vec::new(),
+            world: Mat4f::create_identity(),
+            dirty: true,
+        });
+        id
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id.0].parent
+    }
+
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        &self.nodes[id.0].children
+    }
+
+    /**
+     * Re-attaches a node (including its subtree) to a new parent, or detaches it
+     * if `parent` is `None`. The local transform is kept, i.e., the node moves
+     * along with its new parent.
+     *
+     * Panics if the new parent is the node itself or one of its descendants.
+     */
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) {
+        if let Some(new_parent) = parent {
+            let mut ancestor = Some(new_parent);
+            while let Some(a) = ancestor {
+                assert!(a != id, "Setting parent of {:?} to {:?} would create a cycle", id, new_parent);
+                ancestor = self.nodes[a.0].parent;
+            }
+        }
+        if let Some(old_parent) = self.nodes[id.0].parent {
+            self.nodes[old_parent.0].children.retain(|child| *child != id);
+        }
+        if let Some(new_parent) = parent {
+            self.nodes[new_parent.0].children.push(id);
+        }
+        self.nodes[id.0].parent = parent;
+        self.mark_dirty(id);
+    }
+
+    pub fn local(&self, id: NodeId) -> &Transform {
+        &self.nodes[id.0].local
+    }
+
+    pub fn set_local(&mut self, id: NodeId, local: Transform) {
+        self.nodes[id.0].local = local;
+        self.mark_dirty(id);
+    }
+
+    pub fn is_dirty(&self, id: NodeId) -> bool {
+        self.nodes[id.0].dirty
+    }
+
+    fn mark_dirty(&mut self, id: NodeId) {
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            let node = &mut self.nodes[id.0];
+            // The invariant guarantees that the subtree of a dirty node is dirty already.
+            if !node.dirty {
+                node.dirty = true;
+                stack.extend(node.children.iter().copied());
+            }
+        }
+    }
+
+    /**
+     * Returns the cached world matrix, recomputing it (and the world matrices
+     * of dirty ancestors) if necessary.
+     */
+    pub fn world_matrix(&mut self, id: NodeId) -> &Mat4f {
+        // Collect the dirty chain from the node upwards; since dirtiness propagates
+        // downwards, the chain ends at the first clean ancestor (or the root).
+        let mut chain = Vec::new();
+        let mut current = Some(id);
+        while let Some(c) = current {
+            if !self.nodes[c.0].dirty {
+                break;
+            }
+            chain.push(c);
+            current = self.nodes[c.0].parent;
+        }
+        for c in chain.into_iter().rev() {
+            let local = self.nodes[c.0].local.to_matrix();
+            let world = match self.nodes[c.0].parent {
+                Some(parent) => &self.nodes[parent.0].world * &local,
+                None => local,
+            };
+            let node = &mut self.nodes[c.0];
+            node.world = world;
+            node.dirty = false;
+        }
+        &self.nodes[id.0].world
+    }
+
+    /**
+     * Recomputes all dirty world matrices.
+     */
+    pub fn update(&mut self) {
+        for i in 0..self.nodes.len() {
+            self.world_matrix(NodeId(i));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_close_vec(a: &Vec3f, b: &Vec3f) {
+        assert!((a - b).length() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    fn assert_close_mat(a: &Mat4f, b: &Mat4f) {
+        assert!(a.frobenius_distance(b) < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    fn assert_same_rotation(a: &Quaternion, b: &Quaternion) {
+        // q and -q represent the same rotation
+        assert!(a.dot(b).abs() > 1f32 - 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    fn example_transform() -> Transform {
+        Transform::new(
+            Vec3f::new(1.0, -2.0, 3.0),
+            Quaternion::create(30.0, 0.0, 1.0, 0.0),
+            Vec3f::new(2.0, 2.0, 2.0),
+        )
+    }
+
+    #[test]
+    fn test_to_matrix() {
+        let t = example_transform();
+        let expected = &(&Mat4f::translate(1.0, -2.0, 3.0) * &Mat4f::rotate(30.0, 0.0, 1.0, 0.0)) *
+            &Mat4f::scale(2.0, 2.0, 2.0);
+        assert_close_mat(&t.to_matrix(), &expected);
+        assert_close_mat(&Transform::create_identity().to_matrix(), &Mat4f::create_identity());
+    }
+
+    #[test]
+    fn test_decompose() {
+        let t = Transform::new(
+            Vec3f::new(1.0, -2.0, 3.0),
+            Quaternion::create(-130.0, 1.0, 2.0, 3.0).normalized(),
+            Vec3f::new(0.5, 2.0, 3.0),
+        );
+        let decomposed = t.to_matrix().decompose();
+        assert_close_vec(&decomposed.translation, &t.translation);
+        assert_close_vec(&decomposed.scale, &t.scale);
+        assert_same_rotation(&decomposed.rotation, &t.rotation);
+        assert_close_mat(&decomposed.to_matrix(), &t.to_matrix());
+    }
+
+    #[test]
+    fn test_decompose_rotations() {
+        // exercises all branches of create_from_rotation_matrix
+        for (angle, x, y, z) in [(0.0, 1.0, 0.0, 0.0), (180.0, 1.0, 0.0, 0.0), (180.0, 0.0, 1.0, 0.0), (180.0, 0.0, 0.0, 1.0), (170.0, 1.0, 1.0, 0.0)] {
+            let m = Mat4f::rotate(angle, x, y, z);
+            assert_close_mat(&m.decompose().to_matrix(), &m);
+        }
+        let m = Mat4f::rotate_yaw_pitch_roll_quaternions(10.0, 20.0, 30.0);
+        assert_close_mat(&m.decompose().to_matrix(), &m);
+    }
+
+    #[test]
+    fn test_decompose_mirrored() {
+        let m = &Mat4f::rotate(45.0, 0.0, 0.0, 1.0) * &Mat4f::scale(-1.0, 1.0, 1.0);
+        let decomposed = m.decompose();
+        assert_close_vec(&decomposed.scale, &Vec3f::new(-1.0, 1.0, 1.0));
+        assert_close_mat(&decomposed.to_matrix(), &m);
+    }
+
+    #[test]
+    fn test_compose() {
+        let a = example_transform();
+        let b = Transform::new(
+            Vec3f::new(0.0, 5.0, 0.0),
+            Quaternion::create(90.0, 1.0, 0.0, 0.0),
+            Vec3f::new(0.5, 0.5, 0.5),
+        );
+        assert_close_mat(&a.compose(&b).to_matrix(), &(&a.to_matrix() * &b.to_matrix()));
+    }
+
+    #[test]
+    fn test_inverse() {
+        let t = example_transform();
+        assert_close_mat(&t.compose(&t.inverse()).to_matrix(), &Mat4f::create_identity());
+        assert_close_mat(&t.inverse().compose(&t).to_matrix(), &Mat4f::create_identity());
+        let p = Vec3f::new(4.0, 5.0, 6.0);
+        assert_close_vec(&t.inverse().transform_point(&t.transform_point(&p)), &p);
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let a = Transform::create_identity();
+        let b = Transform::new(
+            Vec3f::new(10.0, 0.0, 0.0),
+            Quaternion::create(90.0, 0.0, 0.0, 1.0),
+            Vec3f::new(3.0, 3.0, 3.0),
+        );
+        let mid = a.interpolate(&b, 0.5);
+        assert_close_vec(&mid.translation, &Vec3f::new(5.0, 0.0, 0.0));
+        assert_close_vec(&mid.scale, &Vec3f::new(2.0, 2.0, 2.0));
+        assert_same_rotation(&mid.rotation, &Quaternion::create(45.0, 0.0, 0.0, 1.0));
+
+        assert_close_mat(&a.interpolate(&b, 0.0).to_matrix(), &a.to_matrix());
+        assert_close_mat(&a.interpolate(&b, 1.0).to_matrix(), &b.to_matrix());
+    }
+
+    #[test]
+    fn test_slerp_shorter_arc() {
+        let a = Quaternion::create(170.0, 0.0, 0.0, 1.0);
+        let b = Quaternion::create(-170.0, 0.0, 0.0, 1.0);
+        // The shorter arc goes through 180 deg, not through 0 deg.
+        assert_same_rotation(&a.slerp(&b, 0.5), &Quaternion::create(180.0, 0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn test_tree_world_matrices() {
+        let mut tree = TransformTree::new();
+        let controller = tree.add_root(Transform::new(
+            Vec3f::new(0.0, 1.0, 0.0),
+            Quaternion::create(90.0, 0.0, 1.0, 0.0),
+            Vec3f::new(1.0, 1.0, 1.0),
+        ));
+        let object = tree.add_child(controller, Transform::new(
+            Vec3f::new(0.0, 0.0, -1.0),
+            Quaternion::create_identity(),
+            Vec3f::new(2.0, 2.0, 2.0),
+        ));
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree.parent(object), Some(controller));
+        assert_eq!(tree.children(controller), &[object]);
+
+        let expected = &tree.local(controller).to_matrix() * &tree.local(object).to_matrix();
+        assert_close_mat(tree.world_matrix(object), &expected);
+        assert!(!tree.is_dirty(controller));
+        assert!(!tree.is_dirty(object));
+
+        // Moving the controller moves the attached object.
+        let mut moved = tree.local(controller).clone();
+        moved.translation = Vec3f::new(5.0, 1.0, 0.0);
+        tree.set_local(controller, moved);
+        assert!(tree.is_dirty(controller));
+        assert!(tree.is_dirty(object));
+
+        let expected = &tree.local(controller).to_matrix() * &tree.local(object).to_matrix();
+        assert_close_mat(tree.world_matrix(object), &expected);
+    }
+
+    #[test]
+    fn test_tree_dirty_propagation() {
+        let mut tree = TransformTree::new();
+        let root = tree.add_root(Transform::create_identity());
+        let a = tree.add_child(root, Transform::create_identity());
+        let b = tree.add_child(a, Transform::create_identity());
+        let c = tree.add_child(root, Transform::create_identity());
+        tree.update();
+        assert!((0..4).all(|i| !tree.is_dirty(NodeId(i))));
+
+        tree.set_local(a, example_transform());
+        assert!(!tree.is_dirty(root));
+        assert!(tree.is_dirty(a));
+        assert!(tree.is_dirty(b));
+        assert!(!tree.is_dirty(c));
+
+        // Marking an already dirty node must still reach its subtree.
+        tree.set_local(a, example_transform());
+        assert!(tree.is_dirty(b));
+
+        tree.update();
+        assert_close_mat(tree.world_matrix(b), &example_transform().to_matrix());
+    }
+
+    #[test]
+    fn test_tree_set_parent() {
+        let mut tree = TransformTree::new();
+        let left = tree.add_root(Transform::new(
+            Vec3f::new(-1.0, 0.0, 0.0),
+            Quaternion::create_identity(),
+            Vec3f::new(1.0, 1.0, 1.0),
+        ));
+        let right = tree.add_root(Transform::new(
+            Vec3f::new(1.0, 0.0, 0.0),
+            Quaternion::create_identity(),
+            Vec3f::new(1.0, 1.0, 1.0),
+        ));
+        let object = tree.add_child(left, Transform::create_identity());
+        assert_close_mat(tree.world_matrix(object), &Mat4f::translate(-1.0, 0.0, 0.0));
+
+        tree.set_parent(object, Some(right));
+        assert!(tree.children(left).is_empty());
+        assert_eq!(tree.children(right), &[object]);
+        assert_close_mat(tree.world_matrix(object), &Mat4f::translate(1.0, 0.0, 0.0));
+
+        tree.set_parent(object, None);
+        assert_close_mat(tree.world_matrix(object), &Mat4f::create_identity());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_tree_set_parent_cycle() {
+        let mut tree = TransformTree::new();
+        let a = tree.add_root(Transform::create_identity());
+        let b = tree.add_child(a, Transform::create_identity());
+        tree.set_parent(a, Some(b));
+    }
+}
@@ -0,0 +1,20 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+
+extern crate serde_checks;
+
+use serde_checks::archived::{access_sequence, archive_sequence};
+
+fuzz_target!(|data: &[u8]| {
+    // Validation must reject everything that would make the accessors panic.
+    let Ok(archived) = access_sequence(data) else {
+        return;
+    };
+    let sequence = archived.to_sequence();
+
+    // Archives are canonical after one round trip, compared as bytes because of NaNs.
+    let rearchived = archive_sequence(&sequence).unwrap();
+    let sequence = access_sequence(&rearchived).unwrap().to_sequence();
+    assert_eq!(archive_sequence(&sequence).unwrap(), rearchived);
+});
@@ -4,8 +4,69 @@ extern crate libfuzzer_sys;
 
 extern crate serde_checks;
 
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 use serde_checks::parse_sequence;
 
+// Length prefixes are only trusted as far as the remaining input could hold that many
+// elements of at least one byte each. Therefore, the memory used by parsing is bounded by
+// the input length times the size of the largest element type (a `Track`), and a huge
+// length prefix must fail before allocating anything.
+const MAX_ALLOCATED_BYTES_PER_INPUT_BYTE: usize = 128;
+const MAX_ALLOCATED_BYTES_BASE: usize = 64 * 1024;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK_ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+struct CountingAllocator;
+
+fn count_allocation(size: usize) {
+    let allocated = ALLOCATED.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK_ALLOCATED.fetch_max(allocated, Ordering::Relaxed);
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            count_allocation(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+            count_allocation(new_size);
+        }
+        new_ptr
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
 fuzz_target!(|data: &[u8]| {
-    let _ = parse_sequence(data);
+    let allocated_before = ALLOCATED.load(Ordering::Relaxed);
+    PEAK_ALLOCATED.store(allocated_before, Ordering::Relaxed);
+
+    let result = parse_sequence(data);
+
+    let peak = PEAK_ALLOCATED.load(Ordering::Relaxed) - allocated_before;
+    let bound = MAX_ALLOCATED_BYTES_BASE + MAX_ALLOCATED_BYTES_PER_INPUT_BYTE * data.len();
+    assert!(
+        peak <= bound,
+        "Parsing {} bytes allocated {} bytes, which exceeds the bound of {} bytes (ok: {})",
+        data.len(),
+        peak,
+        bound,
+        result.is_ok(),
+    );
 });
@@ -0,0 +1,36 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+
+extern crate serde_checks;
+extern crate serde_json;
+
+use serde_checks::types::Sequence;
+use serde_checks::{parse_sequence, serialize_sequence_to_vec, NoteLayout, Params};
+
+fn json_roundtrip(sequence: &Sequence) -> Sequence {
+    let json = serde_json::to_vec(sequence).unwrap();
+    serde_json::from_slice(&json).unwrap()
+}
+
+fuzz_target!(|sequence: Sequence| {
+    // JSON is lossless.
+    assert_eq!(json_roundtrip(&sequence), sequence);
+
+    // Whatever the custom format produces has to survive JSON, i.e., both formats agree on
+    // sequences that are on the grid.
+    for note_layout in [NoteLayout::Interleaved, NoteLayout::Columnar] {
+        let params = Params {
+            note_layout,
+            ..Params::default()
+        };
+        let custom = serialize_sequence_to_vec(&sequence, &params).unwrap();
+        let from_custom = parse_sequence(&custom).unwrap();
+        let from_json = json_roundtrip(&from_custom);
+        assert_eq!(from_json, from_custom);
+
+        // JSON and custom input have to yield the same custom bytes.
+        let custom_from_json = serialize_sequence_to_vec(&json_roundtrip(&sequence), &params);
+        assert_eq!(custom_from_json.unwrap(), custom);
+    }
+});
@@ -0,0 +1,143 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+
+extern crate serde_checks;
+
+use serde_checks::types::{BendData, Sequence, TempoMap};
+use serde_checks::{parse_sequence, serialize_sequence_to_vec, FormatVersion, NoteLayout, Params};
+
+/// Values may differ by half a quantization step, plus the rounding errors of dequantizing
+/// in the precision `epsilon`.
+fn assert_quantized_eq(value: f64, reconstructed: f64, quantization: u64, epsilon: f64) {
+    let tolerance = 0.5 / quantization as f64 + 4.0 * epsilon * value.abs().max(1.0);
+    assert!(
+        (value - reconstructed).abs() <= tolerance,
+        "Value {} was reconstructed as {}, which exceeds the tolerance {}",
+        value,
+        reconstructed,
+        tolerance,
+    );
+}
+
+fn assert_time_eq(value: f64, reconstructed: f64, params: &Params) {
+    assert_quantized_eq(value, reconstructed, params.time_quantization, f64::EPSILON);
+}
+
+fn assert_bend_data_eq(bend_data: &BendData, reconstructed: &BendData, params: &Params) {
+    assert_eq!(bend_data.points.len(), reconstructed.points.len());
+    for (point, point_reconstructed) in bend_data.points.iter().zip(&reconstructed.points) {
+        assert_time_eq(point.pos, point_reconstructed.pos, params);
+        assert_quantized_eq(
+            point.bend as f64,
+            point_reconstructed.bend as f64,
+            params.pitch_quantization,
+            f32::EPSILON as f64,
+        );
+    }
+}
+
+fn assert_tempo_map_eq(tempo_map: &TempoMap, reconstructed: &TempoMap, params: &Params) {
+    assert_eq!(tempo_map.bpm_base, reconstructed.bpm_base);
+    assert_eq!(
+        tempo_map.tempo_changes.len(),
+        reconstructed.tempo_changes.len()
+    );
+    for (change, change_reconstructed) in tempo_map
+        .tempo_changes
+        .iter()
+        .zip(&reconstructed.tempo_changes)
+    {
+        assert_time_eq(change.beat, change_reconstructed.beat, params);
+        assert_eq!(change.bpm, change_reconstructed.bpm);
+    }
+    assert_eq!(
+        tempo_map.time_signatures.len(),
+        reconstructed.time_signatures.len()
+    );
+    for (time_signature, time_signature_reconstructed) in tempo_map
+        .time_signatures
+        .iter()
+        .zip(&reconstructed.time_signatures)
+    {
+        assert_time_eq(
+            time_signature.beat,
+            time_signature_reconstructed.beat,
+            params,
+        );
+        assert_eq!(
+            (time_signature.numerator, time_signature.denominator),
+            (
+                time_signature_reconstructed.numerator,
+                time_signature_reconstructed.denominator
+            )
+        );
+    }
+}
+
+/// Equality modulo quantization, i.e., all fields have to match exactly except for the
+/// quantized times and bends.
+fn assert_sequence_eq(sequence: &Sequence, reconstructed: &Sequence, params: &Params) {
+    assert_tempo_map_eq(&sequence.tempo_map, &reconstructed.tempo_map, params);
+    assert_eq!(sequence.tracks.len(), reconstructed.tracks.len());
+    for (track, track_reconstructed) in sequence.tracks.iter().zip(&reconstructed.tracks) {
+        assert_eq!(track.name, track_reconstructed.name);
+        assert_eq!(track.is_percussion, track_reconstructed.is_percussion);
+        assert_eq!(track.tuning, track_reconstructed.tuning);
+        assert_eq!(track.notes.len(), track_reconstructed.notes.len());
+        for (note, reconstructed) in track.notes.iter().zip(&track_reconstructed.notes) {
+            assert_time_eq(note.s, reconstructed.s, params);
+            assert_time_eq(note.d, reconstructed.d, params);
+            assert_eq!(
+                (note.pitch, note.string, note.fret),
+                (
+                    reconstructed.pitch,
+                    reconstructed.string,
+                    reconstructed.fret
+                )
+            );
+            let (effects, effects_reconstructed) = (&note.effects, &reconstructed.effects);
+            assert_eq!(effects.dead_note, effects_reconstructed.dead_note);
+            assert_eq!(effects.vibrato, effects_reconstructed.vibrato);
+            match (&effects.bend_data, &effects_reconstructed.bend_data) {
+                (Some(bend_data), Some(bend_data_reconstructed)) => {
+                    assert_bend_data_eq(bend_data, bend_data_reconstructed, params)
+                }
+                (None, None) => {}
+                _ => panic!("Bend data was not reconstructed"),
+            }
+        }
+    }
+}
+
+fn roundtrip(sequence: &Sequence, params: &Params) -> Sequence {
+    let serialized = serialize_sequence_to_vec(sequence, params).unwrap();
+    parse_sequence(&serialized).unwrap()
+}
+
+fuzz_target!(|sequence: Sequence| {
+    for (version, note_layout) in [
+        (FormatVersion::V0, NoteLayout::Interleaved),
+        (FormatVersion::V1, NoteLayout::Interleaved),
+        (FormatVersion::V2, NoteLayout::Interleaved),
+        (FormatVersion::V2, NoteLayout::Columnar),
+        (FormatVersion::V3, NoteLayout::Interleaved),
+        (FormatVersion::V3, NoteLayout::Columnar),
+    ] {
+        let params = Params {
+            version,
+            note_layout,
+            ..Params::default()
+        };
+        // Older versions refuse to serialize tempo events instead of dropping them.
+        let mut sequence = sequence.clone();
+        if !version.has_tempo_events() {
+            sequence.tempo_map = TempoMap::new(sequence.tempo_map.bpm_base);
+        }
+        let reconstructed = roundtrip(&sequence, &params);
+        assert_sequence_eq(&sequence, &reconstructed, &params);
+
+        // Values on the grid have to survive another round trip exactly.
+        assert_eq!(roundtrip(&reconstructed, &params), reconstructed);
+    }
+});
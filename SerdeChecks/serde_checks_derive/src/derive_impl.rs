@@ -0,0 +1,285 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::punctuated::Punctuated;
+use syn::{Data, DeriveInput, Error, Field, Fields, Ident, LitStr, Result, Token, Type};
+
+#[derive(Clone, Copy)]
+enum Quantization {
+    Time,
+    Pitch,
+}
+
+#[derive(Clone, Copy)]
+enum Encoding {
+    Plain,
+    Varint,
+    Zigzag,
+    Quantized {
+        quantization: Quantization,
+        signed: bool,
+    },
+    BeatAndOffset,
+}
+
+struct FieldSpec<'a> {
+    ident: &'a Ident,
+    ty: &'a Type,
+    encoding: Encoding,
+    skip_default: bool,
+}
+
+fn support_path() -> TokenStream {
+    quote!(crate::custom_file_format::derive_support)
+}
+
+pub fn derive_serialize(input: DeriveInput) -> Result<TokenStream> {
+    let support = support_path();
+    let name = &input.ident;
+    let fields = field_specs(&input)?;
+
+    let serializations = fields.iter().map(|field| {
+        let ident = field.ident;
+        if field.skip_default {
+            return quote! {
+                #support::serialize_non_default(&self.#ident, wr, context)?;
+            };
+        }
+        match field.encoding {
+            Encoding::Plain => quote! {
+                #support::Serialize::serialize_into(&self.#ident, wr, context)?;
+            },
+            Encoding::Varint => quote! {
+                #support::serialize_varint(self.#ident, wr, context)?;
+            },
+            Encoding::Zigzag => quote! {
+                #support::serialize_zigzag(self.#ident, wr, context)?;
+            },
+            Encoding::Quantized {
+                quantization,
+                signed: false,
+            } => {
+                let quantization = quantization_tokens(quantization);
+                quote! {
+                    #support::serialize_quantized_uint(self.#ident, #quantization, wr, context)?;
+                }
+            }
+            Encoding::Quantized {
+                quantization,
+                signed: true,
+            } => {
+                let quantization = quantization_tokens(quantization);
+                quote! {
+                    #support::serialize_quantized_int(self.#ident, #quantization, wr, context)?;
+                }
+            }
+            Encoding::BeatAndOffset => quote! {
+                #support::serialize_beat_and_offset(self.#ident, wr, context)?;
+            },
+        }
+    });
+    let unused_context = fields.is_empty().then(|| quote!(let _ = (wr, context);));
+
+    Ok(quote! {
+        impl #support::Serialize<#support::Params> for #name {
+            fn serialize_into<W>(&self, wr: &mut W, context: &#support::Params) -> std::io::Result<()>
+            where
+                W: std::io::Write,
+            {
+                #unused_context
+                #(#serializations)*
+                Ok(())
+            }
+        }
+    })
+}
+
+pub fn derive_parse(input: DeriveInput) -> Result<TokenStream> {
+    let support = support_path();
+    let name = &input.ident;
+    let fields = field_specs(&input)?;
+
+    let parsers = fields.iter().map(|field| {
+        let ident = field.ident;
+        let ty = field.ty;
+        let parser = if field.skip_default {
+            quote!(#support::parse_non_default::<#ty>(input, context))
+        } else {
+            match field.encoding {
+                Encoding::Plain => {
+                    quote!(<#ty as #support::Parse<#support::Params>>::parse(input, context))
+                }
+                Encoding::Varint => quote!(#support::parse_varint::<#ty>(input, context)),
+                Encoding::Zigzag => quote!(#support::parse_zigzag::<#ty>(input, context)),
+                Encoding::Quantized {
+                    quantization,
+                    signed: false,
+                } => {
+                    let quantization = quantization_tokens(quantization);
+                    quote!(#support::parse_quantized_uint::<#ty>(input, #quantization, context))
+                }
+                Encoding::Quantized {
+                    quantization,
+                    signed: true,
+                } => {
+                    let quantization = quantization_tokens(quantization);
+                    quote!(#support::parse_quantized_int::<#ty>(input, #quantization, context))
+                }
+                Encoding::BeatAndOffset => {
+                    quote!(#support::parse_beat_and_offset::<#ty>(input, context))
+                }
+            }
+        };
+        let field_name = LitStr::new(&ident.to_string(), ident.span());
+        quote! {
+            let (input, #ident) = #support::field(#field_name, |input| #parser)(input)?;
+        }
+    });
+    let idents = fields.iter().map(|field| field.ident);
+    let unused_context = fields.is_empty().then(|| quote!(let _ = context;));
+
+    Ok(quote! {
+        impl #support::Parse<#support::Params> for #name {
+            fn parse<'a>(
+                input: &'a [u8],
+                context: &#support::Params,
+            ) -> #support::ParseResult<'a, Self> {
+                #unused_context
+                #(#parsers)*
+                Ok((input, #name { #(#idents),* }))
+            }
+        }
+    })
+}
+
+fn quantization_tokens(quantization: Quantization) -> TokenStream {
+    let support = support_path();
+    match quantization {
+        Quantization::Time => quote!(#support::Quantization::Time),
+        Quantization::Pitch => quote!(#support::Quantization::Pitch),
+    }
+}
+
+fn field_specs(input: &DeriveInput) -> Result<Vec<FieldSpec<'_>>> {
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "generic types are not supported",
+        ));
+    }
+    match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => fields.named.iter().map(field_spec).collect(),
+            Fields::Unit => Ok(vec![]),
+            Fields::Unnamed(_) => Err(Error::new_spanned(
+                &input.ident,
+                "only structs with named fields are supported",
+            )),
+        },
+        _ => Err(Error::new_spanned(
+            &input.ident,
+            "only structs are supported",
+        )),
+    }
+}
+
+fn field_spec(field: &Field) -> Result<FieldSpec<'_>> {
+    let mut varint = false;
+    let mut zigzag = false;
+    let mut quantize = None;
+    let mut skip_default = false;
+
+    for attr in &field.attrs {
+        if attr.path.is_ident("varint") {
+            expect_no_args(attr)?;
+            varint = true;
+        } else if attr.path.is_ident("zigzag") {
+            expect_no_args(attr)?;
+            zigzag = true;
+        } else if attr.path.is_ident("skip_default") {
+            expect_no_args(attr)?;
+            skip_default = true;
+        } else if attr.path.is_ident("quantize") {
+            quantize = Some(parse_quantize_args(attr)?);
+        }
+    }
+
+    let encoding = match (quantize, varint, zigzag) {
+        (None, false, false) => Encoding::Plain,
+        (None, true, false) => Encoding::Varint,
+        (None, false, true) => Encoding::Zigzag,
+        (None, true, true) => {
+            return Err(Error::new_spanned(
+                field,
+                "`varint` and `zigzag` are mutually exclusive",
+            ))
+        }
+        (Some(_), true, _) => {
+            return Err(Error::new_spanned(
+                field,
+                "quantized values are always varints, use `zigzag` for signed values",
+            ))
+        }
+        (Some((_, true)), _, true) => {
+            return Err(Error::new_spanned(
+                field,
+                "`split` does not support negative values",
+            ))
+        }
+        (Some((_, true)), _, false) => Encoding::BeatAndOffset,
+        (Some((quantization, false)), _, signed) => Encoding::Quantized {
+            quantization,
+            signed,
+        },
+    };
+    if skip_default && !matches!(encoding, Encoding::Plain) {
+        return Err(Error::new_spanned(
+            field,
+            "`skip_default` cannot be combined with other encodings",
+        ));
+    }
+
+    Ok(FieldSpec {
+        ident: field.ident.as_ref().expect("named field"),
+        ty: &field.ty,
+        encoding,
+        skip_default,
+    })
+}
+
+fn expect_no_args(attr: &syn::Attribute) -> Result<()> {
+    if attr.tokens.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::new_spanned(attr, "attribute takes no arguments"))
+    }
+}
+
+/// Parses `(time)`, `(pitch)`, or `(time, split)`.
+fn parse_quantize_args(attr: &syn::Attribute) -> Result<(Quantization, bool)> {
+    let args = attr.parse_args_with(Punctuated::<Ident, Token![,]>::parse_terminated)?;
+    let mut args = args.iter();
+    let quantization = match args.next() {
+        Some(arg) if arg == "time" => Quantization::Time,
+        Some(arg) if arg == "pitch" => Quantization::Pitch,
+        _ => {
+            return Err(Error::new_spanned(
+                attr,
+                "expected `quantize(time)` or `quantize(pitch)`",
+            ))
+        }
+    };
+    let split = match args.next() {
+        None => false,
+        Some(arg) if arg == "split" && matches!(quantization, Quantization::Time) => true,
+        Some(arg) => {
+            return Err(Error::new_spanned(
+                arg,
+                "only `quantize(time, split)` is supported",
+            ))
+        }
+    };
+    if let Some(arg) = args.next() {
+        return Err(Error::new_spanned(arg, "unexpected argument"));
+    }
+    Ok((quantization, split))
+}
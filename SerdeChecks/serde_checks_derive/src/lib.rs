@@ -0,0 +1,41 @@
+//! Derive macros for the `Serialize<Params>` and `Parse<Params>` traits of the custom file
+//! format of `serde_checks`.
+//!
+//! The generated code refers to `crate::custom_file_format::derive_support`, i.e., the macros
+//! can only be used within `serde_checks` itself.
+//!
+//! Fields are written in declaration order. Without attributes a field uses the `Serialize`
+//! and `Parse` implementations of its type. Supported field attributes:
+//!
+//! - `#[varint]`: unsigned integer stored as varint.
+//! - `#[zigzag]`: signed integer stored as zig-zag encoded varint.
+//! - `#[quantize(time)]`, `#[quantize(pitch)]`: float multiplied by the time/pitch
+//!   quantization of the `Params` and stored as (rounded) varint. Combined with `#[zigzag]`
+//!   negative values are supported as well.
+//! - `#[quantize(time, split)]`: non-negative float stored as whole beats plus the quantized
+//!   remainder, which keeps large onsets exact.
+//! - `#[skip_default]`: a bool tells whether the field differs from its default, and the
+//!   field is only written in that case.
+
+mod derive_impl;
+
+extern crate proc_macro;
+use proc_macro::TokenStream;
+
+#[proc_macro_derive(Serialize, attributes(varint, zigzag, quantize, skip_default))]
+pub fn derive_serialize(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as syn::DeriveInput);
+
+    let output = derive_impl::derive_serialize(input).unwrap_or_else(|err| err.to_compile_error());
+
+    proc_macro::TokenStream::from(output)
+}
+
+#[proc_macro_derive(Parse, attributes(varint, zigzag, quantize, skip_default))]
+pub fn derive_parse(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as syn::DeriveInput);
+
+    let output = derive_impl::derive_parse(input).unwrap_or_else(|err| err.to_compile_error());
+
+    proc_macro::TokenStream::from(output)
+}
@@ -0,0 +1,9 @@
+use serde_checks_derive::Serialize;
+
+#[derive(Serialize)]
+struct Example {
+    #[varint(fast)]
+    count: u32,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use serde_checks_derive::Parse;
+
+#[derive(Parse)]
+enum Kind {
+    A,
+    B,
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use serde_checks_derive::Serialize;
+
+#[derive(Serialize)]
+struct Wrapper<T> {
+    value: T,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use serde_checks_derive::Parse;
+
+#[derive(Parse)]
+struct Example {
+    #[quantize(velocity)]
+    a: f64,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use serde_checks_derive::Serialize;
+
+#[derive(Serialize)]
+struct Example {
+    #[quantize(time, split, exact)]
+    onset: f64,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use serde_checks_derive::Parse;
+
+#[derive(Parse)]
+struct Example {
+    #[quantize(pitch, split)]
+    bend: f32,
+}
+
+fn main() {}
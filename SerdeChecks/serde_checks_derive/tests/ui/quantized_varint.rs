@@ -0,0 +1,10 @@
+use serde_checks_derive::Parse;
+
+#[derive(Parse)]
+struct Example {
+    #[quantize(time)]
+    #[varint]
+    onset: f64,
+}
+
+fn main() {}
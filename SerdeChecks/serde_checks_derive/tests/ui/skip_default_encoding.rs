@@ -0,0 +1,10 @@
+use serde_checks_derive::Serialize;
+
+#[derive(Serialize)]
+struct Example {
+    #[skip_default]
+    #[varint]
+    count: u32,
+}
+
+fn main() {}
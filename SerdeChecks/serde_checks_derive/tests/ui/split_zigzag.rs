@@ -0,0 +1,10 @@
+use serde_checks_derive::Serialize;
+
+#[derive(Serialize)]
+struct Example {
+    #[quantize(time, split)]
+    #[zigzag]
+    onset: f64,
+}
+
+fn main() {}
@@ -0,0 +1,6 @@
+use serde_checks_derive::Serialize;
+
+#[derive(Serialize)]
+struct Tuple(u8, u16);
+
+fn main() {}
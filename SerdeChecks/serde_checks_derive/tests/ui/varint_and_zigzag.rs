@@ -0,0 +1,10 @@
+use serde_checks_derive::Serialize;
+
+#[derive(Serialize)]
+struct Example {
+    #[varint]
+    #[zigzag]
+    value: i32,
+}
+
+fn main() {}
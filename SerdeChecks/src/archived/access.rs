@@ -0,0 +1,416 @@
+use std::str;
+
+use crate::types::{
+    BendData, BendPoint, Note, NoteEffects, Sequence, TempoChange, TempoMap, TimeSignature, Track,
+    Tuning,
+};
+
+use super::*;
+
+fn bytes_at<const N: usize>(data: &[u8], pos: usize) -> [u8; N] {
+    data[pos..pos + N].try_into().unwrap()
+}
+
+fn u32_at(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(bytes_at(data, pos))
+}
+
+fn i32_at(data: &[u8], pos: usize) -> i32 {
+    i32::from_le_bytes(bytes_at(data, pos))
+}
+
+fn f32_at(data: &[u8], pos: usize) -> f32 {
+    f32::from_le_bytes(bytes_at(data, pos))
+}
+
+fn f64_at(data: &[u8], pos: usize) -> f64 {
+    f64::from_le_bytes(bytes_at(data, pos))
+}
+
+/// The records of an array, as absolute positions in the buffer.
+#[derive(Copy, Clone, Debug)]
+struct Array {
+    offset: usize,
+    len: usize,
+    record_size: usize,
+}
+
+impl Array {
+    /// The array at `pos`, which has to be validated.
+    fn at(data: &[u8], pos: usize, record_size: usize) -> Array {
+        Array {
+            offset: u32_at(data, pos) as usize,
+            len: u32_at(data, pos + 4) as usize,
+            record_size,
+        }
+    }
+
+    /// The array at `pos`, if its records are within the buffer and start at or after
+    /// `next`, which is advanced to the end of the array.
+    fn checked(
+        data: &[u8],
+        pos: usize,
+        record_size: usize,
+        what: &'static str,
+        next: &mut usize,
+    ) -> Result<Array, ArchiveError> {
+        let array = Array::at(data, pos, record_size);
+        let end = array
+            .len
+            .checked_mul(record_size)
+            .and_then(|size| array.offset.checked_add(size))
+            .filter(|end| *end <= data.len())
+            .ok_or(ArchiveError::OutOfBounds { what, offset: pos })?;
+        if array.offset < *next {
+            return Err(ArchiveError::Overlapping { what, offset: pos });
+        }
+        *next = end;
+        Ok(array)
+    }
+
+    fn pos(&self, i: usize) -> usize {
+        assert!(i < self.len, "Index {} out of range for {}", i, self.len);
+        self.offset + i * self.record_size
+    }
+
+    fn positions(self) -> impl ExactSizeIterator<Item = usize> {
+        (0..self.len).map(move |i| self.offset + i * self.record_size)
+    }
+
+    fn bytes(self, data: &[u8]) -> &[u8] {
+        &data[self.offset..self.offset + self.len * self.record_size]
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Validation
+// ----------------------------------------------------------------------------
+
+/// Validates the archive and returns its accessor. Validation is linear in the size of the
+/// buffer, since the arrays must not overlap, and does not allocate.
+pub fn access_sequence(data: &[u8]) -> Result<ArchivedSequence<'_>, ArchiveError> {
+    if data.len() < HEADER_SIZE {
+        return Err(ArchiveError::OutOfBounds {
+            what: "header",
+            offset: 0,
+        });
+    }
+    if data[..MAGIC.len()] != MAGIC {
+        return Err(ArchiveError::InvalidHeader { offset: 0 });
+    }
+    let version = u32_at(data, HEADER_VERSION);
+    if version != VERSION {
+        return Err(ArchiveError::UnsupportedVersion {
+            version,
+            offset: HEADER_VERSION,
+        });
+    }
+    let mut next = HEADER_SIZE;
+    Array::checked(
+        data,
+        HEADER_TEMPO_CHANGES,
+        TEMPO_CHANGE_SIZE,
+        "tempo changes",
+        &mut next,
+    )?;
+    Array::checked(
+        data,
+        HEADER_TIME_SIGNATURES,
+        TIME_SIGNATURE_SIZE,
+        "time signatures",
+        &mut next,
+    )?;
+    let tracks = Array::checked(data, HEADER_TRACKS, TRACK_SIZE, "tracks", &mut next)?;
+    for pos in tracks.positions() {
+        validate_track(data, pos, &mut next)?;
+    }
+    Ok(ArchivedSequence { data })
+}
+
+fn validate_track(data: &[u8], pos: usize, next: &mut usize) -> Result<(), ArchiveError> {
+    let name = Array::checked(data, pos + TRACK_NAME, 1, "track name", next)?;
+    if str::from_utf8(name.bytes(data)).is_err() {
+        return Err(ArchiveError::InvalidUtf8 {
+            offset: name.offset,
+        });
+    }
+    Array::checked(data, pos + TRACK_TUNING, 4, "tuning", next)?;
+    let notes = Array::checked(data, pos + TRACK_NOTES, NOTE_SIZE, "notes", next)?;
+    let bend_points = Array::checked(
+        data,
+        pos + TRACK_BEND_POINTS,
+        BEND_POINT_SIZE,
+        "bend points",
+        next,
+    )?;
+    let is_percussion = data[pos + TRACK_IS_PERCUSSION];
+    if is_percussion > 1 {
+        return Err(ArchiveError::InvalidValue {
+            what: "is_percussion",
+            value: is_percussion as u64,
+            offset: pos + TRACK_IS_PERCUSSION,
+        });
+    }
+    let mut next_bend_point = 0;
+    for pos in notes.positions() {
+        let flags = data[pos + NOTE_FLAGS];
+        if flags & !ALL_FLAGS != 0 {
+            return Err(ArchiveError::InvalidValue {
+                what: "note flags",
+                value: flags as u64,
+                offset: pos + NOTE_FLAGS,
+            });
+        }
+        let start = u32_at(data, pos + NOTE_BEND_START) as usize;
+        let len = u32_at(data, pos + NOTE_BEND_LEN) as usize;
+        if start
+            .checked_add(len)
+            .is_none_or(|end| end > bend_points.len)
+        {
+            return Err(ArchiveError::OutOfBounds {
+                what: "bend points",
+                offset: pos + NOTE_BEND_START,
+            });
+        }
+        // like the arrays, the ranges follow each other, so reading them is linear as well
+        if start != next_bend_point {
+            return Err(ArchiveError::InvalidValue {
+                what: "start of the bend points",
+                value: start as u64,
+                offset: pos + NOTE_BEND_START,
+            });
+        }
+        next_bend_point += len;
+        if flags & FLAG_BEND_DATA == 0 && len > 0 {
+            return Err(ArchiveError::InvalidValue {
+                what: "number of bend points",
+                value: len as u64,
+                offset: pos + NOTE_BEND_LEN,
+            });
+        }
+    }
+    Ok(())
+}
+
+// ----------------------------------------------------------------------------
+// Accessors
+// ----------------------------------------------------------------------------
+
+/// A validated archive, see `access_sequence`.
+#[derive(Copy, Clone, Debug)]
+pub struct ArchivedSequence<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> ArchivedSequence<'a> {
+    pub fn bpm_base(&self) -> f64 {
+        f64_at(self.data, HEADER_BPM_BASE)
+    }
+
+    pub fn tempo_changes(&self) -> impl ExactSizeIterator<Item = TempoChange> + 'a {
+        let data = self.data;
+        let array = Array::at(data, HEADER_TEMPO_CHANGES, TEMPO_CHANGE_SIZE);
+        array.positions().map(move |pos| TempoChange {
+            beat: f64_at(data, pos),
+            bpm: f64_at(data, pos + 8),
+        })
+    }
+
+    pub fn time_signatures(&self) -> impl ExactSizeIterator<Item = TimeSignature> + 'a {
+        let data = self.data;
+        let array = Array::at(data, HEADER_TIME_SIGNATURES, TIME_SIGNATURE_SIZE);
+        array.positions().map(move |pos| TimeSignature {
+            beat: f64_at(data, pos),
+            numerator: data[pos + 8],
+            denominator: data[pos + 9],
+        })
+    }
+
+    fn track_array(&self) -> Array {
+        Array::at(self.data, HEADER_TRACKS, TRACK_SIZE)
+    }
+
+    pub fn num_tracks(&self) -> usize {
+        self.track_array().len
+    }
+
+    /// Panics if `i` is out of range.
+    pub fn track(&self, i: usize) -> ArchivedTrack<'a> {
+        ArchivedTrack {
+            data: self.data,
+            pos: self.track_array().pos(i),
+        }
+    }
+
+    pub fn tracks(&self) -> impl ExactSizeIterator<Item = ArchivedTrack<'a>> + 'a {
+        let data = self.data;
+        self.track_array()
+            .positions()
+            .map(move |pos| ArchivedTrack { data, pos })
+    }
+
+    pub fn tempo_map(&self) -> TempoMap {
+        TempoMap {
+            bpm_base: self.bpm_base(),
+            tempo_changes: self.tempo_changes().collect(),
+            time_signatures: self.time_signatures().collect(),
+        }
+    }
+
+    /// Copies the archive into a `Sequence`.
+    pub fn to_sequence(&self) -> Sequence {
+        Sequence {
+            tempo_map: self.tempo_map(),
+            tracks: self.tracks().map(|track| track.to_track()).collect(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ArchivedTrack<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ArchivedTrack<'a> {
+    pub fn name(&self) -> &'a str {
+        let name = Array::at(self.data, self.pos + TRACK_NAME, 1);
+        str::from_utf8(name.bytes(self.data)).expect("validated by access_sequence")
+    }
+
+    pub fn is_percussion(&self) -> bool {
+        self.data[self.pos + TRACK_IS_PERCUSSION] != 0
+    }
+
+    /// The `string_base_pitches` of the tuning.
+    pub fn tuning(&self) -> impl ExactSizeIterator<Item = i32> + 'a {
+        let data = self.data;
+        let array = Array::at(data, self.pos + TRACK_TUNING, 4);
+        array.positions().map(move |pos| i32_at(data, pos))
+    }
+
+    fn note_array(&self) -> Array {
+        Array::at(self.data, self.pos + TRACK_NOTES, NOTE_SIZE)
+    }
+
+    pub fn num_notes(&self) -> usize {
+        self.note_array().len
+    }
+
+    /// Panics if `i` is out of range.
+    pub fn note(&self, i: usize) -> ArchivedNote<'a> {
+        ArchivedNote {
+            data: self.data,
+            pos: self.note_array().pos(i),
+            bend_points: self.bend_point_array(),
+        }
+    }
+
+    pub fn notes(&self) -> impl ExactSizeIterator<Item = ArchivedNote<'a>> + 'a {
+        let data = self.data;
+        let bend_points = self.bend_point_array();
+        self.note_array().positions().map(move |pos| ArchivedNote {
+            data,
+            pos,
+            bend_points,
+        })
+    }
+
+    fn bend_point_array(&self) -> Array {
+        Array::at(self.data, self.pos + TRACK_BEND_POINTS, BEND_POINT_SIZE)
+    }
+
+    pub fn to_track(&self) -> Track {
+        Track {
+            name: self.name().to_string(),
+            is_percussion: self.is_percussion(),
+            tuning: Tuning {
+                string_base_pitches: self.tuning().collect(),
+            },
+            notes: self.notes().map(|note| note.to_note()).collect(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ArchivedNote<'a> {
+    data: &'a [u8],
+    pos: usize,
+    /// The bend points of the track.
+    bend_points: Array,
+}
+
+impl<'a> ArchivedNote<'a> {
+    pub fn s(&self) -> f64 {
+        f64_at(self.data, self.pos + NOTE_S)
+    }
+
+    pub fn d(&self) -> f64 {
+        f64_at(self.data, self.pos + NOTE_D)
+    }
+
+    pub fn pitch(&self) -> u8 {
+        self.data[self.pos + NOTE_PITCH]
+    }
+
+    pub fn string(&self) -> u8 {
+        self.data[self.pos + NOTE_STRING]
+    }
+
+    pub fn fret(&self) -> u8 {
+        self.data[self.pos + NOTE_FRET]
+    }
+
+    fn flag(&self, flag: u8) -> bool {
+        self.data[self.pos + NOTE_FLAGS] & flag != 0
+    }
+
+    pub fn dead_note(&self) -> bool {
+        self.flag(FLAG_DEAD_NOTE)
+    }
+
+    pub fn vibrato(&self) -> bool {
+        self.flag(FLAG_VIBRATO)
+    }
+
+    /// Whether `bend_data` is `Some`, possibly without points.
+    pub fn has_bend_data(&self) -> bool {
+        self.flag(FLAG_BEND_DATA)
+    }
+
+    /// The points of the bend data, empty without bend data.
+    pub fn bend_points(&self) -> impl ExactSizeIterator<Item = BendPoint> + 'a {
+        let data = self.data;
+        let start = u32_at(data, self.pos + NOTE_BEND_START) as usize;
+        let len = u32_at(data, self.pos + NOTE_BEND_LEN) as usize;
+        let first = self.bend_points.offset + start * BEND_POINT_SIZE;
+        (0..len).map(move |i| {
+            let pos = first + i * BEND_POINT_SIZE;
+            BendPoint {
+                pos: f64_at(data, pos),
+                bend: f32_at(data, pos + 8),
+            }
+        })
+    }
+
+    pub fn effects(&self) -> NoteEffects {
+        NoteEffects {
+            dead_note: self.dead_note(),
+            vibrato: self.vibrato(),
+            bend_data: self.has_bend_data().then(|| BendData {
+                points: self.bend_points().collect(),
+            }),
+        }
+    }
+
+    pub fn to_note(&self) -> Note {
+        Note {
+            s: self.s(),
+            d: self.d(),
+            pitch: self.pitch(),
+            string: self.string(),
+            fret: self.fret(),
+            effects: self.effects(),
+        }
+    }
+}
@@ -0,0 +1,447 @@
+//! An archived (zero-copy) representation of a `Sequence`: a layout of fixed-size records
+//! that is read in place, e.g., from a memory-mapped file, without allocating the strings
+//! and vectors of the tracks.
+//!
+//! `access_sequence` validates the whole buffer once, and afterwards the accessors
+//! (`ArchivedSequence`, `ArchivedTrack`, `ArchivedNote`) read the fields directly from the
+//! bytes. The archive stores all values exactly, i.e., `to_sequence` restores the archived
+//! sequence.
+//!
+//! The layout, all numbers are little endian:
+//! - A header of `HEADER_SIZE` bytes: the magic `SQAR`, the version (u32), `bpm_base` (f64)
+//!   and the arrays of the tempo changes, time signatures and tracks.
+//! - An array is the offset of its first element relative to the start of the buffer and
+//!   its length in elements (two u32). Arrays start at multiples of 8 bytes, so that the
+//!   floats are aligned if the buffer is.
+//! - The arrays follow the header without overlapping, in the order tempo changes, time
+//!   signatures, tracks, and per track its name, tuning, notes and bend points.
+//! - A tempo change is `beat` and `bpm` (f64 each), a time signature is `beat` (f64),
+//!   `numerator` and `denominator` (u8 each) and 6 bytes of padding.
+//! - A track is the arrays of its name (UTF-8 bytes), its tuning (i32), its notes and its
+//!   bend points, followed by `is_percussion` (u8) and 7 bytes of padding.
+//! - A note is `s` and `d` (f64 each), `pitch`, `string`, `fret` and the effect flags (u8
+//!   each), and the range of its bend points in the bend points of the track (start and
+//!   length, u32 each) with 4 bytes of padding. The ranges of the notes follow each other,
+//!   and a note without `bend_data` has an empty range.
+//! - A bend point is `pos` (f64) and `bend` (f32) with 4 bytes of padding.
+//!
+//! Offsets and lengths are 32 bits, which limits an archive to 4 GiB.
+
+mod access;
+mod write;
+
+use std::fmt;
+
+pub use access::{access_sequence, ArchivedNote, ArchivedSequence, ArchivedTrack};
+pub use write::archive_sequence;
+
+const MAGIC: [u8; 4] = *b"SQAR";
+const VERSION: u32 = 1;
+
+/// The alignment of the arrays.
+const ALIGNMENT: usize = 8;
+
+const ARRAY_SIZE: usize = 8;
+const HEADER_SIZE: usize = 16 + 3 * ARRAY_SIZE;
+const TEMPO_CHANGE_SIZE: usize = 16;
+const TIME_SIGNATURE_SIZE: usize = 16;
+const TRACK_SIZE: usize = 4 * ARRAY_SIZE + 8;
+const NOTE_SIZE: usize = 32;
+const BEND_POINT_SIZE: usize = 16;
+
+// Offsets of the fields in the header.
+const HEADER_VERSION: usize = 4;
+const HEADER_BPM_BASE: usize = 8;
+const HEADER_TEMPO_CHANGES: usize = 16;
+const HEADER_TIME_SIGNATURES: usize = HEADER_TEMPO_CHANGES + ARRAY_SIZE;
+const HEADER_TRACKS: usize = HEADER_TIME_SIGNATURES + ARRAY_SIZE;
+
+// Offsets of the fields in a track.
+const TRACK_NAME: usize = 0;
+const TRACK_TUNING: usize = ARRAY_SIZE;
+const TRACK_NOTES: usize = 2 * ARRAY_SIZE;
+const TRACK_BEND_POINTS: usize = 3 * ARRAY_SIZE;
+const TRACK_IS_PERCUSSION: usize = 4 * ARRAY_SIZE;
+
+// Offsets of the fields in a note.
+const NOTE_S: usize = 0;
+const NOTE_D: usize = 8;
+const NOTE_PITCH: usize = 16;
+const NOTE_STRING: usize = 17;
+const NOTE_FRET: usize = 18;
+const NOTE_FLAGS: usize = 19;
+const NOTE_BEND_START: usize = 20;
+const NOTE_BEND_LEN: usize = 24;
+
+const FLAG_DEAD_NOTE: u8 = 1;
+const FLAG_VIBRATO: u8 = 2;
+const FLAG_BEND_DATA: u8 = 4;
+const ALL_FLAGS: u8 = FLAG_DEAD_NOTE | FLAG_VIBRATO | FLAG_BEND_DATA;
+
+// ----------------------------------------------------------------------------
+// ArchiveError
+// ----------------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ArchiveError {
+    /// The buffer does not start with the magic `SQAR`.
+    InvalidHeader {
+        offset: usize,
+    },
+    UnsupportedVersion {
+        version: u32,
+        offset: usize,
+    },
+    /// An array (or the header) that does not fit into the buffer, `offset` is the position
+    /// of the array.
+    OutOfBounds {
+        what: &'static str,
+        offset: usize,
+    },
+    /// An array that starts before the end of the previous one, see the layout.
+    Overlapping {
+        what: &'static str,
+        offset: usize,
+    },
+    /// A value that is out of its range, e.g., unknown note flags.
+    InvalidValue {
+        what: &'static str,
+        value: u64,
+        offset: usize,
+    },
+    /// A track name that is not UTF-8.
+    InvalidUtf8 {
+        offset: usize,
+    },
+}
+
+impl ArchiveError {
+    /// Byte offset relative to the start of the buffer.
+    pub fn offset(&self) -> usize {
+        match self {
+            ArchiveError::InvalidHeader { offset }
+            | ArchiveError::UnsupportedVersion { offset, .. }
+            | ArchiveError::OutOfBounds { offset, .. }
+            | ArchiveError::Overlapping { offset, .. }
+            | ArchiveError::InvalidValue { offset, .. }
+            | ArchiveError::InvalidUtf8 { offset } => *offset,
+        }
+    }
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveError::InvalidHeader { .. } => write!(f, "Missing SQAR header")?,
+            ArchiveError::UnsupportedVersion { version, .. } => {
+                write!(f, "Unsupported archive version {}", version)?
+            }
+            ArchiveError::OutOfBounds { what, .. } => {
+                write!(f, "The {} exceed the end of the buffer", what)?
+            }
+            ArchiveError::Overlapping { what, .. } => {
+                write!(f, "The {} overlap the previous array", what)?
+            }
+            ArchiveError::InvalidValue { what, value, .. } => {
+                write!(f, "Invalid {} {}", what, value)?
+            }
+            ArchiveError::InvalidUtf8 { .. } => write!(f, "Track name is not UTF-8")?,
+        }
+        write!(f, " at offset {}", self.offset())
+    }
+}
+
+impl std::error::Error for ArchiveError {}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::synthetic::{gen_sequence_with_tempo_events, gen_sized_sequence};
+    use crate::types::{
+        BendData, BendPoint, Note, NoteEffects, Sequence, TempoChange, TempoMap, TimeSignature,
+        Track, Tuning,
+    };
+
+    use super::*;
+
+    fn sequence() -> Sequence {
+        Sequence {
+            tempo_map: TempoMap {
+                bpm_base: 96.5,
+                tempo_changes: vec![TempoChange {
+                    beat: 8.0,
+                    bpm: 120.0,
+                }],
+                time_signatures: vec![TimeSignature {
+                    beat: 0.0,
+                    numerator: 7,
+                    denominator: 8,
+                }],
+            },
+            tracks: vec![
+                Track {
+                    name: "Gitarre (Drop D) ♪".to_string(),
+                    is_percussion: false,
+                    tuning: Tuning {
+                        string_base_pitches: vec![64, 59, 55, 50, 45, 38],
+                    },
+                    notes: vec![
+                        Note {
+                            s: 0.0,
+                            d: 1.0 / 3.0,
+                            pitch: 38,
+                            string: 5,
+                            fret: 0,
+                            effects: NoteEffects::default(),
+                        },
+                        Note {
+                            s: 0.5,
+                            d: 2.0,
+                            pitch: 62,
+                            string: 2,
+                            fret: 7,
+                            effects: NoteEffects {
+                                dead_note: false,
+                                vibrato: true,
+                                bend_data: Some(BendData {
+                                    points: vec![
+                                        BendPoint {
+                                            pos: 0.0,
+                                            bend: 0.0,
+                                        },
+                                        BendPoint {
+                                            pos: 0.75,
+                                            bend: -1.5,
+                                        },
+                                    ],
+                                }),
+                            },
+                        },
+                        Note {
+                            s: 3.0,
+                            d: 0.25,
+                            pitch: 50,
+                            string: 3,
+                            fret: 0,
+                            effects: NoteEffects {
+                                dead_note: true,
+                                vibrato: false,
+                                bend_data: Some(BendData { points: vec![] }),
+                            },
+                        },
+                    ],
+                },
+                Track {
+                    name: String::new(),
+                    is_percussion: true,
+                    tuning: Tuning::default(),
+                    notes: vec![],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for sequence in [
+            sequence(),
+            Sequence::default(),
+            gen_sequence_with_tempo_events(&mut rng),
+            gen_sized_sequence(&mut rng, 4, 500),
+        ] {
+            let data = archive_sequence(&sequence).unwrap();
+            assert_eq!(data.len() % ALIGNMENT, 0);
+            assert_eq!(access_sequence(&data).unwrap().to_sequence(), sequence);
+
+            // the accessors do not depend on the alignment of the buffer
+            let mut unaligned = vec![0];
+            unaligned.extend_from_slice(&data);
+            assert_eq!(
+                access_sequence(&unaligned[1..]).unwrap().to_sequence(),
+                sequence
+            );
+        }
+    }
+
+    #[test]
+    fn test_accessors() {
+        let data = archive_sequence(&sequence()).unwrap();
+        let archived = access_sequence(&data).unwrap();
+        assert_eq!(archived.bpm_base(), 96.5);
+        assert_eq!(archived.tempo_changes().len(), 1);
+        assert_eq!(archived.time_signatures().next().unwrap().numerator, 7);
+        assert_eq!(archived.num_tracks(), 2);
+
+        let track = archived.track(0);
+        assert_eq!(track.name(), "Gitarre (Drop D) ♪");
+        assert!(!track.is_percussion());
+        assert_eq!(track.tuning().collect::<Vec<_>>(), [64, 59, 55, 50, 45, 38]);
+        assert_eq!(track.num_notes(), 3);
+        let pitches: Vec<u8> = track.notes().map(|note| note.pitch()).collect();
+        assert_eq!(pitches, [38, 62, 50]);
+
+        let note = track.note(1);
+        assert_eq!((note.s(), note.d()), (0.5, 2.0));
+        assert_eq!((note.string(), note.fret()), (2, 7));
+        assert!(note.vibrato() && !note.dead_note() && note.has_bend_data());
+        assert_eq!(note.bend_points().nth(1).unwrap().bend, -1.5);
+        let note = track.note(2);
+        assert!(note.dead_note() && note.has_bend_data());
+        assert_eq!(note.bend_points().len(), 0);
+        assert!(!track.note(0).has_bend_data());
+
+        assert!(archived.track(1).is_percussion());
+        assert_eq!(archived.tracks().map(|t| t.num_notes()).sum::<usize>(), 3);
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        let data = archive_sequence(&sequence()).unwrap();
+        for len in 0..HEADER_SIZE {
+            assert_eq!(
+                access_sequence(&data[..len]).unwrap_err(),
+                ArchiveError::OutOfBounds {
+                    what: "header",
+                    offset: 0
+                }
+            );
+        }
+        let mut invalid = data.clone();
+        invalid[0] = b'X';
+        assert_eq!(
+            access_sequence(&invalid).unwrap_err(),
+            ArchiveError::InvalidHeader { offset: 0 }
+        );
+        let mut invalid = data;
+        invalid[HEADER_VERSION] = 2;
+        assert_eq!(
+            access_sequence(&invalid).unwrap_err().to_string(),
+            "Unsupported archive version 2 at offset 4"
+        );
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        let data = archive_sequence(&sequence()).unwrap();
+        let u32_at = |pos: usize| u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap());
+        let tracks = u32_at(HEADER_TRACKS) as usize;
+        let notes = u32_at(tracks + TRACK_NOTES) as usize;
+        let name = u32_at(tracks + TRACK_NAME) as usize;
+
+        let mut invalid = data.clone();
+        invalid[HEADER_TRACKS + 7] = 1;
+        assert_eq!(
+            access_sequence(&invalid).unwrap_err(),
+            ArchiveError::OutOfBounds {
+                what: "tracks",
+                offset: HEADER_TRACKS
+            }
+        );
+
+        let mut invalid = data.clone();
+        invalid[tracks + TRACK_IS_PERCUSSION] = 2;
+        assert_eq!(
+            access_sequence(&invalid).unwrap_err().to_string(),
+            format!(
+                "Invalid is_percussion 2 at offset {}",
+                tracks + TRACK_IS_PERCUSSION
+            )
+        );
+
+        let mut invalid = data.clone();
+        invalid[notes + NOTE_FLAGS] = 8;
+        assert_eq!(
+            access_sequence(&invalid).unwrap_err(),
+            ArchiveError::InvalidValue {
+                what: "note flags",
+                value: 8,
+                offset: notes + NOTE_FLAGS
+            }
+        );
+
+        // the second note has two bend points, the track has two
+        let mut invalid = data.clone();
+        invalid[notes + NOTE_SIZE + NOTE_BEND_START] = 1;
+        assert_eq!(
+            access_sequence(&invalid).unwrap_err(),
+            ArchiveError::OutOfBounds {
+                what: "bend points",
+                offset: notes + NOTE_SIZE + NOTE_BEND_START
+            }
+        );
+
+        // bend points without bend data
+        let mut invalid = data.clone();
+        invalid[notes + NOTE_SIZE + NOTE_FLAGS] &= !FLAG_BEND_DATA;
+        assert!(matches!(
+            access_sequence(&invalid).unwrap_err(),
+            ArchiveError::InvalidValue {
+                what: "number of bend points",
+                value: 2,
+                ..
+            }
+        ));
+
+        // the second note takes the bend points of the first one
+        let mut invalid = data.clone();
+        invalid[notes + NOTE_BEND_LEN] = 1;
+        invalid[notes + NOTE_FLAGS] |= FLAG_BEND_DATA;
+        assert_eq!(
+            access_sequence(&invalid).unwrap_err(),
+            ArchiveError::InvalidValue {
+                what: "start of the bend points",
+                value: 0,
+                offset: notes + NOTE_SIZE + NOTE_BEND_START
+            }
+        );
+
+        let mut invalid = data;
+        invalid[name] = 0xFF;
+        assert_eq!(
+            access_sequence(&invalid).unwrap_err(),
+            ArchiveError::InvalidUtf8 { offset: name }
+        );
+    }
+
+    /// Tracks that share their notes would make validation quadratic in the buffer size.
+    #[test]
+    fn test_overlapping_arrays() {
+        let mut sequence = sequence();
+        sequence.tracks.truncate(1);
+        sequence.tracks.push(sequence.tracks[0].clone());
+        let data = archive_sequence(&sequence).unwrap();
+        assert!(access_sequence(&data).is_ok());
+
+        let tracks = u32::from_le_bytes(data[HEADER_TRACKS..][..4].try_into().unwrap()) as usize;
+        let first_notes = tracks + TRACK_NOTES;
+        let second_notes = tracks + TRACK_SIZE + TRACK_NOTES;
+        let mut invalid = data;
+        invalid.copy_within(first_notes..first_notes + ARRAY_SIZE, second_notes);
+        assert_eq!(
+            access_sequence(&invalid).unwrap_err().to_string(),
+            format!(
+                "The notes overlap the previous array at offset {}",
+                second_notes
+            )
+        );
+    }
+
+    /// Validated archives never make the accessors panic.
+    #[test]
+    fn test_corrupted_archives() {
+        let data = archive_sequence(&sequence()).unwrap();
+        for i in 0..data.len() {
+            for value in [0x00, 0x01, 0x7F, 0xFF] {
+                let mut corrupted = data.clone();
+                corrupted[i] = value;
+                if let Ok(archived) = access_sequence(&corrupted) {
+                    archived.to_sequence();
+                }
+            }
+        }
+    }
+}
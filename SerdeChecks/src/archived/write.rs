@@ -0,0 +1,168 @@
+use std::io::{Error, ErrorKind, Result};
+
+use crate::types::{Note, Sequence, Track};
+
+use super::*;
+
+fn invalid_input(message: String) -> Error {
+    Error::new(ErrorKind::InvalidInput, message)
+}
+
+fn to_u32(value: usize, what: &str) -> Result<u32> {
+    u32::try_from(value)
+        .map_err(|_| invalid_input(format!("The {} exceed the 4 GiB of an archive", what)))
+}
+
+/// The archive under construction, the arrays are appended at the end.
+struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    fn put(&mut self, pos: usize, bytes: &[u8]) {
+        self.data[pos..pos + bytes.len()].copy_from_slice(bytes);
+    }
+
+    fn put_array(&mut self, pos: usize, offset: usize, len: usize, what: &str) -> Result<()> {
+        let offset = to_u32(offset, what)?;
+        let len = to_u32(len, what)?;
+        self.put(pos, &offset.to_le_bytes());
+        self.put(pos + 4, &len.to_le_bytes());
+        Ok(())
+    }
+
+    /// Appends zeroed space for `len` records, and writes the array to `pos`. Returns the
+    /// offset of the first record.
+    fn append_array(
+        &mut self,
+        pos: usize,
+        len: usize,
+        record_size: usize,
+        what: &str,
+    ) -> Result<usize> {
+        let offset = self.data.len();
+        let size = len
+            .checked_mul(record_size)
+            .ok_or_else(|| invalid_input(format!("Too many {}", what)))?;
+        self.data
+            .resize((offset + size).next_multiple_of(ALIGNMENT), 0);
+        to_u32(self.data.len(), what)?;
+        self.put_array(pos, offset, len, what)?;
+        Ok(offset)
+    }
+}
+
+/// Writes the archived representation of the sequence, fails only for sequences whose
+/// archive would exceed 4 GiB.
+pub fn archive_sequence(sequence: &Sequence) -> Result<Vec<u8>> {
+    let mut wr = Writer {
+        data: vec![0; HEADER_SIZE],
+    };
+    wr.put(0, &MAGIC);
+    wr.put(HEADER_VERSION, &VERSION.to_le_bytes());
+    let tempo_map = &sequence.tempo_map;
+    wr.put(HEADER_BPM_BASE, &tempo_map.bpm_base.to_le_bytes());
+
+    let tempo_changes = &tempo_map.tempo_changes;
+    let mut pos = wr.append_array(
+        HEADER_TEMPO_CHANGES,
+        tempo_changes.len(),
+        TEMPO_CHANGE_SIZE,
+        "tempo changes",
+    )?;
+    for tempo_change in tempo_changes {
+        wr.put(pos, &tempo_change.beat.to_le_bytes());
+        wr.put(pos + 8, &tempo_change.bpm.to_le_bytes());
+        pos += TEMPO_CHANGE_SIZE;
+    }
+
+    let time_signatures = &tempo_map.time_signatures;
+    let mut pos = wr.append_array(
+        HEADER_TIME_SIGNATURES,
+        time_signatures.len(),
+        TIME_SIGNATURE_SIZE,
+        "time signatures",
+    )?;
+    for time_signature in time_signatures {
+        wr.put(pos, &time_signature.beat.to_le_bytes());
+        wr.put(
+            pos + 8,
+            &[time_signature.numerator, time_signature.denominator],
+        );
+        pos += TIME_SIGNATURE_SIZE;
+    }
+
+    let tracks = wr.append_array(HEADER_TRACKS, sequence.tracks.len(), TRACK_SIZE, "tracks")?;
+    for (i, track) in sequence.tracks.iter().enumerate() {
+        write_track(&mut wr, tracks + i * TRACK_SIZE, track)?;
+    }
+    Ok(wr.data)
+}
+
+fn write_track(wr: &mut Writer, pos: usize, track: &Track) -> Result<()> {
+    let name = track.name.as_bytes();
+    let offset = wr.append_array(pos + TRACK_NAME, name.len(), 1, "track names")?;
+    wr.put(offset, name);
+
+    let pitches = &track.tuning.string_base_pitches;
+    let mut offset = wr.append_array(pos + TRACK_TUNING, pitches.len(), 4, "tunings")?;
+    for pitch in pitches {
+        wr.put(offset, &pitch.to_le_bytes());
+        offset += 4;
+    }
+
+    let mut offset = wr.append_array(pos + TRACK_NOTES, track.notes.len(), NOTE_SIZE, "notes")?;
+    let mut num_bend_points = 0;
+    for note in &track.notes {
+        let len = write_note(wr, offset, note, num_bend_points)?;
+        num_bend_points += len;
+        offset += NOTE_SIZE;
+    }
+
+    let mut offset = wr.append_array(
+        pos + TRACK_BEND_POINTS,
+        num_bend_points,
+        BEND_POINT_SIZE,
+        "bend points",
+    )?;
+    let bend_data = track
+        .notes
+        .iter()
+        .filter_map(|n| n.effects.bend_data.as_ref());
+    for point in bend_data.flat_map(|bend_data| &bend_data.points) {
+        wr.put(offset, &point.pos.to_le_bytes());
+        wr.put(offset + 8, &point.bend.to_le_bytes());
+        offset += BEND_POINT_SIZE;
+    }
+
+    wr.put(pos + TRACK_IS_PERCUSSION, &[track.is_percussion as u8]);
+    Ok(())
+}
+
+/// Writes the note with its bend points starting at `bend_start`, returns the number of
+/// bend points.
+fn write_note(wr: &mut Writer, pos: usize, note: &Note, bend_start: usize) -> Result<usize> {
+    let effects = &note.effects;
+    let mut flags = 0;
+    if effects.dead_note {
+        flags |= FLAG_DEAD_NOTE;
+    }
+    if effects.vibrato {
+        flags |= FLAG_VIBRATO;
+    }
+    if effects.bend_data.is_some() {
+        flags |= FLAG_BEND_DATA;
+    }
+    let bend_len = effects.bend_data.as_ref().map_or(0, |b| b.points.len());
+    wr.put(pos + NOTE_S, &note.s.to_le_bytes());
+    wr.put(pos + NOTE_D, &note.d.to_le_bytes());
+    wr.put(
+        pos + NOTE_PITCH,
+        &[note.pitch, note.string, note.fret, flags],
+    );
+    let bend_start = to_u32(bend_start, "bend points")?;
+    let bend_len = to_u32(bend_len, "bend points")?;
+    wr.put(pos + NOTE_BEND_START, &bend_start.to_le_bytes());
+    wr.put(pos + NOTE_BEND_LEN, &bend_len.to_le_bytes());
+    Ok(bend_len as usize)
+}
@@ -0,0 +1,689 @@
+//! Compares the serialization formats by size (raw and compressed) and throughput.
+//!
+//! Throughputs are given in MB/s of serialized data, i.e., encoding 10 MB of JSON in one
+//! second and decoding it again in two seconds are reported as 10 MB/s and 5 MB/s. Note that
+//! this favors verbose formats in the throughput columns, so they should be read together
+//! with the raw sizes.
+//!
+//! Decoding is not guaranteed to work: `types` skips default values via serde attributes,
+//! which formats without field names (e.g. bincode) cannot decode. Such failures are
+//! reported in the table instead of aborting the benchmark.
+//!
+//! The load benchmark measures the time until the notes of a sequence can be read (open),
+//! and until all of them have been read (scan), in microseconds. It compares the archived
+//! representation, which is read in place, with parsing the custom format (as is and in a
+//! container, which only reads its table of contents when opened) and bincode.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+
+use crate::archived::{access_sequence, archive_sequence};
+use crate::container::{write_container_to_vec, ChunkCompression, ContainerReader};
+use crate::types::{BendData, Sequence, TempoChange, TimeSignature, Tuning};
+use crate::{parse_sequence, serialize_sequence_to_vec, NoteLayout, Params};
+
+pub type BoxError = Box<dyn Error + Send + Sync>;
+
+// Compression levels, all of them correspond to the defaults of the respective tools.
+const GZIP_LEVEL: u32 = 6;
+const ZSTD_LEVEL: i32 = 3;
+const BROTLI_QUALITY: u32 = 11;
+const BROTLI_LG_WINDOW_SIZE: u32 = 22;
+
+// ----------------------------------------------------------------------------
+// Formats
+// ----------------------------------------------------------------------------
+
+pub struct Format {
+    pub name: &'static str,
+    pub encode: fn(&Sequence) -> Result<Vec<u8>, BoxError>,
+    pub decode: fn(&[u8]) -> Result<Sequence, BoxError>,
+}
+
+pub fn all_formats() -> Vec<Format> {
+    vec![
+        Format {
+            name: "json",
+            encode: |seq| Ok(serde_json::to_vec(seq)?),
+            decode: |data| Ok(serde_json::from_slice(data)?),
+        },
+        Format {
+            name: "pretty.json",
+            encode: |seq| Ok(serde_json::to_vec_pretty(seq)?),
+            decode: |data| Ok(serde_json::from_slice(data)?),
+        },
+        Format {
+            name: "msgpack (compact)",
+            encode: |seq| Ok(rmp_serde::encode::to_vec(seq)?),
+            decode: |data| Ok(rmp_serde::decode::from_slice(data)?),
+        },
+        Format {
+            name: "msgpack (named)",
+            encode: |seq| Ok(rmp_serde::encode::to_vec_named(seq)?),
+            decode: |data| Ok(rmp_serde::decode::from_slice(data)?),
+        },
+        Format {
+            name: "bincode",
+            encode: |seq| Ok(bincode::serialize(seq)?),
+            decode: |data| Ok(bincode::deserialize(data)?),
+        },
+        Format {
+            name: "cbor",
+            encode: |seq| {
+                let mut data = Vec::new();
+                ciborium::ser::into_writer(seq, &mut data)?;
+                Ok(data)
+            },
+            decode: |data| Ok(ciborium::de::from_reader(data)?),
+        },
+        Format {
+            name: "bare",
+            encode: |seq| Ok(serde_bare::to_vec(seq)?),
+            decode: |data| Ok(serde_bare::from_slice(data)?),
+        },
+        Format {
+            name: "custom",
+            encode: |seq| Ok(serialize_sequence_to_vec(seq, &Params::default())?),
+            decode: |data| Ok(parse_sequence(data)?),
+        },
+        Format {
+            name: "custom (columnar)",
+            encode: |seq| {
+                let params = Params {
+                    note_layout: NoteLayout::Columnar,
+                    ..Params::default()
+                };
+                Ok(serialize_sequence_to_vec(seq, &params)?)
+            },
+            decode: |data| Ok(parse_sequence(data)?),
+        },
+        Format {
+            name: "archived",
+            encode: |seq| Ok(archive_sequence(seq)?),
+            decode: |data| Ok(access_sequence(data)?.to_sequence()),
+        },
+    ]
+}
+
+// ----------------------------------------------------------------------------
+// Measurements
+// ----------------------------------------------------------------------------
+
+pub struct Input {
+    pub name: String,
+    pub sequence: Sequence,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sizes {
+    pub raw: usize,
+    pub gzip: usize,
+    pub zstd: usize,
+    pub brotli: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Measurement {
+    pub input: String,
+    pub format: &'static str,
+    pub sizes: Sizes,
+    pub encode_mb_per_s: f64,
+    /// `None` if the format fails to decode its own output.
+    pub decode_mb_per_s: Option<f64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct BenchmarkOptions {
+    /// Encoding and decoding are repeated until they took at least this long.
+    pub min_duration: Duration,
+}
+
+impl Default for BenchmarkOptions {
+    fn default() -> Self {
+        BenchmarkOptions {
+            min_duration: Duration::from_millis(200),
+        }
+    }
+}
+
+pub fn run_benchmark(
+    inputs: &[Input],
+    formats: &[Format],
+    options: &BenchmarkOptions,
+) -> Result<Vec<Measurement>, BoxError> {
+    let mut measurements = Vec::new();
+    for input in inputs {
+        for format in formats {
+            measurements.push(measure(input, format, options)?);
+        }
+    }
+    Ok(measurements)
+}
+
+fn measure(
+    input: &Input,
+    format: &Format,
+    options: &BenchmarkOptions,
+) -> Result<Measurement, BoxError> {
+    let data = (format.encode)(&input.sequence)?;
+    let encode_mb_per_s = throughput(data.len(), options, || {
+        (format.encode)(&input.sequence).map(|_| ())
+    })?;
+    let decode_mb_per_s = match (format.decode)(&data) {
+        Ok(_) => Some(throughput(data.len(), options, || {
+            (format.decode)(&data).map(|_| ())
+        })?),
+        Err(_) => None,
+    };
+    Ok(Measurement {
+        input: input.name.clone(),
+        format: format.name,
+        sizes: Sizes {
+            raw: data.len(),
+            gzip: gzip_size(&data)?,
+            zstd: zstd::bulk::compress(&data, ZSTD_LEVEL)?.len(),
+            brotli: brotli_size(&data)?,
+        },
+        encode_mb_per_s,
+        decode_mb_per_s,
+    })
+}
+
+fn throughput<F>(num_bytes: usize, options: &BenchmarkOptions, f: F) -> Result<f64, BoxError>
+where
+    F: FnMut() -> Result<(), BoxError>,
+{
+    // bytes per microsecond are MB/s
+    Ok(num_bytes as f64 / mean_duration_us(options, f)?)
+}
+
+/// The mean duration of `f` in microseconds.
+fn mean_duration_us<F>(options: &BenchmarkOptions, mut f: F) -> Result<f64, BoxError>
+where
+    F: FnMut() -> Result<(), BoxError>,
+{
+    let start = Instant::now();
+    let mut iterations = 0u64;
+    loop {
+        f()?;
+        iterations += 1;
+        if start.elapsed() >= options.min_duration {
+            break;
+        }
+    }
+    Ok(start.elapsed().as_secs_f64() * 1e6 / iterations as f64)
+}
+
+fn gzip_size(data: &[u8]) -> io::Result<usize> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::new(GZIP_LEVEL));
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?.len())
+}
+
+fn brotli_size(data: &[u8]) -> io::Result<usize> {
+    let mut compressed = Vec::new();
+    {
+        let mut writer = brotli::CompressorWriter::new(
+            &mut compressed,
+            4096,
+            BROTLI_QUALITY,
+            BROTLI_LG_WINDOW_SIZE,
+        );
+        writer.write_all(data)?;
+    }
+    Ok(compressed.len())
+}
+
+// ----------------------------------------------------------------------------
+// Loading
+// ----------------------------------------------------------------------------
+
+pub struct Loader {
+    pub name: &'static str,
+    pub encode: fn(&Sequence) -> Result<Vec<u8>, BoxError>,
+    /// Makes the notes readable, e.g., by parsing or validating the data.
+    pub open: fn(&[u8]) -> Result<(), BoxError>,
+    /// Opens the data and reads all notes, returns a checksum of the notes.
+    pub scan: fn(&[u8]) -> Result<f64, BoxError>,
+}
+
+/// `Sequence` without the fields that `types` skips, so that bincode can decode it.
+#[derive(Serialize, Deserialize)]
+struct PlainSequence {
+    bpm_base: f64,
+    tempo_changes: Vec<TempoChange>,
+    time_signatures: Vec<TimeSignature>,
+    tracks: Vec<PlainTrack>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PlainTrack {
+    name: String,
+    is_percussion: bool,
+    tuning: Tuning,
+    notes: Vec<PlainNote>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PlainNote {
+    s: f64,
+    d: f64,
+    pitch: u8,
+    string: u8,
+    fret: u8,
+    dead_note: bool,
+    vibrato: bool,
+    bend_data: Option<BendData>,
+}
+
+impl From<&Sequence> for PlainSequence {
+    fn from(sequence: &Sequence) -> Self {
+        let tempo_map = &sequence.tempo_map;
+        PlainSequence {
+            bpm_base: tempo_map.bpm_base,
+            tempo_changes: tempo_map.tempo_changes.clone(),
+            time_signatures: tempo_map.time_signatures.clone(),
+            tracks: sequence
+                .tracks
+                .iter()
+                .map(|track| PlainTrack {
+                    name: track.name.clone(),
+                    is_percussion: track.is_percussion,
+                    tuning: track.tuning.clone(),
+                    notes: track
+                        .notes
+                        .iter()
+                        .map(|note| PlainNote {
+                            s: note.s,
+                            d: note.d,
+                            pitch: note.pitch,
+                            string: note.string,
+                            fret: note.fret,
+                            dead_note: note.effects.dead_note,
+                            vibrato: note.effects.vibrato,
+                            bend_data: note.effects.bend_data.clone(),
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Sums up the onsets and pitches of the notes.
+fn checksum(notes: impl Iterator<Item = (f64, u8)>) -> f64 {
+    notes.map(|(s, pitch)| s + pitch as f64).sum()
+}
+
+pub fn all_loaders() -> Vec<Loader> {
+    vec![
+        Loader {
+            name: "archived",
+            encode: |seq| Ok(archive_sequence(seq)?),
+            open: |data| {
+                access_sequence(data)?;
+                Ok(())
+            },
+            scan: |data| {
+                let archived = access_sequence(data)?;
+                Ok(checksum(archived.tracks().flat_map(|track| {
+                    track.notes().map(|note| (note.s(), note.pitch()))
+                })))
+            },
+        },
+        Loader {
+            name: "custom",
+            encode: |seq| Ok(serialize_sequence_to_vec(seq, &Params::default())?),
+            open: |data| {
+                parse_sequence(data)?;
+                Ok(())
+            },
+            scan: |data| {
+                let sequence = parse_sequence(data)?;
+                Ok(checksum(sequence.tracks.iter().flat_map(|track| {
+                    track.notes.iter().map(|note| (note.s, note.pitch))
+                })))
+            },
+        },
+        Loader {
+            name: "container (zstd)",
+            encode: |seq| {
+                Ok(write_container_to_vec(
+                    seq,
+                    &Params::default(),
+                    ChunkCompression::Zstd,
+                )?)
+            },
+            open: |data| {
+                ContainerReader::new(io::Cursor::new(data))?;
+                Ok(())
+            },
+            scan: |data| {
+                let sequence = ContainerReader::new(io::Cursor::new(data))?.read_sequence()?;
+                Ok(checksum(sequence.tracks.iter().flat_map(|track| {
+                    track.notes.iter().map(|note| (note.s, note.pitch))
+                })))
+            },
+        },
+        Loader {
+            name: "bincode",
+            encode: |seq| Ok(bincode::serialize(&PlainSequence::from(seq))?),
+            open: |data| {
+                bincode::deserialize::<PlainSequence>(data)?;
+                Ok(())
+            },
+            scan: |data| {
+                let sequence: PlainSequence = bincode::deserialize(data)?;
+                Ok(checksum(sequence.tracks.iter().flat_map(|track| {
+                    track.notes.iter().map(|note| (note.s, note.pitch))
+                })))
+            },
+        },
+    ]
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoadMeasurement {
+    pub input: String,
+    pub loader: &'static str,
+    pub size: usize,
+    pub open_us: f64,
+    pub scan_us: f64,
+}
+
+pub fn run_load_benchmark(
+    inputs: &[Input],
+    loaders: &[Loader],
+    options: &BenchmarkOptions,
+) -> Result<Vec<LoadMeasurement>, BoxError> {
+    let mut measurements = Vec::new();
+    for input in inputs {
+        for loader in loaders {
+            let data = (loader.encode)(&input.sequence)?;
+            let open_us = mean_duration_us(options, || (loader.open)(&data))?;
+            let scan_us = mean_duration_us(options, || {
+                std::hint::black_box((loader.scan)(&data)?);
+                Ok(())
+            })?;
+            measurements.push(LoadMeasurement {
+                input: input.name.clone(),
+                loader: loader.name,
+                size: data.len(),
+                open_us,
+                scan_us,
+            });
+        }
+    }
+    Ok(measurements)
+}
+
+// ----------------------------------------------------------------------------
+// Tables
+// ----------------------------------------------------------------------------
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TableFormat {
+    Markdown,
+    Csv,
+}
+
+impl FromStr for TableFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "markdown" | "md" => Ok(TableFormat::Markdown),
+            "csv" => Ok(TableFormat::Csv),
+            _ => Err(format!("Unknown table format '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for TableFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TableFormat::Markdown => write!(f, "markdown"),
+            TableFormat::Csv => write!(f, "csv"),
+        }
+    }
+}
+
+const COLUMNS: [&str; 8] = [
+    "input",
+    "format",
+    "raw",
+    "gzip",
+    "zstd",
+    "brotli",
+    "encode MB/s",
+    "decode MB/s",
+];
+
+fn cells(measurement: &Measurement) -> [String; 8] {
+    let sizes = &measurement.sizes;
+    [
+        measurement.input.clone(),
+        measurement.format.to_string(),
+        sizes.raw.to_string(),
+        sizes.gzip.to_string(),
+        sizes.zstd.to_string(),
+        sizes.brotli.to_string(),
+        format!("{:.1}", measurement.encode_mb_per_s),
+        match measurement.decode_mb_per_s {
+            Some(decode_mb_per_s) => format!("{:.1}", decode_mb_per_s),
+            None => "failed".to_string(),
+        },
+    ]
+}
+
+pub fn write_table<W: Write>(
+    wr: &mut W,
+    measurements: &[Measurement],
+    table_format: TableFormat,
+) -> io::Result<()> {
+    let rows = measurements.iter().map(cells);
+    write_rows(wr, &COLUMNS, rows, table_format)
+}
+
+const LOAD_COLUMNS: [&str; 5] = ["input", "loader", "size", "open µs", "scan µs"];
+
+fn load_cells(measurement: &LoadMeasurement) -> [String; 5] {
+    [
+        measurement.input.clone(),
+        measurement.loader.to_string(),
+        measurement.size.to_string(),
+        format!("{:.1}", measurement.open_us),
+        format!("{:.1}", measurement.scan_us),
+    ]
+}
+
+pub fn write_load_table<W: Write>(
+    wr: &mut W,
+    measurements: &[LoadMeasurement],
+    table_format: TableFormat,
+) -> io::Result<()> {
+    let rows = measurements.iter().map(load_cells);
+    write_rows(wr, &LOAD_COLUMNS, rows, table_format)
+}
+
+/// The first two columns are text, the others numbers.
+fn write_rows<W: Write, const N: usize>(
+    wr: &mut W,
+    columns: &[&str; N],
+    rows: impl Iterator<Item = [String; N]>,
+    table_format: TableFormat,
+) -> io::Result<()> {
+    match table_format {
+        TableFormat::Markdown => {
+            // Text columns are left aligned, numbers right aligned.
+            writeln!(wr, "| {} |", columns.join(" | "))?;
+            let separators: Vec<_> = (0..N)
+                .map(|i| if i < 2 { ":---" } else { "---:" })
+                .collect();
+            writeln!(wr, "| {} |", separators.join(" | "))?;
+            for row in rows {
+                let cells = row.map(|cell| cell.replace('|', "\\|"));
+                writeln!(wr, "| {} |", cells.join(" | "))?;
+            }
+        }
+        TableFormat::Csv => {
+            writeln!(wr, "{}", columns.join(","))?;
+            for row in rows {
+                let cells = row.map(|cell| csv_escape(&cell));
+                writeln!(wr, "{}", cells.join(","))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn csv_escape(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::synthetic::gen_sized_sequence;
+
+    use super::*;
+
+    fn measurement(input: &str, format: &'static str) -> Measurement {
+        Measurement {
+            input: input.to_string(),
+            format,
+            sizes: Sizes {
+                raw: 1000,
+                gzip: 500,
+                zstd: 400,
+                brotli: 300,
+            },
+            encode_mb_per_s: 12.34,
+            decode_mb_per_s: Some(5.0),
+        }
+    }
+
+    fn table(table_format: TableFormat) -> String {
+        let measurements = [
+            measurement("a.json", "json"),
+            Measurement {
+                decode_mb_per_s: None,
+                ..measurement("b|\"c\", d", "custom")
+            },
+        ];
+        let mut data = Vec::new();
+        write_table(&mut data, &measurements, table_format).unwrap();
+        String::from_utf8(data).unwrap()
+    }
+
+    #[test]
+    fn test_write_table() {
+        assert_eq!(
+            table(TableFormat::Markdown),
+            "\
+| input | format | raw | gzip | zstd | brotli | encode MB/s | decode MB/s |
+| :--- | :--- | ---: | ---: | ---: | ---: | ---: | ---: |
+| a.json | json | 1000 | 500 | 400 | 300 | 12.3 | 5.0 |
+| b\\|\"c\", d | custom | 1000 | 500 | 400 | 300 | 12.3 | failed |
+"
+        );
+        assert_eq!(
+            table(TableFormat::Csv),
+            "\
+input,format,raw,gzip,zstd,brotli,encode MB/s,decode MB/s
+a.json,json,1000,500,400,300,12.3,5.0
+\"b|\"\"c\"\", d\",custom,1000,500,400,300,12.3,failed
+"
+        );
+    }
+
+    #[test]
+    fn test_run_benchmark() {
+        let inputs = [Input {
+            name: "synthetic".to_string(),
+            sequence: gen_sized_sequence(&mut StdRng::seed_from_u64(0), 2, 100),
+        }];
+        let formats = all_formats();
+        let options = BenchmarkOptions {
+            min_duration: Duration::ZERO,
+        };
+        let measurements = run_benchmark(&inputs, &formats, &options).unwrap();
+
+        assert_eq!(measurements.len(), formats.len());
+        for measurement in &measurements {
+            let sizes = &measurement.sizes;
+            assert!(sizes.gzip < sizes.raw, "{:?}", measurement);
+            assert!(sizes.zstd < sizes.raw, "{:?}", measurement);
+            assert!(sizes.brotli < sizes.raw, "{:?}", measurement);
+            assert!(measurement.encode_mb_per_s > 0.0);
+        }
+        let decodes = |format| {
+            measurements
+                .iter()
+                .find(|m| m.format == format)
+                .unwrap()
+                .decode_mb_per_s
+                .is_some()
+        };
+        assert!(decodes("json"));
+        assert!(decodes("cbor"));
+        assert!(decodes("custom"));
+        assert!(decodes("custom (columnar)"));
+        // Skipped fields break formats without field names.
+        assert!(!decodes("bincode"));
+        let size_of = |format| {
+            measurements
+                .iter()
+                .find(|m| m.format == format)
+                .map(|m| m.sizes.raw)
+                .unwrap()
+        };
+        assert!(size_of("custom") < size_of("json"));
+        assert!(size_of("custom (columnar)") < size_of("custom"));
+        assert!(decodes("archived"));
+    }
+
+    #[test]
+    fn test_run_load_benchmark() {
+        let sequence = gen_sized_sequence(&mut StdRng::seed_from_u64(0), 2, 100);
+        let loaders = all_loaders();
+        let checksums: Vec<f64> = loaders
+            .iter()
+            .map(|loader| (loader.scan)(&(loader.encode)(&sequence).unwrap()).unwrap())
+            .collect();
+        assert!(checksums.iter().all(|checksum| *checksum == checksums[0]));
+
+        let inputs = [Input {
+            name: "synthetic".to_string(),
+            sequence,
+        }];
+        let options = BenchmarkOptions {
+            min_duration: Duration::ZERO,
+        };
+        let measurements = run_load_benchmark(&inputs, &loaders, &options).unwrap();
+        let names: Vec<_> = measurements.iter().map(|m| m.loader).collect();
+        assert_eq!(names, ["archived", "custom", "container (zstd)", "bincode"]);
+        for measurement in &measurements {
+            assert!(measurement.size > 0 && measurement.scan_us > 0.0);
+        }
+
+        let mut data = Vec::new();
+        write_load_table(&mut data, &measurements[..1], TableFormat::Csv).unwrap();
+        let table = String::from_utf8(data).unwrap();
+        assert!(table.starts_with("input,loader,size,open µs,scan µs\nsynthetic,archived,"));
+    }
+}
@@ -1,11 +1,33 @@
+//! Cereal-style archives: every type implements a single `archive` function, which either
+//! writes or reads the type depending on the `Archive` it is given.
+//!
+//! The drafts below explore the idea, the `Archive` section at the end turns it into an API
+//! that produces the same bytes as `serialize_sequence`. The flaws noted in Draft v2 remain
+//! inherent to the approach: writing also requires `&mut self` (the value is left untouched
+//! though), and reading fills in a value in-place, i.e., the types must be `Default`.
 #![allow(dead_code)]
 
 use std::io::Empty;
+use std::io::Error;
+use std::io::ErrorKind;
 use std::io::Read;
 use std::io::Result;
 use std::io::Sink;
 use std::io::Write;
 
+use crate::custom_file_format::columnar::{
+    note_flags, FLAG_BEND_DATA, FLAG_DEAD_NOTE, FLAG_VIBRATO, NOTE_FLAGS,
+};
+use crate::custom_file_format::quantization::{
+    check_quantization_error, quantize_int, quantize_uint, roundtrip_beat_and_offset,
+    split_in_beat_and_offset, Float,
+};
+use crate::custom_file_format::{FormatVersion, NoteLayout, Params, MAGIC};
+use crate::types::{
+    BendData, BendPoint, Note, NoteEffects, Sequence, TempoChange, TempoMap, TimeSignature, Track,
+    Tuning,
+};
+
 /*
 
 // Draft v1
@@ -161,10 +183,592 @@ impl<R: Read> Deserializer for BinaryDeserializer<R> {
 type BinarySerializerHandler<W> = Handler<BinarySerializer<W>, BinaryDeserializer<Empty>>;
 type BinaryDeserializerHandler<R> = Handler<BinarySerializer<Sink>, BinaryDeserializer<R>>;
 
+// ----------------------------------------------------------------------------
+// Archive
+// ----------------------------------------------------------------------------
+
+/// The backend of an archive, i.e., either a `Writer` or a `Reader`.
+///
+/// Backends only need to transfer raw bytes and varints, everything else is built on top.
+pub trait Archive {
+    fn is_reading(&self) -> bool;
+
+    /// The parameters of the custom file format. A `Reader` updates them when it reads the
+    /// header of a sequence.
+    fn params(&mut self) -> &mut Params;
+
+    /// Writes the bytes, or overwrites them with the bytes read.
+    fn bytes(&mut self, buf: &mut [u8]) -> Result<()>;
+
+    fn varint(&mut self, value: &mut u64) -> Result<()>;
+
+    fn zigzag(&mut self, value: &mut i64) -> Result<()> {
+        let mut encoded = zig_zag_encode(*value);
+        self.varint(&mut encoded)?;
+        *value = zig_zag_decode(encoded);
+        Ok(())
+    }
+
+    fn len(&mut self, len: &mut usize) -> Result<()> {
+        let mut value = *len as u64;
+        self.varint(&mut value)?;
+        *len = usize::try_from(value)
+            .map_err(|_| invalid_data(format!("Length {} is out of range", value)))?;
+        Ok(())
+    }
+}
+
+pub trait Archivable {
+    fn archive(&mut self, ar: &mut impl Archive) -> Result<()>;
+}
+
+pub struct Writer<W: Write> {
+    wr: W,
+    params: Params,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(wr: W, params: Params) -> Self {
+        Writer { wr, params }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.wr
+    }
+}
+
+impl<W: Write> Archive for Writer<W> {
+    fn is_reading(&self) -> bool {
+        false
+    }
+
+    fn params(&mut self) -> &mut Params {
+        &mut self.params
+    }
+
+    fn bytes(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.wr.write_all(buf)
+    }
+
+    fn varint(&mut self, value: &mut u64) -> Result<()> {
+        let mut x = *value;
+        let mut buf = [0u8; 9];
+        let mut i = 0usize;
+        loop {
+            let is_last = x < 0x80 || i == 8;
+            buf[i] = if is_last { x as u8 } else { (x as u8) | 0x80 };
+            x >>= 7;
+            i += 1;
+            if is_last {
+                break;
+            }
+        }
+        self.wr.write_all(&buf[..i])
+    }
+}
+
+pub struct Reader<R: Read> {
+    rd: R,
+    params: Params,
+}
+
+impl<R: Read> Reader<R> {
+    /// The params are taken from the header of the sequence once it has been read.
+    pub fn new(rd: R) -> Self {
+        Reader {
+            rd,
+            params: Params::default(),
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.rd
+    }
+}
+
+impl<R: Read> Archive for Reader<R> {
+    fn is_reading(&self) -> bool {
+        true
+    }
+
+    fn params(&mut self) -> &mut Params {
+        &mut self.params
+    }
+
+    fn bytes(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.rd.read_exact(buf)
+    }
+
+    fn varint(&mut self, value: &mut u64) -> Result<()> {
+        let mut x = 0u64;
+        for i in 0..9 {
+            let mut byte = [0u8];
+            self.rd.read_exact(&mut byte)?;
+            let c = byte[0];
+            let is_last = i == 8;
+            x |= (if is_last { c } else { c & 0x7f } as u64) << (i * 7);
+            if c < 0x80 || is_last {
+                break;
+            }
+        }
+        *value = x;
+        Ok(())
+    }
+}
+
+fn invalid_data(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+fn zig_zag_encode(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn zig_zag_decode(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+// Primitives
+
+macro_rules! impl_archivable_le_bytes {
+    ($($t:ty),*) => {
+        $(
+            impl Archivable for $t {
+                fn archive(&mut self, ar: &mut impl Archive) -> Result<()> {
+                    let mut buf = self.to_le_bytes();
+                    ar.bytes(&mut buf)?;
+                    *self = <$t>::from_le_bytes(buf);
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+impl_archivable_le_bytes!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+impl Archivable for bool {
+    fn archive(&mut self, ar: &mut impl Archive) -> Result<()> {
+        let mut value = *self as u8;
+        value.archive(ar)?;
+        *self = match value {
+            0 => false,
+            1 => true,
+            _ => return Err(invalid_data(format!("Invalid bool value {}", value))),
+        };
+        Ok(())
+    }
+}
+
+// Containers
+
+impl Archivable for String {
+    fn archive(&mut self, ar: &mut impl Archive) -> Result<()> {
+        let mut len = self.len();
+        ar.len(&mut len)?;
+        if ar.is_reading() {
+            let mut bytes = Vec::new();
+            ar_read_bytes(ar, len, &mut bytes)?;
+            *self = String::from_utf8(bytes)
+                .map_err(|_| invalid_data("Invalid UTF-8 in string".to_string()))?;
+            Ok(())
+        } else {
+            ar.bytes(&mut self.clone().into_bytes())
+        }
+    }
+}
+
+/// Reading grows the vector element by element, so that a corrupt length cannot trigger a
+/// huge allocation up front.
+impl<T: Archivable + Default> Archivable for Vec<T> {
+    fn archive(&mut self, ar: &mut impl Archive) -> Result<()> {
+        let mut len = self.len();
+        ar.len(&mut len)?;
+        if ar.is_reading() {
+            self.clear();
+            for _ in 0..len {
+                let mut element = T::default();
+                element.archive(ar)?;
+                self.push(element);
+            }
+        } else {
+            for element in self.iter_mut() {
+                element.archive(ar)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: Archivable + Default> Archivable for Option<T> {
+    fn archive(&mut self, ar: &mut impl Archive) -> Result<()> {
+        let mut is_some = self.is_some();
+        is_some.archive(ar)?;
+        if ar.is_reading() {
+            *self = if is_some { Some(T::default()) } else { None };
+        }
+        if let Some(value) = self {
+            value.archive(ar)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads `len` bytes in chunks, for the same reason as in `Vec::archive`.
+fn ar_read_bytes(ar: &mut impl Archive, len: usize, bytes: &mut Vec<u8>) -> Result<()> {
+    let mut chunk = [0u8; 4096];
+    let mut remaining = len;
+    while remaining > 0 {
+        let n = remaining.min(chunk.len());
+        ar.bytes(&mut chunk[..n])?;
+        bytes.extend_from_slice(&chunk[..n]);
+        remaining -= n;
+    }
+    Ok(())
+}
+
+// Quantization, see `custom_file_format::quantization`. Values are checked against the
+// `max_error` and `strict` params before they are written.
+
+fn archive_quantized_uint<T: Float>(
+    ar: &mut impl Archive,
+    value: &mut T,
+    quantization: u64,
+) -> Result<()> {
+    let params = *ar.params();
+    let mut quantized = quantize_uint(value.to_f64(), quantization, params.rounding).0;
+    if !ar.is_reading() {
+        check_quantization_error(*value, T::dequantize_uint(quantized, quantization), &params)?;
+    }
+    ar.varint(&mut quantized)?;
+    if ar.is_reading() {
+        *value = T::dequantize_uint(quantized, quantization);
+    }
+    Ok(())
+}
+
+fn archive_quantized_int<T: Float>(
+    ar: &mut impl Archive,
+    value: &mut T,
+    quantization: u64,
+) -> Result<()> {
+    let params = *ar.params();
+    let mut quantized = quantize_int(value.to_f64(), quantization, params.rounding).0;
+    if !ar.is_reading() {
+        check_quantization_error(*value, T::dequantize_int(quantized, quantization), &params)?;
+    }
+    ar.zigzag(&mut quantized)?;
+    if ar.is_reading() {
+        *value = T::dequantize_int(quantized, quantization);
+    }
+    Ok(())
+}
+
+fn archive_beat_and_offset(ar: &mut impl Archive, value: &mut f64) -> Result<()> {
+    let params = *ar.params();
+    let time_quantization = params.time_quantization;
+    let (beat, offset) = split_in_beat_and_offset(*value, time_quantization, params.rounding);
+    let (mut beat, mut offset) = (beat.0, offset.0);
+    if !ar.is_reading() {
+        let dequantized = f64::dequantize_beat_and_offset(beat, offset, time_quantization);
+        check_quantization_error(*value, dequantized, &params)?;
+    }
+    ar.varint(&mut beat)?;
+    ar.varint(&mut offset)?;
+    if ar.is_reading() {
+        *value = f64::dequantize_beat_and_offset(beat, offset, time_quantization);
+    }
+    Ok(())
+}
+
+// The sequence tree
+
+impl Archivable for FormatVersion {
+    fn archive(&mut self, ar: &mut impl Archive) -> Result<()> {
+        let has_magic_header = if ar.is_reading() {
+            // Legacy files have no magic header, and start with the version 0 instead.
+            let mut first = 0u8;
+            first.archive(ar)?;
+            if first == 0 {
+                *self = FormatVersion::V0;
+                return Ok(());
+            }
+            let mut magic = MAGIC;
+            magic[0] = first;
+            ar.bytes(&mut magic[1..])?;
+            if magic != MAGIC {
+                return Err(invalid_data("Invalid magic header".to_string()));
+            }
+            true
+        } else {
+            if self.has_magic_header() {
+                ar.bytes(&mut MAGIC.clone())?;
+            }
+            self.has_magic_header()
+        };
+        let mut version = self.as_i8();
+        version.archive(ar)?;
+        *self = FormatVersion::from_i8(version)
+            .filter(|version| version.has_magic_header() == has_magic_header)
+            .ok_or_else(|| invalid_data(format!("Unsupported file version {}", version)))?;
+        Ok(())
+    }
+}
+
+impl Archivable for NoteLayout {
+    fn archive(&mut self, ar: &mut impl Archive) -> Result<()> {
+        let mut layout: u8 = match self {
+            NoteLayout::Interleaved => 0,
+            NoteLayout::Columnar => 1,
+        };
+        layout.archive(ar)?;
+        *self = match layout {
+            0 => NoteLayout::Interleaved,
+            1 => NoteLayout::Columnar,
+            _ => return Err(invalid_data(format!("Invalid note layout {}", layout))),
+        };
+        Ok(())
+    }
+}
+
+/// The header of a sequence file.
+impl Archivable for Params {
+    fn archive(&mut self, ar: &mut impl Archive) -> Result<()> {
+        if !ar.is_reading()
+            && self.note_layout != NoteLayout::Interleaved
+            && !self.version.has_note_layout()
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Note layout {:?} is not supported by format version {:?}",
+                    self.note_layout, self.version
+                ),
+            ));
+        }
+        self.version.archive(ar)?;
+        for quantization in [&mut self.time_quantization, &mut self.pitch_quantization] {
+            ar.varint(quantization)?;
+            if ar.is_reading() && *quantization == 0 {
+                return Err(invalid_data("Quantization must not be zero".to_string()));
+            }
+        }
+        if self.version.has_note_layout() {
+            self.note_layout.archive(ar)?;
+        } else {
+            self.note_layout = NoteLayout::Interleaved;
+        }
+        Ok(())
+    }
+}
+
+/// Extension blocks are never written, and skipped when reading.
+fn archive_extension_blocks(ar: &mut impl Archive) -> Result<()> {
+    if !ar.params().version.has_extension_blocks() {
+        return Ok(());
+    }
+    let mut num_blocks = 0usize;
+    ar.len(&mut num_blocks)?;
+    for _ in 0..num_blocks {
+        let mut tag = 0u64;
+        ar.varint(&mut tag)?;
+        let mut len = 0usize;
+        ar.len(&mut len)?;
+        ar_read_bytes(ar, len, &mut Vec::new())?;
+    }
+    Ok(())
+}
+
+impl Archivable for Sequence {
+    fn archive(&mut self, ar: &mut impl Archive) -> Result<()> {
+        let mut params = *ar.params();
+        params.archive(ar)?;
+        *ar.params() = params;
+        self.tempo_map.archive(ar)?;
+        self.tracks.archive(ar)?;
+        archive_extension_blocks(ar)
+    }
+}
+
+impl Archivable for TempoMap {
+    fn archive(&mut self, ar: &mut impl Archive) -> Result<()> {
+        self.bpm_base.archive(ar)?;
+        let version = ar.params().version;
+        if version.has_tempo_events() {
+            self.tempo_changes.archive(ar)?;
+            self.time_signatures.archive(ar)?;
+        } else if ar.is_reading() {
+            self.tempo_changes.clear();
+            self.time_signatures.clear();
+        } else if !self.tempo_changes.is_empty() || !self.time_signatures.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Tempo changes and time signatures are not supported by format version {:?}",
+                    version
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Archivable for TempoChange {
+    fn archive(&mut self, ar: &mut impl Archive) -> Result<()> {
+        archive_beat_and_offset(ar, &mut self.beat)?;
+        self.bpm.archive(ar)
+    }
+}
+
+impl Archivable for TimeSignature {
+    fn archive(&mut self, ar: &mut impl Archive) -> Result<()> {
+        archive_beat_and_offset(ar, &mut self.beat)?;
+        self.numerator.archive(ar)?;
+        self.denominator.archive(ar)
+    }
+}
+
+impl Archivable for Track {
+    fn archive(&mut self, ar: &mut impl Archive) -> Result<()> {
+        self.name.archive(ar)?;
+        self.is_percussion.archive(ar)?;
+        self.tuning.archive(ar)?;
+        match ar.params().note_layout {
+            NoteLayout::Interleaved => self.notes.archive(ar)?,
+            NoteLayout::Columnar => archive_note_columns(&mut self.notes, ar)?,
+        }
+        archive_extension_blocks(ar)
+    }
+}
+
+impl Archivable for Tuning {
+    fn archive(&mut self, ar: &mut impl Archive) -> Result<()> {
+        self.string_base_pitches.archive(ar)
+    }
+}
+
+impl Archivable for Note {
+    fn archive(&mut self, ar: &mut impl Archive) -> Result<()> {
+        let time_quantization = ar.params().time_quantization;
+        archive_beat_and_offset(ar, &mut self.s)?;
+        archive_quantized_uint(ar, &mut self.d, time_quantization)?;
+        self.pitch.archive(ar)?;
+        self.string.archive(ar)?;
+        self.fret.archive(ar)?;
+        self.effects.archive(ar)
+    }
+}
+
+impl Archivable for NoteEffects {
+    fn archive(&mut self, ar: &mut impl Archive) -> Result<()> {
+        self.dead_note.archive(ar)?;
+        self.vibrato.archive(ar)?;
+        self.bend_data.archive(ar)
+    }
+}
+
+impl Archivable for BendData {
+    fn archive(&mut self, ar: &mut impl Archive) -> Result<()> {
+        self.points.archive(ar)
+    }
+}
+
+impl Archivable for BendPoint {
+    fn archive(&mut self, ar: &mut impl Archive) -> Result<()> {
+        let Params {
+            time_quantization,
+            pitch_quantization,
+            ..
+        } = *ar.params();
+        archive_quantized_uint(ar, &mut self.pos, time_quantization)?;
+        archive_quantized_int(ar, &mut self.bend, pitch_quantization)
+    }
+}
+
+/// The columnar note layout, see `custom_file_format::columnar`. When reading, the notes
+/// are created by the onset column and filled in by the remaining columns.
+fn archive_note_columns(notes: &mut Vec<Note>, ar: &mut impl Archive) -> Result<()> {
+    let params = *ar.params();
+    let time_quantization = params.time_quantization;
+    if time_quantization == 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "The columnar note layout requires a non-zero time quantization",
+        ));
+    }
+    let mut num_notes = notes.len();
+    ar.len(&mut num_notes)?;
+    if ar.is_reading() {
+        notes.clear();
+    }
+
+    let mut prev_onset = 0u64;
+    for i in 0..num_notes {
+        if ar.is_reading() {
+            notes.push(Note::default());
+        }
+        let note = &mut notes[i];
+        if !ar.is_reading() {
+            check_quantization_error(note.s, roundtrip_beat_and_offset(note.s, &params), &params)?;
+        }
+        let (beat, offset) = split_in_beat_and_offset(note.s, time_quantization, params.rounding);
+        let onset = beat
+            .0
+            .wrapping_mul(time_quantization)
+            .wrapping_add(offset.0);
+        let mut delta = onset.wrapping_sub(prev_onset) as i64;
+        ar.zigzag(&mut delta)?;
+        let onset = prev_onset.wrapping_add(delta as u64);
+        if ar.is_reading() {
+            let (beat, offset) = (onset / time_quantization, onset % time_quantization);
+            note.s = f64::dequantize_beat_and_offset(beat, offset, time_quantization);
+        }
+        prev_onset = onset;
+    }
+    for note in notes.iter_mut() {
+        archive_quantized_uint(ar, &mut note.d, time_quantization)?;
+    }
+    for note in notes.iter_mut() {
+        note.pitch.archive(ar)?;
+    }
+    for note in notes.iter_mut() {
+        note.string.archive(ar)?;
+    }
+    for note in notes.iter_mut() {
+        note.fret.archive(ar)?;
+    }
+    for note in notes.iter_mut() {
+        let effects = &mut note.effects;
+        let mut flags = note_flags(effects);
+        flags.archive(ar)?;
+        if flags & !NOTE_FLAGS != 0 {
+            return Err(invalid_data(format!("Invalid note flags {:#04x}", flags)));
+        }
+        if ar.is_reading() {
+            effects.dead_note = flags & FLAG_DEAD_NOTE != 0;
+            effects.vibrato = flags & FLAG_VIBRATO != 0;
+            effects.bend_data = (flags & FLAG_BEND_DATA != 0).then(BendData::default);
+        }
+    }
+    for note in notes.iter_mut() {
+        if let Some(bend_data) = &mut note.effects.bend_data {
+            bend_data.archive(ar)?;
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
     use std::io::Cursor;
 
+    use pretty_assertions::assert_eq;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::custom_file_format::serialize_sequence_to_vec;
+    use crate::synthetic::{gen_sequence, gen_sequence_with_tempo_events};
+
     use super::*;
 
     #[test]
@@ -191,4 +795,114 @@ mod test {
 
         // Conclusion: Separate serialize/deserialize methods like serde uses are just more idiomatic.
     }
+
+    fn all_params() -> Vec<Params> {
+        [
+            (FormatVersion::V0, NoteLayout::Interleaved),
+            (FormatVersion::V1, NoteLayout::Interleaved),
+            (FormatVersion::V2, NoteLayout::Interleaved),
+            (FormatVersion::V2, NoteLayout::Columnar),
+            (FormatVersion::V3, NoteLayout::Interleaved),
+            (FormatVersion::V3, NoteLayout::Columnar),
+        ]
+        .into_iter()
+        .map(|(version, note_layout)| Params {
+            version,
+            note_layout,
+            ..Params::default()
+        })
+        .collect()
+    }
+
+    fn write(sequence: &Sequence, params: Params) -> Vec<u8> {
+        let mut writer = Writer::new(Vec::new(), params);
+        sequence.clone().archive(&mut writer).unwrap();
+        writer.into_inner()
+    }
+
+    fn read(data: &[u8]) -> Result<(Sequence, Params)> {
+        let mut reader = Reader::new(data);
+        let mut sequence = Sequence::default();
+        sequence.archive(&mut reader)?;
+        Ok((sequence, *reader.params()))
+    }
+
+    #[test]
+    fn test_archive_matches_serialize_sequence() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..100 {
+            let sequence = gen_sequence(&mut rng);
+            let sequence_with_events = gen_sequence_with_tempo_events(&mut rng);
+            for params in all_params() {
+                let sequence = if params.version.has_tempo_events() {
+                    &sequence_with_events
+                } else {
+                    &sequence
+                };
+                let data = write(sequence, params);
+                assert_eq!(data, serialize_sequence_to_vec(sequence, &params).unwrap());
+
+                let (sequence_read, params_read) = read(&data).unwrap();
+                assert_eq!(sequence_read, *sequence);
+                assert_eq!(params_read.version, params.version);
+                assert_eq!(params_read.note_layout, params.note_layout);
+            }
+        }
+    }
+
+    #[test]
+    fn test_archive_writing_leaves_value_untouched() {
+        let mut sequence = gen_sequence(&mut StdRng::seed_from_u64(1));
+        let expected = sequence.clone();
+        let mut writer = Writer::new(Vec::new(), Params::default());
+        sequence.archive(&mut writer).unwrap();
+        assert_eq!(sequence, expected);
+    }
+
+    #[test]
+    fn test_archive_invalid_input() {
+        let sequence = gen_sequence(&mut StdRng::seed_from_u64(2));
+        for params in all_params() {
+            let data = write(&sequence, params);
+            for len in 0..data.len() {
+                let err = read(&data[..len]).unwrap_err();
+                assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+            }
+        }
+
+        let err = read(b"SEQX\x01").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "Invalid magic header");
+        let err = read(b"SEQF\x00").unwrap_err();
+        assert_eq!(err.to_string(), "Unsupported file version 0");
+        let err = read(b"SEQF\x02\x00").unwrap_err();
+        assert_eq!(err.to_string(), "Quantization must not be zero");
+
+        let params = Params {
+            version: FormatVersion::V1,
+            note_layout: NoteLayout::Columnar,
+            ..Params::default()
+        };
+        let mut writer = Writer::new(Vec::new(), params);
+        let err = Sequence::default().archive(&mut writer).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+        // Quantization errors are checked the same way as in `serialize_sequence`.
+        let params = Params {
+            strict: true,
+            ..Params::default()
+        };
+        let mut sequence = Sequence {
+            tracks: vec![Track::default()],
+            ..Sequence::default()
+        };
+        sequence.tracks[0].notes.push(Note {
+            d: 0.0001,
+            ..Note::default()
+        });
+        let mut writer = Writer::new(Vec::new(), params);
+        let err = sequence.archive(&mut writer).unwrap_err();
+        let expected = serialize_sequence_to_vec(&sequence, &params).unwrap_err();
+        assert_eq!(err.to_string(), expected.to_string());
+    }
 }
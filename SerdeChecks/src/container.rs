@@ -0,0 +1,714 @@
+//! A container around the custom format, which compresses every track separately, so that a
+//! single track can be read without decompressing the others.
+//!
+//! The layout, all numbers are little endian:
+//! - The magic `SEQC`, the container version (u8), the `ChunkCompression` (u8) and the
+//!   number of chunks (u32).
+//! - The table of contents, per chunk: its offset relative to the start of the container
+//!   (u64), its compressed and its uncompressed size (u32 each) and the CRC32 of its
+//!   compressed bytes (u32).
+//! - The CRC32 of everything before (u32).
+//! - The chunks. The first one holds the tempo map, each of the others one track. Every
+//!   chunk is a sequence in the custom format (see `serialize_sequence`): the tempo map
+//!   chunk without tracks, the track chunks with a single track and only the base tempo.
+
+use std::fmt;
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+
+use crate::types::{Sequence, TempoMap, Track};
+use crate::{parse_sequence, FormatError, Params, SequenceWriter, TrackHeader};
+
+pub const CONTAINER_MAGIC: [u8; 4] = *b"SEQC";
+const CONTAINER_VERSION: u8 = 1;
+
+/// The default limit of the uncompressed size of a chunk, which the table of contents could
+/// otherwise set to 4 GiB for a tiny, highly compressed chunk.
+pub const DEFAULT_MAX_CHUNK_LEN: u32 = 1 << 28;
+
+const HEADER_SIZE: usize = 10;
+const TOC_ENTRY_SIZE: usize = 20;
+
+// Compression levels, the defaults of the respective tools.
+const DEFLATE_LEVEL: u32 = 6;
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChunkCompression {
+    Deflate,
+    Zstd,
+}
+
+impl ChunkCompression {
+    fn to_u8(self) -> u8 {
+        match self {
+            ChunkCompression::Deflate => 1,
+            ChunkCompression::Zstd => 2,
+        }
+    }
+
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(ChunkCompression::Deflate),
+            2 => Some(ChunkCompression::Zstd),
+            _ => None,
+        }
+    }
+
+    fn compress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            ChunkCompression::Deflate => {
+                let level = flate2::Compression::new(DEFLATE_LEVEL);
+                let mut encoder = DeflateEncoder::new(Vec::new(), level);
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            ChunkCompression::Zstd => zstd::bulk::compress(data, ZSTD_LEVEL),
+        }
+    }
+
+    /// Decompresses at most `max_len + 1` bytes, so that the caller can detect chunks that
+    /// are larger than announced without decompressing all of them.
+    fn decompress(self, data: &[u8], max_len: usize) -> io::Result<Vec<u8>> {
+        let limit = max_len as u64 + 1;
+        let mut decompressed = Vec::new();
+        match self {
+            ChunkCompression::Deflate => {
+                DeflateDecoder::new(data)
+                    .take(limit)
+                    .read_to_end(&mut decompressed)?;
+            }
+            ChunkCompression::Zstd => {
+                zstd::stream::read::Decoder::with_buffer(data)?
+                    .take(limit)
+                    .read_to_end(&mut decompressed)?;
+            }
+        }
+        Ok(decompressed)
+    }
+}
+
+/// A part of the container, for error messages.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Chunk {
+    TableOfContents,
+    TempoMap,
+    Track(usize),
+}
+
+impl Chunk {
+    fn from_index(index: usize) -> Chunk {
+        match index {
+            0 => Chunk::TempoMap,
+            _ => Chunk::Track(index - 1),
+        }
+    }
+}
+
+impl fmt::Display for Chunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chunk::TableOfContents => write!(f, "table of contents"),
+            Chunk::TempoMap => write!(f, "tempo map chunk"),
+            Chunk::Track(index) => write!(f, "chunk of track {}", index),
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// ContainerError
+// ----------------------------------------------------------------------------
+
+#[derive(Debug)]
+pub enum ContainerError {
+    Io(io::Error),
+    /// The input does not start with the magic `SEQC`.
+    InvalidHeader,
+    UnsupportedVersion {
+        version: u8,
+    },
+    UnknownCompression {
+        value: u8,
+    },
+    /// The CRC32 of the stored bytes does not match, i.e., the container is corrupt.
+    ChecksumMismatch {
+        chunk: Chunk,
+        expected: u32,
+        actual: u32,
+    },
+    /// A chunk that is not within the container.
+    OutOfBounds {
+        chunk: Chunk,
+    },
+    /// A chunk whose uncompressed size exceeds the limit of the reader.
+    ChunkTooLarge {
+        chunk: Chunk,
+        len: u32,
+        max: u32,
+    },
+    /// A chunk that fails to decompress, or whose size differs from the table of contents.
+    Decompression {
+        chunk: Chunk,
+        error: io::Error,
+    },
+    /// A chunk that is not in the custom format.
+    Format {
+        chunk: Chunk,
+        error: FormatError,
+    },
+    /// A track chunk without exactly one track, or a tempo map chunk with tracks.
+    UnexpectedNumTracks {
+        chunk: Chunk,
+        num_tracks: usize,
+    },
+    TrackOutOfRange {
+        index: usize,
+        num_tracks: usize,
+    },
+}
+
+impl fmt::Display for ContainerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContainerError::Io(err) => write!(f, "I/O error: {}", err),
+            ContainerError::InvalidHeader => write!(f, "Missing SEQC header"),
+            ContainerError::UnsupportedVersion { version } => {
+                write!(f, "Unsupported container version {}", version)
+            }
+            ContainerError::UnknownCompression { value } => {
+                write!(f, "Unknown chunk compression {}", value)
+            }
+            ContainerError::ChecksumMismatch {
+                chunk,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "CRC32 mismatch in the {}: expected {:#010x}, got {:#010x}",
+                chunk, expected, actual
+            ),
+            ContainerError::OutOfBounds { chunk } => {
+                write!(f, "The {} exceeds the end of the container", chunk)
+            }
+            ContainerError::ChunkTooLarge { chunk, len, max } => write!(
+                f,
+                "The {} of {} bytes exceeds the maximum of {} bytes",
+                chunk, len, max
+            ),
+            ContainerError::Decompression { chunk, error } => {
+                write!(f, "Failed to decompress the {}: {}", chunk, error)
+            }
+            ContainerError::Format { chunk, error } => write!(f, "Invalid {}: {}", chunk, error),
+            ContainerError::UnexpectedNumTracks { chunk, num_tracks } => {
+                write!(f, "The {} holds {} tracks", chunk, num_tracks)
+            }
+            ContainerError::TrackOutOfRange { index, num_tracks } => write!(
+                f,
+                "Track {} is out of range for {} tracks",
+                index, num_tracks
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ContainerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ContainerError::Io(err) | ContainerError::Decompression { error: err, .. } => Some(err),
+            ContainerError::Format { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ContainerError {
+    fn from(err: io::Error) -> Self {
+        ContainerError::Io(err)
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Writing
+// ----------------------------------------------------------------------------
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn to_u32(len: usize, chunk: Chunk) -> io::Result<u32> {
+    u32::try_from(len).map_err(|_| invalid_input(format!("The {} exceeds 4 GiB", chunk)))
+}
+
+fn encode_track(track: &Track, bpm_base: f64, params: &Params) -> io::Result<Vec<u8>> {
+    let tempo_map = TempoMap::new(bpm_base);
+    let mut wr = SequenceWriter::new(Vec::new(), params, &tempo_map, 1)?;
+    wr.begin_track(&TrackHeader {
+        name: track.name.clone(),
+        is_percussion: track.is_percussion,
+        tuning: track.tuning.clone(),
+        num_notes: track.notes.len(),
+    })?;
+    for note in &track.notes {
+        wr.write_note(note)?;
+    }
+    wr.finish()
+}
+
+/// Writes the sequence in the custom format with `params`, one chunk per track.
+pub fn write_container<W: Write>(
+    sequence: &Sequence,
+    mut wr: W,
+    params: &Params,
+    compression: ChunkCompression,
+) -> io::Result<()> {
+    let tempo_map = &sequence.tempo_map;
+    let mut chunks = vec![SequenceWriter::new(Vec::new(), params, tempo_map, 0)?.finish()?];
+    for track in &sequence.tracks {
+        chunks.push(encode_track(track, tempo_map.bpm_base, params)?);
+    }
+
+    let num_chunks = to_u32(chunks.len(), Chunk::TableOfContents)?;
+    let header_len = HEADER_SIZE + chunks.len() * TOC_ENTRY_SIZE + 4;
+    let mut header = Vec::with_capacity(header_len);
+    header.extend_from_slice(&CONTAINER_MAGIC);
+    header.push(CONTAINER_VERSION);
+    header.push(compression.to_u8());
+    header.extend_from_slice(&num_chunks.to_le_bytes());
+    let mut offset = header_len as u64;
+    let mut compressed_chunks = Vec::with_capacity(chunks.len());
+    for (index, chunk) in chunks.iter().enumerate() {
+        let compressed = compression.compress(chunk)?;
+        let id = Chunk::from_index(index);
+        header.extend_from_slice(&offset.to_le_bytes());
+        header.extend_from_slice(&to_u32(compressed.len(), id)?.to_le_bytes());
+        header.extend_from_slice(&to_u32(chunk.len(), id)?.to_le_bytes());
+        header.extend_from_slice(&crc32fast::hash(&compressed).to_le_bytes());
+        offset += compressed.len() as u64;
+        compressed_chunks.push(compressed);
+    }
+    let crc = crc32fast::hash(&header);
+    header.extend_from_slice(&crc.to_le_bytes());
+
+    wr.write_all(&header)?;
+    for compressed in &compressed_chunks {
+        wr.write_all(compressed)?;
+    }
+    wr.flush()
+}
+
+pub fn write_container_to_vec(
+    sequence: &Sequence,
+    params: &Params,
+    compression: ChunkCompression,
+) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    write_container(sequence, &mut buf, params, compression)?;
+    Ok(buf)
+}
+
+// ----------------------------------------------------------------------------
+// Reading
+// ----------------------------------------------------------------------------
+
+#[derive(Copy, Clone, Debug)]
+struct TocEntry {
+    offset: u64,
+    compressed_len: u32,
+    len: u32,
+    crc: u32,
+}
+
+/// Random access to the tracks of a container. Only the table of contents is read up front,
+/// every track is read (and checked) when it is requested.
+pub struct ContainerReader<R> {
+    rd: R,
+    compression: ChunkCompression,
+    toc: Vec<TocEntry>,
+    max_chunk_len: u32,
+}
+
+fn u32_at(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap())
+}
+
+impl<R: Read + Seek> ContainerReader<R> {
+    /// Reads and checks the header and the table of contents, with `DEFAULT_MAX_CHUNK_LEN`.
+    pub fn new(rd: R) -> Result<Self, ContainerError> {
+        Self::with_max_chunk_len(rd, DEFAULT_MAX_CHUNK_LEN)
+    }
+
+    /// Like `new`, chunks with an uncompressed size above `max_chunk_len` fail to read.
+    pub fn with_max_chunk_len(mut rd: R, max_chunk_len: u32) -> Result<Self, ContainerError> {
+        let container_len = rd.seek(SeekFrom::End(0))?;
+        rd.seek(SeekFrom::Start(0))?;
+        let mut header = [0; HEADER_SIZE];
+        if container_len < HEADER_SIZE as u64 {
+            return Err(ContainerError::InvalidHeader);
+        }
+        rd.read_exact(&mut header)?;
+        if header[..4] != CONTAINER_MAGIC {
+            return Err(ContainerError::InvalidHeader);
+        }
+        if header[4] != CONTAINER_VERSION {
+            return Err(ContainerError::UnsupportedVersion { version: header[4] });
+        }
+        let compression = ChunkCompression::from_u8(header[5])
+            .ok_or(ContainerError::UnknownCompression { value: header[5] })?;
+        let num_chunks = u32_at(&header, 6) as usize;
+        let toc_len = num_chunks as u64 * TOC_ENTRY_SIZE as u64 + 4;
+        if HEADER_SIZE as u64 + toc_len > container_len {
+            return Err(ContainerError::OutOfBounds {
+                chunk: Chunk::TableOfContents,
+            });
+        }
+        let mut toc_data = vec![0; toc_len as usize];
+        rd.read_exact(&mut toc_data)?;
+        let (toc_data, crc) = toc_data.split_at(toc_data.len() - 4);
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&header);
+        hasher.update(toc_data);
+        check_crc(Chunk::TableOfContents, u32_at(crc, 0), hasher.finalize())?;
+
+        let mut toc = Vec::with_capacity(num_chunks);
+        for (index, entry) in toc_data.chunks_exact(TOC_ENTRY_SIZE).enumerate() {
+            let entry = TocEntry {
+                offset: u64::from_le_bytes(entry[..8].try_into().unwrap()),
+                compressed_len: u32_at(entry, 8),
+                len: u32_at(entry, 12),
+                crc: u32_at(entry, 16),
+            };
+            let end = entry.offset.checked_add(entry.compressed_len as u64);
+            if end.is_none_or(|end| end > container_len) {
+                return Err(ContainerError::OutOfBounds {
+                    chunk: Chunk::from_index(index),
+                });
+            }
+            toc.push(entry);
+        }
+        if toc.is_empty() {
+            return Err(ContainerError::OutOfBounds {
+                chunk: Chunk::TempoMap,
+            });
+        }
+        Ok(ContainerReader {
+            rd,
+            compression,
+            toc,
+            max_chunk_len,
+        })
+    }
+
+    pub fn compression(&self) -> ChunkCompression {
+        self.compression
+    }
+
+    pub fn num_tracks(&self) -> usize {
+        self.toc.len() - 1
+    }
+
+    /// Reads, checks, decompresses and parses a chunk.
+    fn read_chunk(&mut self, index: usize) -> Result<Sequence, ContainerError> {
+        let entry = self.toc[index];
+        let chunk = Chunk::from_index(index);
+        if entry.len > self.max_chunk_len {
+            return Err(ContainerError::ChunkTooLarge {
+                chunk,
+                len: entry.len,
+                max: self.max_chunk_len,
+            });
+        }
+        self.rd.seek(SeekFrom::Start(entry.offset))?;
+        let mut compressed = vec![0; entry.compressed_len as usize];
+        self.rd.read_exact(&mut compressed)?;
+        check_crc(chunk, entry.crc, crc32fast::hash(&compressed))?;
+
+        let decompressed = self
+            .compression
+            .decompress(&compressed, entry.len as usize)
+            .map_err(|error| ContainerError::Decompression { chunk, error })?;
+        if decompressed.len() != entry.len as usize {
+            let message = if decompressed.len() > entry.len as usize {
+                format!("Expected {} bytes, got more", entry.len)
+            } else {
+                format!("Expected {} bytes, got {}", entry.len, decompressed.len())
+            };
+            return Err(ContainerError::Decompression {
+                chunk,
+                error: io::Error::new(io::ErrorKind::InvalidData, message),
+            });
+        }
+        parse_sequence(&decompressed).map_err(|error| ContainerError::Format { chunk, error })
+    }
+
+    pub fn tempo_map(&mut self) -> Result<TempoMap, ContainerError> {
+        let sequence = self.read_chunk(0)?;
+        check_num_tracks(Chunk::TempoMap, &sequence, 0)?;
+        Ok(sequence.tempo_map)
+    }
+
+    /// Reads a single track, without reading the chunks of the other tracks.
+    pub fn read_track(&mut self, index: usize) -> Result<Track, ContainerError> {
+        let num_tracks = self.num_tracks();
+        if index >= num_tracks {
+            return Err(ContainerError::TrackOutOfRange { index, num_tracks });
+        }
+        let mut sequence = self.read_chunk(index + 1)?;
+        check_num_tracks(Chunk::Track(index), &sequence, 1)?;
+        Ok(sequence.tracks.pop().expect("one track"))
+    }
+
+    pub fn read_sequence(&mut self) -> Result<Sequence, ContainerError> {
+        Ok(Sequence {
+            tempo_map: self.tempo_map()?,
+            tracks: (0..self.num_tracks())
+                .map(|index| self.read_track(index))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+fn check_crc(chunk: Chunk, expected: u32, actual: u32) -> Result<(), ContainerError> {
+    if expected != actual {
+        return Err(ContainerError::ChecksumMismatch {
+            chunk,
+            expected,
+            actual,
+        });
+    }
+    Ok(())
+}
+
+fn check_num_tracks(
+    chunk: Chunk,
+    sequence: &Sequence,
+    expected: usize,
+) -> Result<(), ContainerError> {
+    let num_tracks = sequence.tracks.len();
+    if num_tracks != expected {
+        return Err(ContainerError::UnexpectedNumTracks { chunk, num_tracks });
+    }
+    Ok(())
+}
+
+pub fn read_container(data: &[u8]) -> Result<Sequence, ContainerError> {
+    ContainerReader::new(Cursor::new(data))?.read_sequence()
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::serialize_sequence_to_vec;
+    use crate::synthetic::{gen_sequence_with_tempo_events, gen_sized_sequence};
+
+    use super::*;
+
+    const COMPRESSIONS: [ChunkCompression; 2] = [ChunkCompression::Deflate, ChunkCompression::Zstd];
+
+    fn sequence() -> Sequence {
+        let mut rng = StdRng::seed_from_u64(0);
+        Sequence {
+            tempo_map: gen_sequence_with_tempo_events(&mut rng).tempo_map,
+            ..gen_sized_sequence(&mut rng, 4, 200)
+        }
+    }
+
+    /// The sequence after a round trip through the custom format.
+    fn quantized(sequence: &Sequence) -> Sequence {
+        parse_sequence(&serialize_sequence_to_vec(sequence, &Params::default()).unwrap()).unwrap()
+    }
+
+    fn reader(data: &[u8]) -> ContainerReader<Cursor<&[u8]>> {
+        ContainerReader::new(Cursor::new(data)).unwrap()
+    }
+
+    /// The offset of a chunk, from the table of contents.
+    fn chunk_offset(data: &[u8], index: usize) -> usize {
+        let pos = HEADER_SIZE + index * TOC_ENTRY_SIZE;
+        u64::from_le_bytes(data[pos..pos + 8].try_into().unwrap()) as usize
+    }
+
+    /// Replaces a field of the table of contents, keeping its CRC32 valid.
+    fn patch_toc(data: &mut [u8], pos: usize, value: u32) {
+        data[pos..pos + 4].copy_from_slice(&value.to_le_bytes());
+        let toc_end = HEADER_SIZE + (u32_at(data, 6) as usize) * TOC_ENTRY_SIZE;
+        let crc = crc32fast::hash(&data[..toc_end]);
+        data[toc_end..toc_end + 4].copy_from_slice(&crc.to_le_bytes());
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let sequence = sequence();
+        for compression in COMPRESSIONS {
+            let data = write_container_to_vec(&sequence, &Params::default(), compression).unwrap();
+            assert_eq!(&data[..4], b"SEQC");
+            assert_eq!(read_container(&data).unwrap(), quantized(&sequence));
+
+            let empty =
+                write_container_to_vec(&Sequence::default(), &Params::default(), compression)
+                    .unwrap();
+            assert_eq!(read_container(&empty).unwrap(), Sequence::default());
+        }
+    }
+
+    #[test]
+    fn test_read_track() {
+        let sequence = sequence();
+        let expected = quantized(&sequence);
+        let data =
+            write_container_to_vec(&sequence, &Params::default(), ChunkCompression::Zstd).unwrap();
+        let mut reader = reader(&data);
+        assert_eq!(reader.compression(), ChunkCompression::Zstd);
+        assert_eq!(reader.num_tracks(), 4);
+        assert_eq!(reader.read_track(2).unwrap(), expected.tracks[2]);
+        assert_eq!(reader.read_track(0).unwrap(), expected.tracks[0]);
+        assert_eq!(reader.tempo_map().unwrap(), expected.tempo_map);
+        assert_eq!(
+            reader.read_track(4).unwrap_err().to_string(),
+            "Track 4 is out of range for 4 tracks"
+        );
+    }
+
+    #[test]
+    fn test_corrupted_chunks() {
+        let sequence = sequence();
+        let expected = quantized(&sequence);
+        for compression in COMPRESSIONS {
+            let mut data =
+                write_container_to_vec(&sequence, &Params::default(), compression).unwrap();
+            // corrupts the chunk of track 1
+            let pos = chunk_offset(&data, 2) + 3;
+            data[pos] ^= 0x10;
+            let mut reader = reader(&data);
+            let err = reader.read_track(1).unwrap_err();
+            assert!(
+                matches!(
+                    err,
+                    ContainerError::ChecksumMismatch {
+                        chunk: Chunk::Track(1),
+                        ..
+                    }
+                ),
+                "{:?}",
+                err
+            );
+            assert!(err
+                .to_string()
+                .starts_with("CRC32 mismatch in the chunk of track 1"));
+            // the other tracks are still readable
+            assert_eq!(reader.read_track(0).unwrap(), expected.tracks[0]);
+            assert_eq!(reader.read_track(2).unwrap(), expected.tracks[2]);
+            assert!(read_container(&data).is_err());
+        }
+    }
+
+    #[test]
+    fn test_corrupted_table_of_contents() {
+        let data = write_container_to_vec(&sequence(), &Params::default(), ChunkCompression::Zstd)
+            .unwrap();
+        for pos in 0..chunk_offset(&data, 0) {
+            let mut corrupted = data.clone();
+            corrupted[pos] ^= 0x01;
+            // the magic, version and compression are reported as such
+            let err = ContainerReader::new(Cursor::new(&corrupted[..]))
+                .err()
+                .unwrap();
+            match pos {
+                0..=3 => assert!(matches!(err, ContainerError::InvalidHeader)),
+                4 => assert!(matches!(
+                    err,
+                    ContainerError::UnsupportedVersion { version: 0 }
+                )),
+                5 => assert!(matches!(
+                    err,
+                    ContainerError::UnknownCompression { value: 3 }
+                )),
+                // a different number of chunks does not fit the CRC32 or the container
+                _ => assert!(
+                    matches!(
+                        err,
+                        ContainerError::ChecksumMismatch {
+                            chunk: Chunk::TableOfContents,
+                            ..
+                        } | ContainerError::OutOfBounds {
+                            chunk: Chunk::TableOfContents
+                        }
+                    ),
+                    "{}: {:?}",
+                    pos,
+                    err
+                ),
+            }
+        }
+        for len in 0..data.len() {
+            assert!(read_container(&data[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_invalid_sizes() {
+        let data =
+            write_container_to_vec(&sequence(), &Params::default(), ChunkCompression::Deflate)
+                .unwrap();
+        let entry = HEADER_SIZE + TOC_ENTRY_SIZE;
+
+        // a chunk that is larger than announced
+        let mut invalid = data.clone();
+        let len = u32_at(&data, entry + 12);
+        patch_toc(&mut invalid, entry + 12, len - 1);
+        assert_eq!(
+            reader(&invalid).read_track(0).unwrap_err().to_string(),
+            format!(
+                "Failed to decompress the chunk of track 0: Expected {} bytes, got more",
+                len - 1
+            )
+        );
+
+        let mut invalid = data.clone();
+        patch_toc(&mut invalid, entry + 8, u32::MAX);
+        assert!(matches!(
+            ContainerReader::new(Cursor::new(&invalid[..]))
+                .err()
+                .unwrap(),
+            ContainerError::OutOfBounds {
+                chunk: Chunk::Track(0)
+            }
+        ));
+
+        // a chunk that could expand to 4 GiB is not decompressed at all
+        let mut invalid = data.clone();
+        patch_toc(&mut invalid, entry + 12, u32::MAX);
+        assert_eq!(
+            reader(&invalid).read_track(0).unwrap_err().to_string(),
+            format!(
+                "The chunk of track 0 of {} bytes exceeds the maximum of {} bytes",
+                u32::MAX,
+                DEFAULT_MAX_CHUNK_LEN
+            )
+        );
+        let mut limited =
+            ContainerReader::with_max_chunk_len(Cursor::new(&data[..]), len - 1).unwrap();
+        assert!(matches!(
+            limited.read_track(0).unwrap_err(),
+            ContainerError::ChunkTooLarge { max, .. } if max == len - 1
+        ));
+
+        // the tempo map chunk in place of a track, i.e., the same table of contents entry
+        let mut invalid = data;
+        for field in [0, 8, 12, 16] {
+            let value = u32_at(&invalid, HEADER_SIZE + field);
+            patch_toc(&mut invalid, entry + field, value);
+        }
+        assert!(matches!(
+            reader(&invalid).read_track(0).unwrap_err(),
+            ContainerError::UnexpectedNumTracks {
+                chunk: Chunk::Track(0),
+                num_tracks: 0
+            }
+        ));
+    }
+}
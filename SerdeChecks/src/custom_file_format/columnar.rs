@@ -0,0 +1,335 @@
+//! Columnar note layout (available since format v2).
+//!
+//! Instead of writing the notes of a track one after another, the columnar layout stores
+//! all onsets, then all durations, pitches, strings, frets, and finally the effects. The
+//! onsets are stored as the (zig-zag encoded) difference of their quantized time to the
+//! onset of the previous note. For typical sorted notes these deltas are small, i.e., they
+//! fit into one or two varint bytes, and the homogeneous columns are easier to compress
+//! for a downstream compressor.
+
+use std::io::{Error, ErrorKind, Result, Write};
+
+use crate::types::{BendData, Note, NoteEffects};
+
+use super::deserialize_fundamentals::{parse_count, parse_u8, take_bytes};
+use super::errors::{fail, field, with_path_segment, FormatError, ParseResult, PathSegment};
+use super::parse::Parse;
+use super::quantization::{
+    check_quantization_error, quantize_uint, roundtrip_beat_and_offset, split_in_beat_and_offset,
+    Float,
+};
+use super::serialize::Serialize;
+use super::serialize_types::Params;
+use super::varint::{parse_int, parse_uint, Int, Uint};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NoteLayout {
+    /// All fields of a note are stored consecutively (the only layout before v2).
+    Interleaved,
+    /// Each track stores its notes as columns with delta encoded onsets.
+    Columnar,
+}
+
+impl<C> Serialize<C> for NoteLayout {
+    fn serialize_into<W>(&self, wr: &mut W, context: &C) -> Result<()>
+    where
+        W: Write,
+    {
+        let layout: u8 = match self {
+            NoteLayout::Interleaved => 0,
+            NoteLayout::Columnar => 1,
+        };
+        layout.serialize_into(wr, context)
+    }
+}
+
+pub fn parse_note_layout(input: &[u8]) -> ParseResult<'_, NoteLayout> {
+    match parse_u8(input)? {
+        (rest, 0) => Ok((rest, NoteLayout::Interleaved)),
+        (rest, 1) => Ok((rest, NoteLayout::Columnar)),
+        (_, value) => fail(input, |location| FormatError::InvalidNoteLayout {
+            value,
+            location,
+        }),
+    }
+}
+
+// The flags are shared with the columnar layout of `cereal_like`.
+pub(crate) const FLAG_DEAD_NOTE: u8 = 1 << 0;
+pub(crate) const FLAG_VIBRATO: u8 = 1 << 1;
+pub(crate) const FLAG_BEND_DATA: u8 = 1 << 2;
+pub(crate) const NOTE_FLAGS: u8 = FLAG_DEAD_NOTE | FLAG_VIBRATO | FLAG_BEND_DATA;
+
+/// The flags of the effects, the bend data itself is stored separately.
+pub(crate) fn note_flags(effects: &NoteEffects) -> u8 {
+    let mut flags = 0u8;
+    if effects.dead_note {
+        flags |= FLAG_DEAD_NOTE;
+    }
+    if effects.vibrato {
+        flags |= FLAG_VIBRATO;
+    }
+    if effects.bend_data.is_some() {
+        flags |= FLAG_BEND_DATA;
+    }
+    flags
+}
+
+/// Onsets in units of the time quantization. Note that the wrapping arithmetic is only
+/// relevant for onsets beyond ~10^16 beats, but it keeps the encoding total.
+fn quantized_onset(note: &Note, params: &Params) -> u64 {
+    let time_quantization = params.time_quantization;
+    let (beat, offset) = split_in_beat_and_offset(note.s, time_quantization, params.rounding);
+    beat.0
+        .wrapping_mul(time_quantization)
+        .wrapping_add(offset.0)
+}
+
+pub fn serialize_notes_columnar<W>(notes: &[Note], wr: &mut W, context: &Params) -> Result<()>
+where
+    W: Write,
+{
+    Uint(notes.len() as u64).serialize_into(wr, context)?;
+    serialize_note_columns(notes, wr, context)
+}
+
+/// Serializes the columns without the preceding number of notes.
+pub fn serialize_note_columns<W>(notes: &[Note], wr: &mut W, context: &Params) -> Result<()>
+where
+    W: Write,
+{
+    if context.time_quantization == 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "The columnar note layout requires a non-zero time quantization",
+        ));
+    }
+
+    let mut prev_onset = 0u64;
+    for note in notes {
+        check_quantization_error(note.s, roundtrip_beat_and_offset(note.s, context), context)?;
+        let onset = quantized_onset(note, context);
+        Int(onset.wrapping_sub(prev_onset) as i64).serialize_into(wr, context)?;
+        prev_onset = onset;
+    }
+    for note in notes {
+        let time_quantization = context.time_quantization;
+        let duration = quantize_uint(note.d, time_quantization, context.rounding);
+        check_quantization_error(
+            note.d,
+            f64::dequantize_uint(duration.0, time_quantization),
+            context,
+        )?;
+        duration.serialize_into(wr, context)?;
+    }
+    for note in notes {
+        note.pitch.serialize_into(wr, context)?;
+    }
+    for note in notes {
+        note.string.serialize_into(wr, context)?;
+    }
+    for note in notes {
+        note.fret.serialize_into(wr, context)?;
+    }
+    for note in notes {
+        note_flags(&note.effects).serialize_into(wr, context)?;
+    }
+    for note in notes {
+        if let Some(bend_data) = &note.effects.bend_data {
+            bend_data.serialize_into(wr, context)?;
+        }
+    }
+    Ok(())
+}
+
+/// Parses the columns of `num_notes` notes, i.e., without the preceding number of notes.
+///
+/// Errors in the columns are reported with the column as path, e.g. `durations[3]`, errors
+/// in the bend data with the path of the note, e.g. `[3].effects.bend_data`.
+pub fn parse_note_columns(
+    params: Params,
+    num_notes: usize,
+) -> impl Fn(&[u8]) -> ParseResult<'_, Vec<Note>> {
+    let time_quantization = params.time_quantization;
+    move |input: &[u8]| {
+        if time_quantization == 0 {
+            return fail(input, |location| FormatError::QuantizationZero { location });
+        }
+        let (input, onset_deltas) = field("onsets", parse_count(parse_int, num_notes))(input)?;
+        let (input, durations) = field("durations", parse_count(parse_uint, num_notes))(input)?;
+        let (input, pitches) = field("pitches", take_bytes(num_notes))(input)?;
+        let (input, strings) = field("strings", take_bytes(num_notes))(input)?;
+        let (input, frets) = field("frets", take_bytes(num_notes))(input)?;
+        let flags_input = input;
+        let (mut input, flags) = field("flags", take_bytes(num_notes))(input)?;
+
+        let mut notes = Vec::with_capacity(num_notes);
+        let mut onset = 0u64;
+        for i in 0..num_notes {
+            if flags[i] & !NOTE_FLAGS != 0 {
+                let value = flags[i];
+                let invalid_flags = |_| {
+                    fail(&flags_input[i..], |location| {
+                        FormatError::InvalidNoteFlags { value, location }
+                    })
+                };
+                return field(
+                    "flags",
+                    with_path_segment(PathSegment::Index(i), invalid_flags),
+                )(input);
+            }
+            let bend_data = if flags[i] & FLAG_BEND_DATA != 0 {
+                let (rest, bend_data) = with_path_segment(
+                    PathSegment::Index(i),
+                    field(
+                        "effects",
+                        field("bend_data", |input| BendData::parse(input, &params)),
+                    ),
+                )(input)?;
+                input = rest;
+                Some(bend_data)
+            } else {
+                None
+            };
+
+            onset = onset.wrapping_add(onset_deltas[i] as u64);
+            let beat = onset / time_quantization;
+            let offset = onset % time_quantization;
+            notes.push(Note {
+                s: beat as f64 + offset as f64 / time_quantization as f64,
+                d: durations[i] as f64 / time_quantization as f64,
+                pitch: pitches[i],
+                string: strings[i],
+                fret: frets[i],
+                effects: NoteEffects {
+                    dead_note: flags[i] & FLAG_DEAD_NOTE != 0,
+                    vibrato: flags[i] & FLAG_VIBRATO != 0,
+                    bend_data,
+                },
+            });
+        }
+        Ok((input, notes))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use crate::custom_file_format::FormatVersion;
+    use crate::types::BendPoint;
+
+    use super::super::deserialize_fundamentals::parse_len;
+    use super::*;
+
+    fn note(s: f64, pitch: u8, effects: NoteEffects) -> Note {
+        Note {
+            s,
+            d: 0.5,
+            pitch,
+            string: 1,
+            fret: pitch - 40,
+            effects,
+        }
+    }
+
+    fn params() -> Params {
+        Params {
+            time_quantization: 4,
+            pitch_quantization: 2,
+            version: FormatVersion::V2,
+            note_layout: NoteLayout::Columnar,
+            ..Params::default()
+        }
+    }
+
+    fn parse_notes_columnar(params: Params) -> impl Fn(&[u8]) -> ParseResult<'_, Vec<Note>> {
+        move |input: &[u8]| {
+            let (input, num_notes) = parse_len(input)?;
+            parse_note_columns(params, num_notes)(input)
+        }
+    }
+
+    fn serialize(notes: &[Note], params: &Params) -> Vec<u8> {
+        let mut data = vec![];
+        serialize_notes_columnar(notes, &mut data, params).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_columnar_bytes() {
+        let notes = [
+            note(1.0, 50, NoteEffects::default()),
+            note(
+                1.25,
+                52,
+                NoteEffects {
+                    dead_note: true,
+                    vibrato: false,
+                    bend_data: None,
+                },
+            ),
+            // notes don't have to be sorted
+            note(
+                0.5,
+                54,
+                NoteEffects {
+                    dead_note: false,
+                    vibrato: true,
+                    bend_data: Some(BendData {
+                        points: vec![BendPoint {
+                            pos: 0.5,
+                            bend: 1.0,
+                        }],
+                    }),
+                },
+            ),
+        ];
+        let data = serialize(&notes, &params());
+        #[rustfmt::skip]
+        let expected = [
+            3,                  // number of notes
+            8, 2, 5,            // zig-zag encoded onset deltas: +4, +1, -3
+            2, 2, 2,            // durations
+            50, 52, 54,         // pitches
+            1, 1, 1,            // strings
+            10, 12, 14,         // frets
+            0, 1, 6,            // flags
+            1, 2, 4,            // bend data of the third note
+        ];
+        assert_eq!(data, expected);
+
+        let (rest, parsed) = parse_notes_columnar(params())(&data).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(parsed, notes);
+    }
+
+    #[test]
+    fn test_columnar_offset_rounding_to_next_beat() {
+        // The offset gets rounded up to a full beat, which the interleaved layout stores
+        // as an offset equal to the time quantization.
+        let notes = [note(2.99, 60, NoteEffects::default())];
+        let data = serialize(&notes, &params());
+        let (_, parsed) = parse_notes_columnar(params())(&data).unwrap();
+        assert_eq!(parsed[0].s, 3.0);
+    }
+
+    #[test]
+    fn test_columnar_invalid_input() {
+        // zero time quantization
+        let zero_params = Params {
+            time_quantization: 0,
+            ..params()
+        };
+        assert!(serialize_notes_columnar(&[], &mut vec![], &zero_params).is_err());
+        assert!(parse_notes_columnar(zero_params)(&[0]).is_err());
+        // too many notes for the input
+        assert!(parse_notes_columnar(params())(&[2, 0, 0, 0, 0, 0]).is_err());
+        // unknown flag
+        assert!(parse_notes_columnar(params())(&[1, 0, 0, 60, 0, 20, 8]).is_err());
+        // truncated columns
+        assert!(parse_notes_columnar(params())(&[1, 0, 0, 60, 0, 20]).is_err());
+        // unknown layout
+        assert!(parse_note_layout(&[2]).is_err());
+    }
+}
@@ -0,0 +1,320 @@
+//! Everything the code generated by `serde_checks_derive` refers to. The field encodings
+//! are implemented here as functions, so that the generated code stays small.
+
+use std::io::{Error, ErrorKind, Result, Write};
+
+pub use super::errors::{field, ParseResult};
+pub use super::parse::Parse;
+pub use super::serialize::Serialize;
+pub use super::serialize_types::Params;
+
+use super::deserialize_fundamentals::parse_option;
+use super::errors::{fail, FormatError};
+use super::quantization::{
+    check_quantization_error, quantize_int, quantize_uint, split_in_beat_and_offset, Float,
+};
+use super::varint::{parse_uint, zig_zag_decode, Int, Uint};
+
+#[derive(Copy, Clone, Debug)]
+pub enum Quantization {
+    Time,
+    Pitch,
+}
+
+impl Quantization {
+    fn of(self, params: &Params) -> u64 {
+        match self {
+            Quantization::Time => params.time_quantization,
+            Quantization::Pitch => params.pitch_quantization,
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Varints
+// ----------------------------------------------------------------------------
+
+pub fn serialize_varint<T, W>(value: T, wr: &mut W, context: &Params) -> Result<()>
+where
+    T: TryInto<u64>,
+    W: Write,
+{
+    let value = value
+        .try_into()
+        .map_err(|_| Error::new(ErrorKind::InvalidInput, "Value does not fit into u64"))?;
+    Uint(value).serialize_into(wr, context)
+}
+
+pub fn parse_varint<'a, T>(input: &'a [u8], _context: &Params) -> ParseResult<'a, T>
+where
+    T: TryFrom<u64>,
+{
+    let (rest, value) = parse_uint(input)?;
+    match T::try_from(value) {
+        Ok(value) => Ok((rest, value)),
+        Err(_) => fail(input, |location| FormatError::VarintOverflow {
+            value,
+            location,
+        }),
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Zig-zag varints
+// ----------------------------------------------------------------------------
+
+// Only the tests use `#[zigzag]` without `#[quantize]` so far.
+
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn serialize_zigzag<T, W>(value: T, wr: &mut W, context: &Params) -> Result<()>
+where
+    T: TryInto<i64>,
+    W: Write,
+{
+    let value = value
+        .try_into()
+        .map_err(|_| Error::new(ErrorKind::InvalidInput, "Value does not fit into i64"))?;
+    Int(value).serialize_into(wr, context)
+}
+
+/// An overflow is reported with the zig-zag encoded value.
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn parse_zigzag<'a, T>(input: &'a [u8], _context: &Params) -> ParseResult<'a, T>
+where
+    T: TryFrom<i64>,
+{
+    let (rest, value) = parse_uint(input)?;
+    match T::try_from(zig_zag_decode(value)) {
+        Ok(decoded) => Ok((rest, decoded)),
+        Err(_) => fail(input, |location| FormatError::VarintOverflow {
+            value,
+            location,
+        }),
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Quantization
+// ----------------------------------------------------------------------------
+
+pub fn serialize_quantized_uint<T, W>(
+    value: T,
+    quantization: Quantization,
+    wr: &mut W,
+    context: &Params,
+) -> Result<()>
+where
+    T: Float,
+    W: Write,
+{
+    let quantization = quantization.of(context);
+    let quantized = quantize_uint(value.to_f64(), quantization, context.rounding);
+    check_quantization_error(
+        value,
+        T::dequantize_uint(quantized.0, quantization),
+        context,
+    )?;
+    quantized.serialize_into(wr, context)
+}
+
+pub fn parse_quantized_uint<'a, T>(
+    input: &'a [u8],
+    quantization: Quantization,
+    context: &Params,
+) -> ParseResult<'a, T>
+where
+    T: Float,
+{
+    let (input, value) = parse_uint(input)?;
+    Ok((input, T::dequantize_uint(value, quantization.of(context))))
+}
+
+pub fn serialize_quantized_int<T, W>(
+    value: T,
+    quantization: Quantization,
+    wr: &mut W,
+    context: &Params,
+) -> Result<()>
+where
+    T: Float,
+    W: Write,
+{
+    let quantization = quantization.of(context);
+    let quantized = quantize_int(value.to_f64(), quantization, context.rounding);
+    check_quantization_error(value, T::dequantize_int(quantized.0, quantization), context)?;
+    quantized.serialize_into(wr, context)
+}
+
+pub fn parse_quantized_int<'a, T>(
+    input: &'a [u8],
+    quantization: Quantization,
+    context: &Params,
+) -> ParseResult<'a, T>
+where
+    T: Float,
+{
+    let (input, value) = parse_uint(input)?;
+    Ok((
+        input,
+        T::dequantize_int(zig_zag_decode(value), quantization.of(context)),
+    ))
+}
+
+pub fn serialize_beat_and_offset<T, W>(value: T, wr: &mut W, context: &Params) -> Result<()>
+where
+    T: Float,
+    W: Write,
+{
+    let time_quantization = context.time_quantization;
+    let (beat, offset) =
+        split_in_beat_and_offset(value.to_f64(), time_quantization, context.rounding);
+    let dequantized = T::dequantize_beat_and_offset(beat.0, offset.0, time_quantization);
+    check_quantization_error(value, dequantized, context)?;
+    beat.serialize_into(wr, context)?;
+    offset.serialize_into(wr, context)
+}
+
+pub fn parse_beat_and_offset<'a, T>(input: &'a [u8], context: &Params) -> ParseResult<'a, T>
+where
+    T: Float,
+{
+    let (input, beat) = parse_uint(input)?;
+    let (input, offset) = parse_uint(input)?;
+    Ok((
+        input,
+        T::dequantize_beat_and_offset(beat, offset, context.time_quantization),
+    ))
+}
+
+// ----------------------------------------------------------------------------
+// Defaults
+// ----------------------------------------------------------------------------
+
+// Only the tests use `#[skip_default]` so far.
+
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn serialize_non_default<T, W>(value: &T, wr: &mut W, context: &Params) -> Result<()>
+where
+    T: Serialize<Params> + Default + PartialEq,
+    W: Write,
+{
+    let is_default = *value == T::default();
+    (!is_default).serialize_into(wr, context)?;
+    if !is_default {
+        value.serialize_into(wr, context)?;
+    }
+    Ok(())
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn parse_non_default<'a, T>(input: &'a [u8], context: &Params) -> ParseResult<'a, T>
+where
+    T: Parse<Params> + Default,
+{
+    let (input, value) = parse_option(|input| T::parse(input, context))(input)?;
+    Ok((input, value.unwrap_or_default()))
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use serde_checks_derive::{Parse as CustomParse, Serialize as CustomSerialize};
+
+    use crate::custom_file_format::{FormatVersion, NoteLayout};
+
+    use super::super::errors::into_format_error;
+    use super::*;
+
+    #[derive(Debug, Default, PartialEq, CustomSerialize, CustomParse)]
+    struct Inner {
+        #[varint]
+        id: u32,
+    }
+
+    #[derive(Debug, PartialEq, CustomSerialize, CustomParse)]
+    struct Example {
+        plain: u8,
+        #[varint]
+        count: u16,
+        #[zigzag]
+        delta: i32,
+        #[quantize(time, split)]
+        onset: f64,
+        #[quantize(pitch)]
+        #[zigzag]
+        bend: f32,
+        #[skip_default]
+        inner: Inner,
+    }
+
+    fn params() -> Params {
+        Params {
+            time_quantization: 4,
+            pitch_quantization: 2,
+            version: FormatVersion::V2,
+            note_layout: NoteLayout::Interleaved,
+            ..Params::default()
+        }
+    }
+
+    fn serialize(example: &Example) -> Vec<u8> {
+        let mut data = vec![];
+        example.serialize_into(&mut data, &params()).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_derived_encodings() {
+        let example = Example {
+            plain: 7,
+            count: 300,
+            delta: -2,
+            onset: 1.25,
+            bend: -0.5,
+            inner: Inner::default(),
+        };
+        let data = serialize(&example);
+        #[rustfmt::skip]
+        assert_eq!(data, [
+            7,          // plain
+            0xAC, 0x02, // count
+            3,          // delta
+            1, 1,       // onset
+            1,          // bend
+            0,          // inner is default
+        ]);
+        assert_eq!(
+            Example::parse(&data, &params()).unwrap(),
+            (&[][..], example)
+        );
+
+        let example = Example {
+            inner: Inner { id: 5 },
+            ..Example::parse(&data, &params()).unwrap().1
+        };
+        let data = serialize(&example);
+        assert_eq!(data[data.len() - 2..], [1, 5]);
+        assert_eq!(
+            Example::parse(&data, &params()).unwrap(),
+            (&[][..], example)
+        );
+    }
+
+    #[test]
+    fn test_derived_errors() {
+        // 2^16 does not fit into the u16 of `count`
+        let data = [7, 0x80, 0x80, 0x04, 0, 0, 0, 0, 0];
+        let err = Example::parse(&data, &params()).unwrap_err();
+        assert_eq!(
+            into_format_error(&data, err).to_string(),
+            "Varint value 65536 is out of range at offset 1 in count",
+        );
+
+        let data = [7, 1, 0, 0, 0, 0, 1];
+        let err = Example::parse(&data, &params()).unwrap_err();
+        assert_eq!(
+            into_format_error(&data, err).to_string(),
+            "Unexpected end of input, expected 1 more byte(s) at offset 7 in inner.id",
+        );
+    }
+}
@@ -1,64 +1,325 @@
-use std::ops::RangeFrom;
+use super::errors::{
+    fail, require_bytes, with_path_segment, FormatError, ParseResult, PathSegment,
+};
+use super::limits::{check_string_len, check_vector_len, nested};
+use super::parse::Parse;
+use super::serialize_types::Params;
+use super::varint::parse_uint;
+
+pub fn take_bytes(num_bytes: usize) -> impl Fn(&[u8]) -> ParseResult<'_, &[u8]> {
+    move |input: &[u8]| {
+        require_bytes(input, num_bytes)?;
+        let (bytes, rest) = input.split_at(num_bytes);
+        Ok((rest, bytes))
+    }
+}
 
-use nom::bytes::complete::take;
-use nom::combinator::map;
-use nom::error::{Error, ErrorKind};
-use nom::multi::count;
-use nom::number::complete::le_u8;
-use nom::Err;
-use nom::{IResult, InputIter, InputLength, Parser, Slice};
+fn take_array<const N: usize>(input: &[u8]) -> ParseResult<'_, [u8; N]> {
+    let (input, bytes) = take_bytes(N)(input)?;
+    Ok((input, bytes.try_into().expect("slice has length N")))
+}
 
-use super::varint::parse_uint;
+pub fn parse_u8(input: &[u8]) -> ParseResult<'_, u8> {
+    let (input, [x]) = take_array::<1>(input)?;
+    Ok((input, x))
+}
+
+pub fn parse_i8(input: &[u8]) -> ParseResult<'_, i8> {
+    let (input, x) = take_array(input)?;
+    Ok((input, i8::from_le_bytes(x)))
+}
+
+pub fn parse_i32(input: &[u8]) -> ParseResult<'_, i32> {
+    let (input, x) = take_array(input)?;
+    Ok((input, i32::from_le_bytes(x)))
+}
+
+pub fn parse_f32(input: &[u8]) -> ParseResult<'_, f32> {
+    let (input, x) = take_array(input)?;
+    Ok((input, f32::from_le_bytes(x)))
+}
+
+pub fn parse_f64(input: &[u8]) -> ParseResult<'_, f64> {
+    let (input, x) = take_array(input)?;
+    Ok((input, f64::from_le_bytes(x)))
+}
+
+pub fn parse_bool(input: &[u8]) -> ParseResult<'_, bool> {
+    match parse_u8(input)? {
+        (rest, 0) => Ok((rest, false)),
+        (rest, 1) => Ok((rest, true)),
+        (_, value) => fail(input, |location| FormatError::InvalidBool {
+            value,
+            location,
+        }),
+    }
+}
+
+/// Parses a varint that is used as a length or index, i.e., has to fit into `usize`.
+pub fn parse_len(input: &[u8]) -> ParseResult<'_, usize> {
+    let (rest, value) = parse_uint(input)?;
+    match usize::try_from(value) {
+        Ok(len) => Ok((rest, len)),
+        Err(_) => fail(input, |location| FormatError::VarintOverflow {
+            value,
+            location,
+        }),
+    }
+}
 
-pub fn parse_bool<I>(input: I) -> IResult<I, bool>
+/// Parses a length prefixed byte slice.
+pub fn parse_bytes(input: &[u8]) -> ParseResult<'_, &[u8]> {
+    let (input, num_bytes) = parse_len(input)?;
+    take_bytes(num_bytes)(input)
+}
+
+/// Parses a string of at most `max_len` bytes.
+pub fn parse_string(max_len: usize) -> impl Fn(&[u8]) -> ParseResult<'_, String> {
+    move |input: &[u8]| {
+        let (_, len) = parse_len(input)?;
+        check_string_len(input, len, max_len)?;
+        let (rest, bytes) = parse_bytes(input)?;
+        match String::from_utf8(bytes.to_owned()) {
+            Ok(res) => Ok((rest, res)),
+            Err(_) => fail(input, |location| FormatError::InvalidUtf8 { location }),
+        }
+    }
+}
+
+/// Parses exactly `num_elements` elements, adding the element index to the path of errors.
+pub fn parse_count<'a, O, F>(
+    mut f: F,
+    num_elements: usize,
+) -> impl FnMut(&'a [u8]) -> ParseResult<'a, Vec<O>>
 where
-    I: Slice<RangeFrom<usize>> + InputIter<Item = u8> + InputLength,
+    F: FnMut(&'a [u8]) -> ParseResult<'a, O>,
 {
-    map(le_u8, |x| x != 0)(input)
+    move |mut input: &'a [u8]| {
+        // Assuming an element has a minimum size of 1, we need at least that many bytes.
+        require_bytes(input, num_elements)?;
+        let mut res = Vec::with_capacity(num_elements);
+        for i in 0..num_elements {
+            let (rest, element) = with_path_segment(PathSegment::Index(i), &mut f)(input)?;
+            res.push(element);
+            input = rest;
+        }
+        Ok((input, res))
+    }
 }
 
-pub fn parse_string(input: &[u8]) -> IResult<&[u8], String> {
-    let (input, num_bytes) = parse_uint(input)?;
-    if num_bytes as usize > input.len() {
-        Err(nom::Err::Error(Error::new(input, ErrorKind::TooLarge)))
-    } else {
-        let (input, bytes) = take(num_bytes)(input)?;
-        let res = String::from_utf8(bytes.to_owned())
-            .map_err(|_| Err::Error(Error::new(input, ErrorKind::Fail)))?; // for lack of more fitting error kind
-        Ok((input, res))
+/// Parses the length prefix of a vector of at most `max_len` elements.
+pub fn parse_vector_len(max_len: usize) -> impl Fn(&[u8]) -> ParseResult<'_, usize> {
+    move |input: &[u8]| {
+        let (rest, len) = parse_len(input)?;
+        check_vector_len(input, len, max_len)?;
+        Ok((rest, len))
     }
 }
 
-pub fn parse_vector<'a, O, F>(mut f: F) -> impl FnMut(&'a [u8]) -> IResult<&[u8], Vec<O>>
+/// Parses a length prefixed vector of at most `max_len` elements.
+pub fn parse_vector<'a, O, F>(
+    mut f: F,
+    max_len: usize,
+) -> impl FnMut(&'a [u8]) -> ParseResult<'a, Vec<O>>
 where
-    F: Parser<&'a [u8], O, Error<&'a [u8]>>,
+    F: FnMut(&'a [u8]) -> ParseResult<'a, O>,
 {
     move |input: &'a [u8]| {
-        let (input, num_elements) = parse_uint(input)?;
-        // Assuming an element has a minimum size of 1, we need at least that many bytes.
-        if num_elements as usize > input.len() {
-            Err(Err::Error(Error::new(input, ErrorKind::TooLarge)))
-        } else {
-            let size: usize = num_elements
-                .try_into()
-                .map_err(|_| Err::Error(Error::new(input, ErrorKind::TooLarge)))?;
-            let (input, res) = count(|input| f.parse(input), size)(input)?;
-            Ok((input, res))
-        }
+        let (input, num_elements) = parse_vector_len(max_len)(input)?;
+        parse_count(&mut f, num_elements)(input)
     }
 }
 
-pub fn parse_option<'a, O, F>(mut f: F) -> impl FnMut(&'a [u8]) -> IResult<&[u8], Option<O>>
+pub fn parse_option<'a, O, F>(mut f: F) -> impl FnMut(&'a [u8]) -> ParseResult<'a, Option<O>>
 where
-    F: Parser<&'a [u8], O, Error<&'a [u8]>>,
+    F: FnMut(&'a [u8]) -> ParseResult<'a, O>,
 {
     move |input: &'a [u8]| {
         let (input, is_defined) = parse_bool(input)?;
         if is_defined {
-            let (input, res) = f.parse(input)?;
+            let (input, res) = f(input)?;
             Ok((input, Some(res)))
         } else {
             Ok((input, None))
         }
     }
 }
+
+impl<C> Parse<C> for bool {
+    fn parse<'a>(input: &'a [u8], _context: &C) -> ParseResult<'a, Self> {
+        parse_bool(input)
+    }
+}
+
+impl<C> Parse<C> for u8 {
+    fn parse<'a>(input: &'a [u8], _context: &C) -> ParseResult<'a, Self> {
+        parse_u8(input)
+    }
+}
+
+impl<C> Parse<C> for i8 {
+    fn parse<'a>(input: &'a [u8], _context: &C) -> ParseResult<'a, Self> {
+        parse_i8(input)
+    }
+}
+
+impl<C> Parse<C> for i32 {
+    fn parse<'a>(input: &'a [u8], _context: &C) -> ParseResult<'a, Self> {
+        parse_i32(input)
+    }
+}
+
+impl<C> Parse<C> for f32 {
+    fn parse<'a>(input: &'a [u8], _context: &C) -> ParseResult<'a, Self> {
+        parse_f32(input)
+    }
+}
+
+impl<C> Parse<C> for f64 {
+    fn parse<'a>(input: &'a [u8], _context: &C) -> ParseResult<'a, Self> {
+        parse_f64(input)
+    }
+}
+
+impl Parse<Params> for String {
+    fn parse<'a>(input: &'a [u8], context: &Params) -> ParseResult<'a, Self> {
+        parse_string(context.limits.max_string_len)(input)
+    }
+}
+
+impl<T> Parse<Params> for Vec<T>
+where
+    T: Parse<Params>,
+{
+    fn parse<'a>(input: &'a [u8], context: &Params) -> ParseResult<'a, Self> {
+        let (input, context) = nested(input, context)?;
+        parse_vector(
+            move |input| T::parse(input, &context),
+            context.limits.max_vector_len,
+        )(input)
+    }
+}
+
+impl<C, T> Parse<C> for Option<T>
+where
+    T: Parse<C>,
+{
+    fn parse<'a>(input: &'a [u8], context: &C) -> ParseResult<'a, Self> {
+        parse_option(|input| T::parse(input, context))(input)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::super::errors::{into_format_error, Location, Path};
+    use super::*;
+
+    fn parse_err<'a, O>(
+        mut parser: impl FnMut(&'a [u8]) -> ParseResult<'a, O>,
+        input: &'a [u8],
+    ) -> FormatError {
+        match parser(input) {
+            Ok(_) => panic!("Expected parse error"),
+            Err(err) => into_format_error(input, err),
+        }
+    }
+
+    fn location(offset: usize, path: Vec<PathSegment>) -> Location {
+        Location {
+            offset,
+            path: Path(path),
+        }
+    }
+
+    #[test]
+    fn test_parse_bool() {
+        assert_eq!(parse_bool(&[0, 5]).unwrap(), (&[5][..], false));
+        assert_eq!(parse_bool(&[1]).unwrap(), (&[][..], true));
+        assert_eq!(
+            parse_err(parse_bool, &[2]),
+            FormatError::InvalidBool {
+                value: 2,
+                location: location(0, vec![]),
+            }
+        );
+        assert_eq!(
+            parse_err(parse_bool, &[]),
+            FormatError::Truncated {
+                expected: 1,
+                location: location(0, vec![]),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_string() {
+        assert_eq!(
+            parse_string(usize::MAX)(&[2, b'a', b'b']).unwrap(),
+            (&[][..], "ab".to_string())
+        );
+        assert_eq!(
+            parse_err(parse_string(usize::MAX), &[5, b'a', b'b']),
+            FormatError::Truncated {
+                expected: 3,
+                location: location(1, vec![]),
+            }
+        );
+        assert_eq!(
+            parse_err(parse_string(usize::MAX), &[1, 0xff]),
+            FormatError::InvalidUtf8 {
+                location: location(0, vec![]),
+            }
+        );
+        assert_eq!(
+            parse_string(2)(&[2, b'a', b'b']).unwrap(),
+            (&[][..], "ab".to_string())
+        );
+        assert_eq!(
+            parse_err(parse_string(2), &[3, b'a', b'b', b'c']),
+            FormatError::StringTooLong {
+                len: 3,
+                max: 2,
+                location: location(0, vec![]),
+            }
+        );
+    }
+
+    fn parse_nested_vector(input: &[u8]) -> ParseResult<'_, Vec<Vec<bool>>> {
+        parse_vector(parse_vector(parse_bool, usize::MAX), usize::MAX)(input)
+    }
+
+    #[test]
+    fn test_parse_vector() {
+        let parser = parse_nested_vector;
+        assert_eq!(
+            parser(&[2, 1, 1, 0]).unwrap(),
+            (&[][..], vec![vec![true], vec![]])
+        );
+        assert_eq!(
+            parse_err(parser, &[2, 0, 2, 1, 3]),
+            FormatError::InvalidBool {
+                value: 3,
+                location: location(4, vec![PathSegment::Index(1), PathSegment::Index(1)]),
+            }
+        );
+        assert_eq!(
+            parse_err(parser, &[3, 0]),
+            FormatError::Truncated {
+                expected: 2,
+                location: location(1, vec![]),
+            }
+        );
+
+        let parser = parse_vector(parse_vector(parse_bool, 1), 2);
+        assert_eq!(
+            parse_err(parser, &[2, 0, 2, 1, 1]),
+            FormatError::TooManyElements {
+                len: 2,
+                max: 1,
+                location: location(2, vec![PathSegment::Index(1)]),
+            }
+        );
+    }
+}
@@ -1,146 +1,174 @@
-use nom::error::{Error, ErrorKind};
-use nom::number::complete::{le_f64, le_i8, le_i32, le_u8};
-use nom::{Err, IResult};
-
-use crate::types::BendData;
-use crate::types::BendPoint;
 use crate::types::Note;
-use crate::types::NoteEffects;
 use crate::types::Sequence;
 use crate::types::TempoMap;
 use crate::types::Track;
-use crate::types::Tuning;
-
-use super::deserialize_fundamentals::{parse_bool, parse_option, parse_string, parse_vector};
-use super::varint::{parse_int, parse_uint};
 
-// Using a type alias for the verbose return type is impossible?
-// https://stackoverflow.com/questions/53916203/alias-a-generic-function-with-lifetimes
-// https://stackoverflow.com/a/26071172/1804173
-// trait DefaultParser<T>: Fn(&[u8]) -> IResult<&[u8], T> {}
-// impl<T, F: Fn(&[u8]) -> IResult<&[u8], T>> DefaultParser<T> for F {}
-// Actually the following would work, but then the lifetime parameter has
-// to be templated everywhere, making it just as verbose.
-// trait DefaultParser<'a, T>: Fn(&'a [u8]) -> IResult<&'a [u8], T> {}
-// impl<'a, T, F: Fn(&'a [u8]) -> IResult<&'a [u8], T>> DefaultParser<'a, T> for F {}
-
-pub fn parse_sequence(input: &[u8]) -> IResult<&[u8], Sequence> {
-    let (input, file_version) = le_i8(input)?;
-    if file_version != 0 {
-        return Err(Err::Error(Error::new(input, ErrorKind::Fail))); // for lack of more fitting error kind
-    }
-    let (input, time_quantization) = parse_uint(input)?;
-    let (input, pitch_quantization) = parse_uint(input)?;
-    let (input, tempo_map) = parse_tempo_map(input)?;
-    let (input, tracks) = parse_vector(parse_track(time_quantization, pitch_quantization))(input)?;
-    Ok((input, Sequence { tempo_map, tracks }))
-}
+use super::columnar::{parse_note_columns, parse_note_layout, NoteLayout};
+use super::deserialize_fundamentals::{parse_count, parse_vector};
+use super::errors::{fail, field, into_format_error, FormatError, Location, ParseResult, Path};
+use super::limits::{check_num_notes, nested, ParseLimits};
+use super::parse::Parse;
+use super::serialize_types::Params;
+use super::streaming::TrackHeader;
+use super::varint::parse_uint;
+use super::versioning::{parse_extension_block, parse_format_version};
 
-fn parse_tempo_map(input: &[u8]) -> IResult<&[u8], TempoMap> {
-    let (input, bpm_base) = le_f64(input)?;
-    Ok((input, TempoMap { bpm_base }))
+/// Parses a complete sequence file with the default `ParseLimits`. Trailing bytes after the
+/// sequence are an error.
+pub fn parse_sequence(input: &[u8]) -> Result<Sequence, FormatError> {
+    parse_sequence_with_limits(input, &ParseLimits::default())
 }
 
-fn parse_track(
-    time_quantization: u64,
-    pitch_quantization: u64,
-) -> impl Fn(&[u8]) -> IResult<&[u8], Track> {
-    move |input: &[u8]| {
-        let (input, name) = parse_string(input)?;
-        let (input, is_percussion) = parse_bool(input)?;
-        let (input, tuning) = parse_tuning(input)?;
-        let (input, notes) =
-            parse_vector(parse_note(time_quantization, pitch_quantization))(input)?;
-        Ok((
-            input,
-            Track {
-                name,
-                is_percussion,
-                tuning,
-                notes,
+/// Like `parse_sequence`, but rejects inputs exceeding the given `limits`, e.g., to parse
+/// untrusted input with tighter limits.
+pub fn parse_sequence_with_limits(
+    input: &[u8],
+    limits: &ParseLimits,
+) -> Result<Sequence, FormatError> {
+    match sequence(input, limits) {
+        Ok(([], sequence)) => Ok(sequence),
+        Ok((rest, _)) => Err(FormatError::TrailingData {
+            num_bytes: rest.len(),
+            location: Location {
+                offset: input.len() - rest.len(),
+                path: Path::default(),
             },
-        ))
+        }),
+        Err(err) => Err(into_format_error(input, err)),
     }
 }
 
-fn parse_tuning(input: &[u8]) -> IResult<&[u8], Tuning> {
-    let (input, string_base_pitches) = parse_vector(le_i32)(input)?;
+fn sequence<'a>(input: &'a [u8], limits: &ParseLimits) -> ParseResult<'a, Sequence> {
+    let (input, header) = parse_header(*limits)(input)?;
+    let params = header.params;
+    let (input, tracks) = field("tracks", parse_tracks(params))(input)?;
+    let (input, _) = field("extension_blocks", parse_extension_blocks(params))(input)?;
     Ok((
         input,
-        Tuning {
-            string_base_pitches,
+        Sequence {
+            tempo_map: header.tempo_map,
+            tracks,
         },
     ))
 }
 
-fn parse_note(
-    time_quantization: u64,
-    pitch_quantization: u64,
-) -> impl Fn(&[u8]) -> IResult<&[u8], Note> {
+/// Everything that precedes the tracks of a sequence. The `Params` are the ones the file
+/// has been written with, and the `ParseLimits` of the parser.
+pub(super) struct Header {
+    pub params: Params,
+    pub tempo_map: TempoMap,
+}
+
+pub(super) fn parse_header(limits: ParseLimits) -> impl Fn(&[u8]) -> ParseResult<'_, Header> {
     move |input: &[u8]| {
-        let (input, beat) = parse_uint(input)?;
-        let (input, quantized_offset) = parse_uint(input)?;
-        let (input, quantized_duration) = parse_uint(input)?;
-        let (input, pitch) = le_u8(input)?;
-        let (input, string) = le_u8(input)?;
-        let (input, fret) = le_u8(input)?;
-        let (input, effects) = parse_note_effects(time_quantization, pitch_quantization)(input)?;
-        let s = beat as f64 + quantized_offset as f64 / time_quantization as f64;
-        let d = quantized_duration as f64 / time_quantization as f64;
+        let (input, version) = field("version", parse_format_version)(input)?;
+        let (input, time_quantization) = field("time_quantization", parse_quantization)(input)?;
+        let (input, pitch_quantization) = field("pitch_quantization", parse_quantization)(input)?;
+        let (input, note_layout) = if version.has_note_layout() {
+            field("note_layout", parse_note_layout)(input)?
+        } else {
+            (input, NoteLayout::Interleaved)
+        };
+        let params = Params {
+            time_quantization,
+            pitch_quantization,
+            version,
+            note_layout,
+            limits,
+            ..Params::default()
+        };
+        let (input, tempo_map) =
+            field("tempo_map", |input| TempoMap::parse(input, &params))(input)?;
+        Ok((input, Header { params, tempo_map }))
+    }
+}
+
+impl Parse<Params> for TempoMap {
+    fn parse<'a>(input: &'a [u8], context: &Params) -> ParseResult<'a, Self> {
+        let (input, bpm_base) = field("bpm_base", |input| f64::parse(input, context))(input)?;
+        let (input, tempo_changes, time_signatures) = if context.version.has_tempo_events() {
+            let (input, tempo_changes) =
+                field("tempo_changes", |input| Vec::parse(input, context))(input)?;
+            let (input, time_signatures) =
+                field("time_signatures", |input| Vec::parse(input, context))(input)?;
+            (input, tempo_changes, time_signatures)
+        } else {
+            (input, Vec::new(), Vec::new())
+        };
         Ok((
             input,
-            Note {
-                s,
-                d,
-                pitch,
-                string,
-                fret,
-                effects,
+            TempoMap {
+                bpm_base,
+                tempo_changes,
+                time_signatures,
             },
         ))
     }
 }
 
-fn parse_note_effects(
-    time_quantization: u64,
-    pitch_quantization: u64,
-) -> impl Fn(&[u8]) -> IResult<&[u8], NoteEffects> {
+fn parse_quantization(input: &[u8]) -> ParseResult<'_, u64> {
+    match parse_uint(input)? {
+        (_, 0) => fail(input, |location| FormatError::QuantizationZero { location }),
+        (rest, quantization) => Ok((rest, quantization)),
+    }
+}
+
+/// Skips the extension blocks of versions that have them. None of the tags are known yet.
+pub(super) fn parse_extension_blocks(params: Params) -> impl Fn(&[u8]) -> ParseResult<'_, ()> {
     move |input: &[u8]| {
-        let (input, dead_note) = parse_bool(input)?;
-        let (input, vibrato) = parse_bool(input)?;
-        let (input, bend_data) =
-            parse_option(parse_bend_data(time_quantization, pitch_quantization))(input)?;
-        Ok((
-            input,
-            NoteEffects {
-                dead_note,
-                vibrato,
-                bend_data,
-            },
-        ))
+        if params.version.has_extension_blocks() {
+            let (input, params) = nested(input, &params)?;
+            let max_len = params.limits.max_vector_len;
+            let (input, _) = parse_vector(parse_extension_block, max_len)(input)?;
+            Ok((input, ()))
+        } else {
+            Ok((input, ()))
+        }
     }
 }
 
-fn parse_bend_data(
-    time_quantization: u64,
-    pitch_quantization: u64,
-) -> impl Fn(&[u8]) -> IResult<&[u8], BendData> {
+fn parse_tracks(params: Params) -> impl Fn(&[u8]) -> ParseResult<'_, Vec<Track>> {
     move |input: &[u8]| {
-        let (input, points) =
-            parse_vector(parse_bend_point(time_quantization, pitch_quantization))(input)?;
-        Ok((input, BendData { points }))
+        let (input, params) = nested(input, &params)?;
+        let mut num_notes_before = 0;
+        let track = move |input| {
+            let (input, track) = parse_track(params, num_notes_before)(input)?;
+            num_notes_before += track.notes.len();
+            Ok((input, track))
+        };
+        parse_vector(track, params.limits.max_vector_len)(input)
     }
 }
 
-fn parse_bend_point(
-    time_quantization: u64,
-    pitch_quantization: u64,
-) -> impl Fn(&[u8]) -> IResult<&[u8], BendPoint> {
+/// `num_notes_before` is the number of notes of the previous tracks.
+fn parse_track(
+    params: Params,
+    num_notes_before: usize,
+) -> impl Fn(&[u8]) -> ParseResult<'_, Track> {
     move |input: &[u8]| {
-        let (input, quantized_pos) = parse_uint(input)?;
-        let (input, quantized_bend) = parse_int(input)?;
-        let pos = quantized_pos as f64 / time_quantization as f64;
-        let bend = quantized_bend as f32 / pitch_quantization as f32;
-        Ok((input, BendPoint { pos, bend }))
+        // Both note layouts start with the number of notes, which is part of the header.
+        let (input, track_header) = TrackHeader::parse(input, &params)?;
+        let num_notes = track_header.num_notes;
+        let notes = |input| {
+            check_num_notes(input, num_notes, num_notes_before, &params.limits)?;
+            let (input, params) = nested(input, &params)?;
+            match params.note_layout {
+                NoteLayout::Interleaved => {
+                    parse_count(|input| Note::parse(input, &params), num_notes)(input)
+                }
+                NoteLayout::Columnar => parse_note_columns(params, num_notes)(input),
+            }
+        };
+        let (input, notes) = field("notes", notes)(input)?;
+        let (input, _) = field("extension_blocks", parse_extension_blocks(params))(input)?;
+        Ok((
+            input,
+            Track {
+                name: track_header.name,
+                is_percussion: track_header.is_percussion,
+                tuning: track_header.tuning,
+                notes,
+            },
+        ))
     }
 }
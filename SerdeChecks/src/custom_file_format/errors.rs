@@ -0,0 +1,414 @@
+use std::fmt;
+
+use nom::error::ErrorKind;
+use nom::{Err, IResult, Needed};
+
+// ----------------------------------------------------------------------------
+// Path
+// ----------------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PathSegment {
+    Field(&'static str),
+    Index(usize),
+}
+
+/// The position of a value in the structure of a `Sequence`, e.g. `tracks[2].notes[17].effects`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Path(pub Vec<PathSegment>);
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "<root>");
+        }
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                PathSegment::Field(name) if i == 0 => write!(f, "{}", name)?,
+                PathSegment::Field(name) => write!(f, ".{}", name)?,
+                PathSegment::Index(index) => write!(f, "[{}]", index)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Location {
+    /// Byte offset relative to the start of the file.
+    pub offset: usize,
+    pub path: Path,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at offset {} in {}", self.offset, self.path)
+    }
+}
+
+// ----------------------------------------------------------------------------
+// FormatError
+// ----------------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FormatError {
+    /// The file neither starts with the magic header nor is a legacy (v0) file.
+    InvalidMagic {
+        location: Location,
+    },
+    UnsupportedVersion {
+        version: i8,
+        location: Location,
+    },
+    /// The input ended, although at least `expected` more bytes were required.
+    Truncated {
+        expected: usize,
+        location: Location,
+    },
+    /// A varint value does not fit into the type it is decoded into (e.g. a length into `usize`).
+    VarintOverflow {
+        value: u64,
+        location: Location,
+    },
+    InvalidUtf8 {
+        location: Location,
+    },
+    InvalidBool {
+        value: u8,
+        location: Location,
+    },
+    QuantizationZero {
+        location: Location,
+    },
+    InvalidNoteLayout {
+        value: u8,
+        location: Location,
+    },
+    InvalidNoteFlags {
+        value: u8,
+        location: Location,
+    },
+    /// The input has bytes left after the sequence has been parsed completely.
+    TrailingData {
+        num_bytes: usize,
+        location: Location,
+    },
+    /// A string is longer than `ParseLimits::max_string_len`.
+    StringTooLong {
+        len: usize,
+        max: usize,
+        location: Location,
+    },
+    /// A vector has more elements than `ParseLimits::max_vector_len`.
+    TooManyElements {
+        len: usize,
+        max: usize,
+        location: Location,
+    },
+    /// The tracks have more notes than `ParseLimits::max_total_notes` in total.
+    TooManyNotes {
+        max: usize,
+        location: Location,
+    },
+    /// Vectors are nested deeper than `ParseLimits::max_nesting`.
+    NestingTooDeep {
+        location: Location,
+    },
+}
+
+impl FormatError {
+    pub fn location(&self) -> &Location {
+        match self {
+            FormatError::InvalidMagic { location }
+            | FormatError::UnsupportedVersion { location, .. }
+            | FormatError::Truncated { location, .. }
+            | FormatError::VarintOverflow { location, .. }
+            | FormatError::InvalidUtf8 { location }
+            | FormatError::InvalidBool { location, .. }
+            | FormatError::QuantizationZero { location }
+            | FormatError::InvalidNoteLayout { location, .. }
+            | FormatError::InvalidNoteFlags { location, .. }
+            | FormatError::TrailingData { location, .. }
+            | FormatError::StringTooLong { location, .. }
+            | FormatError::TooManyElements { location, .. }
+            | FormatError::TooManyNotes { location, .. }
+            | FormatError::NestingTooDeep { location } => location,
+        }
+    }
+
+    fn location_mut(&mut self) -> &mut Location {
+        match self {
+            FormatError::InvalidMagic { location }
+            | FormatError::UnsupportedVersion { location, .. }
+            | FormatError::Truncated { location, .. }
+            | FormatError::VarintOverflow { location, .. }
+            | FormatError::InvalidUtf8 { location }
+            | FormatError::InvalidBool { location, .. }
+            | FormatError::QuantizationZero { location }
+            | FormatError::InvalidNoteLayout { location, .. }
+            | FormatError::InvalidNoteFlags { location, .. }
+            | FormatError::TrailingData { location, .. }
+            | FormatError::StringTooLong { location, .. }
+            | FormatError::TooManyElements { location, .. }
+            | FormatError::TooManyNotes { location, .. }
+            | FormatError::NestingTooDeep { location } => location,
+        }
+    }
+
+    /// Converts the location of an error of a parser operating on a part of the input, i.e.,
+    /// a part starting at `base_offset` and located at `base_path`.
+    pub(super) fn relative_to(mut self, base_offset: usize, base_path: &[PathSegment]) -> Self {
+        let location = self.location_mut();
+        location.offset += base_offset;
+        location.path.0.splice(0..0, base_path.iter().cloned());
+        self
+    }
+
+    pub fn offset(&self) -> usize {
+        self.location().offset
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.location().path
+    }
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::InvalidMagic { .. } => write!(f, "Invalid magic header")?,
+            FormatError::UnsupportedVersion { version, .. } => {
+                write!(f, "Unsupported file version {}", version)?
+            }
+            FormatError::Truncated { expected, .. } => write!(
+                f,
+                "Unexpected end of input, expected {} more byte(s)",
+                expected
+            )?,
+            FormatError::VarintOverflow { value, .. } => {
+                write!(f, "Varint value {} is out of range", value)?
+            }
+            FormatError::InvalidUtf8 { .. } => write!(f, "Invalid UTF-8 in string")?,
+            FormatError::InvalidBool { value, .. } => write!(f, "Invalid bool value {}", value)?,
+            FormatError::QuantizationZero { .. } => write!(f, "Quantization must not be zero")?,
+            FormatError::InvalidNoteLayout { value, .. } => {
+                write!(f, "Invalid note layout {}", value)?
+            }
+            FormatError::InvalidNoteFlags { value, .. } => {
+                write!(f, "Invalid note flags {:#04x}", value)?
+            }
+            FormatError::TrailingData { num_bytes, .. } => write!(
+                f,
+                "Unexpected {} byte(s) after the end of the sequence",
+                num_bytes
+            )?,
+            FormatError::StringTooLong { len, max, .. } => write!(
+                f,
+                "String length {} exceeds the limit of {} bytes",
+                len, max
+            )?,
+            FormatError::TooManyElements { len, max, .. } => write!(
+                f,
+                "Vector length {} exceeds the limit of {} elements",
+                len, max
+            )?,
+            FormatError::TooManyNotes { max, .. } => {
+                write!(f, "Number of notes exceeds the limit of {} in total", max)?
+            }
+            FormatError::NestingTooDeep { .. } => write!(f, "Vectors are nested too deeply")?,
+        }
+        write!(f, " {}", self.location())
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+// ----------------------------------------------------------------------------
+// ReadError
+// ----------------------------------------------------------------------------
+
+/// Error of reading from a `std::io::Read`, see `SequenceReader`.
+#[derive(Debug)]
+pub enum ReadError {
+    Io(std::io::Error),
+    Format(FormatError),
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadError::Io(err) => write!(f, "I/O error: {}", err),
+            ReadError::Format(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for ReadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReadError::Io(err) => Some(err),
+            ReadError::Format(err) => Some(err),
+        }
+    }
+}
+
+impl From<std::io::Error> for ReadError {
+    fn from(err: std::io::Error) -> Self {
+        ReadError::Io(err)
+    }
+}
+
+impl From<FormatError> for ReadError {
+    fn from(err: FormatError) -> Self {
+        ReadError::Format(err)
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Internal nom error type
+// ----------------------------------------------------------------------------
+
+/// The nom error type of all parsers. The location of the contained error is only complete
+/// after `into_format_error`: While the error propagates up, the path gets collected in
+/// reverse order, and the offset can only be computed relative to the full input.
+#[derive(Debug, PartialEq)]
+pub struct ParseError<'a> {
+    input: &'a [u8],
+    error: FormatError,
+}
+
+pub type ParseResult<'a, T> = IResult<&'a [u8], T, ParseError<'a>>;
+
+impl<'a> ParseError<'a> {
+    pub fn new(input: &'a [u8], error: FormatError) -> Self {
+        ParseError { input, error }
+    }
+}
+
+impl<'a> nom::error::ParseError<&'a [u8]> for ParseError<'a> {
+    fn from_error_kind(input: &'a [u8], kind: ErrorKind) -> Self {
+        // Only reachable if a nom parser is used directly, which should be limited to
+        // parsers that can only fail by running out of input.
+        debug_assert!(
+            kind == ErrorKind::Eof,
+            "Unexpected nom error kind {:?}",
+            kind
+        );
+        ParseError::new(
+            input,
+            FormatError::Truncated {
+                expected: 1,
+                location: Location::default(),
+            },
+        )
+    }
+
+    fn append(_input: &'a [u8], _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+/// Shorthand to return an error at `input` with a location that gets completed on the way up.
+pub fn fail<T, F>(input: &[u8], error: F) -> ParseResult<'_, T>
+where
+    F: FnOnce(Location) -> FormatError,
+{
+    Err(Err::Error(ParseError::new(
+        input,
+        error(Location::default()),
+    )))
+}
+
+/// Fails with `Truncated` unless `input` has at least `num_bytes` bytes.
+pub fn require_bytes(input: &[u8], num_bytes: usize) -> ParseResult<'_, ()> {
+    if num_bytes > input.len() {
+        let expected = num_bytes - input.len();
+        fail(input, |location| FormatError::Truncated {
+            expected,
+            location,
+        })
+    } else {
+        Ok((input, ()))
+    }
+}
+
+/// Adds a path segment to the errors of `f`.
+pub fn with_path_segment<'a, O, F>(
+    segment: PathSegment,
+    mut f: F,
+) -> impl FnMut(&'a [u8]) -> ParseResult<'a, O>
+where
+    F: FnMut(&'a [u8]) -> ParseResult<'a, O>,
+{
+    move |input: &'a [u8]| {
+        f(input).map_err(|err| {
+            err.map(|mut err| {
+                err.error.location_mut().path.0.push(segment.clone());
+                err
+            })
+        })
+    }
+}
+
+pub fn field<'a, O, F>(name: &'static str, f: F) -> impl FnMut(&'a [u8]) -> ParseResult<'a, O>
+where
+    F: FnMut(&'a [u8]) -> ParseResult<'a, O>,
+{
+    with_path_segment(PathSegment::Field(name), f)
+}
+
+/// Converts the result of a parser operating on `full_input` into a `FormatError`.
+pub fn into_format_error(full_input: &[u8], err: Err<ParseError>) -> FormatError {
+    match err {
+        Err::Error(err) | Err::Failure(err) => {
+            let mut error = err.error;
+            let location = error.location_mut();
+            location.offset = full_input.len() - err.input.len();
+            location.path.0.reverse();
+            error
+        }
+        Err::Incomplete(needed) => FormatError::Truncated {
+            expected: match needed {
+                Needed::Size(size) => size.get(),
+                Needed::Unknown => 1,
+            },
+            location: Location {
+                offset: full_input.len(),
+                path: Path::default(),
+            },
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_path_display() {
+        let path = Path(vec![
+            PathSegment::Field("tracks"),
+            PathSegment::Index(2),
+            PathSegment::Field("notes"),
+            PathSegment::Index(17),
+            PathSegment::Field("effects"),
+        ]);
+        assert_eq!(path.to_string(), "tracks[2].notes[17].effects");
+        assert_eq!(Path::default().to_string(), "<root>");
+    }
+
+    #[test]
+    fn test_error_display() {
+        let error = FormatError::InvalidBool {
+            value: 7,
+            location: Location {
+                offset: 42,
+                path: Path(vec![PathSegment::Field("tracks"), PathSegment::Index(0)]),
+            },
+        };
+        assert_eq!(
+            error.to_string(),
+            "Invalid bool value 7 at offset 42 in tracks[0]"
+        );
+    }
+}
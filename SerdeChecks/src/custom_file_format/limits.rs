@@ -0,0 +1,270 @@
+//! Limits that protect the parser against inputs requesting huge amounts of memory.
+//!
+//! Length prefixes are never trusted beyond the remaining input, i.e., a vector of `n`
+//! elements requires at least `n` more bytes before anything gets allocated. This bounds the
+//! memory by a multiple of the input length, which can still be a lot for a large input.
+//! The `ParseLimits` bound the sizes independent of the input length.
+
+use super::errors::{fail, FormatError, ParseResult};
+use super::serialize_types::Params;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParseLimits {
+    /// In bytes.
+    pub max_string_len: usize,
+    /// Applies to all vectors, including the tracks and the notes of a track.
+    pub max_vector_len: usize,
+    /// Number of notes of all tracks together.
+    pub max_total_notes: usize,
+    /// Number of nested vectors, e.g., the points of a bend are nested three levels deep
+    /// (`tracks`, `notes`, `points`).
+    pub max_nesting: usize,
+}
+
+impl Default for ParseLimits {
+    fn default() -> Self {
+        ParseLimits {
+            max_string_len: 1 << 20,
+            max_vector_len: 1 << 24,
+            max_total_notes: 1 << 24,
+            max_nesting: 16,
+        }
+    }
+}
+
+impl ParseLimits {
+    /// Only the input length limits the sizes.
+    pub fn unlimited() -> Self {
+        ParseLimits {
+            max_string_len: usize::MAX,
+            max_vector_len: usize::MAX,
+            max_total_notes: usize::MAX,
+            max_nesting: usize::MAX,
+        }
+    }
+}
+
+/// Fails with `StringTooLong` at `input`, which should point to the length prefix.
+pub fn check_string_len<'a>(input: &'a [u8], len: usize, max: usize) -> ParseResult<'a, ()> {
+    if len > max {
+        fail(input, |location| FormatError::StringTooLong {
+            len,
+            max,
+            location,
+        })
+    } else {
+        Ok((input, ()))
+    }
+}
+
+/// Fails with `TooManyElements` at `input`, which should point to the length prefix.
+pub fn check_vector_len<'a>(input: &'a [u8], len: usize, max: usize) -> ParseResult<'a, ()> {
+    if len > max {
+        fail(input, |location| FormatError::TooManyElements {
+            len,
+            max,
+            location,
+        })
+    } else {
+        Ok((input, ()))
+    }
+}
+
+/// Fails with `TooManyNotes` if a track with `num_notes` notes exceeds the total limit
+/// after `num_notes_before` notes of the previous tracks.
+pub fn check_num_notes<'a>(
+    input: &'a [u8],
+    num_notes: usize,
+    num_notes_before: usize,
+    limits: &ParseLimits,
+) -> ParseResult<'a, ()> {
+    let (input, _) = check_vector_len(input, num_notes, limits.max_vector_len)?;
+    let max = limits.max_total_notes;
+    if num_notes > max.saturating_sub(num_notes_before) {
+        fail(input, |location| FormatError::TooManyNotes {
+            max,
+            location,
+        })
+    } else {
+        Ok((input, ()))
+    }
+}
+
+/// The context for the elements of a vector, i.e., with one level of nesting less. Fails with
+/// `NestingTooDeep` if no level is left.
+pub fn nested<'a>(input: &'a [u8], params: &Params) -> ParseResult<'a, Params> {
+    match params.limits.max_nesting.checked_sub(1) {
+        Some(max_nesting) => Ok((
+            input,
+            Params {
+                limits: ParseLimits {
+                    max_nesting,
+                    ..params.limits
+                },
+                ..*params
+            },
+        )),
+        None => fail(input, |location| FormatError::NestingTooDeep { location }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use crate::custom_file_format::{
+        parse_sequence_with_limits, serialize_sequence_to_vec, NoteLayout, ReadError,
+        SequenceReader,
+    };
+    use crate::types::{BendData, BendPoint, Note, NoteEffects, Sequence, Track, Tuning};
+
+    use super::*;
+
+    fn track(name: &str, num_notes: usize) -> Track {
+        let note = Note {
+            s: 1.0,
+            d: 0.5,
+            effects: NoteEffects {
+                bend_data: Some(BendData {
+                    points: vec![BendPoint {
+                        pos: 0.25,
+                        bend: 1.0,
+                    }],
+                }),
+                ..NoteEffects::default()
+            },
+            ..Note::default()
+        };
+        Track {
+            name: name.to_string(),
+            tuning: Tuning {
+                string_base_pitches: vec![64, 59, 55, 50, 45, 40],
+            },
+            notes: vec![note; num_notes],
+            ..Track::default()
+        }
+    }
+
+    fn sequence() -> Sequence {
+        Sequence {
+            tracks: vec![track("Guitar", 3), track("Bass", 3)],
+            ..Sequence::default()
+        }
+    }
+
+    fn read_all(data: &[u8], limits: ParseLimits) -> Result<(), ReadError> {
+        let mut reader = SequenceReader::with_limits(data, limits)?;
+        while reader.next_track()?.is_some() {
+            for note in reader.notes() {
+                note?;
+            }
+        }
+        reader.finish()?;
+        Ok(())
+    }
+
+    /// The error messages of parsing `sequence()` in the interleaved and the columnar layout,
+    /// which have to be the same for the streaming reader.
+    fn parse_err(limits: ParseLimits) -> Vec<String> {
+        let mut errors = vec![];
+        for note_layout in [NoteLayout::Interleaved, NoteLayout::Columnar] {
+            let params = Params {
+                note_layout,
+                ..Params::default()
+            };
+            let data = serialize_sequence_to_vec(&sequence(), &params).unwrap();
+            let err = parse_sequence_with_limits(&data, &limits).unwrap_err();
+            let stream_err = read_all(&data, limits).unwrap_err();
+            assert_eq!(stream_err.to_string(), err.to_string());
+            errors.push(err.to_string());
+        }
+        errors
+    }
+
+    #[test]
+    fn test_within_limits() {
+        let tight_limits = ParseLimits {
+            max_string_len: 6,
+            max_vector_len: 6,
+            max_total_notes: 6,
+            max_nesting: 3,
+        };
+        for limits in [
+            ParseLimits::default(),
+            ParseLimits::unlimited(),
+            tight_limits,
+        ] {
+            let data = serialize_sequence_to_vec(&sequence(), &Params::default()).unwrap();
+            assert_eq!(
+                parse_sequence_with_limits(&data, &limits).unwrap(),
+                sequence()
+            );
+            read_all(&data, limits).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_max_string_len() {
+        let limits = ParseLimits {
+            max_string_len: 5,
+            ..ParseLimits::default()
+        };
+        let message = "String length 6 exceeds the limit of 5 bytes at offset 21 in tracks[0].name";
+        assert_eq!(parse_err(limits), [message, message]);
+    }
+
+    #[test]
+    fn test_max_vector_len() {
+        let limits = ParseLimits {
+            max_vector_len: 5,
+            ..ParseLimits::default()
+        };
+        let message = "Vector length 6 exceeds the limit of 5 elements \
+                       at offset 29 in tracks[0].tuning.string_base_pitches";
+        assert_eq!(parse_err(limits), [message, message]);
+        let limits = ParseLimits {
+            max_vector_len: 1,
+            ..ParseLimits::default()
+        };
+        let message = "Vector length 2 exceeds the limit of 1 elements at offset 20 in tracks";
+        assert_eq!(parse_err(limits), [message, message]);
+    }
+
+    #[test]
+    fn test_max_total_notes() {
+        let limits = ParseLimits {
+            max_total_notes: 5,
+            ..ParseLimits::default()
+        };
+        assert_eq!(
+            parse_err(limits),
+            [
+                "Number of notes exceeds the limit of 5 in total at offset 133 in tracks[1].notes",
+                "Number of notes exceeds the limit of 5 in total at offset 125 in tracks[1].notes",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_max_nesting() {
+        let limits = ParseLimits {
+            max_nesting: 2,
+            ..ParseLimits::default()
+        };
+        assert_eq!(
+            parse_err(limits),
+            [
+                "Vectors are nested too deeply at offset 65 \
+                 in tracks[0].notes[0].effects.bend_data.points",
+                "Vectors are nested too deeply at offset 77 \
+                 in tracks[0].notes[0].effects.bend_data.points",
+            ]
+        );
+        let limits = ParseLimits {
+            max_nesting: 0,
+            ..ParseLimits::default()
+        };
+        let message = "Vectors are nested too deeply at offset 18 in tempo_map.tempo_changes";
+        assert_eq!(parse_err(limits), [message, message]);
+    }
+}
@@ -1,128 +1,75 @@
+pub(crate) mod columnar;
+pub(crate) mod derive_support;
 mod deserialize_fundamentals;
 mod deserialize_types;
+mod errors;
+mod limits;
+mod parse;
+pub(crate) mod quantization;
 mod serialize;
 mod serialize_fundamentals;
 mod serialize_types;
+mod streaming;
 mod varint;
+mod versioning;
 
-pub use deserialize_types::parse_sequence;
+pub use columnar::NoteLayout;
+pub use deserialize_types::{parse_sequence, parse_sequence_with_limits};
+pub use errors::{FormatError, Location, Path, PathSegment, ReadError};
+pub use limits::ParseLimits;
+pub use quantization::{quantization_report, ErrorStats, QuantizationReport, Rounding};
 pub use serialize::serialize_into_vec;
 pub use serialize_types::{serialize_sequence, serialize_sequence_to_vec, Params};
+pub use streaming::{SequenceReader, SequenceWriter, TrackHeader};
+pub use versioning::{FormatVersion, MAGIC};
 
 #[cfg(test)]
 mod test {
     use pretty_assertions::assert_eq;
     use rand::rngs::StdRng;
-    use rand::{Rng, SeedableRng};
+    use rand::SeedableRng;
 
     use crate::custom_file_format::serialize_types::Params;
-    use crate::types::BendData;
-    use crate::types::BendPoint;
-    use crate::types::Note;
-    use crate::types::NoteEffects;
-    use crate::types::Sequence;
-    use crate::types::TempoMap;
-    use crate::types::Track;
-    use crate::types::Tuning;
+    use crate::synthetic::{
+        gen_sequence, gen_sequence_with_tempo_events, PITCH_QUANTIZATION, TIME_QUANTIZATION,
+    };
 
     use super::*;
 
-    const TIME_QUANTIZATION: i32 = 960;
-    const PITCH_QUANTIZATION: i32 = 256;
-
-    fn gen_random_string<R: Rng>(rng: &mut R) -> String {
-        let len = rng.gen_range(0..=10);
-        rng.sample_iter::<char, _>(rand::distributions::Standard)
-            .take(len)
-            .collect()
-    }
-
-    fn gen_sequence<R: Rng>(rng: &mut R) -> Sequence {
-        Sequence {
-            tempo_map: gen_tempo_map(rng),
-            tracks: (0..rng.gen_range(0..=10)).map(|_| gen_track(rng)).collect(),
-        }
-    }
-
-    fn gen_tempo_map<R: Rng>(rng: &mut R) -> TempoMap {
-        TempoMap {
-            bpm_base: rng.gen_range(20.0..=300.0),
-        }
-    }
-
-    fn gen_track<R: Rng>(rng: &mut R) -> Track {
-        Track {
-            name: gen_random_string(rng),
-            is_percussion: rng.gen_bool(0.5),
-            tuning: gen_tuning(rng),
-            notes: (0..rng.gen_range(0..=10)).map(|_| gen_note(rng)).collect(),
-        }
-    }
-
-    fn gen_tuning<R: Rng>(rng: &mut R) -> Tuning {
-        Tuning {
-            string_base_pitches: (0..rng.gen_range(0..=10))
-                .map(|_| rng.gen::<i32>())
-                .collect(),
-        }
-    }
-
-    fn gen_note<R: Rng>(rng: &mut R) -> Note {
-        Note {
-            s: rng.gen::<u16>() as f64
-                + (rng.gen_range(0..TIME_QUANTIZATION) as f64 / TIME_QUANTIZATION as f64),
-            d: rng.gen_range(0..TIME_QUANTIZATION * 10) as f64 / TIME_QUANTIZATION as f64,
-            pitch: rng.gen::<u8>(),
-            string: rng.gen::<u8>(),
-            fret: rng.gen::<u8>(),
-            effects: gen_note_effects(rng),
-        }
-    }
-
-    fn gen_note_effects<R: Rng>(rng: &mut R) -> NoteEffects {
-        NoteEffects {
-            dead_note: rng.gen_bool(0.5),
-            vibrato: rng.gen_bool(0.5),
-            bend_data: if rng.gen_bool(0.5) {
-                Some(gen_bend_data(rng))
-            } else {
-                None
-            },
-        }
-    }
-
-    fn gen_bend_data<R: Rng>(rng: &mut R) -> BendData {
-        BendData {
-            points: (0..rng.gen_range(0..=10))
-                .map(|_| gen_bend_point(rng))
-                .collect(),
-        }
-    }
-
-    fn gen_bend_point<R: Rng>(rng: &mut R) -> BendPoint {
-        BendPoint {
-            pos: rng.gen_range(0..TIME_QUANTIZATION * 10) as f64 / TIME_QUANTIZATION as f64,
-            bend: rng.gen_range(-PITCH_QUANTIZATION * 5..PITCH_QUANTIZATION * 5) as f32
-                / PITCH_QUANTIZATION as f32,
-        }
-    }
-
     #[test]
     fn test_roundtrip() {
         let mut rng = StdRng::seed_from_u64(0);
         let num_runs = 100;
         for _ in 0..num_runs {
             let sequence = gen_sequence(&mut rng);
-
-            let params = Params {
-                time_quantization: TIME_QUANTIZATION as u64,
-                pitch_quantization: PITCH_QUANTIZATION as u64,
-            };
-            let serialized = serialize_sequence_to_vec(&sequence, &params).unwrap();
-
-            let (_, sequence_reconstructed) = parse_sequence(&serialized).unwrap();
-
-            assert_eq!(sequence, sequence_reconstructed);
+            let sequence_with_events = gen_sequence_with_tempo_events(&mut rng);
+
+            for (version, note_layout) in [
+                (FormatVersion::V0, NoteLayout::Interleaved),
+                (FormatVersion::V1, NoteLayout::Interleaved),
+                (FormatVersion::V2, NoteLayout::Interleaved),
+                (FormatVersion::V2, NoteLayout::Columnar),
+                (FormatVersion::V3, NoteLayout::Interleaved),
+                (FormatVersion::V3, NoteLayout::Columnar),
+            ] {
+                let params = Params {
+                    time_quantization: TIME_QUANTIZATION as u64,
+                    pitch_quantization: PITCH_QUANTIZATION as u64,
+                    version,
+                    note_layout,
+                    ..Params::default()
+                };
+                let sequence = if version.has_tempo_events() {
+                    &sequence_with_events
+                } else {
+                    &sequence
+                };
+                let serialized = serialize_sequence_to_vec(sequence, &params).unwrap();
+
+                let sequence_reconstructed = parse_sequence(&serialized).unwrap();
+
+                assert_eq!(*sequence, sequence_reconstructed);
+            }
         }
     }
 }
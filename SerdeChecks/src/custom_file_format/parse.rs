@@ -0,0 +1,10 @@
+use super::errors::ParseResult;
+
+/// Counterpart of `Serialize<C>`: Parses a value with the help of a context.
+///
+/// The implementations for the fundamental types ignore the context, which allows to derive
+/// the trait for user defined types (see `serde_checks_derive`). Only `String` and `Vec`
+/// require `Params` as context, because they apply its `ParseLimits`.
+pub trait Parse<C>: Sized {
+    fn parse<'a>(input: &'a [u8], context: &C) -> ParseResult<'a, Self>;
+}
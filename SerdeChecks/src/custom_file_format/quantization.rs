@@ -0,0 +1,393 @@
+//! Quantization of note times and bends.
+//!
+//! Times and bends are stored as multiples of `1 / quantization`, which is lossy for values
+//! off that grid. The `Params` control how values are rounded onto the grid (`rounding`),
+//! and which quantization errors are acceptable (`max_error`, `strict`). A value that is
+//! not acceptable makes the serialization fail with `ErrorKind::InvalidInput`.
+//!
+//! `quantization_report` measures the quantization errors of a sequence, which helps to
+//! choose the quantization levels from data.
+
+use std::fmt;
+use std::io::{Error, ErrorKind, Result};
+
+use crate::types::Sequence;
+
+use super::serialize_types::Params;
+use super::varint::{Int, Uint};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Rounding {
+    /// Rounds to the closest grid point, and half-way cases away from zero.
+    #[default]
+    Nearest,
+    /// Rounds towards negative infinity.
+    Down,
+    /// Rounds towards positive infinity.
+    Up,
+}
+
+impl Rounding {
+    fn apply(self, value: f64) -> f64 {
+        match self {
+            Rounding::Nearest => value.round(),
+            Rounding::Down => value.floor(),
+            Rounding::Up => value.ceil(),
+        }
+    }
+}
+
+/// Negative values saturate to zero.
+pub(crate) fn quantize_uint(value: f64, quantization: u64, rounding: Rounding) -> Uint {
+    Uint(rounding.apply(value * quantization as f64) as u64)
+}
+
+pub(crate) fn quantize_int(value: f64, quantization: u64, rounding: Rounding) -> Int {
+    Int(rounding.apply(value * quantization as f64) as i64)
+}
+
+/// Splits a time into whole beats and the quantized remainder. Note that the remainder may
+/// get rounded up to a whole beat, i.e., it is not necessarily below the quantization.
+pub(crate) fn split_in_beat_and_offset(
+    t: f64,
+    time_quantization: u64,
+    rounding: Rounding,
+) -> (Uint, Uint) {
+    let beat = Uint(t as u64);
+    let offset = quantize_uint(t - (beat.0 as f64), time_quantization, rounding);
+    (beat, offset)
+}
+
+/// Floats that can be quantized. Dequantizing divides in the precision of the type itself,
+/// so that it matches what the parser produces.
+pub trait Float: Copy {
+    fn to_f64(self) -> f64;
+    fn dequantize_uint(value: u64, quantization: u64) -> Self;
+    fn dequantize_int(value: i64, quantization: u64) -> Self;
+    fn dequantize_beat_and_offset(beat: u64, offset: u64, time_quantization: u64) -> Self;
+}
+
+impl Float for f32 {
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+    fn dequantize_uint(value: u64, quantization: u64) -> Self {
+        value as f32 / quantization as f32
+    }
+    fn dequantize_int(value: i64, quantization: u64) -> Self {
+        value as f32 / quantization as f32
+    }
+    fn dequantize_beat_and_offset(beat: u64, offset: u64, time_quantization: u64) -> Self {
+        beat as f32 + offset as f32 / time_quantization as f32
+    }
+}
+
+impl Float for f64 {
+    fn to_f64(self) -> f64 {
+        self
+    }
+    fn dequantize_uint(value: u64, quantization: u64) -> Self {
+        value as f64 / quantization as f64
+    }
+    fn dequantize_int(value: i64, quantization: u64) -> Self {
+        value as f64 / quantization as f64
+    }
+    fn dequantize_beat_and_offset(beat: u64, offset: u64, time_quantization: u64) -> Self {
+        beat as f64 + offset as f64 / time_quantization as f64
+    }
+}
+
+/// The value the parser reconstructs for `value`.
+pub(crate) fn roundtrip_uint<T: Float>(value: T, quantization: u64, params: &Params) -> T {
+    let quantized = quantize_uint(value.to_f64(), quantization, params.rounding);
+    T::dequantize_uint(quantized.0, quantization)
+}
+
+pub(crate) fn roundtrip_int<T: Float>(value: T, quantization: u64, params: &Params) -> T {
+    let quantized = quantize_int(value.to_f64(), quantization, params.rounding);
+    T::dequantize_int(quantized.0, quantization)
+}
+
+pub(crate) fn roundtrip_beat_and_offset<T: Float>(value: T, params: &Params) -> T {
+    let time_quantization = params.time_quantization;
+    let (beat, offset) =
+        split_in_beat_and_offset(value.to_f64(), time_quantization, params.rounding);
+    T::dequantize_beat_and_offset(beat.0, offset.0, time_quantization)
+}
+
+/// Fails if `value` cannot be serialized as `dequantized` due to `max_error` or `strict`.
+pub(crate) fn check_quantization_error<T: Float>(
+    value: T,
+    dequantized: T,
+    params: &Params,
+) -> Result<()> {
+    let (value, dequantized) = (value.to_f64(), dequantized.to_f64());
+    if params.strict && value != dequantized {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "Value {} is not representable, the closest value is {}",
+                value, dequantized
+            ),
+        ));
+    }
+    if let Some(max_error) = params.max_error {
+        let error = (value - dequantized).abs();
+        // Also catches NaN errors.
+        if error > max_error || error.is_nan() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Quantization error {} of value {} exceeds the maximum of {}",
+                    error, value, max_error
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+// ----------------------------------------------------------------------------
+// Report
+// ----------------------------------------------------------------------------
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ErrorStats {
+    pub num_values: usize,
+    /// Values that do not survive a round trip exactly, i.e., values rejected by `strict`.
+    pub num_inexact: usize,
+    pub max_error: f64,
+    pub mean_error: f64,
+}
+
+impl ErrorStats {
+    fn add<T: Float>(&mut self, value: T, dequantized: T) {
+        let error = (value.to_f64() - dequantized.to_f64()).abs();
+        self.num_values += 1;
+        if value.to_f64() != dequantized.to_f64() {
+            self.num_inexact += 1;
+        }
+        // NaN errors should be visible in the report.
+        if error > self.max_error || error.is_nan() {
+            self.max_error = error;
+        }
+        self.mean_error += (error - self.mean_error) / self.num_values as f64;
+    }
+}
+
+/// The quantization errors per field, see `quantization_report`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QuantizationReport {
+    /// `Note::s`
+    pub note_onsets: ErrorStats,
+    /// `Note::d`
+    pub note_durations: ErrorStats,
+    /// `BendPoint::pos`
+    pub bend_positions: ErrorStats,
+    /// `BendPoint::bend`
+    pub bends: ErrorStats,
+}
+
+impl QuantizationReport {
+    pub fn fields(&self) -> [(&'static str, &ErrorStats); 4] {
+        [
+            ("note_onsets", &self.note_onsets),
+            ("note_durations", &self.note_durations),
+            ("bend_positions", &self.bend_positions),
+            ("bends", &self.bends),
+        ]
+    }
+}
+
+impl fmt::Display for QuantizationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<16} {:>10} {:>10} {:>12} {:>12}",
+            "field", "values", "inexact", "max error", "mean error"
+        )?;
+        for (name, stats) in self.fields() {
+            writeln!(
+                f,
+                "{:<16} {:>10} {:>10} {:>12.3e} {:>12.3e}",
+                name, stats.num_values, stats.num_inexact, stats.max_error, stats.mean_error
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Measures the errors that serializing `sequence` with `params` would introduce. The
+/// `max_error` and `strict` options of the params are ignored.
+pub fn quantization_report(sequence: &Sequence, params: &Params) -> QuantizationReport {
+    let mut report = QuantizationReport::default();
+    for note in sequence.tracks.iter().flat_map(|track| &track.notes) {
+        report
+            .note_onsets
+            .add(note.s, roundtrip_beat_and_offset(note.s, params));
+        report.note_durations.add(
+            note.d,
+            roundtrip_uint(note.d, params.time_quantization, params),
+        );
+        for point in note.effects.bend_data.iter().flat_map(|data| &data.points) {
+            report.bend_positions.add(
+                point.pos,
+                roundtrip_uint(point.pos, params.time_quantization, params),
+            );
+            report.bends.add(
+                point.bend,
+                roundtrip_int(point.bend, params.pitch_quantization, params),
+            );
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use crate::custom_file_format::{
+        parse_sequence, serialize_sequence_to_vec, FormatVersion, NoteLayout,
+    };
+    use crate::types::{BendData, BendPoint, Note, NoteEffects, TempoMap, Track};
+
+    use super::*;
+
+    fn sequence(s: f64, d: f64, bend: f32) -> Sequence {
+        Sequence {
+            tempo_map: TempoMap::new(120.0),
+            tracks: vec![Track {
+                notes: vec![Note {
+                    s,
+                    d,
+                    effects: NoteEffects {
+                        bend_data: Some(BendData {
+                            points: vec![BendPoint { pos: 0.5, bend }],
+                        }),
+                        ..NoteEffects::default()
+                    },
+                    ..Note::default()
+                }],
+                ..Track::default()
+            }],
+        }
+    }
+
+    fn params(rounding: Rounding, max_error: Option<f64>, strict: bool) -> Params {
+        Params {
+            time_quantization: 4,
+            pitch_quantization: 2,
+            rounding,
+            max_error,
+            strict,
+            ..Params::default()
+        }
+    }
+
+    fn roundtrip(sequence: &Sequence, params: &Params) -> std::io::Result<Sequence> {
+        let mut result = None;
+        for note_layout in [NoteLayout::Interleaved, NoteLayout::Columnar] {
+            let params = Params {
+                version: FormatVersion::V2,
+                note_layout,
+                ..*params
+            };
+            let data = serialize_sequence_to_vec(sequence, &params)?;
+            let parsed = parse_sequence(&data).unwrap();
+            if let Some(result) = &result {
+                assert_eq!(&parsed, result);
+            }
+            result = Some(parsed);
+        }
+        Ok(result.unwrap())
+    }
+
+    fn first_note(sequence: &Sequence) -> &Note {
+        &sequence.tracks[0].notes[0]
+    }
+
+    #[test]
+    fn test_rounding() {
+        let sequence = sequence(1.6, 0.3, -0.3);
+        for (rounding, s, d, bend) in [
+            (Rounding::Nearest, 1.5, 0.25, -0.5),
+            (Rounding::Down, 1.5, 0.25, -0.5),
+            (Rounding::Up, 1.75, 0.5, 0.0),
+        ] {
+            let parsed = roundtrip(&sequence, &params(rounding, None, false)).unwrap();
+            let note = first_note(&parsed);
+            assert_eq!((note.s, note.d), (s, d), "{:?}", rounding);
+            let bend_data = note.effects.bend_data.as_ref().unwrap();
+            assert_eq!(bend_data.points[0].bend, bend, "{:?}", rounding);
+        }
+    }
+
+    #[test]
+    fn test_strict() {
+        let params = params(Rounding::Nearest, None, true);
+        let sequence = sequence(1.75, 0.25, -1.5);
+        assert_eq!(roundtrip(&sequence, &params).unwrap(), sequence);
+
+        for sequence in [
+            self::sequence(1.7, 0.25, -1.5),
+            self::sequence(1.75, 0.2, -1.5),
+            self::sequence(1.75, -0.25, -1.5),
+            self::sequence(1.75, 0.25, -1.4),
+            self::sequence(f64::NAN, 0.25, -1.5),
+        ] {
+            let err = roundtrip(&sequence, &params).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        }
+        let err = roundtrip(&self::sequence(1.7, 0.25, -1.5), &params).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Value 1.7 is not representable, the closest value is 1.75"
+        );
+    }
+
+    #[test]
+    fn test_max_error() {
+        let params = params(Rounding::Nearest, Some(0.1), false);
+        let sequence = sequence(1.7, 0.3, -0.4);
+        assert!(roundtrip(&sequence, &params).is_ok());
+
+        let err = roundtrip(&self::sequence(1.6, 0.3, -0.4), &params).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert_eq!(
+            err.to_string(),
+            "Quantization error 0.10000000000000009 of value 1.6 exceeds the maximum of 0.1"
+        );
+    }
+
+    #[test]
+    fn test_quantization_report() {
+        let mut sequence = sequence(1.6, 0.25, -0.3);
+        sequence.tracks[0].notes.push(Note {
+            s: 2.0,
+            d: 0.2,
+            ..Note::default()
+        });
+        let report = quantization_report(&sequence, &params(Rounding::Nearest, None, true));
+
+        assert_eq!(report.note_onsets.num_values, 2);
+        assert_eq!(report.note_onsets.num_inexact, 1);
+        assert!((report.note_onsets.max_error - 0.1).abs() < 1e-9);
+        assert!((report.note_onsets.mean_error - 0.05).abs() < 1e-9);
+        assert_eq!(report.note_durations.num_inexact, 1);
+        assert!((report.note_durations.max_error - 0.05).abs() < 1e-9);
+        assert_eq!(
+            report.bend_positions,
+            ErrorStats {
+                num_values: 1,
+                ..ErrorStats::default()
+            }
+        );
+        assert_eq!(report.bends.num_values, 1);
+        assert!((report.bends.max_error - 0.2).abs() < 1e-6);
+
+        let lines: Vec<_> = report.to_string().lines().map(str::to_owned).collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[1].starts_with("note_onsets"));
+    }
+}
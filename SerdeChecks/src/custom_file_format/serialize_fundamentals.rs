@@ -152,6 +152,24 @@ impl<T: Serialize<C>, C> Serialize<C> for [T] {
     }
 }
 
+impl<C> Serialize<C> for String {
+    fn serialize_into<W>(&self, wr: &mut W, context: &C) -> Result<()>
+    where
+        W: Write,
+    {
+        self.as_str().serialize_into(wr, context)
+    }
+}
+
+impl<T: Serialize<C>, C> Serialize<C> for Vec<T> {
+    fn serialize_into<W>(&self, wr: &mut W, context: &C) -> Result<()>
+    where
+        W: Write,
+    {
+        self.as_slice().serialize_into(wr, context)
+    }
+}
+
 impl<T: Serialize<C>, C> Serialize<C> for Option<T> {
     fn serialize_into<W>(&self, wr: &mut W, context: &C) -> Result<()>
     where
@@ -1,22 +1,37 @@
+use std::io::Error;
+use std::io::ErrorKind;
 use std::io::Result;
 use std::io::Write;
 
-use crate::types::BendData;
-use crate::types::BendPoint;
-use crate::types::Note;
-use crate::types::NoteEffects;
 use crate::types::Sequence;
 use crate::types::TempoMap;
 use crate::types::Track;
-use crate::types::Tuning;
 
+use super::columnar::{serialize_notes_columnar, NoteLayout};
+use super::limits::ParseLimits;
+use super::quantization::Rounding;
 use super::serialize::Serialize;
-use super::varint::Int;
 use super::varint::Uint;
+use super::versioning::{ExtensionBlock, FormatVersion};
 
+/// No extension blocks are written yet, see `versioning` for their purpose.
+pub(super) const NO_EXTENSION_BLOCKS: &[ExtensionBlock] = &[];
+
+#[derive(Copy, Clone, Debug)]
 pub struct Params {
     pub time_quantization: u64,
     pub pitch_quantization: u64,
+    pub version: FormatVersion,
+    /// Layouts other than `NoteLayout::Interleaved` require format v2 or newer.
+    pub note_layout: NoteLayout,
+    /// How times and bends are rounded onto the quantization grid.
+    pub rounding: Rounding,
+    /// Serialization fails if the (absolute) quantization error of a value exceeds this.
+    pub max_error: Option<f64>,
+    /// Serialization fails if a value does not survive a round trip exactly.
+    pub strict: bool,
+    /// Only used for parsing, see `parse_sequence_with_limits`.
+    pub limits: ParseLimits,
 }
 
 impl Default for Params {
@@ -24,6 +39,12 @@ impl Default for Params {
         Params {
             time_quantization: 960,
             pitch_quantization: 256,
+            version: FormatVersion::LATEST,
+            note_layout: NoteLayout::Interleaved,
+            rounding: Rounding::Nearest,
+            max_error: None,
+            strict: false,
+            limits: ParseLimits::default(),
         }
     }
 }
@@ -46,22 +67,57 @@ impl Serialize<Params> for Sequence {
     where
         W: Write,
     {
-        let file_version: i8 = 0;
-        file_version.serialize_into(wr, context)?;
-        Uint(context.time_quantization).serialize_into(wr, context)?;
-        Uint(context.pitch_quantization).serialize_into(wr, context)?;
-        self.tempo_map.serialize_into(wr, context)?;
+        serialize_header(&self.tempo_map, wr, context)?;
         self.tracks.serialize_into(wr, context)?;
+        if context.version.has_extension_blocks() {
+            NO_EXTENSION_BLOCKS.serialize_into(wr, context)?;
+        }
         Ok(())
     }
 }
 
+/// Serializes everything that precedes the tracks of a sequence.
+pub(super) fn serialize_header<W>(tempo_map: &TempoMap, wr: &mut W, context: &Params) -> Result<()>
+where
+    W: Write,
+{
+    if context.note_layout != NoteLayout::Interleaved && !context.version.has_note_layout() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "Note layout {:?} is not supported by format version {:?}",
+                context.note_layout, context.version
+            ),
+        ));
+    }
+    context.version.serialize_into(wr, context)?;
+    Uint(context.time_quantization).serialize_into(wr, context)?;
+    Uint(context.pitch_quantization).serialize_into(wr, context)?;
+    if context.version.has_note_layout() {
+        context.note_layout.serialize_into(wr, context)?;
+    }
+    tempo_map.serialize_into(wr, context)?;
+    Ok(())
+}
+
 impl Serialize<Params> for TempoMap {
     fn serialize_into<W>(&self, wr: &mut W, context: &Params) -> Result<()>
     where
         W: Write,
     {
         self.bpm_base.serialize_into(wr, context)?;
+        if context.version.has_tempo_events() {
+            self.tempo_changes.serialize_into(wr, context)?;
+            self.time_signatures.serialize_into(wr, context)?;
+        } else if !self.tempo_changes.is_empty() || !self.time_signatures.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Tempo changes and time signatures are not supported by format version {:?}",
+                    context.version
+                ),
+            ));
+        }
         Ok(())
     }
 }
@@ -74,86 +130,14 @@ impl Serialize<Params> for Track {
         self.name.serialize_into(wr, context)?;
         self.is_percussion.serialize_into(wr, context)?;
         self.tuning.serialize_into(wr, context)?;
-        self.notes.serialize_into(wr, context)?;
-        Ok(())
-    }
-}
-
-impl Serialize<Params> for Tuning {
-    fn serialize_into<W>(&self, wr: &mut W, context: &Params) -> Result<()>
-    where
-        W: Write,
-    {
-        self.string_base_pitches.serialize_into(wr, context)?;
-        Ok(())
-    }
-}
-
-impl Serialize<Params> for Note {
-    fn serialize_into<W>(&self, wr: &mut W, context: &Params) -> Result<()>
-    where
-        W: Write,
-    {
-        let (beat, quantized_offset) = split_in_beat_and_offset(self.s, context.time_quantization);
-        let quantized_duration = quantize_uint(self.d, context.time_quantization);
-        beat.serialize_into(wr, context)?;
-        quantized_offset.serialize_into(wr, context)?;
-        quantized_duration.serialize_into(wr, context)?;
-        self.pitch.serialize_into(wr, context)?;
-        self.string.serialize_into(wr, context)?;
-        self.fret.serialize_into(wr, context)?;
-        self.effects.serialize_into(wr, context)?;
-        Ok(())
-    }
-}
-
-impl Serialize<Params> for NoteEffects {
-    fn serialize_into<W>(&self, wr: &mut W, context: &Params) -> Result<()>
-    where
-        W: Write,
-    {
-        self.dead_note.serialize_into(wr, context)?;
-        self.vibrato.serialize_into(wr, context)?;
-        self.bend_data.serialize_into(wr, context)?;
-        Ok(())
-    }
-}
-
-impl Serialize<Params> for BendData {
-    fn serialize_into<W>(&self, wr: &mut W, context: &Params) -> Result<()>
-    where
-        W: Write,
-    {
-        self.points.serialize_into(wr, context)?;
-        Ok(())
-    }
-}
-
-impl Serialize<Params> for BendPoint {
-    fn serialize_into<W>(&self, wr: &mut W, context: &Params) -> Result<()>
-    where
-        W: Write,
-    {
-        let quantized_pos = quantize_uint(self.pos, context.time_quantization); // Is it guaranteed that pos is never negative?
-        let quantized_bend = quantize_int(self.bend as f64, context.pitch_quantization);
-        quantized_pos.serialize_into(wr, context)?;
-        quantized_bend.serialize_into(wr, context)?;
+        // Both layouts start with the number of notes, see `TrackHeader`.
+        match context.note_layout {
+            NoteLayout::Interleaved => self.notes.serialize_into(wr, context)?,
+            NoteLayout::Columnar => serialize_notes_columnar(&self.notes, wr, context)?,
+        }
+        if context.version.has_extension_blocks() {
+            NO_EXTENSION_BLOCKS.serialize_into(wr, context)?;
+        }
         Ok(())
     }
 }
-
-fn split_in_beat_and_offset(t: f64, time_quantization: u64) -> (Uint, Uint) {
-    let beat = Uint(t as u64);
-    let offset = quantize_uint(t - (beat.0 as f64), time_quantization);
-    (beat, offset)
-}
-
-fn quantize_uint(value: f64, quantization: u64) -> Uint {
-    let quantization = quantization as f64;
-    Uint((value * quantization).round() as u64)
-}
-
-fn quantize_int(value: f64, quantization: u64) -> Int {
-    let quantization = quantization as f64;
-    Int((value * quantization).round() as i64)
-}
@@ -0,0 +1,604 @@
+//! Streaming access to sequence files.
+//!
+//! `SequenceReader` reads a sequence from any `std::io::Read` (a file, a socket, or the
+//! `&[u8]` of a memory map) one track header and one note at a time. `SequenceWriter` is
+//! its counterpart, producing the same bytes as `serialize_sequence`.
+//!
+//! Limitations that follow from the format:
+//!
+//! - Tracks and notes are length prefixed, i.e., the writer needs the number of tracks and
+//!   the number of notes per track up front.
+//! - The notes of a track in the columnar layout can only be decoded (and encoded) as a
+//!   whole, so reader and writer buffer the notes of one track in that case.
+
+use std::io::{Error, ErrorKind, Read, Write};
+
+use serde_checks_derive::{Parse as CustomParse, Serialize as CustomSerialize};
+
+use crate::types::{Note, TempoMap, Tuning};
+
+use super::columnar::{parse_note_columns, serialize_note_columns, NoteLayout};
+use super::deserialize_fundamentals::parse_vector_len;
+use super::deserialize_types::{parse_extension_blocks, parse_header, Header};
+use super::errors::{
+    into_format_error, require_bytes, FormatError, Location, ParseResult, Path, PathSegment,
+    ReadError,
+};
+use super::limits::{check_num_notes, nested, ParseLimits};
+use super::parse::Parse;
+use super::serialize::Serialize;
+use super::serialize_types::{serialize_header, Params, NO_EXTENSION_BLOCKS};
+use super::varint::Uint;
+use super::versioning::FormatVersion;
+
+/// A `Track` without its notes.
+#[derive(Clone, Debug, PartialEq, CustomSerialize, CustomParse)]
+pub struct TrackHeader {
+    pub name: String,
+    pub is_percussion: bool,
+    pub tuning: Tuning,
+    #[varint]
+    pub num_notes: usize,
+}
+
+// ----------------------------------------------------------------------------
+// InputBuffer
+// ----------------------------------------------------------------------------
+
+const MIN_READ_SIZE: usize = 64 * 1024;
+
+/// Runs the slice based parsers on a buffered window of a reader.
+struct InputBuffer<R> {
+    rd: R,
+    buf: Vec<u8>,
+    /// Start of the unparsed part of `buf`.
+    pos: usize,
+    /// Offset of `buf[0]` in the file.
+    buf_offset: usize,
+    eof: bool,
+}
+
+impl<R: Read> InputBuffer<R> {
+    fn new(rd: R) -> Self {
+        InputBuffer {
+            rd,
+            buf: Vec::new(),
+            pos: 0,
+            buf_offset: 0,
+            eof: false,
+        }
+    }
+
+    fn offset(&self) -> usize {
+        self.buf_offset + self.pos
+    }
+
+    /// Runs `parser` on the unparsed input. If it runs out of input, more input is read and
+    /// the parser is restarted, so it should parse small units (a header, a note, ...).
+    fn parse<T, F>(&mut self, path: &[PathSegment], parser: F) -> Result<T, ReadError>
+    where
+        F: Fn(&[u8]) -> ParseResult<'_, T>,
+    {
+        loop {
+            let input = &self.buf[self.pos..];
+            let error = match parser(input) {
+                Ok((rest, value)) => {
+                    self.pos = self.buf.len() - rest.len();
+                    return Ok(value);
+                }
+                Err(err) => into_format_error(input, err),
+            };
+            match error {
+                FormatError::Truncated { expected, .. } if !self.eof => self.fill(expected)?,
+                _ => return Err(error.relative_to(self.offset(), path).into()),
+            }
+        }
+    }
+
+    /// Reads at least `min_bytes` (unless the reader ends). The buffer grows geometrically
+    /// so that restarting a parser on a large unit has amortized linear costs.
+    fn fill(&mut self, min_bytes: usize) -> std::io::Result<()> {
+        self.buf.drain(..self.pos);
+        self.buf_offset += self.pos;
+        self.pos = 0;
+        let num_bytes = min_bytes.max(MIN_READ_SIZE).max(self.buf.len());
+        let num_read = (&mut self.rd)
+            .take(num_bytes as u64)
+            .read_to_end(&mut self.buf)?;
+        self.eof = num_read < num_bytes;
+        Ok(())
+    }
+
+    /// Consumes the remaining input and returns its length.
+    fn skip_to_end(&mut self) -> std::io::Result<usize> {
+        let num_buffered = self.buf.len() - self.pos;
+        self.pos = self.buf.len();
+        let num_read = std::io::copy(&mut self.rd, &mut std::io::sink())?;
+        self.eof = true;
+        Ok(num_buffered + num_read as usize)
+    }
+}
+
+// ----------------------------------------------------------------------------
+// SequenceReader
+// ----------------------------------------------------------------------------
+
+struct CurrentTrack {
+    index: usize,
+    /// The `Params` for the notes.
+    params: Params,
+    num_notes: usize,
+    num_notes_read: usize,
+    /// Only used for the columnar layout.
+    buffered_notes: std::vec::IntoIter<Note>,
+}
+
+/// Pull based reader, see the module documentation.
+///
+/// Errors have the same offset and path as the errors of `parse_sequence`, but only the
+/// parts that have been read are validated. Call `finish` to validate the rest.
+pub struct SequenceReader<R> {
+    input: InputBuffer<R>,
+    /// The `Params` of the header contain the `ParseLimits`.
+    header: Header,
+    /// The `Params` for the contents of the tracks, i.e., one level of nesting deeper.
+    track_params: Params,
+    num_tracks: usize,
+    num_tracks_read: usize,
+    /// Number of notes of all tracks read so far, including the current one.
+    num_notes_total: usize,
+    current_track: Option<CurrentTrack>,
+}
+
+impl<R: Read> SequenceReader<R> {
+    /// Reads everything up to the first track, with the default `ParseLimits`.
+    pub fn new(rd: R) -> Result<Self, ReadError> {
+        Self::with_limits(rd, ParseLimits::default())
+    }
+
+    /// Like `new`, but rejects inputs exceeding the given `limits` (see
+    /// `parse_sequence_with_limits`).
+    pub fn with_limits(rd: R, limits: ParseLimits) -> Result<Self, ReadError> {
+        let mut input = InputBuffer::new(rd);
+        let header = input.parse(&[], parse_header(limits))?;
+        let params = header.params;
+        let path = [PathSegment::Field("tracks")];
+        // Same validation as `parse_vector`, which gives the same errors as `parse_sequence`.
+        let track_params = input.parse(&path, |input| nested(input, &params))?;
+        let max_len = track_params.limits.max_vector_len;
+        let num_tracks = input.parse(&path, parse_vector_len(max_len))?;
+        input.parse(&path, |input| require_bytes(input, num_tracks))?;
+        Ok(SequenceReader {
+            input,
+            header,
+            track_params,
+            num_tracks,
+            num_tracks_read: 0,
+            num_notes_total: 0,
+            current_track: None,
+        })
+    }
+
+    pub fn version(&self) -> FormatVersion {
+        self.header.params.version
+    }
+
+    pub fn note_layout(&self) -> NoteLayout {
+        self.header.params.note_layout
+    }
+
+    pub fn tempo_map(&self) -> &TempoMap {
+        &self.header.tempo_map
+    }
+
+    pub fn num_tracks(&self) -> usize {
+        self.num_tracks
+    }
+
+    /// Advances to the next track, skipping the unread notes of the current track.
+    pub fn next_track(&mut self) -> Result<Option<TrackHeader>, ReadError> {
+        self.finish_track()?;
+        if self.num_tracks_read == self.num_tracks {
+            return Ok(None);
+        }
+        let index = self.num_tracks_read;
+        let path = [PathSegment::Field("tracks"), PathSegment::Index(index)];
+        let params = self.track_params;
+        let track_header = self
+            .input
+            .parse(&path, |input| TrackHeader::parse(input, &params))?;
+
+        let path = [&path[..], &[PathSegment::Field("notes")]].concat();
+        let num_notes_before = self.num_notes_total;
+        let params = self.input.parse(&path, |input| {
+            check_num_notes(
+                input,
+                track_header.num_notes,
+                num_notes_before,
+                &params.limits,
+            )?;
+            nested(input, &params)
+        })?;
+        self.num_notes_total += track_header.num_notes;
+        let buffered_notes = match params.note_layout {
+            NoteLayout::Interleaved => {
+                let num_notes = track_header.num_notes;
+                self.input
+                    .parse(&path, |input| require_bytes(input, num_notes))?;
+                Vec::new()
+            }
+            NoteLayout::Columnar => {
+                let num_notes = track_header.num_notes;
+                self.input
+                    .parse(&path, |input| parse_note_columns(params, num_notes)(input))?
+            }
+        };
+
+        self.num_tracks_read += 1;
+        self.current_track = Some(CurrentTrack {
+            index,
+            params,
+            num_notes: track_header.num_notes,
+            num_notes_read: 0,
+            buffered_notes: buffered_notes.into_iter(),
+        });
+        Ok(Some(track_header))
+    }
+
+    /// Returns the next note of the current track, or `None` after its last note (and
+    /// before the first call of `next_track`).
+    pub fn next_note(&mut self) -> Result<Option<Note>, ReadError> {
+        let track = match &mut self.current_track {
+            Some(track) if track.num_notes_read < track.num_notes => track,
+            _ => return Ok(None),
+        };
+        let params = track.params;
+        let note = match params.note_layout {
+            NoteLayout::Interleaved => {
+                let path = [
+                    PathSegment::Field("tracks"),
+                    PathSegment::Index(track.index),
+                    PathSegment::Field("notes"),
+                    PathSegment::Index(track.num_notes_read),
+                ];
+                self.input
+                    .parse(&path, |input| Note::parse(input, &params))?
+            }
+            NoteLayout::Columnar => track
+                .buffered_notes
+                .next()
+                .expect("all notes of the track are buffered"),
+        };
+        track.num_notes_read += 1;
+        Ok(Some(note))
+    }
+
+    /// Iterates over the remaining notes of the current track.
+    pub fn notes(&mut self) -> impl Iterator<Item = Result<Note, ReadError>> + '_ {
+        std::iter::from_fn(move || self.next_note().transpose())
+    }
+
+    /// Reads (and validates) the remaining input, which must end with the sequence.
+    pub fn finish(mut self) -> Result<R, ReadError> {
+        while self.next_track()?.is_some() {}
+        let params = self.header.params;
+        self.input
+            .parse(&[PathSegment::Field("extension_blocks")], |input| {
+                parse_extension_blocks(params)(input)
+            })?;
+        let offset = self.input.offset();
+        let num_bytes = self.input.skip_to_end()?;
+        if num_bytes > 0 {
+            return Err(FormatError::TrailingData {
+                num_bytes,
+                location: Location {
+                    offset,
+                    path: Path::default(),
+                },
+            }
+            .into());
+        }
+        Ok(self.input.rd)
+    }
+
+    fn finish_track(&mut self) -> Result<(), ReadError> {
+        while self.next_note()?.is_some() {}
+        if let Some(track) = self.current_track.take() {
+            let params = self.track_params;
+            let path = [
+                PathSegment::Field("tracks"),
+                PathSegment::Index(track.index),
+                PathSegment::Field("extension_blocks"),
+            ];
+            self.input
+                .parse(&path, |input| parse_extension_blocks(params)(input))?;
+        }
+        Ok(())
+    }
+}
+
+// ----------------------------------------------------------------------------
+// SequenceWriter
+// ----------------------------------------------------------------------------
+
+struct OpenTrack {
+    num_notes: usize,
+    num_notes_written: usize,
+    /// Only used for the columnar layout.
+    buffered_notes: Vec<Note>,
+}
+
+/// Push based writer, see the module documentation.
+///
+/// Every track is started with `begin_track` and ends automatically with its last note.
+pub struct SequenceWriter<W> {
+    wr: W,
+    params: Params,
+    num_tracks: usize,
+    num_tracks_written: usize,
+    open_track: Option<OpenTrack>,
+}
+
+fn invalid_input(message: String) -> Error {
+    Error::new(ErrorKind::InvalidInput, message)
+}
+
+impl<W: Write> SequenceWriter<W> {
+    /// Writes everything up to the first track.
+    pub fn new(
+        mut wr: W,
+        params: &Params,
+        tempo_map: &TempoMap,
+        num_tracks: usize,
+    ) -> std::io::Result<Self> {
+        serialize_header(tempo_map, &mut wr, params)?;
+        Uint(num_tracks as u64).serialize_into(&mut wr, params)?;
+        Ok(SequenceWriter {
+            wr,
+            params: *params,
+            num_tracks,
+            num_tracks_written: 0,
+            open_track: None,
+        })
+    }
+
+    pub fn begin_track(&mut self, track_header: &TrackHeader) -> std::io::Result<()> {
+        if let Some(track) = &self.open_track {
+            return Err(invalid_input(format!(
+                "Cannot begin a track before the previous one is complete ({} of {} notes written)",
+                track.num_notes_written, track.num_notes
+            )));
+        }
+        if self.num_tracks_written == self.num_tracks {
+            return Err(invalid_input(format!(
+                "All {} tracks have been written already",
+                self.num_tracks
+            )));
+        }
+        track_header.serialize_into(&mut self.wr, &self.params)?;
+        self.num_tracks_written += 1;
+        self.open_track = Some(OpenTrack {
+            num_notes: track_header.num_notes,
+            num_notes_written: 0,
+            buffered_notes: Vec::new(),
+        });
+        self.end_track_if_complete()
+    }
+
+    pub fn write_note(&mut self, note: &Note) -> std::io::Result<()> {
+        let track = self.open_track.as_mut().ok_or_else(|| {
+            invalid_input("Notes can only be written after `begin_track`".to_string())
+        })?;
+        match self.params.note_layout {
+            NoteLayout::Interleaved => note.serialize_into(&mut self.wr, &self.params)?,
+            NoteLayout::Columnar => track.buffered_notes.push(note.clone()),
+        }
+        track.num_notes_written += 1;
+        self.end_track_if_complete()
+    }
+
+    /// Writes the end of the sequence and returns the underlying writer.
+    pub fn finish(mut self) -> std::io::Result<W> {
+        if self.open_track.is_some() || self.num_tracks_written < self.num_tracks {
+            return Err(invalid_input(format!(
+                "Cannot finish the sequence before all {} tracks are complete",
+                self.num_tracks
+            )));
+        }
+        if self.params.version.has_extension_blocks() {
+            NO_EXTENSION_BLOCKS.serialize_into(&mut self.wr, &self.params)?;
+        }
+        self.wr.flush()?;
+        Ok(self.wr)
+    }
+
+    fn end_track_if_complete(&mut self) -> std::io::Result<()> {
+        match &self.open_track {
+            Some(track) if track.num_notes_written == track.num_notes => {}
+            _ => return Ok(()),
+        }
+        let track = self.open_track.take().expect("track is open");
+        if self.params.note_layout == NoteLayout::Columnar {
+            serialize_note_columns(&track.buffered_notes, &mut self.wr, &self.params)?;
+        }
+        if self.params.version.has_extension_blocks() {
+            NO_EXTENSION_BLOCKS.serialize_into(&mut self.wr, &self.params)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::custom_file_format::{parse_sequence, serialize_sequence_to_vec};
+    use crate::synthetic::{gen_sequence, gen_sequence_with_tempo_events};
+    use crate::types::{NoteEffects, Sequence, Track};
+
+    use super::*;
+
+    /// Returns at most 3 bytes per `read` call to exercise the buffering.
+    struct SlowReader<'a>(&'a [u8]);
+
+    impl Read for SlowReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let num_bytes = buf.len().min(self.0.len()).min(3);
+            buf[..num_bytes].copy_from_slice(&self.0[..num_bytes]);
+            self.0 = &self.0[num_bytes..];
+            Ok(num_bytes)
+        }
+    }
+
+    fn read_sequence<R: Read>(rd: R) -> Result<Sequence, ReadError> {
+        let mut reader = SequenceReader::new(rd)?;
+        let tempo_map = reader.tempo_map().clone();
+        let mut tracks = vec![];
+        while let Some(header) = reader.next_track()? {
+            tracks.push(Track {
+                name: header.name,
+                is_percussion: header.is_percussion,
+                tuning: header.tuning,
+                notes: reader.notes().collect::<Result<_, _>>()?,
+            });
+        }
+        reader.finish()?;
+        Ok(Sequence { tempo_map, tracks })
+    }
+
+    fn write_sequence(sequence: &Sequence, params: &Params) -> Vec<u8> {
+        let mut writer =
+            SequenceWriter::new(vec![], params, &sequence.tempo_map, sequence.tracks.len())
+                .unwrap();
+        for track in &sequence.tracks {
+            writer
+                .begin_track(&TrackHeader {
+                    name: track.name.clone(),
+                    is_percussion: track.is_percussion,
+                    tuning: track.tuning.clone(),
+                    num_notes: track.notes.len(),
+                })
+                .unwrap();
+            for note in &track.notes {
+                writer.write_note(note).unwrap();
+            }
+        }
+        writer.finish().unwrap()
+    }
+
+    fn all_params() -> Vec<Params> {
+        [
+            (FormatVersion::V0, NoteLayout::Interleaved),
+            (FormatVersion::V1, NoteLayout::Interleaved),
+            (FormatVersion::V2, NoteLayout::Interleaved),
+            (FormatVersion::V2, NoteLayout::Columnar),
+            (FormatVersion::V3, NoteLayout::Interleaved),
+            (FormatVersion::V3, NoteLayout::Columnar),
+        ]
+        .into_iter()
+        .map(|(version, note_layout)| Params {
+            version,
+            note_layout,
+            ..Params::default()
+        })
+        .collect()
+    }
+
+    #[test]
+    fn test_streaming_roundtrip() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..20 {
+            let sequence = gen_sequence(&mut rng);
+            let sequence_with_events = gen_sequence_with_tempo_events(&mut rng);
+            for params in all_params() {
+                let sequence = if params.version.has_tempo_events() {
+                    &sequence_with_events
+                } else {
+                    &sequence
+                };
+                let data = write_sequence(sequence, &params);
+                assert_eq!(data, serialize_sequence_to_vec(sequence, &params).unwrap());
+
+                let expected = parse_sequence(&data).unwrap();
+                assert_eq!(read_sequence(&data[..]).unwrap(), expected);
+                assert_eq!(read_sequence(SlowReader(&data)).unwrap(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_skipping_notes() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let sequence = gen_sequence(&mut rng);
+        for params in all_params() {
+            let data = serialize_sequence_to_vec(&sequence, &params).unwrap();
+            let mut reader = SequenceReader::new(&data[..]).unwrap();
+            assert_eq!(reader.version(), params.version);
+            assert_eq!(reader.num_tracks(), sequence.tracks.len());
+            for track in &sequence.tracks {
+                let header = reader.next_track().unwrap().unwrap();
+                assert_eq!(header.name, track.name);
+                assert_eq!(header.num_notes, track.notes.len());
+                // only read the first note
+                if let Some(note) = track.notes.first() {
+                    let quantized = reader.next_note().unwrap().unwrap();
+                    assert!((quantized.s - note.s).abs() < 1e-3);
+                }
+            }
+            assert!(reader.next_track().unwrap().is_none());
+            assert!(reader.next_note().unwrap().is_none());
+            reader.finish().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_errors_match_parse_sequence() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let sequence = gen_sequence(&mut rng);
+        for params in all_params() {
+            let mut data = serialize_sequence_to_vec(&sequence, &params).unwrap();
+            data.push(0);
+            for len in 0..data.len() + 1 {
+                let expected = parse_sequence(&data[..len]).map_err(|err| err.to_string());
+                let result = read_sequence(SlowReader(&data[..len])).map_err(|err| err.to_string());
+                assert_eq!(result, expected, "len = {}", len);
+            }
+        }
+    }
+
+    #[test]
+    fn test_writer_misuse() {
+        let params = Params::default();
+        let tempo_map = TempoMap::new(120.0);
+        let header = TrackHeader {
+            name: "A".to_string(),
+            is_percussion: false,
+            tuning: Tuning {
+                string_base_pitches: vec![],
+            },
+            num_notes: 1,
+        };
+        let note = Note {
+            s: 0.0,
+            d: 1.0,
+            pitch: 60,
+            string: 0,
+            fret: 0,
+            effects: NoteEffects::default(),
+        };
+
+        let mut writer = SequenceWriter::new(vec![], &params, &tempo_map, 1).unwrap();
+        assert!(writer.write_note(&note).is_err());
+        writer.begin_track(&header).unwrap();
+        assert!(writer.begin_track(&header).is_err());
+        writer.write_note(&note).unwrap();
+        assert!(writer.write_note(&note).is_err());
+        assert!(writer.begin_track(&header).is_err());
+        writer.finish().unwrap();
+
+        let writer = SequenceWriter::new(vec![], &params, &tempo_map, 1).unwrap();
+        assert!(writer.finish().is_err());
+    }
+}
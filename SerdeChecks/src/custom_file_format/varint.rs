@@ -1,9 +1,7 @@
-use std::cell::Cell;
 use std::io::Result;
 use std::io::Write;
 
-use nom::{bytes::complete::take_while, IResult};
-
+use super::errors::{fail, FormatError, ParseResult};
 use super::serialize::Serialize;
 
 // ----------------------------------------------------------------------------
@@ -42,29 +40,20 @@ impl<C> Serialize<C> for Uint {
     }
 }
 
-pub fn parse_uint(input: &[u8]) -> IResult<&[u8], u64> {
-    let x_cell = Cell::<u64>::new(0);
-    let i_cell = Cell::new(0);
-    let end_reached_cell = Cell::new(false);
-
-    let (input, _) = take_while(|c| {
-        let end_reached = end_reached_cell.get();
-        if end_reached {
-            false
-        } else {
-            let i = i_cell.get();
-            let x = x_cell.get();
-            let is_last = i == 8;
-
-            i_cell.set(i + 1);
-            x_cell.set(x + ((if is_last { c } else { c & 0x7f } as u64) << (i as u64 * 7)));
-
-            end_reached_cell.set(c < 0x80 || is_last);
-            true
+pub fn parse_uint(input: &[u8]) -> ParseResult<'_, u64> {
+    let mut x = 0u64;
+    for (i, &c) in input.iter().take(9).enumerate() {
+        let is_last = i == 8;
+        x |= (if is_last { c } else { c & 0x7f } as u64) << (i as u64 * 7);
+        if c < 0x80 || is_last {
+            return Ok((&input[i + 1..], x));
         }
-    })(input)?;
-
-    Ok((input, x_cell.get()))
+    }
+    // Either empty, or all available bytes have the continuation bit set.
+    fail(input, |location| FormatError::Truncated {
+        expected: 1,
+        location,
+    })
 }
 
 // ----------------------------------------------------------------------------
@@ -84,7 +73,7 @@ impl<C> Serialize<C> for Int {
     }
 }
 
-pub fn parse_int(input: &[u8]) -> IResult<&[u8], i64> {
+pub fn parse_int(input: &[u8]) -> ParseResult<'_, i64> {
     let (input, uint) = parse_uint(input)?;
     Ok((input, zig_zag_decode(uint)))
 }
@@ -97,7 +86,7 @@ fn zig_zag_encode(x: i64) -> u64 {
     }
 }
 
-fn zig_zag_decode(x: u64) -> i64 {
+pub(super) fn zig_zag_decode(x: u64) -> i64 {
     if x & 1 == 1 {
         !(x >> 1) as i64
     } else {
@@ -294,6 +283,13 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_parse_uint_truncated() {
+        assert!(parse_uint(&[]).is_err());
+        assert!(parse_uint(&[0x80]).is_err());
+        assert!(parse_uint(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]).is_err());
+    }
+
     #[test]
     fn test_zig_zag_encode() {
         assert_eq!(zig_zag_encode(0), 0);
@@ -0,0 +1,584 @@
+//! Versioning of the custom file format.
+//!
+//! Layouts:
+//!
+//! - v0 (legacy): No magic header, the file starts with `file_version: i8 = 0`, followed by
+//!   the quantization params, the tempo map and the tracks.
+//! - v1: The file starts with the magic bytes `SEQF` and `file_version: i8 = 1`. The body is
+//!   the same as v0, but every track and the sequence itself end with a list of extension
+//!   blocks.
+//! - v2: Same as v1, but the quantization params are followed by a `NoteLayout` byte, which
+//!   allows to store the notes of all tracks in the columnar layout (see `columnar`).
+//! - v3: Same as v2, but the tempo map is followed by its tempo changes and time signatures.
+//!   These are not an extension block, because the sequence level blocks follow the tracks,
+//!   and a streaming reader needs the complete tempo map before the first track.
+//!
+//! An extension block is a tag followed by a length prefixed payload. Readers skip blocks
+//! with unknown tags, which allows to add data without breaking older readers. Per note data
+//! should not be added via a per note block (too costly), but rather as a track level block
+//! storing a column with one value per note.
+//!
+//! Changes that cannot be expressed as an extension block require a new version, which
+//! `parse_sequence` has to dispatch on.
+
+use std::io::Result;
+use std::io::Write;
+
+use super::deserialize_fundamentals::{parse_bytes, parse_i8, take_bytes};
+use super::errors::{fail, FormatError, ParseResult};
+use super::serialize::Serialize;
+use super::varint::{parse_uint, Uint};
+
+pub const MAGIC: [u8; 4] = *b"SEQF";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FormatVersion {
+    V0,
+    V1,
+    V2,
+    V3,
+}
+
+impl FormatVersion {
+    pub const LATEST: FormatVersion = FormatVersion::V3;
+
+    pub fn as_i8(self) -> i8 {
+        match self {
+            FormatVersion::V0 => 0,
+            FormatVersion::V1 => 1,
+            FormatVersion::V2 => 2,
+            FormatVersion::V3 => 3,
+        }
+    }
+
+    pub fn from_i8(version: i8) -> Option<FormatVersion> {
+        match version {
+            0 => Some(FormatVersion::V0),
+            1 => Some(FormatVersion::V1),
+            2 => Some(FormatVersion::V2),
+            3 => Some(FormatVersion::V3),
+            _ => None,
+        }
+    }
+
+    pub fn has_magic_header(self) -> bool {
+        self >= FormatVersion::V1
+    }
+
+    pub fn has_extension_blocks(self) -> bool {
+        self >= FormatVersion::V1
+    }
+
+    pub fn has_note_layout(self) -> bool {
+        self >= FormatVersion::V2
+    }
+
+    pub fn has_tempo_events(self) -> bool {
+        self >= FormatVersion::V3
+    }
+}
+
+impl<C> Serialize<C> for FormatVersion {
+    fn serialize_into<W>(&self, wr: &mut W, context: &C) -> Result<()>
+    where
+        W: Write,
+    {
+        if self.has_magic_header() {
+            wr.write_all(&MAGIC)?;
+        }
+        self.as_i8().serialize_into(wr, context)
+    }
+}
+
+pub fn parse_format_version(input: &[u8]) -> ParseResult<'_, FormatVersion> {
+    // Legacy files have no magic header, but since their first byte is the version 0,
+    // they cannot be confused with the magic header.
+    let (input, has_magic_header) = if input.first() == Some(&0) {
+        (input, false)
+    } else {
+        // Only a proper prefix of the magic header counts as truncated.
+        let prefix_len = input.len().min(MAGIC.len());
+        if input[..prefix_len] != MAGIC[..prefix_len] {
+            return fail(input, |location| FormatError::InvalidMagic { location });
+        }
+        (take_bytes(MAGIC.len())(input)?.0, true)
+    };
+    let (rest, file_version) = parse_i8(input)?;
+    match FormatVersion::from_i8(file_version) {
+        Some(version) if version.has_magic_header() == has_magic_header => Ok((rest, version)),
+        _ => fail(input, |location| FormatError::UnsupportedVersion {
+            version: file_version,
+            location,
+        }),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExtensionBlock<'a> {
+    pub tag: u64,
+    pub payload: &'a [u8],
+}
+
+impl<'a, C> Serialize<C> for ExtensionBlock<'a> {
+    fn serialize_into<W>(&self, wr: &mut W, context: &C) -> Result<()>
+    where
+        W: Write,
+    {
+        Uint(self.tag).serialize_into(wr, context)?;
+        self.payload.serialize_into(wr, context)?;
+        Ok(())
+    }
+}
+
+pub fn parse_extension_block(input: &[u8]) -> ParseResult<'_, ExtensionBlock<'_>> {
+    let (input, tag) = parse_uint(input)?;
+    let (input, payload) = parse_bytes(input)?;
+    Ok((input, ExtensionBlock { tag, payload }))
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use crate::custom_file_format::{
+        parse_sequence, serialize_sequence_to_vec, FormatError, Location, NoteLayout, Params, Path,
+    };
+    use crate::types::{
+        BendData, BendPoint, Note, NoteEffects, Sequence, TempoChange, TempoMap, TimeSignature,
+        Track, Tuning,
+    };
+
+    use super::super::errors::into_format_error;
+    use super::*;
+
+    const GOLDEN_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/golden");
+
+    const GOLDEN_FILES: [(FormatVersion, NoteLayout, &str); 6] = [
+        (
+            FormatVersion::V0,
+            NoteLayout::Interleaved,
+            "sequence_v0.bin",
+        ),
+        (
+            FormatVersion::V1,
+            NoteLayout::Interleaved,
+            "sequence_v1.bin",
+        ),
+        (
+            FormatVersion::V2,
+            NoteLayout::Interleaved,
+            "sequence_v2.bin",
+        ),
+        (
+            FormatVersion::V2,
+            NoteLayout::Columnar,
+            "sequence_v2_columnar.bin",
+        ),
+        (
+            FormatVersion::V3,
+            NoteLayout::Interleaved,
+            "sequence_v3.bin",
+        ),
+        (
+            FormatVersion::V3,
+            NoteLayout::Columnar,
+            "sequence_v3_columnar.bin",
+        ),
+    ];
+
+    fn golden_sequence() -> Sequence {
+        Sequence {
+            tempo_map: TempoMap::new(120.0),
+            tracks: vec![
+                Track {
+                    name: "Guitar".to_string(),
+                    is_percussion: false,
+                    tuning: Tuning {
+                        string_base_pitches: vec![64, 59, 55, 50, 45, 40],
+                    },
+                    notes: vec![
+                        Note {
+                            s: 0.0,
+                            d: 1.0,
+                            pitch: 64,
+                            string: 0,
+                            fret: 0,
+                            effects: NoteEffects::default(),
+                        },
+                        Note {
+                            s: 1.5,
+                            d: 0.25,
+                            pitch: 57,
+                            string: 2,
+                            fret: 2,
+                            effects: NoteEffects {
+                                dead_note: true,
+                                vibrato: false,
+                                bend_data: None,
+                            },
+                        },
+                        Note {
+                            s: 300.75,
+                            d: 2.0,
+                            pitch: 69,
+                            string: 1,
+                            fret: 10,
+                            effects: NoteEffects {
+                                dead_note: false,
+                                vibrato: true,
+                                bend_data: Some(BendData {
+                                    points: vec![
+                                        BendPoint {
+                                            pos: 0.0,
+                                            bend: 0.0,
+                                        },
+                                        BendPoint {
+                                            pos: 0.5,
+                                            bend: 1.0,
+                                        },
+                                        BendPoint {
+                                            pos: 1.0,
+                                            bend: -0.5,
+                                        },
+                                    ],
+                                }),
+                            },
+                        },
+                    ],
+                },
+                Track {
+                    name: "Drums".to_string(),
+                    is_percussion: true,
+                    tuning: Tuning {
+                        string_base_pitches: vec![],
+                    },
+                    notes: vec![Note {
+                        s: 2.0,
+                        d: 0.5,
+                        pitch: 36,
+                        string: 0,
+                        fret: 0,
+                        effects: NoteEffects::default(),
+                    }],
+                },
+            ],
+        }
+    }
+
+    /// The golden sequence with the tempo events that `version` supports.
+    fn golden_sequence_for(version: FormatVersion) -> Sequence {
+        let mut sequence = golden_sequence();
+        if version.has_tempo_events() {
+            sequence.tempo_map.tempo_changes = vec![
+                TempoChange {
+                    beat: 4.0,
+                    bpm: 90.0,
+                },
+                TempoChange {
+                    beat: 8.5,
+                    bpm: 140.25,
+                },
+            ];
+            sequence.tempo_map.time_signatures = vec![
+                TimeSignature {
+                    beat: 0.0,
+                    numerator: 4,
+                    denominator: 4,
+                },
+                TimeSignature {
+                    beat: 16.0,
+                    numerator: 7,
+                    denominator: 8,
+                },
+            ];
+        }
+        sequence
+    }
+
+    fn params(version: FormatVersion, note_layout: NoteLayout) -> Params {
+        Params {
+            version,
+            note_layout,
+            ..Params::default()
+        }
+    }
+
+    fn read_golden_file(name: &str) -> Vec<u8> {
+        let path = format!("{}/{}", GOLDEN_DIR, name);
+        std::fs::read(&path).unwrap_or_else(|e| panic!("Failed to read {}: {}", path, e))
+    }
+
+    /// Run with `cargo test -- --ignored` after an intended format change.
+    #[test]
+    #[ignore]
+    fn regenerate_golden_files() {
+        std::fs::create_dir_all(GOLDEN_DIR).unwrap();
+        for (version, note_layout, name) in GOLDEN_FILES {
+            let params = params(version, note_layout);
+            let data = serialize_sequence_to_vec(&golden_sequence_for(version), &params).unwrap();
+            std::fs::write(format!("{}/{}", GOLDEN_DIR, name), data).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_golden_files_serialize() {
+        for (version, note_layout, name) in GOLDEN_FILES {
+            let params = params(version, note_layout);
+            let data = serialize_sequence_to_vec(&golden_sequence_for(version), &params).unwrap();
+            assert_eq!(data, read_golden_file(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_golden_files_parse() {
+        for (version, _, name) in GOLDEN_FILES {
+            let data = read_golden_file(name);
+            assert_eq!(parse_format_version(&data).unwrap().1, version);
+            let sequence = parse_sequence(&data).unwrap();
+            assert_eq!(sequence, golden_sequence_for(version), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_golden_files_header() {
+        let v0 = read_golden_file("sequence_v0.bin");
+        let v1 = read_golden_file("sequence_v1.bin");
+        assert_eq!(v0[0], 0);
+        assert_eq!(v1[..5], *b"SEQF\x01");
+        // v1 only adds the header and one empty extension list per track and sequence.
+        assert_eq!(v1.len(), v0.len() + 4 + 2 + 1);
+
+        let v2 = read_golden_file("sequence_v2.bin");
+        let v2_columnar = read_golden_file("sequence_v2_columnar.bin");
+        assert_eq!(v2[..5], *b"SEQF\x02");
+        // v2 only adds the note layout byte
+        assert_eq!(v2.len(), v1.len() + 1);
+        // after magic, version, and the two 2-byte varints of the quantization params
+        assert_eq!(v2[9], 0);
+        assert_eq!(v2_columnar[9], 1);
+
+        let v3 = read_golden_file("sequence_v3.bin");
+        assert_eq!(v3[..5], *b"SEQF\x03");
+        // v3 adds the lists of tempo changes (22 bytes) and time signatures (9 bytes) after
+        // the base tempo, which ends at offset 18
+        assert_eq!(v3.len(), v2.len() + 22 + 9);
+        assert_eq!(v3[18], 2);
+    }
+
+    #[test]
+    fn test_tempo_events_require_v3() {
+        let sequence = golden_sequence_for(FormatVersion::V3);
+        for version in [FormatVersion::V0, FormatVersion::V1, FormatVersion::V2] {
+            let err =
+                serialize_sequence_to_vec(&sequence, &params(version, NoteLayout::Interleaved))
+                    .unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!(
+                    "Tempo changes and time signatures are not supported by format version {:?}",
+                    version
+                )
+            );
+        }
+        // Without events, a v3 file only differs in the header and the two empty lists.
+        let v2 = read_golden_file("sequence_v2.bin");
+        let v3 = serialize_sequence_to_vec(
+            &golden_sequence(),
+            &params(FormatVersion::V3, NoteLayout::Interleaved),
+        )
+        .unwrap();
+        assert_eq!(v3.len(), v2.len() + 2);
+        assert_eq!(parse_sequence(&v3).unwrap(), golden_sequence());
+    }
+
+    fn minimal_sequence() -> Sequence {
+        Sequence {
+            tempo_map: TempoMap::new(120.0),
+            tracks: vec![Track {
+                name: "A".to_string(),
+                is_percussion: false,
+                tuning: Tuning {
+                    string_base_pitches: vec![],
+                },
+                notes: vec![Note {
+                    s: 1.5,
+                    d: 0.25,
+                    pitch: 64,
+                    string: 1,
+                    fret: 5,
+                    effects: NoteEffects::default(),
+                }],
+            }],
+        }
+    }
+
+    fn minimal_sequence_v1(track_extensions: &[u8], sequence_extensions: &[u8]) -> Vec<u8> {
+        let mut data = vec![];
+        data.extend(b"SEQF");
+        data.extend([1, 4, 2]); // version, time_quantization, pitch_quantization
+        data.extend(120f64.to_le_bytes());
+        data.extend([1, 1, b'A', 0, 0]); // one track with name, is_percussion, tuning
+        data.extend([1, 1, 2, 1, 64, 1, 5, 0, 0, 0]); // one note
+        data.extend(track_extensions);
+        data.extend(sequence_extensions);
+        data
+    }
+
+    #[test]
+    fn test_minimal_v1_bytes() {
+        let params = Params {
+            time_quantization: 4,
+            pitch_quantization: 2,
+            version: FormatVersion::V1,
+            note_layout: NoteLayout::Interleaved,
+            ..Params::default()
+        };
+        assert_eq!(
+            serialize_sequence_to_vec(&minimal_sequence(), &params).unwrap(),
+            minimal_sequence_v1(&[0], &[0]),
+        );
+    }
+
+    #[test]
+    fn test_unknown_extension_blocks_are_skipped() {
+        let track_extensions = [1, 7, 3, 1, 2, 3];
+        let sequence_extensions = [2, 1, 0, 0xac, 0x02, 1, 0xff]; // tags 1 and 300
+        let data = minimal_sequence_v1(&track_extensions, &sequence_extensions);
+        let sequence = parse_sequence(&data).unwrap();
+        assert_eq!(sequence, minimal_sequence());
+
+        let (rest, blocks) = parse_extension_block(&track_extensions[1..]).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(
+            blocks,
+            ExtensionBlock {
+                tag: 7,
+                payload: &[1, 2, 3]
+            }
+        );
+    }
+
+    /// Errors are compared by their message, which contains the offset and the path.
+    fn parse_error(data: &[u8]) -> String {
+        parse_sequence(data).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_truncated_extension_block() {
+        let data = minimal_sequence_v1(&[1, 7, 3, 1, 2], &[]);
+        assert_eq!(
+            parse_error(&data),
+            "Unexpected end of input, expected 1 more byte(s) at offset 33 in \
+             tracks[0].extension_blocks[0]"
+        );
+    }
+
+    #[test]
+    fn test_error_locations() {
+        // Offsets in `minimal_sequence_v1`: version at 4, time_quantization at 5, the track
+        // name at 16, and the note from 21 to 29.
+        let valid = minimal_sequence_v1(&[0], &[0]);
+        let with_byte = |index: usize, value: u8| {
+            let mut data = valid.clone();
+            data[index] = value;
+            data
+        };
+        assert_eq!(
+            parse_error(&with_byte(4, 4)),
+            "Unsupported file version 4 at offset 4 in version"
+        );
+        assert_eq!(
+            parse_error(&with_byte(5, 0)),
+            "Quantization must not be zero at offset 5 in time_quantization"
+        );
+        assert_eq!(
+            parse_error(&with_byte(17, 0xff)),
+            "Invalid UTF-8 in string at offset 16 in tracks[0].name"
+        );
+        assert_eq!(
+            parse_error(&with_byte(27, 2)),
+            "Invalid bool value 2 at offset 27 in tracks[0].notes[0].effects.dead_note"
+        );
+        assert_eq!(
+            parse_error(&valid[..25]),
+            "Unexpected end of input, expected 1 more byte(s) at offset 25 in \
+             tracks[0].notes[0].string"
+        );
+        assert_eq!(
+            parse_sequence(&[&valid[..], &[0, 0]].concat()).unwrap_err(),
+            FormatError::TrailingData {
+                num_bytes: 2,
+                location: Location {
+                    offset: 32,
+                    path: Path::default(),
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_error_locations_columnar() {
+        let params = params(FormatVersion::V2, NoteLayout::Columnar);
+        let valid = serialize_sequence_to_vec(&golden_sequence(), &params).unwrap();
+        let flags_offset = valid
+            .windows(3)
+            .position(|flags| flags == [0, 1, 6])
+            .expect("flags column of the first track");
+
+        let mut data = valid.clone();
+        data[flags_offset + 1] = 0x81;
+        assert_eq!(
+            parse_error(&data),
+            format!(
+                "Invalid note flags 0x81 at offset {} in tracks[0].notes.flags[1]",
+                flags_offset + 1
+            )
+        );
+        // the bend data starts after the flags with the number of points (3)
+        assert_eq!(
+            parse_error(&valid[..flags_offset + 4]),
+            format!(
+                "Unexpected end of input, expected 3 more byte(s) at offset {} in \
+                 tracks[0].notes[2].effects.bend_data.points",
+                flags_offset + 4
+            )
+        );
+    }
+
+    #[test]
+    fn test_invalid_headers() {
+        let error = |input: &[u8]| {
+            let err = parse_format_version(input).unwrap_err();
+            into_format_error(input, err).to_string()
+        };
+        assert_eq!(
+            error(b"SEQF\x04"),
+            "Unsupported file version 4 at offset 4 in <root>"
+        );
+        assert_eq!(
+            error(b"SEQX\x01"),
+            "Invalid magic header at offset 0 in <root>"
+        );
+        assert_eq!(
+            error(b"SE"),
+            "Unexpected end of input, expected 2 more byte(s) at offset 0 in <root>"
+        );
+
+        // unknown version
+        assert!(parse_format_version(b"SEQF\x04").is_err());
+        // v0 must not have a magic header, and v1 must have one
+        assert!(parse_format_version(b"SEQF\x00").is_err());
+        assert!(parse_format_version(&[1]).is_err());
+        // wrong magic
+        assert!(parse_format_version(b"SEQX\x01").is_err());
+        assert!(parse_format_version(b"").is_err());
+        assert_eq!(
+            parse_format_version(&[0, 42]).unwrap(),
+            (&[42][..], FormatVersion::V0)
+        );
+        assert_eq!(
+            parse_format_version(b"SEQF\x01").unwrap(),
+            (&[][..], FormatVersion::V1)
+        );
+    }
+}
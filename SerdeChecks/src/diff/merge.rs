@@ -0,0 +1,567 @@
+//! Three-way merge, every element matched by key is merged like a value: a change on one
+//! side wins over the base, the same change on both sides is taken once, and different
+//! changes on both sides are a conflict.
+
+use std::fmt;
+
+use crate::types::{Note, Sequence, TempoChange, TempoMap, TimeSignature, Track};
+
+use super::*;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Side {
+    Ours,
+    Theirs,
+}
+
+/// Different changes on both sides. `None` stands for an element that does not exist on
+/// that side.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Conflict {
+    BpmBase {
+        base: f64,
+        ours: f64,
+        theirs: f64,
+    },
+    Tempo {
+        beat: f64,
+        base: Option<f64>,
+        ours: Option<f64>,
+        theirs: Option<f64>,
+    },
+    TimeSignature {
+        beat: f64,
+        base: Option<(u8, u8)>,
+        ours: Option<(u8, u8)>,
+        theirs: Option<(u8, u8)>,
+    },
+    /// Removed on one side and modified on the other.
+    TrackRemoved {
+        track: String,
+        removed_by: Side,
+    },
+    /// `property` is "percussion" or "tuning".
+    TrackProperty {
+        track: String,
+        property: &'static str,
+    },
+    Note {
+        track: String,
+        /// Whether the notes are matched by pitch instead of string, i.e., percussion.
+        keyed_by_pitch: bool,
+        base: Option<Note>,
+        ours: Option<Note>,
+        theirs: Option<Note>,
+    },
+}
+
+/// The conflicts of a merge, and the merge with every conflict resolved in favor of ours.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MergeConflicts {
+    pub conflicts: Vec<Conflict>,
+    pub resolved_with_ours: Sequence,
+}
+
+/// The three-way merge of a value, `None` for a conflict.
+fn merge_value<T: PartialEq + Clone>(base: &T, ours: &T, theirs: &T) -> Option<T> {
+    if ours == theirs || theirs == base {
+        Some(ours.clone())
+    } else if ours == base {
+        Some(theirs.clone())
+    } else {
+        None
+    }
+}
+
+/// Merges the changes from `base` to `ours` and from `base` to `theirs`.
+///
+/// Tracks keep the order of ours, followed by the tracks only theirs added. Where both
+/// sides changed the notes of a track, the merged notes are sorted by onset and string.
+///
+/// Unlike `diff_sequences`, notes are matched by their exact onset and string (or pitch),
+/// without an alignment. A note moved in time on one side is removed and added there, so if
+/// the other side modified it, that is a conflict of the removal and the modification.
+pub fn merge_sequences(
+    base: &Sequence,
+    ours: &Sequence,
+    theirs: &Sequence,
+) -> Result<Sequence, MergeConflicts> {
+    let mut conflicts = vec![];
+    let tempo_map = merge_tempo_maps(
+        &base.tempo_map,
+        &ours.tempo_map,
+        &theirs.tempo_map,
+        &mut conflicts,
+    );
+
+    let tracks_by_name = [
+        &tracks_by_name(&base.tracks),
+        &tracks_by_name(&ours.tracks),
+        &tracks_by_name(&theirs.tracks),
+    ];
+    let mut tracks = vec![];
+    for (_, [b, o, t]) in join(tracks_by_name) {
+        let (b, o, t) = (
+            b.map(|i| &base.tracks[*i]),
+            o.map(|i| (*i, &ours.tracks[*i])),
+            t.map(|i| (*i, &theirs.tracks[*i])),
+        );
+        let (our_track, their_track) = (o.map(|(_, track)| track), t.map(|(_, track)| track));
+        let track = match merge_value(&b, &our_track, &their_track) {
+            Some(track) => track.cloned(),
+            None => match (our_track, their_track) {
+                (Some(ours), Some(theirs)) => Some(merge_tracks(b, ours, theirs, &mut conflicts)),
+                _ => {
+                    let track = b.unwrap().name.clone();
+                    let removed_by = if o.is_none() {
+                        Side::Ours
+                    } else {
+                        Side::Theirs
+                    };
+                    conflicts.push(Conflict::TrackRemoved { track, removed_by });
+                    our_track.cloned()
+                }
+            },
+        };
+        // the tracks of ours, followed by the tracks only theirs has
+        let order = match (o, t) {
+            (Some((i, _)), _) => (0, i),
+            (None, Some((j, _))) => (1, j),
+            (None, None) => continue,
+        };
+        if let Some(track) = track {
+            tracks.push((order, track));
+        }
+    }
+    tracks.sort_by_key(|(order, _)| *order);
+
+    let sequence = Sequence {
+        tempo_map,
+        tracks: tracks.into_iter().map(|(.., track)| track).collect(),
+    };
+    if conflicts.is_empty() {
+        Ok(sequence)
+    } else {
+        Err(MergeConflicts {
+            conflicts,
+            resolved_with_ours: sequence,
+        })
+    }
+}
+
+fn merge_tempo_maps(
+    base: &TempoMap,
+    ours: &TempoMap,
+    theirs: &TempoMap,
+    conflicts: &mut Vec<Conflict>,
+) -> TempoMap {
+    let bpm_base =
+        merge_value(&base.bpm_base, &ours.bpm_base, &theirs.bpm_base).unwrap_or_else(|| {
+            conflicts.push(Conflict::BpmBase {
+                base: base.bpm_base,
+                ours: ours.bpm_base,
+                theirs: theirs.bpm_base,
+            });
+            ours.bpm_base
+        });
+
+    let mut tempo_changes = vec![];
+    let tempos = [
+        &tempos_by_beat(base),
+        &tempos_by_beat(ours),
+        &tempos_by_beat(theirs),
+    ];
+    for (beat, [b, o, t]) in join(tempos) {
+        let (b, o, t) = (b.copied(), o.copied(), t.copied());
+        let bpm = merge_value(&b, &o, &t).unwrap_or_else(|| {
+            conflicts.push(Conflict::Tempo {
+                beat: beat.0,
+                base: b,
+                ours: o,
+                theirs: t,
+            });
+            o
+        });
+        if let Some(bpm) = bpm {
+            tempo_changes.push(TempoChange { beat: beat.0, bpm });
+        }
+    }
+
+    let mut time_signatures = vec![];
+    let by_beat = [
+        &time_signatures_by_beat(base),
+        &time_signatures_by_beat(ours),
+        &time_signatures_by_beat(theirs),
+    ];
+    for (beat, [b, o, t]) in join(by_beat) {
+        let (b, o, t) = (b.copied(), o.copied(), t.copied());
+        let time_signature = merge_value(&b, &o, &t).unwrap_or_else(|| {
+            conflicts.push(Conflict::TimeSignature {
+                beat: beat.0,
+                base: b,
+                ours: o,
+                theirs: t,
+            });
+            o
+        });
+        if let Some((numerator, denominator)) = time_signature {
+            time_signatures.push(TimeSignature {
+                beat: beat.0,
+                numerator,
+                denominator,
+            });
+        }
+    }
+
+    TempoMap {
+        bpm_base,
+        tempo_changes,
+        time_signatures,
+    }
+}
+
+/// The merged value of a track property, `base` is `None` for tracks both sides added.
+fn merge_property<T: PartialEq + Clone>(
+    track: &str,
+    property: &'static str,
+    base: Option<&T>,
+    ours: &T,
+    theirs: &T,
+    conflicts: &mut Vec<Conflict>,
+) -> T {
+    match merge_value(&base, &Some(ours), &Some(theirs)).flatten() {
+        Some(value) => value.clone(),
+        None => {
+            conflicts.push(Conflict::TrackProperty {
+                track: track.to_string(),
+                property,
+            });
+            ours.clone()
+        }
+    }
+}
+
+/// Merges tracks changed on both sides, `base` is `None` for tracks both sides added.
+fn merge_tracks(
+    base: Option<&Track>,
+    ours: &Track,
+    theirs: &Track,
+    conflicts: &mut Vec<Conflict>,
+) -> Track {
+    let is_percussion = merge_property(
+        &ours.name,
+        "percussion",
+        base.map(|b| &b.is_percussion),
+        &ours.is_percussion,
+        &theirs.is_percussion,
+        conflicts,
+    );
+    let tuning = merge_property(
+        &ours.name,
+        "tuning",
+        base.map(|b| &b.tuning),
+        &ours.tuning,
+        &theirs.tuning,
+        conflicts,
+    );
+    let base_notes = base.map(|b| &b.notes);
+    let notes = match merge_value(&base_notes, &Some(&ours.notes), &Some(&theirs.notes)) {
+        Some(Some(notes)) => notes.clone(),
+        _ => merge_notes(base, ours, theirs, is_percussion, conflicts),
+    };
+    Track {
+        name: ours.name.clone(),
+        is_percussion,
+        tuning,
+        notes,
+    }
+}
+
+fn merge_notes(
+    base: Option<&Track>,
+    ours: &Track,
+    theirs: &Track,
+    is_percussion: bool,
+    conflicts: &mut Vec<Conflict>,
+) -> Vec<Note> {
+    let empty = Track::default();
+    let notes_by_key = [
+        &notes_by_key(base.unwrap_or(&empty), is_percussion),
+        &notes_by_key(ours, is_percussion),
+        &notes_by_key(theirs, is_percussion),
+    ];
+    let mut notes = vec![];
+    for (_, notes_with_key) in join(notes_by_key) {
+        let [b, o, t] = notes_with_key.map(|note| note.map(|(_, note)| *note));
+        let note = merge_value(&b, &o, &t).unwrap_or_else(|| {
+            conflicts.push(Conflict::Note {
+                track: ours.name.clone(),
+                keyed_by_pitch: is_percussion,
+                base: b.cloned(),
+                ours: o.cloned(),
+                theirs: t.cloned(),
+            });
+            o
+        });
+        notes.extend(note.cloned());
+    }
+    notes
+}
+
+// ----------------------------------------------------------------------------
+// Report
+// ----------------------------------------------------------------------------
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Side::Ours => write!(f, "ours"),
+            Side::Theirs => write!(f, "theirs"),
+        }
+    }
+}
+
+/// A note that may not exist.
+struct DisplayOptionNote<'a> {
+    note: Option<&'a Note>,
+    keyed_by_pitch: bool,
+}
+
+impl fmt::Display for DisplayOptionNote<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.note {
+            Some(note) => {
+                let keyed_by_pitch = self.keyed_by_pitch;
+                write!(
+                    f,
+                    "{}",
+                    DisplayNote {
+                        note,
+                        keyed_by_pitch
+                    }
+                )
+            }
+            None => write!(f, "none"),
+        }
+    }
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Conflict::BpmBase { base, ours, theirs } => write!(
+                f,
+                "Base tempo: {} bpm in base, {} bpm in ours, {} bpm in theirs",
+                base, ours, theirs
+            ),
+            Conflict::Tempo {
+                beat,
+                base,
+                ours,
+                theirs,
+            } => write!(
+                f,
+                "Tempo at beat {}: {} in base, {} in ours, {} in theirs",
+                beat,
+                DisplayOption(*base),
+                DisplayOption(*ours),
+                DisplayOption(*theirs)
+            ),
+            Conflict::TimeSignature {
+                beat,
+                base,
+                ours,
+                theirs,
+            } => write!(
+                f,
+                "Time signature at beat {}: {} in base, {} in ours, {} in theirs",
+                beat,
+                DisplayOption(*base),
+                DisplayOption(*ours),
+                DisplayOption(*theirs)
+            ),
+            Conflict::TrackRemoved { track, removed_by } => {
+                let modified_by = match removed_by {
+                    Side::Ours => Side::Theirs,
+                    Side::Theirs => Side::Ours,
+                };
+                write!(
+                    f,
+                    "Track {:?}: removed in {}, modified in {}",
+                    track, removed_by, modified_by
+                )
+            }
+            Conflict::TrackProperty { track, property } => {
+                write!(f, "Track {:?}: {} changed on both sides", track, property)
+            }
+            Conflict::Note {
+                track,
+                keyed_by_pitch,
+                base,
+                ours,
+                theirs,
+            } => {
+                let display = |note| DisplayOptionNote {
+                    note,
+                    keyed_by_pitch: *keyed_by_pitch,
+                };
+                write!(
+                    f,
+                    "Track {:?}, note changed on both sides:\n  base:   {}\n  ours:   {}\n  theirs: {}",
+                    track,
+                    display(base.as_ref()),
+                    display(ours.as_ref()),
+                    display(theirs.as_ref())
+                )
+            }
+        }
+    }
+}
+
+impl fmt::Display for MergeConflicts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} merge conflicts:", self.conflicts.len())?;
+        for conflict in &self.conflicts {
+            write!(f, "\n{}", conflict)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for MergeConflicts {}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use crate::diff::test::{note, sequence, track};
+
+    use super::*;
+
+    #[test]
+    fn test_independent_changes() {
+        let base = sequence();
+        let mut ours = sequence();
+        ours.tracks[0].notes[1].effects.vibrato = true;
+        ours.tracks[1].notes.remove(0);
+        let mut theirs = sequence();
+        theirs.tempo_map.bpm_base = 90.0;
+        theirs.tracks[0].notes.push(note(4.0, 0, 65));
+        theirs.tracks[1].tuning.string_base_pitches[0] = 62;
+        theirs.tracks.push(track("Bass", vec![note(0.0, 3, 40)]));
+
+        let merged = merge_sequences(&base, &ours, &theirs).unwrap();
+        let mut expected = sequence();
+        expected.tempo_map.bpm_base = 90.0;
+        expected.tracks[0].notes[1].effects.vibrato = true;
+        expected.tracks[0].notes.push(note(4.0, 0, 65));
+        expected.tracks[1].notes.remove(0);
+        expected.tracks[1].tuning.string_base_pitches[0] = 62;
+        expected.tracks.push(track("Bass", vec![note(0.0, 3, 40)]));
+        assert_eq!(merged, expected);
+
+        // the same change on both sides is not a conflict
+        assert_eq!(merge_sequences(&base, &ours, &ours), Ok(ours.clone()));
+        assert_eq!(merge_sequences(&base, &base, &theirs), Ok(theirs.clone()));
+        assert_eq!(merge_sequences(&base, &ours, &base), Ok(ours));
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let base = sequence();
+        let mut ours = sequence();
+        ours.tempo_map.bpm_base = 100.0;
+        ours.tracks[0].notes[1].pitch = 61;
+        ours.tracks[1].notes[0].d = 2.0;
+        let mut theirs = sequence();
+        theirs.tempo_map.bpm_base = 140.0;
+        theirs.tracks[0].notes[1].pitch = 63;
+        // independent of the conflict in the same track
+        theirs.tracks[0].notes[2].fret = 3;
+        theirs.tracks.remove(1);
+
+        let error = merge_sequences(&base, &ours, &theirs).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "\
+3 merge conflicts:
+Base tempo: 120 bpm in base, 100 bpm in ours, 140 bpm in theirs
+Track \"Lead\", note changed on both sides:
+  base:   beat 1, string 1: pitch 62, fret 22, duration 1
+  ours:   beat 1, string 1: pitch 61, fret 22, duration 1
+  theirs: beat 1, string 1: pitch 63, fret 22, duration 1
+Track \"Rhythm\": removed in theirs, modified in ours"
+        );
+        let mut expected = ours.clone();
+        expected.tracks[0].notes[2].fret = 3;
+        assert_eq!(error.resolved_with_ours, expected);
+    }
+
+    #[test]
+    fn test_moved_and_modified_note() {
+        let base = sequence();
+        let mut ours = sequence();
+        ours.tracks[0].notes[1].s = 1.5;
+        let mut theirs = sequence();
+        theirs.tracks[0].notes[1].d = 0.5;
+
+        let error = merge_sequences(&base, &ours, &theirs).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "\
+1 merge conflicts:
+Track \"Lead\", note changed on both sides:
+  base:   beat 1, string 1: pitch 62, fret 22, duration 1
+  ours:   none
+  theirs: beat 1, string 1: pitch 62, fret 22, duration 0.5"
+        );
+        assert_eq!(error.resolved_with_ours, ours);
+    }
+
+    #[test]
+    fn test_tracks_added_on_both_sides() {
+        let base = Sequence::default();
+        let ours = Sequence {
+            tracks: vec![track("Guitar", vec![note(0.0, 0, 64), note(1.0, 0, 65)])],
+            ..Sequence::default()
+        };
+        let mut theirs = Sequence {
+            tracks: vec![track("Guitar", vec![note(1.0, 0, 65), note(2.0, 0, 67)])],
+            ..Sequence::default()
+        };
+        theirs.tempo_map.tempo_changes.push(TempoChange {
+            beat: 4.0,
+            bpm: 80.0,
+        });
+        let merged = merge_sequences(&base, &ours, &theirs).unwrap();
+        assert_eq!(
+            merged.tracks[0].notes,
+            [note(0.0, 0, 64), note(1.0, 0, 65), note(2.0, 0, 67)]
+        );
+        assert_eq!(merged.tempo_map, theirs.tempo_map);
+
+        // different notes at the same onset and string, and different tunings
+        theirs.tracks[0].notes[0].pitch = 66;
+        theirs.tracks[0].tuning.string_base_pitches.pop();
+        let error = merge_sequences(&base, &ours, &theirs).unwrap_err();
+        assert_eq!(
+            error.conflicts,
+            [
+                Conflict::TrackProperty {
+                    track: "Guitar".to_string(),
+                    property: "tuning",
+                },
+                Conflict::Note {
+                    track: "Guitar".to_string(),
+                    keyed_by_pitch: false,
+                    base: None,
+                    ours: Some(note(1.0, 0, 65)),
+                    theirs: Some(Note {
+                        pitch: 66,
+                        ..note(1.0, 0, 65)
+                    }),
+                },
+            ]
+        );
+    }
+}
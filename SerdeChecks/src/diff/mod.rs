@@ -0,0 +1,956 @@
+//! Structural diff and three-way merge of sequences.
+//!
+//! Elements are matched by their content instead of by position:
+//! - Tracks by name, the n-th track with a name matches the n-th track with that name.
+//! - Tempo changes and time signatures by beat.
+//! - Notes are aligned per lane, i.e., per string (per pitch in percussion tracks, where the
+//!   string has no meaning). Unchanged notes are matched by onset first. The remaining notes
+//!   of a lane are aligned in the order of their onsets with the least total cost, where a
+//!   removed or an added note costs 1, and a modified note its onset distance relative to
+//!   `MAX_ONSET_SHIFT` plus a little per other changed field. Notes further apart than
+//!   `MAX_ONSET_SHIFT` are never paired. The alignment is quadratic in the number of changed
+//!   notes of a lane.
+//!
+//! Matched elements that differ are modified, unmatched ones added or removed. The merge
+//! matches notes by onset and lane only, see `merge_sequences`.
+
+mod merge;
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::types::{BendData, Note, Sequence, TempoMap, Track, Tuning};
+
+pub use merge::{merge_sequences, Conflict, MergeConflicts, Side};
+
+// ----------------------------------------------------------------------------
+// Keys
+// ----------------------------------------------------------------------------
+
+/// A time in beats, ordered by `f64::total_cmp` so that it can be a key.
+#[derive(Copy, Clone, Debug)]
+struct Beat(f64);
+
+impl PartialEq for Beat {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Beat {}
+
+impl PartialOrd for Beat {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Beat {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// The furthest a note can move in time and still be reported as modified, in beats.
+pub const MAX_ONSET_SHIFT: f64 = 4.0;
+
+/// The onset, the string (or the pitch in percussion tracks), and the position among the
+/// notes with the same onset and string.
+type NoteKey = (Beat, u8, usize);
+
+/// The notes of a track by key, with their indices.
+fn notes_by_key(track: &Track, is_percussion: bool) -> BTreeMap<NoteKey, (usize, &Note)> {
+    let lane = |note: &Note| {
+        if is_percussion {
+            note.pitch
+        } else {
+            note.string
+        }
+    };
+    let mut order: Vec<usize> = (0..track.notes.len()).collect();
+    order.sort_by_key(|i| {
+        let note = &track.notes[*i];
+        (Beat(note.s), lane(note), note.pitch, *i)
+    });
+    let mut notes = BTreeMap::new();
+    let mut previous: Option<(Beat, u8, usize)> = None;
+    for i in order {
+        let note = &track.notes[i];
+        let (beat, lane) = (Beat(note.s), lane(note));
+        let occurrence = match previous {
+            Some((b, l, occurrence)) if (b, l) == (beat, lane) => occurrence + 1,
+            _ => 0,
+        };
+        previous = Some((beat, lane, occurrence));
+        notes.insert((beat, lane, occurrence), (i, note));
+    }
+    notes
+}
+
+/// The tracks by name and occurrence of the name.
+fn tracks_by_name(tracks: &[Track]) -> BTreeMap<(&str, usize), usize> {
+    let mut occurrences: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut result = BTreeMap::new();
+    for (i, track) in tracks.iter().enumerate() {
+        let occurrence = occurrences.entry(&track.name).or_default();
+        result.insert((track.name.as_str(), *occurrence), i);
+        *occurrence += 1;
+    }
+    result
+}
+
+/// The values of two or three maps per key, in key order.
+fn join<K: Ord + Copy, V, const N: usize>(maps: [&BTreeMap<K, V>; N]) -> Vec<(K, [Option<&V>; N])> {
+    let mut joined: BTreeMap<K, [Option<&V>; N]> = BTreeMap::new();
+    for (i, map) in maps.iter().enumerate() {
+        for (key, value) in map.iter() {
+            joined.entry(*key).or_insert([None; N])[i] = Some(value);
+        }
+    }
+    joined.into_iter().collect()
+}
+
+// ----------------------------------------------------------------------------
+// Diff
+// ----------------------------------------------------------------------------
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SequenceDiff {
+    pub tempo_map: Vec<TempoMapChange>,
+    /// In the order of the old tracks, followed by the added tracks.
+    pub tracks: Vec<TrackDiff>,
+}
+
+/// `None` stands for a tempo change or time signature that does not exist on that side.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TempoMapChange {
+    BpmBase {
+        old: f64,
+        new: f64,
+    },
+    Tempo {
+        beat: f64,
+        old: Option<f64>,
+        new: Option<f64>,
+    },
+    TimeSignature {
+        beat: f64,
+        old: Option<(u8, u8)>,
+        new: Option<(u8, u8)>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TrackDiff {
+    Added {
+        index: usize,
+        track: Track,
+    },
+    Removed {
+        index: usize,
+        track: Track,
+    },
+    Modified {
+        old_index: usize,
+        new_index: usize,
+        name: String,
+        is_percussion: Option<(bool, bool)>,
+        tuning: Option<(Tuning, Tuning)>,
+        /// Whether the notes are matched by pitch instead of string, i.e., whether the new
+        /// track is percussion.
+        keyed_by_pitch: bool,
+        /// By onset, and by string (or pitch) for the same onset.
+        notes: Vec<NoteChange>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum NoteChange {
+    /// `index` is the index in the new track.
+    Added { index: usize, note: Note },
+    /// `index` is the index in the old track.
+    Removed { index: usize, note: Note },
+    Modified {
+        old_index: usize,
+        new_index: usize,
+        old: Note,
+        new: Note,
+        changes: Vec<FieldChange>,
+    },
+}
+
+/// A changed field of a matched note, as old and new value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FieldChange {
+    /// By up to `MAX_ONSET_SHIFT`.
+    Onset(f64, f64),
+    Duration(f64, f64),
+    Pitch(u8, u8),
+    String(u8, u8),
+    Fret(u8, u8),
+    DeadNote(bool, bool),
+    Vibrato(bool, bool),
+    BendData(Option<BendData>, Option<BendData>),
+}
+
+impl SequenceDiff {
+    pub fn is_empty(&self) -> bool {
+        self.tempo_map.is_empty() && self.tracks.is_empty()
+    }
+}
+
+/// The changes from `old` to `new`.
+pub fn diff_sequences(old: &Sequence, new: &Sequence) -> SequenceDiff {
+    let mut tracks = vec![];
+    let (old_tracks, new_tracks) = (tracks_by_name(&old.tracks), tracks_by_name(&new.tracks));
+    let mut added = vec![];
+    for (_, [old_index, new_index]) in join([&old_tracks, &new_tracks]) {
+        match (old_index, new_index) {
+            (Some(i), Some(j)) => {
+                tracks.extend(diff_tracks(*i, &old.tracks[*i], *j, &new.tracks[*j]))
+            }
+            (Some(i), None) => tracks.push((
+                *i,
+                TrackDiff::Removed {
+                    index: *i,
+                    track: old.tracks[*i].clone(),
+                },
+            )),
+            (None, Some(j)) => added.push(TrackDiff::Added {
+                index: *j,
+                track: new.tracks[*j].clone(),
+            }),
+            (None, None) => unreachable!(),
+        }
+    }
+    tracks.sort_by_key(|(old_index, _)| *old_index);
+    added.sort_by_key(|diff| match diff {
+        TrackDiff::Added { index, .. } => *index,
+        _ => unreachable!(),
+    });
+    SequenceDiff {
+        tempo_map: diff_tempo_maps(&old.tempo_map, &new.tempo_map),
+        tracks: tracks
+            .into_iter()
+            .map(|(_, diff)| diff)
+            .chain(added)
+            .collect(),
+    }
+}
+
+fn tempos_by_beat(tempo_map: &TempoMap) -> BTreeMap<Beat, f64> {
+    let changes = tempo_map.tempo_changes.iter();
+    changes
+        .map(|change| (Beat(change.beat), change.bpm))
+        .collect()
+}
+
+fn time_signatures_by_beat(tempo_map: &TempoMap) -> BTreeMap<Beat, (u8, u8)> {
+    let time_signatures = tempo_map.time_signatures.iter();
+    time_signatures
+        .map(|ts| (Beat(ts.beat), (ts.numerator, ts.denominator)))
+        .collect()
+}
+
+fn diff_tempo_maps(old: &TempoMap, new: &TempoMap) -> Vec<TempoMapChange> {
+    let mut changes = vec![];
+    if old.bpm_base != new.bpm_base {
+        changes.push(TempoMapChange::BpmBase {
+            old: old.bpm_base,
+            new: new.bpm_base,
+        });
+    }
+    let tempos = [&tempos_by_beat(old), &tempos_by_beat(new)];
+    for (beat, [old, new]) in join(tempos) {
+        if old != new {
+            changes.push(TempoMapChange::Tempo {
+                beat: beat.0,
+                old: old.copied(),
+                new: new.copied(),
+            });
+        }
+    }
+    let time_signatures = [&time_signatures_by_beat(old), &time_signatures_by_beat(new)];
+    for (beat, [old, new]) in join(time_signatures) {
+        if old != new {
+            changes.push(TempoMapChange::TimeSignature {
+                beat: beat.0,
+                old: old.copied(),
+                new: new.copied(),
+            });
+        }
+    }
+    changes
+}
+
+/// `None` if the tracks are equal, together with the index of the old track.
+fn diff_tracks(
+    old_index: usize,
+    old: &Track,
+    new_index: usize,
+    new: &Track,
+) -> Option<(usize, TrackDiff)> {
+    // notes are keyed like in the new track, so that switching to percussion shows up
+    let is_percussion = new.is_percussion;
+    let (old_notes, new_notes) = (
+        notes_by_key(old, is_percussion),
+        notes_by_key(new, is_percussion),
+    );
+    // with the onset and string (or pitch), for sorting
+    let mut notes = vec![];
+    // the notes that are not unchanged, by lane
+    let mut lanes: BTreeMap<u8, [Vec<(usize, &Note)>; 2]> = BTreeMap::new();
+    for ((_, lane, _), [old_note, new_note]) in join([&old_notes, &new_notes]) {
+        if let (Some((_, old)), Some((_, new))) = (old_note, new_note) {
+            if old == new {
+                continue;
+            }
+        }
+        let [removed, added] = lanes.entry(lane).or_default();
+        removed.extend(old_note.copied());
+        added.extend(new_note.copied());
+    }
+    for (lane, [mut removed, mut added]) in lanes {
+        removed.sort_by_key(|(i, note)| (Beat(note.s), note.pitch, *i));
+        added.sort_by_key(|(j, note)| (Beat(note.s), note.pitch, *j));
+        let (mut next_removed, mut next_added) = (0, 0);
+        let pairs = align(&removed, &added);
+        for (i, j) in pairs.into_iter().chain([(removed.len(), added.len())]) {
+            for (index, note) in &removed[next_removed..i] {
+                let change = NoteChange::Removed {
+                    index: *index,
+                    note: (*note).clone(),
+                };
+                notes.push((Beat(note.s), lane, change));
+            }
+            for (index, note) in &added[next_added..j] {
+                let change = NoteChange::Added {
+                    index: *index,
+                    note: (*note).clone(),
+                };
+                notes.push((Beat(note.s), lane, change));
+            }
+            if let (Some((i, old)), Some((j, new))) = (removed.get(i), added.get(j)) {
+                notes.extend(modified(*i, old, *j, new).map(|change| (Beat(old.s), lane, change)));
+            }
+            (next_removed, next_added) = (i + 1, j + 1);
+        }
+    }
+    notes.sort_by_key(|(beat, lane, _)| (*beat, *lane));
+    let notes: Vec<NoteChange> = notes.into_iter().map(|(.., change)| change).collect();
+
+    let is_percussion =
+        (old.is_percussion != new.is_percussion).then_some((old.is_percussion, new.is_percussion));
+    let tuning = (old.tuning != new.tuning).then(|| (old.tuning.clone(), new.tuning.clone()));
+    if notes.is_empty() && is_percussion.is_none() && tuning.is_none() && old_index == new_index {
+        return None;
+    }
+    Some((
+        old_index,
+        TrackDiff::Modified {
+            old_index,
+            new_index,
+            name: new.name.clone(),
+            is_percussion,
+            tuning,
+            keyed_by_pitch: new.is_percussion,
+            notes,
+        },
+    ))
+}
+
+/// The cost of reporting `old` and `new` as one modified note, `None` if they are more than
+/// `MAX_ONSET_SHIFT` apart. It is below 2, i.e., below removing one and adding the other.
+fn pair_cost(old: &Note, new: &Note) -> Option<f64> {
+    // also `None` for NaN onsets
+    let shift = Some((old.s - new.s).abs()).filter(|shift| *shift <= MAX_ONSET_SHIFT)?;
+    let changes = field_changes(old, new);
+    let other_changes = changes
+        .iter()
+        .filter(|change| !matches!(change, FieldChange::Onset(..)))
+        .count();
+    Some(shift / MAX_ONSET_SHIFT + 0.1 * other_changes as f64)
+}
+
+/// Aligns the notes of a lane, both sorted by onset, with the least total cost, see
+/// `pair_cost`. Returns the positions of the paired notes in ascending order.
+fn align(old: &[(usize, &Note)], new: &[(usize, &Note)]) -> Vec<(usize, usize)> {
+    let (n, m) = (old.len(), new.len());
+    let width = m + 1;
+    let pair_cost = |i: usize, j: usize| pair_cost(old[i].1, new[j].1);
+    // `cost[i * width + j]` is the cost of aligning the first `i` old and `j` new notes
+    let mut cost = vec![0.0; (n + 1) * width];
+    for i in 0..=n {
+        for j in 0..=m {
+            cost[i * width + j] = match (i, j) {
+                (0, _) => j as f64,
+                (_, 0) => i as f64,
+                _ => {
+                    let gap = cost[(i - 1) * width + j].min(cost[i * width + j - 1]) + 1.0;
+                    match pair_cost(i - 1, j - 1) {
+                        Some(pair) => gap.min(cost[(i - 1) * width + j - 1] + pair),
+                        None => gap,
+                    }
+                }
+            };
+        }
+    }
+    let mut pairs = vec![];
+    let (mut i, mut j) = (n, m);
+    while i > 0 && j > 0 {
+        let current = cost[i * width + j];
+        let paired = pair_cost(i - 1, j - 1)
+            .is_some_and(|pair| cost[(i - 1) * width + j - 1] + pair == current);
+        if paired {
+            pairs.push((i - 1, j - 1));
+            (i, j) = (i - 1, j - 1);
+        } else if cost[(i - 1) * width + j] + 1.0 == current {
+            i -= 1;
+        } else {
+            j -= 1;
+        }
+    }
+    pairs.reverse();
+    pairs
+}
+
+fn modified(old_index: usize, old: &Note, new_index: usize, new: &Note) -> Option<NoteChange> {
+    let changes = field_changes(old, new);
+    (!changes.is_empty()).then(|| NoteChange::Modified {
+        old_index,
+        new_index,
+        old: old.clone(),
+        new: new.clone(),
+        changes,
+    })
+}
+
+fn field_changes(old: &Note, new: &Note) -> Vec<FieldChange> {
+    let mut changes = vec![];
+    if old.s != new.s {
+        changes.push(FieldChange::Onset(old.s, new.s));
+    }
+    if old.d != new.d {
+        changes.push(FieldChange::Duration(old.d, new.d));
+    }
+    if old.pitch != new.pitch {
+        changes.push(FieldChange::Pitch(old.pitch, new.pitch));
+    }
+    if old.string != new.string {
+        changes.push(FieldChange::String(old.string, new.string));
+    }
+    if old.fret != new.fret {
+        changes.push(FieldChange::Fret(old.fret, new.fret));
+    }
+    let (old, new) = (&old.effects, &new.effects);
+    if old.dead_note != new.dead_note {
+        changes.push(FieldChange::DeadNote(old.dead_note, new.dead_note));
+    }
+    if old.vibrato != new.vibrato {
+        changes.push(FieldChange::Vibrato(old.vibrato, new.vibrato));
+    }
+    if old.bend_data != new.bend_data {
+        changes.push(FieldChange::BendData(
+            old.bend_data.clone(),
+            new.bend_data.clone(),
+        ));
+    }
+    changes
+}
+
+// ----------------------------------------------------------------------------
+// Report
+// ----------------------------------------------------------------------------
+
+/// A note, identified by onset and string, or onset and pitch if `keyed_by_pitch`.
+struct DisplayNote<'a> {
+    note: &'a Note,
+    keyed_by_pitch: bool,
+}
+
+impl DisplayNote<'_> {
+    fn key(&self) -> String {
+        let note = self.note;
+        if self.keyed_by_pitch {
+            format!("beat {}, pitch {}", note.s, note.pitch)
+        } else {
+            format!("beat {}, string {}", note.s, note.string)
+        }
+    }
+}
+
+impl fmt::Display for DisplayNote<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let note = self.note;
+        write!(f, "{}: ", self.key())?;
+        if self.keyed_by_pitch {
+            write!(f, "string {}", note.string)?;
+        } else {
+            write!(f, "pitch {}", note.pitch)?;
+        }
+        write!(f, ", fret {}, duration {}", note.fret, note.d)?;
+        let effects = &note.effects;
+        if effects.dead_note {
+            write!(f, ", dead note")?;
+        }
+        if effects.vibrato {
+            write!(f, ", vibrato")?;
+        }
+        if let Some(bend_data) = &effects.bend_data {
+            write!(f, ", bend {}", DisplayBend(Some(bend_data)))?;
+        }
+        Ok(())
+    }
+}
+
+struct DisplayBend<'a>(Option<&'a BendData>);
+
+impl fmt::Display for DisplayBend<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(bend_data) = self.0 else {
+            return write!(f, "none");
+        };
+        write!(f, "[")?;
+        for (i, point) in bend_data.points.iter().enumerate() {
+            let separator = if i == 0 { "" } else { ", " };
+            write!(f, "{}{} at {}", separator, point.bend, point.pos)?;
+        }
+        write!(f, "]")
+    }
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+impl fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldChange::Onset(old, new) => write!(f, "onset {} -> {}", old, new),
+            FieldChange::Duration(old, new) => write!(f, "duration {} -> {}", old, new),
+            FieldChange::Pitch(old, new) => write!(f, "pitch {} -> {}", old, new),
+            FieldChange::String(old, new) => write!(f, "string {} -> {}", old, new),
+            FieldChange::Fret(old, new) => write!(f, "fret {} -> {}", old, new),
+            FieldChange::DeadNote(old, new) => {
+                write!(f, "dead note {} -> {}", on_off(*old), on_off(*new))
+            }
+            FieldChange::Vibrato(old, new) => {
+                write!(f, "vibrato {} -> {}", on_off(*old), on_off(*new))
+            }
+            FieldChange::BendData(old, new) => write!(
+                f,
+                "bend {} -> {}",
+                DisplayBend(old.as_ref()),
+                DisplayBend(new.as_ref())
+            ),
+        }
+    }
+}
+
+/// A tempo or time signature that may not exist.
+struct DisplayOption<T>(Option<T>);
+
+impl fmt::Display for DisplayOption<f64> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(bpm) => write!(f, "{} bpm", bpm),
+            None => write!(f, "none"),
+        }
+    }
+}
+
+impl fmt::Display for DisplayOption<(u8, u8)> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some((numerator, denominator)) => write!(f, "{}/{}", numerator, denominator),
+            None => write!(f, "none"),
+        }
+    }
+}
+
+impl fmt::Display for TempoMapChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TempoMapChange::BpmBase { old, new } => {
+                write!(f, "base tempo {} bpm -> {} bpm", old, new)
+            }
+            TempoMapChange::Tempo { beat, old, new } => write!(
+                f,
+                "tempo at beat {}: {} -> {}",
+                beat,
+                DisplayOption(*old),
+                DisplayOption(*new)
+            ),
+            TempoMapChange::TimeSignature { beat, old, new } => write!(
+                f,
+                "time signature at beat {}: {} -> {}",
+                beat,
+                DisplayOption(*old),
+                DisplayOption(*new)
+            ),
+        }
+    }
+}
+
+/// A note change in a track whose notes are matched by pitch if `keyed_by_pitch`.
+struct DisplayNoteChange<'a> {
+    change: &'a NoteChange,
+    keyed_by_pitch: bool,
+}
+
+impl fmt::Display for DisplayNoteChange<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let display = |note| DisplayNote {
+            note,
+            keyed_by_pitch: self.keyed_by_pitch,
+        };
+        match self.change {
+            NoteChange::Added { note, .. } => write!(f, "+ {}", display(note)),
+            NoteChange::Removed { note, .. } => write!(f, "- {}", display(note)),
+            NoteChange::Modified { old, changes, .. } => {
+                write!(f, "~ {}:", display(old).key())?;
+                for (i, change) in changes.iter().enumerate() {
+                    let separator = if i == 0 { " " } else { ", " };
+                    write!(f, "{}{}", separator, change)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A readable report, one change per line.
+impl fmt::Display for SequenceDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No changes");
+        }
+        if !self.tempo_map.is_empty() {
+            writeln!(f, "Tempo map:")?;
+            for change in &self.tempo_map {
+                writeln!(f, "  {}", change)?;
+            }
+        }
+        for track in &self.tracks {
+            match track {
+                TrackDiff::Added { index, track } => writeln!(
+                    f,
+                    "Track {:?} added as #{} ({} notes)",
+                    track.name,
+                    index,
+                    track.notes.len()
+                )?,
+                TrackDiff::Removed { index, track } => writeln!(
+                    f,
+                    "Track {:?} (#{}) removed ({} notes)",
+                    track.name,
+                    index,
+                    track.notes.len()
+                )?,
+                TrackDiff::Modified {
+                    old_index,
+                    new_index,
+                    name,
+                    is_percussion,
+                    tuning,
+                    keyed_by_pitch,
+                    notes,
+                } => {
+                    write!(f, "Track {:?} (#{}", name, old_index)?;
+                    if old_index != new_index {
+                        write!(f, " -> #{}", new_index)?;
+                    }
+                    writeln!(f, "):")?;
+                    if let Some((old, new)) = is_percussion {
+                        writeln!(f, "  percussion {} -> {}", on_off(*old), on_off(*new))?;
+                    }
+                    if let Some((old, new)) = tuning {
+                        writeln!(
+                            f,
+                            "  tuning {:?} -> {:?}",
+                            old.string_base_pitches, new.string_base_pitches
+                        )?;
+                    }
+                    for change in notes {
+                        let change = DisplayNoteChange {
+                            change,
+                            keyed_by_pitch: *keyed_by_pitch,
+                        };
+                        writeln!(f, "  {}", change)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(super) mod test {
+    use pretty_assertions::assert_eq;
+
+    use crate::types::{BendPoint, NoteEffects, TempoChange, TimeSignature};
+
+    use super::*;
+
+    pub(super) fn note(s: f64, string: u8, pitch: u8) -> Note {
+        Note {
+            s,
+            d: 1.0,
+            pitch,
+            string,
+            fret: pitch - 40,
+            effects: NoteEffects::default(),
+        }
+    }
+
+    pub(super) fn track(name: &str, notes: Vec<Note>) -> Track {
+        Track {
+            name: name.to_string(),
+            is_percussion: false,
+            tuning: Tuning {
+                string_base_pitches: vec![64, 59, 55, 50, 45, 40],
+            },
+            notes,
+        }
+    }
+
+    pub(super) fn sequence() -> Sequence {
+        Sequence {
+            tempo_map: TempoMap::new(120.0),
+            tracks: vec![
+                track(
+                    "Lead",
+                    vec![note(0.0, 0, 64), note(1.0, 1, 62), note(2.0, 2, 57)],
+                ),
+                track("Rhythm", vec![note(0.0, 5, 40), note(0.0, 4, 47)]),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_no_changes() {
+        let diff = diff_sequences(&sequence(), &sequence());
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_string(), "No changes\n");
+
+        // the order of notes with different keys does not matter
+        let mut reordered = sequence();
+        reordered.tracks[0].notes.reverse();
+        assert_eq!(
+            diff_sequences(&sequence(), &reordered),
+            SequenceDiff::default()
+        );
+    }
+
+    #[test]
+    fn test_note_changes() {
+        let old = sequence();
+        let mut new = sequence();
+        let lead = &mut new.tracks[0].notes;
+        // moved in time
+        lead[2].s = 3.0;
+        lead[1].pitch = 61;
+        lead[1].fret = 21;
+        lead[1].effects.vibrato = true;
+        lead[1].effects.bend_data = Some(BendData {
+            points: vec![BendPoint {
+                pos: 0.5,
+                bend: 1.0,
+            }],
+        });
+        // a second note at the same onset on another string
+        lead.push(note(0.0, 1, 60));
+
+        let diff = diff_sequences(&old, &new);
+        assert_eq!(diff.tracks.len(), 1);
+        let TrackDiff::Modified { notes, .. } = &diff.tracks[0] else {
+            panic!("{:?}", diff.tracks[0]);
+        };
+        assert_eq!(
+            notes[0],
+            NoteChange::Added {
+                index: 3,
+                note: note(0.0, 1, 60)
+            }
+        );
+        assert!(matches!(
+            &notes[1],
+            NoteChange::Modified {
+                old_index: 1,
+                new_index: 1,
+                ..
+            }
+        ));
+        assert_eq!(
+            diff.to_string(),
+            "\
+Track \"Lead\" (#0):
+  + beat 0, string 1: pitch 60, fret 20, duration 1
+  ~ beat 1, string 1: pitch 62 -> 61, fret 22 -> 21, vibrato off -> on, bend none -> [1 at 0.5]
+  ~ beat 2, string 2: onset 2 -> 3
+"
+        );
+    }
+
+    #[test]
+    fn test_track_and_tempo_changes() {
+        let old = sequence();
+        let mut new = sequence();
+        new.tempo_map = TempoMap {
+            bpm_base: 100.0,
+            tempo_changes: vec![TempoChange {
+                beat: 8.0,
+                bpm: 140.0,
+            }],
+            time_signatures: vec![TimeSignature {
+                beat: 0.0,
+                numerator: 3,
+                denominator: 4,
+            }],
+        };
+        new.tracks.swap(0, 1);
+        new.tracks[0].is_percussion = true;
+        new.tracks[0].tuning = Tuning::default();
+        new.tracks.push(track("Lead", vec![]));
+        new.tracks.remove(1);
+        new.tracks.push(track("Bass", vec![note(0.0, 0, 40)]));
+
+        assert_eq!(
+            diff_sequences(&old, &new).to_string(),
+            "\
+Tempo map:
+  base tempo 120 bpm -> 100 bpm
+  tempo at beat 8: none -> 140 bpm
+  time signature at beat 0: none -> 3/4
+Track \"Lead\" (#0 -> #1):
+  - beat 0, string 0: pitch 64, fret 24, duration 1
+  - beat 1, string 1: pitch 62, fret 22, duration 1
+  - beat 2, string 2: pitch 57, fret 17, duration 1
+Track \"Rhythm\" (#1 -> #0):
+  percussion off -> on
+  tuning [64, 59, 55, 50, 45, 40] -> []
+Track \"Bass\" added as #2 (1 notes)
+"
+        );
+
+        // percussion notes are keyed by pitch
+        let mut drums = track("Drums", vec![note(0.0, 0, 42), note(0.0, 0, 40)]);
+        drums.is_percussion = true;
+        let old = Sequence {
+            tracks: vec![drums.clone()],
+            ..Sequence::default()
+        };
+        drums.notes.remove(0);
+        let new = Sequence {
+            tracks: vec![drums.clone()],
+            ..Sequence::default()
+        };
+        let diff = diff_sequences(&old, &new);
+        assert_eq!(
+            diff.tracks,
+            [TrackDiff::Modified {
+                old_index: 0,
+                new_index: 0,
+                name: "Drums".to_string(),
+                is_percussion: None,
+                tuning: None,
+                keyed_by_pitch: true,
+                notes: vec![NoteChange::Removed {
+                    index: 0,
+                    note: note(0.0, 0, 42)
+                }],
+            }]
+        );
+        drums.notes[0].d = 0.5;
+        let modified = Sequence {
+            tracks: vec![drums],
+            ..Sequence::default()
+        };
+        assert_eq!(
+            diff_sequences(&new, &modified).to_string(),
+            "\
+Track \"Drums\" (#0):
+  ~ beat 0, pitch 40: duration 1 -> 0.5
+"
+        );
+    }
+
+    #[test]
+    fn test_moved_notes() {
+        let old = sequence();
+        let mut new = sequence();
+        let lead = &mut new.tracks[0].notes;
+        // float jitter
+        lead[0].s += 1e-9;
+        // moved by a 16th, but to another string
+        lead[1].s += 0.25;
+        lead[1].string = 2;
+        // moved by a 16th and modified, which is cheaper than pairing it with the note
+        // moved to its string
+        lead[2].s += 0.25;
+        lead[2].d = 0.5;
+        let rhythm = &mut new.tracks[1].notes;
+        // beyond `MAX_ONSET_SHIFT`
+        rhythm[0].s += MAX_ONSET_SHIFT + 1.0;
+        rhythm[1].s += 0.5;
+
+        assert_eq!(
+            diff_sequences(&old, &new).to_string(),
+            "\
+Track \"Lead\" (#0):
+  ~ beat 0, string 0: onset 0 -> 0.000000001
+  - beat 1, string 1: pitch 62, fret 22, duration 1
+  + beat 1.25, string 2: pitch 62, fret 22, duration 1
+  ~ beat 2, string 2: onset 2 -> 2.25, duration 1 -> 0.5
+Track \"Rhythm\" (#1):
+  ~ beat 0, string 4: onset 0 -> 0.5
+  - beat 0, string 5: pitch 40, fret 0, duration 1
+  + beat 5, string 5: pitch 40, fret 0, duration 1
+"
+        );
+
+        // the alignment keeps the order of the notes of a lane
+        let old = Sequence {
+            tracks: vec![track("Bass", vec![note(0.0, 3, 40), note(1.0, 3, 42)])],
+            ..Sequence::default()
+        };
+        let new = Sequence {
+            tracks: vec![track("Bass", vec![note(0.75, 3, 40), note(1.75, 3, 42)])],
+            ..Sequence::default()
+        };
+        assert_eq!(
+            diff_sequences(&old, &new).to_string(),
+            "\
+Track \"Bass\" (#0):
+  ~ beat 0, string 3: onset 0 -> 0.75
+  ~ beat 1, string 3: onset 1 -> 1.75
+"
+        );
+    }
+
+    #[test]
+    fn test_duplicate_track_names() {
+        let old = Sequence {
+            tracks: vec![track("Guitar", vec![]), track("Guitar", vec![])],
+            ..Sequence::default()
+        };
+        let mut new = old.clone();
+        new.tracks[1].notes.push(note(0.0, 0, 64));
+        new.tracks.push(track("Guitar", vec![]));
+        let diff = diff_sequences(&old, &new);
+        assert_eq!(
+            diff.to_string(),
+            "\
+Track \"Guitar\" (#1):
+  + beat 0, string 0: pitch 64, fret 24, duration 1
+Track \"Guitar\" added as #2 (0 notes)
+"
+        );
+    }
+}
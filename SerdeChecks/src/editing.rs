@@ -0,0 +1,703 @@
+//! Editing and queries of sequences: time slices, transposition, quantization of onsets,
+//! merging and splitting of tracks, lookup of notes by time, and statistics.
+//!
+//! All operations keep the `NoteEffects` of the notes. Times are in beats like in `types`.
+
+use std::fmt;
+use std::ops::Range;
+
+use crate::types::{Note, Sequence, TempoChange, TempoMap, TimeSignature, Track};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SequenceStats {
+    pub num_notes: usize,
+    pub num_notes_per_track: Vec<usize>,
+    /// The lowest and highest pitch of the notes of non-percussion tracks.
+    pub pitch_range: Option<(u8, u8)>,
+    /// The end of the last note, zero without notes.
+    pub duration: f64,
+    /// `duration` according to the tempo map, `None` if the tempo map is invalid (see
+    /// `TempoMap::timeline`).
+    pub duration_seconds: Option<f64>,
+}
+
+// ----------------------------------------------------------------------------
+// EditError
+// ----------------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EditError {
+    /// A transposed pitch is outside of 0..=127.
+    PitchOutOfRange {
+        track: usize,
+        note: usize,
+        pitch: i32,
+    },
+    /// A transposed pitch is below the open string (or too high) on the string of the note.
+    FretOutOfRange {
+        track: usize,
+        note: usize,
+        fret: i32,
+    },
+    /// Tracks with different tunings or percussion flags cannot be merged.
+    IncompatibleTracks { track: usize, other: usize },
+    /// There is no track at `index`.
+    InvalidTrackIndex { index: usize, num_tracks: usize },
+    /// A track cannot be merged into itself.
+    SelfMerge { track: usize },
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EditError::PitchOutOfRange { track, note, pitch } => write!(
+                f,
+                "Track {}, note {}: Pitch {} is out of the MIDI range",
+                track, note, pitch
+            ),
+            EditError::FretOutOfRange { track, note, fret } => write!(
+                f,
+                "Track {}, note {}: Fret {} is out of range for the string",
+                track, note, fret
+            ),
+            EditError::IncompatibleTracks { track, other } => write!(
+                f,
+                "Tracks {} and {} differ in tuning or percussion",
+                track, other
+            ),
+            EditError::InvalidTrackIndex { index, num_tracks } => write!(
+                f,
+                "Track {} does not exist, there are {} tracks",
+                index, num_tracks
+            ),
+            EditError::SelfMerge { track } => write!(f, "Track {} is merged with itself", track),
+        }
+    }
+}
+
+impl std::error::Error for EditError {}
+
+impl Sequence {
+    /// The notes starting in `range`, shifted to start at beat 0 and cut at the end of the
+    /// range, with the tempo and time signature at the start of the range and the changes
+    /// within it. The slice starts a new bar.
+    pub fn time_slice(&self, range: Range<f64>) -> Sequence {
+        let tracks = self
+            .tracks
+            .iter()
+            .map(|track| Track {
+                notes: track
+                    .notes
+                    .iter()
+                    .filter(|note| range.contains(&note.s))
+                    .map(|note| Note {
+                        s: note.s - range.start,
+                        d: note.d.min(range.end - note.s),
+                        ..note.clone()
+                    })
+                    .collect(),
+                ..track.clone()
+            })
+            .collect();
+        Sequence {
+            tempo_map: slice_tempo_map(&self.tempo_map, &range),
+            tracks,
+        }
+    }
+
+    /// Moves all pitches of non-percussion tracks by `semitones`. Notes stay on their string
+    /// and the frets are recomputed from the tuning, see `fingering::assign_fingering` for
+    /// choosing new strings. The sequence is unchanged if any note fails.
+    pub fn transpose(&mut self, semitones: i32) -> Result<(), EditError> {
+        let mut transposed = vec![];
+        for (track_index, track) in self.tracks.iter().enumerate() {
+            if track.is_percussion {
+                continue;
+            }
+            let base_pitches = &track.tuning.string_base_pitches;
+            for (note_index, note) in track.notes.iter().enumerate() {
+                // saturated values are out of range as well, i.e., overflows are reported
+                let pitch = (note.pitch as i32).saturating_add(semitones);
+                if !(0..=127).contains(&pitch) {
+                    return Err(EditError::PitchOutOfRange {
+                        track: track_index,
+                        note: note_index,
+                        pitch,
+                    });
+                }
+                // notes on strings that are not in the tuning keep their fret
+                let fret = match base_pitches.get(note.string as usize) {
+                    Some(base_pitch) => pitch.saturating_sub(*base_pitch),
+                    None => note.fret as i32,
+                };
+                let fret = u8::try_from(fret).map_err(|_| EditError::FretOutOfRange {
+                    track: track_index,
+                    note: note_index,
+                    fret,
+                })?;
+                transposed.push((track_index, note_index, pitch as u8, fret));
+            }
+        }
+        for (track_index, note_index, pitch, fret) in transposed {
+            let note = &mut self.tracks[track_index].notes[note_index];
+            note.pitch = pitch;
+            note.fret = fret;
+        }
+        Ok(())
+    }
+
+    /// Rounds the onsets of all notes to the closest multiple of `grid` (in beats), which
+    /// must be positive. Durations and the tempo map are unchanged.
+    pub fn quantize_onsets(&mut self, grid: f64) {
+        assert!(grid > 0.0, "The grid must be positive, not {}", grid);
+        for note in self.tracks.iter_mut().flat_map(|track| &mut track.notes) {
+            note.s = (note.s / grid).round() * grid;
+        }
+    }
+
+    /// Moves the notes of the tracks at `indices` into the first of them, ordered by onset,
+    /// and removes the others. The tracks need to exist, and need equal tunings and
+    /// percussion flags. The first track must not be one of the others. Nothing is changed on
+    /// errors.
+    pub fn merge_tracks(&mut self, indices: &[usize]) -> Result<(), EditError> {
+        let num_tracks = self.tracks.len();
+        if let Some(index) = indices.iter().find(|index| **index >= num_tracks) {
+            return Err(EditError::InvalidTrackIndex {
+                index: *index,
+                num_tracks,
+            });
+        }
+        let Some((first, others)) = indices.split_first() else {
+            return Ok(());
+        };
+        if others.contains(first) {
+            return Err(EditError::SelfMerge { track: *first });
+        }
+        for other in others {
+            let (track, other_track) = (&self.tracks[*first], &self.tracks[*other]);
+            if track.tuning != other_track.tuning
+                || track.is_percussion != other_track.is_percussion
+            {
+                return Err(EditError::IncompatibleTracks {
+                    track: *first,
+                    other: *other,
+                });
+            }
+        }
+        let mut notes = vec![];
+        for index in others {
+            notes.append(&mut self.tracks[*index].notes);
+        }
+        let track = &mut self.tracks[*first];
+        track.notes.append(&mut notes);
+        track.notes.sort_by(|a, b| a.s.total_cmp(&b.s));
+
+        let mut others = others.to_vec();
+        others.sort_unstable();
+        others.dedup();
+        for index in others.into_iter().rev() {
+            self.tracks.remove(index);
+        }
+        Ok(())
+    }
+
+    /// Replaces the track at `index` by a track per string that has notes, in the order of
+    /// the strings, and returns their number. Their names get the string counted from one.
+    pub fn split_track_by_string(&mut self, index: usize) -> usize {
+        let track = self.tracks.remove(index);
+        let mut strings: Vec<u8> = track.notes.iter().map(|note| note.string).collect();
+        strings.sort_unstable();
+        strings.dedup();
+        let split_tracks: Vec<Track> = strings
+            .iter()
+            .map(|string| Track {
+                name: format!("{} (string {})", track.name, *string as u32 + 1),
+                is_percussion: track.is_percussion,
+                tuning: track.tuning.clone(),
+                notes: track
+                    .notes
+                    .iter()
+                    .filter(|note| note.string == *string)
+                    .cloned()
+                    .collect(),
+            })
+            .collect();
+        let num_tracks = split_tracks.len();
+        self.tracks.splice(index..index, split_tracks);
+        num_tracks
+    }
+
+    pub fn stats(&self) -> SequenceStats {
+        let notes = || self.tracks.iter().flat_map(|track| &track.notes);
+        let pitch_range = self
+            .tracks
+            .iter()
+            .filter(|track| !track.is_percussion)
+            .flat_map(|track| &track.notes)
+            .fold(None, |range, note| match range {
+                Some((min, max)) => Some((note.pitch.min(min), note.pitch.max(max))),
+                None => Some((note.pitch, note.pitch)),
+            });
+        let duration = notes().map(|note| note.s + note.d).fold(0.0, f64::max);
+        SequenceStats {
+            num_notes: notes().count(),
+            num_notes_per_track: self.tracks.iter().map(|track| track.notes.len()).collect(),
+            pitch_range,
+            duration,
+            duration_seconds: self
+                .tempo_map
+                .timeline()
+                .ok()
+                .map(|timeline| timeline.beats_to_seconds(duration)),
+        }
+    }
+}
+
+fn slice_tempo_map(tempo_map: &TempoMap, range: &Range<f64>) -> TempoMap {
+    let bpm_base = tempo_map
+        .tempo_changes
+        .iter()
+        .rev()
+        .find(|change| change.beat <= range.start)
+        .map_or(tempo_map.bpm_base, |change| change.bpm);
+    let tempo_changes = tempo_map
+        .tempo_changes
+        .iter()
+        .filter(|change| change.beat > range.start && change.beat < range.end)
+        .map(|change| TempoChange {
+            beat: change.beat - range.start,
+            ..change.clone()
+        })
+        .collect();
+    let initial_time_signature = tempo_map
+        .time_signatures
+        .iter()
+        .rev()
+        .find(|time_signature| time_signature.beat <= range.start)
+        .map(|time_signature| TimeSignature {
+            beat: 0.0,
+            ..time_signature.clone()
+        });
+    let time_signatures = initial_time_signature
+        .into_iter()
+        .chain(
+            tempo_map
+                .time_signatures
+                .iter()
+                .filter(|time_signature| {
+                    time_signature.beat > range.start && time_signature.beat < range.end
+                })
+                .map(|time_signature| TimeSignature {
+                    beat: time_signature.beat - range.start,
+                    ..time_signature.clone()
+                }),
+        )
+        .collect();
+    TempoMap {
+        bpm_base,
+        tempo_changes,
+        time_signatures,
+    }
+}
+
+// ----------------------------------------------------------------------------
+// NoteIndex
+// ----------------------------------------------------------------------------
+
+/// Finds the notes of a track that sound at a time or in a range. Notes sound from their
+/// onset up to (excluding) their end.
+///
+/// The notes are sorted by onset and form an implicit binary search tree, where every node
+/// stores the latest end in its subtree. A query visits `O(log n + k)` nodes for `k` results.
+pub struct NoteIndex {
+    /// The note indices ordered by onset.
+    order: Vec<usize>,
+    starts: Vec<f64>,
+    ends: Vec<f64>,
+    max_ends: Vec<f64>,
+}
+
+impl NoteIndex {
+    pub fn new(track: &Track) -> Self {
+        let mut order: Vec<usize> = (0..track.notes.len()).collect();
+        order.sort_by(|a, b| track.notes[*a].s.total_cmp(&track.notes[*b].s));
+        let starts: Vec<f64> = order.iter().map(|i| track.notes[*i].s).collect();
+        let ends: Vec<f64> = order
+            .iter()
+            .map(|i| track.notes[*i].s + track.notes[*i].d)
+            .collect();
+        let mut index = NoteIndex {
+            max_ends: vec![f64::NEG_INFINITY; order.len()],
+            order,
+            starts,
+            ends,
+        };
+        index.compute_max_ends(0, index.order.len());
+        index
+    }
+
+    fn compute_max_ends(&mut self, lo: usize, hi: usize) -> f64 {
+        if lo >= hi {
+            return f64::NEG_INFINITY;
+        }
+        let mid = lo + (hi - lo) / 2;
+        let max_end = self.ends[mid]
+            .max(self.compute_max_ends(lo, mid))
+            .max(self.compute_max_ends(mid + 1, hi));
+        self.max_ends[mid] = max_end;
+        max_end
+    }
+
+    /// The indices of the notes sounding at `beat`, ordered by onset. Notes without duration
+    /// never sound.
+    pub fn notes_at(&self, beat: f64) -> Vec<usize> {
+        let mut result = vec![];
+        self.query(
+            0,
+            self.order.len(),
+            beat,
+            &|start| start <= beat,
+            &mut result,
+        );
+        result
+    }
+
+    /// The indices of the notes overlapping `range`, ordered by onset.
+    pub fn notes_in(&self, range: Range<f64>) -> Vec<usize> {
+        let mut result = vec![];
+        let starts_before_end = |start| start < range.end;
+        self.query(
+            0,
+            self.order.len(),
+            range.start,
+            &starts_before_end,
+            &mut result,
+        );
+        result
+    }
+
+    /// Collects the notes in `lo..hi` that end after `after` and satisfy `starts_before`.
+    fn query(
+        &self,
+        lo: usize,
+        hi: usize,
+        after: f64,
+        starts_before: &dyn Fn(f64) -> bool,
+        result: &mut Vec<usize>,
+    ) {
+        if lo >= hi {
+            return;
+        }
+        let mid = lo + (hi - lo) / 2;
+        if self.max_ends[mid] <= after {
+            return;
+        }
+        self.query(lo, mid, after, starts_before, result);
+        // the notes after `mid` start even later
+        if !starts_before(self.starts[mid]) {
+            return;
+        }
+        if self.ends[mid] > after {
+            result.push(self.order[mid]);
+        }
+        self.query(mid + 1, hi, after, starts_before, result);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use crate::types::{BendData, BendPoint, NoteEffects, Tuning};
+
+    use super::*;
+
+    fn note(s: f64, d: f64, pitch: u8, string: u8, fret: u8) -> Note {
+        Note {
+            s,
+            d,
+            pitch,
+            string,
+            fret,
+            ..Note::default()
+        }
+    }
+
+    fn effects() -> NoteEffects {
+        NoteEffects {
+            dead_note: true,
+            vibrato: true,
+            bend_data: Some(BendData {
+                points: vec![BendPoint {
+                    pos: 0.5,
+                    bend: 1.0,
+                }],
+            }),
+        }
+    }
+
+    fn guitar(notes: Vec<Note>) -> Track {
+        Track {
+            name: "Guitar".to_string(),
+            is_percussion: false,
+            tuning: Tuning {
+                string_base_pitches: vec![64, 59, 55, 50, 45, 40],
+            },
+            notes,
+        }
+    }
+
+    fn drums(notes: Vec<Note>) -> Track {
+        Track {
+            name: "Drums".to_string(),
+            is_percussion: true,
+            tuning: Tuning::default(),
+            notes,
+        }
+    }
+
+    fn sequence() -> Sequence {
+        Sequence {
+            tempo_map: TempoMap {
+                bpm_base: 120.0,
+                tempo_changes: vec![
+                    TempoChange {
+                        beat: 2.0,
+                        bpm: 60.0,
+                    },
+                    TempoChange {
+                        beat: 6.0,
+                        bpm: 90.0,
+                    },
+                ],
+                time_signatures: vec![TimeSignature {
+                    beat: 4.0,
+                    numerator: 3,
+                    denominator: 4,
+                }],
+            },
+            tracks: vec![
+                guitar(vec![
+                    note(0.0, 1.0, 64, 0, 0),
+                    Note {
+                        effects: effects(),
+                        ..note(1.0, 4.0, 62, 1, 3)
+                    },
+                    note(5.0, 0.5, 45, 4, 0),
+                ]),
+                drums(vec![note(0.0, 0.25, 36, 0, 0), note(4.5, 0.25, 38, 0, 0)]),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_time_slice() {
+        let slice = sequence().time_slice(1.0..5.0);
+        assert_eq!(
+            slice.tempo_map,
+            TempoMap {
+                bpm_base: 120.0,
+                tempo_changes: vec![TempoChange {
+                    beat: 1.0,
+                    bpm: 60.0,
+                }],
+                time_signatures: vec![TimeSignature {
+                    beat: 3.0,
+                    numerator: 3,
+                    denominator: 4,
+                }],
+            }
+        );
+        assert_eq!(
+            slice.tracks,
+            vec![
+                guitar(vec![Note {
+                    effects: effects(),
+                    ..note(0.0, 4.0, 62, 1, 3)
+                }]),
+                drums(vec![note(3.5, 0.25, 38, 0, 0)]),
+            ]
+        );
+
+        let slice = sequence().time_slice(4.5..8.0);
+        assert_eq!(slice.tempo_map.bpm_base, 60.0);
+        assert_eq!(slice.tempo_map.tempo_changes[0].beat, 1.5);
+        assert_eq!(slice.tempo_map.time_signatures[0].beat, 0.0);
+        assert_eq!(slice.tracks[0].notes, vec![note(0.5, 0.5, 45, 4, 0)]);
+
+        let slice = sequence().time_slice(0.0..2.0);
+        assert_eq!(slice.tracks[0].notes[1].d, 1.0);
+        assert_eq!(slice.tracks[0].notes[1].effects, effects());
+    }
+
+    #[test]
+    fn test_transpose() {
+        let mut sequence = sequence();
+        sequence.transpose(2).unwrap();
+        assert_eq!(
+            sequence.tracks[0].notes,
+            vec![
+                note(0.0, 1.0, 66, 0, 2),
+                Note {
+                    effects: effects(),
+                    ..note(1.0, 4.0, 64, 1, 5)
+                },
+                note(5.0, 0.5, 47, 4, 2),
+            ]
+        );
+        // percussion is unchanged
+        assert_eq!(sequence.tracks[1].notes[1].pitch, 38);
+
+        let original = sequence.clone();
+        assert_eq!(
+            sequence.transpose(-3),
+            Err(EditError::FretOutOfRange {
+                track: 0,
+                note: 0,
+                fret: -1,
+            })
+        );
+        assert_eq!(
+            sequence.transpose(100).unwrap_err().to_string(),
+            "Track 0, note 0: Pitch 166 is out of the MIDI range"
+        );
+        assert_eq!(
+            sequence.transpose(i32::MAX),
+            Err(EditError::PitchOutOfRange {
+                track: 0,
+                note: 0,
+                pitch: i32::MAX,
+            })
+        );
+        assert_eq!(sequence, original);
+
+        sequence.tracks[0].tuning.string_base_pitches[0] = i32::MIN;
+        assert_eq!(
+            sequence.transpose(0),
+            Err(EditError::FretOutOfRange {
+                track: 0,
+                note: 0,
+                fret: i32::MAX,
+            })
+        );
+    }
+
+    #[test]
+    fn test_quantize_onsets() {
+        let mut sequence = sequence();
+        sequence.tracks[0].notes[1].s = 1.2;
+        sequence.tracks[1].notes[1].s = 4.4;
+        sequence.quantize_onsets(0.5);
+        assert_eq!(sequence.tracks[0].notes[1].s, 1.0);
+        assert_eq!(sequence.tracks[0].notes[1].effects, effects());
+        assert_eq!(sequence.tracks[1].notes[1].s, 4.5);
+        assert_eq!(sequence.tempo_map, self::sequence().tempo_map);
+    }
+
+    #[test]
+    fn test_merge_and_split_tracks() {
+        let mut sequence = sequence();
+        assert_eq!(
+            sequence.merge_tracks(&[0, 1]),
+            Err(EditError::IncompatibleTracks { track: 0, other: 1 })
+        );
+
+        let original = sequence.tracks[0].clone();
+        assert_eq!(sequence.split_track_by_string(0), 3);
+        let names: Vec<&str> = sequence
+            .tracks
+            .iter()
+            .map(|track| track.name.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "Guitar (string 1)",
+                "Guitar (string 2)",
+                "Guitar (string 5)",
+                "Drums"
+            ]
+        );
+        assert_eq!(sequence.tracks[1].notes, original.notes[1..2]);
+
+        let tracks = sequence.tracks.clone();
+        assert_eq!(
+            sequence.merge_tracks(&[0, 0]),
+            Err(EditError::SelfMerge { track: 0 })
+        );
+        assert_eq!(
+            sequence.merge_tracks(&[0, 99]),
+            Err(EditError::InvalidTrackIndex {
+                index: 99,
+                num_tracks: 4
+            })
+        );
+        assert_eq!(
+            sequence.merge_tracks(&[99]).unwrap_err().to_string(),
+            "Track 99 does not exist, there are 4 tracks"
+        );
+        assert_eq!(sequence.tracks, tracks);
+
+        sequence.merge_tracks(&[1, 2, 0]).unwrap();
+        assert_eq!(sequence.tracks.len(), 2);
+        assert_eq!(
+            sequence.tracks[0],
+            Track {
+                name: "Guitar (string 2)".to_string(),
+                ..original
+            }
+        );
+    }
+
+    #[test]
+    fn test_note_index() {
+        let track = guitar(vec![
+            note(4.0, 1.0, 60, 0, 0),
+            note(0.0, 8.0, 61, 0, 0),
+            note(1.0, 1.0, 62, 0, 0),
+            note(2.0, 0.0, 63, 0, 0),
+            note(2.0, 2.0, 64, 0, 0),
+            note(3.0, 0.5, 65, 0, 0),
+        ]);
+        let index = NoteIndex::new(&track);
+        assert_eq!(index.notes_at(0.0), [1]);
+        assert_eq!(index.notes_at(2.0), [1, 4]);
+        assert_eq!(index.notes_at(4.0), [1, 0]);
+        assert_eq!(index.notes_at(8.0), [] as [usize; 0]);
+        assert_eq!(index.notes_in(1.5..3.0), [1, 2, 3, 4]);
+        assert_eq!(index.notes_in(2.0..3.5), [1, 4, 5]);
+
+        // compare with a linear search
+        for i in 0..40 {
+            let start = i as f64 * 0.25 - 1.0;
+            let end = start + 0.75;
+            let mut expected: Vec<usize> = (0..track.notes.len())
+                .filter(|j| {
+                    let note = &track.notes[*j];
+                    note.s < end && note.s + note.d > start
+                })
+                .collect();
+            expected.sort_by(|a, b| track.notes[*a].s.total_cmp(&track.notes[*b].s));
+            assert_eq!(index.notes_in(start..end), expected, "{}..{}", start, end);
+        }
+    }
+
+    #[test]
+    fn test_stats() {
+        assert_eq!(
+            sequence().stats(),
+            SequenceStats {
+                num_notes: 5,
+                num_notes_per_track: vec![3, 2],
+                pitch_range: Some((45, 64)),
+                duration: 5.5,
+                // 0.5 s per beat up to beat 2, then 1 s per beat
+                duration_seconds: Some(4.5),
+            }
+        );
+        assert_eq!(Sequence::default().stats().pitch_range, None);
+        assert_eq!(Sequence::default().stats().duration, 0.0);
+        // the default tempo map has a base tempo of zero
+        assert_eq!(Sequence::default().stats().duration_seconds, None);
+    }
+}
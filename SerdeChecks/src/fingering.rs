@@ -0,0 +1,612 @@
+//! The consistency of `pitch`, `string` and `fret` of notes, and the assignment of strings
+//! and frets to notes that only have a pitch (e.g., from `midi::read_smf`).
+//!
+//! A note is consistent if `pitch == string_base_pitches[string] + fret`. Frets are counted
+//! from the nut, also with a capo, i.e., with a capo at fret 2, fret 2 is the open string.
+//! Percussion tracks and tracks without tuning are skipped.
+//!
+//! The assignment groups the notes of a track into chords of notes with the same onset, and
+//! picks a string and fret per note such that the total cost of all chords is minimal
+//! (dynamic programming over the candidate fingerings of the chords). The cost models the
+//! fretting hand:
+//! - Every fretted note costs a little, open strings cost depending on `OpenStrings`.
+//! - The hand position (the lowest fretted fret above the capo) costs its height, and the
+//!   span of the fretted frets costs its width, and a lot more beyond four frets.
+//! - Moving the hand between consecutive chords costs the distance, and reusing a string
+//!   that still sounds from the previous chord costs a lot.
+
+use std::fmt;
+
+use crate::types::{Note, Sequence, Track};
+
+const FRETTED_NOTE_COST: u32 = 2;
+const AVOIDED_OPEN_STRING_COST: u32 = 10;
+const POSITION_WEIGHT: u32 = 1;
+const SPAN_WEIGHT: u32 = 1;
+/// The span of four frets, e.g., from index to pinky finger.
+const MAX_SPAN: u32 = 3;
+const STRETCH_COST: u32 = 10;
+const MOVE_WEIGHT: u32 = 2;
+const RINGING_STRING_COST: u32 = 20;
+
+/// Bounds the work per chord for tunings with many strings of similar pitch.
+const MAX_ENUMERATED_FINGERINGS: usize = 4096;
+/// The cheapest fingerings of a chord that are considered.
+const MAX_CANDIDATES: usize = 256;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum OpenStrings {
+    /// Open strings are cheaper than fretted notes.
+    #[default]
+    Prefer,
+    /// Open strings cost the same as fretted notes, but they do not bind the hand position.
+    Neutral,
+    /// Open strings are only used if a fretted note would be much more expensive.
+    Avoid,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FingeringOptions {
+    /// The fret of the capo, zero without capo. Frets below the capo are not used.
+    pub capo: u8,
+    /// The highest fret that is used.
+    pub max_fret: u8,
+    pub open_strings: OpenStrings,
+}
+
+impl Default for FingeringOptions {
+    fn default() -> Self {
+        FingeringOptions {
+            capo: 0,
+            max_fret: 24,
+            open_strings: OpenStrings::Prefer,
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// FingeringError
+// ----------------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FingeringError {
+    /// Index into `Sequence::tracks`.
+    pub track: usize,
+    /// Index into `Track::notes`.
+    pub note: usize,
+    pub kind: FingeringErrorKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FingeringErrorKind {
+    /// `string` is not a string of the tuning.
+    InvalidString { string: u8, num_strings: usize },
+    /// `pitch` differs from the pitch of the string and fret.
+    PitchMismatch { pitch: u8, expected: i64 },
+    /// No string has `pitch` between the capo and the maximum fret.
+    Unplayable { pitch: u8 },
+    /// The notes of a chord starting with `note` cannot be played on different strings.
+    UnplayableChord { num_notes: usize },
+}
+
+impl fmt::Display for FingeringError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Track {}, note {}: ", self.track, self.note)?;
+        match &self.kind {
+            FingeringErrorKind::InvalidString {
+                string,
+                num_strings,
+            } => write!(
+                f,
+                "String {} does not exist, the tuning has {} string(s)",
+                string, num_strings
+            ),
+            FingeringErrorKind::PitchMismatch { pitch, expected } => write!(
+                f,
+                "Pitch {} differs from the pitch {} of string and fret",
+                pitch, expected
+            ),
+            FingeringErrorKind::Unplayable { pitch } => write!(
+                f,
+                "Pitch {} is not playable on any string in the fret range",
+                pitch
+            ),
+            FingeringErrorKind::UnplayableChord { num_notes } => write!(
+                f,
+                "The {} notes of the chord are not playable on different strings",
+                num_notes
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FingeringError {}
+
+fn is_fretted(track: &Track) -> bool {
+    !track.is_percussion && !track.tuning.string_base_pitches.is_empty()
+}
+
+/// Checks that the pitch of every note is the pitch of its string and fret.
+pub fn validate_sequence(sequence: &Sequence) -> Result<(), FingeringError> {
+    for (track_index, track) in sequence.tracks.iter().enumerate() {
+        if !is_fretted(track) {
+            continue;
+        }
+        let base_pitches = &track.tuning.string_base_pitches;
+        for (note_index, note) in track.notes.iter().enumerate() {
+            let error = |kind| FingeringError {
+                track: track_index,
+                note: note_index,
+                kind,
+            };
+            let base_pitch = base_pitches.get(note.string as usize).ok_or_else(|| {
+                error(FingeringErrorKind::InvalidString {
+                    string: note.string,
+                    num_strings: base_pitches.len(),
+                })
+            })?;
+            // tunings are arbitrary `i32`, the sum may not fit
+            let expected = *base_pitch as i64 + note.fret as i64;
+            if expected != note.pitch as i64 {
+                return Err(error(FingeringErrorKind::PitchMismatch {
+                    pitch: note.pitch,
+                    expected,
+                }));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sets `string` and `fret` of all notes from their pitch, see `fingering` for the cost
+/// model. The sequence is unchanged if any track fails.
+pub fn assign_fingering(
+    sequence: &mut Sequence,
+    options: &FingeringOptions,
+) -> Result<(), FingeringError> {
+    let mut assignments = vec![];
+    for (track_index, track) in sequence.tracks.iter().enumerate() {
+        if is_fretted(track) {
+            let assignment =
+                assign_track(track, options).map_err(|(note, kind)| FingeringError {
+                    track: track_index,
+                    note,
+                    kind,
+                })?;
+            assignments.push((track_index, assignment));
+        }
+    }
+    for (track_index, assignment) in assignments {
+        let notes = &mut sequence.tracks[track_index].notes;
+        for (note, (string, fret)) in notes.iter_mut().zip(assignment) {
+            note.string = string;
+            note.fret = fret;
+        }
+    }
+    Ok(())
+}
+
+/// A string and fret for each note of a chord.
+struct Fingering {
+    strings_and_frets: Vec<(u8, u8)>,
+    /// The lowest fretted fret, none if all strings are open.
+    position: Option<u8>,
+    cost: u32,
+}
+
+/// Returns the string and fret of every note, or the index of the failing note.
+fn assign_track(
+    track: &Track,
+    options: &FingeringOptions,
+) -> Result<Vec<(u8, u8)>, (usize, FingeringErrorKind)> {
+    let notes = &track.notes;
+    let mut order: Vec<usize> = (0..notes.len()).collect();
+    order.sort_by(|a, b| notes[*a].s.total_cmp(&notes[*b].s));
+    let chords: Vec<&[usize]> = order.chunk_by(|a, b| notes[*a].s == notes[*b].s).collect();
+
+    let mut candidates: Vec<Vec<Fingering>> = vec![];
+    for chord in &chords {
+        candidates.push(chord_fingerings(track, chord, options)?);
+    }
+
+    // costs[i][j]: the cheapest total cost of the chords up to i with fingering j of chord i
+    let mut costs: Vec<Vec<u32>> = vec![];
+    let mut predecessors: Vec<Vec<usize>> = vec![];
+    for (i, fingerings) in candidates.iter().enumerate() {
+        let mut chord_costs = vec![];
+        let mut chord_predecessors = vec![];
+        for fingering in fingerings {
+            let (predecessor, cost) = if i == 0 {
+                (0, 0)
+            } else {
+                candidates[i - 1]
+                    .iter()
+                    .zip(&costs[i - 1])
+                    .map(|(previous, cost)| {
+                        let transition = transition_cost(
+                            notes,
+                            (chords[i - 1], previous),
+                            (chords[i], fingering),
+                        );
+                        cost + transition
+                    })
+                    .enumerate()
+                    .min_by_key(|(_, cost)| *cost)
+                    .expect("chords have at least one fingering")
+            };
+            chord_costs.push(cost + fingering.cost);
+            chord_predecessors.push(predecessor);
+        }
+        costs.push(chord_costs);
+        predecessors.push(chord_predecessors);
+    }
+
+    let mut assignment = vec![(0, 0); notes.len()];
+    let mut best = match costs.last() {
+        Some(last) => (0..last.len()).min_by_key(|j| last[*j]).unwrap_or(0),
+        None => return Ok(assignment),
+    };
+    for i in (0..chords.len()).rev() {
+        let fingering = &candidates[i][best];
+        for (note, string_and_fret) in chords[i].iter().zip(&fingering.strings_and_frets) {
+            assignment[*note] = *string_and_fret;
+        }
+        best = predecessors[i][best];
+    }
+    Ok(assignment)
+}
+
+/// The cheapest fingerings of a chord, given as indices of its notes.
+fn chord_fingerings(
+    track: &Track,
+    chord: &[usize],
+    options: &FingeringOptions,
+) -> Result<Vec<Fingering>, (usize, FingeringErrorKind)> {
+    let base_pitches = &track.tuning.string_base_pitches;
+    let mut options_per_note = vec![];
+    for note_index in chord {
+        let pitch = track.notes[*note_index].pitch;
+        let strings_and_frets: Vec<(u8, u8)> = base_pitches
+            .iter()
+            .enumerate()
+            .filter_map(|(string, base_pitch)| {
+                let fret = u8::try_from(pitch as i64 - *base_pitch as i64).ok()?;
+                let string = u8::try_from(string).ok()?;
+                (options.capo..=options.max_fret)
+                    .contains(&fret)
+                    .then_some((string, fret))
+            })
+            .collect();
+        if strings_and_frets.is_empty() {
+            return Err((*note_index, FingeringErrorKind::Unplayable { pitch }));
+        }
+        options_per_note.push(strings_and_frets);
+    }
+
+    let mut fingerings = vec![];
+    let mut used_strings = vec![false; base_pitches.len()];
+    let mut current = vec![];
+    enumerate_fingerings(
+        &options_per_note,
+        &mut used_strings,
+        &mut current,
+        &mut fingerings,
+    );
+    if fingerings.is_empty() {
+        return Err((
+            chord[0],
+            FingeringErrorKind::UnplayableChord {
+                num_notes: chord.len(),
+            },
+        ));
+    }
+    let mut fingerings: Vec<Fingering> = fingerings
+        .into_iter()
+        .map(|strings_and_frets| fingering(strings_and_frets, options))
+        .collect();
+    fingerings.sort_by_key(|fingering| fingering.cost);
+    fingerings.truncate(MAX_CANDIDATES);
+    Ok(fingerings)
+}
+
+/// Collects the combinations of the options of the notes that use every string at most once.
+fn enumerate_fingerings(
+    options_per_note: &[Vec<(u8, u8)>],
+    used_strings: &mut [bool],
+    current: &mut Vec<(u8, u8)>,
+    fingerings: &mut Vec<Vec<(u8, u8)>>,
+) {
+    if fingerings.len() >= MAX_ENUMERATED_FINGERINGS {
+        return;
+    }
+    let Some((options, remaining)) = options_per_note.split_first() else {
+        fingerings.push(current.clone());
+        return;
+    };
+    for (string, fret) in options {
+        if !used_strings[*string as usize] {
+            used_strings[*string as usize] = true;
+            current.push((*string, *fret));
+            enumerate_fingerings(remaining, used_strings, current, fingerings);
+            current.pop();
+            used_strings[*string as usize] = false;
+        }
+    }
+}
+
+fn fingering(strings_and_frets: Vec<(u8, u8)>, options: &FingeringOptions) -> Fingering {
+    let mut cost = 0;
+    let mut range: Option<(u8, u8)> = None;
+    for (_, fret) in &strings_and_frets {
+        if *fret == options.capo {
+            cost += match options.open_strings {
+                OpenStrings::Prefer => 0,
+                OpenStrings::Neutral => FRETTED_NOTE_COST,
+                OpenStrings::Avoid => FRETTED_NOTE_COST + AVOIDED_OPEN_STRING_COST,
+            };
+        } else {
+            cost += FRETTED_NOTE_COST;
+            range = Some(match range {
+                Some((min, max)) => (min.min(*fret), max.max(*fret)),
+                None => (*fret, *fret),
+            });
+        }
+    }
+    if let Some((min, max)) = range {
+        let span = (max - min) as u32;
+        cost += POSITION_WEIGHT * (min - options.capo) as u32
+            + SPAN_WEIGHT * span
+            + STRETCH_COST * span.saturating_sub(MAX_SPAN);
+    }
+    Fingering {
+        strings_and_frets,
+        position: range.map(|(min, _)| min),
+        cost,
+    }
+}
+
+fn transition_cost(
+    notes: &[Note],
+    (previous_chord, previous): (&[usize], &Fingering),
+    (chord, fingering): (&[usize], &Fingering),
+) -> u32 {
+    let mut cost = match (previous.position, fingering.position) {
+        (Some(a), Some(b)) => MOVE_WEIGHT * a.abs_diff(b) as u32,
+        _ => 0,
+    };
+    let onset = notes[chord[0]].s;
+    for (note, (string, _)) in previous_chord.iter().zip(&previous.strings_and_frets) {
+        let is_ringing = notes[*note].s + notes[*note].d > onset;
+        if is_ringing
+            && fingering
+                .strings_and_frets
+                .iter()
+                .any(|(other, _)| other == string)
+        {
+            cost += RINGING_STRING_COST;
+        }
+    }
+    cost
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use crate::types::{TempoMap, Tuning};
+
+    use super::*;
+
+    const STANDARD_TUNING: [i32; 6] = [64, 59, 55, 50, 45, 40];
+
+    fn note(s: f64, d: f64, pitch: u8) -> Note {
+        Note {
+            s,
+            d,
+            pitch,
+            ..Note::default()
+        }
+    }
+
+    fn sequence(notes: Vec<Note>) -> Sequence {
+        Sequence {
+            tempo_map: TempoMap::new(120.0),
+            tracks: vec![Track {
+                name: "Guitar".to_string(),
+                is_percussion: false,
+                tuning: Tuning {
+                    string_base_pitches: STANDARD_TUNING.to_vec(),
+                },
+                notes,
+            }],
+        }
+    }
+
+    /// The strings and frets assigned to `notes`.
+    fn assign(notes: Vec<Note>, options: &FingeringOptions) -> Vec<(u8, u8)> {
+        let mut sequence = sequence(notes);
+        assign_fingering(&mut sequence, options).unwrap();
+        validate_sequence(&sequence).unwrap();
+        sequence.tracks[0]
+            .notes
+            .iter()
+            .map(|note| (note.string, note.fret))
+            .collect()
+    }
+
+    fn assign_err(notes: Vec<Note>, options: &FingeringOptions) -> FingeringError {
+        let mut sequence = sequence(notes);
+        let original = sequence.clone();
+        let err = assign_fingering(&mut sequence, options).unwrap_err();
+        assert_eq!(sequence, original);
+        err
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut sequence = sequence(vec![
+            Note {
+                string: 1,
+                fret: 5,
+                ..note(0.0, 1.0, 64)
+            },
+            Note {
+                string: 5,
+                fret: 3,
+                ..note(1.0, 1.0, 43)
+            },
+        ]);
+        assert_eq!(validate_sequence(&sequence), Ok(()));
+
+        sequence.tracks[0].notes[1].fret = 2;
+        let err = validate_sequence(&sequence).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Track 0, note 1: Pitch 43 differs from the pitch 42 of string and fret"
+        );
+
+        sequence.tracks[0].notes[1].string = 6;
+        assert_eq!(
+            validate_sequence(&sequence).unwrap_err().kind,
+            FingeringErrorKind::InvalidString {
+                string: 6,
+                num_strings: 6,
+            }
+        );
+
+        // extreme tunings neither overflow here nor in the assignment
+        sequence.tracks[0].notes[1].string = 0;
+        sequence.tracks[0].tuning.string_base_pitches = vec![i32::MAX, i32::MIN];
+        assert_eq!(
+            validate_sequence(&sequence).unwrap_err().kind,
+            FingeringErrorKind::PitchMismatch {
+                pitch: 64,
+                expected: i32::MIN as i64 + 5,
+            }
+        );
+        assert_eq!(
+            assign_fingering(&mut sequence, &FingeringOptions::default())
+                .unwrap_err()
+                .kind,
+            FingeringErrorKind::Unplayable { pitch: 64 }
+        );
+    }
+
+    #[test]
+    fn test_validate_skips_percussion_and_missing_tuning() {
+        let mut sequence = sequence(vec![Note {
+            fret: 36,
+            ..note(0.0, 1.0, 36)
+        }]);
+        sequence.tracks[0].is_percussion = true;
+        assert_eq!(validate_sequence(&sequence), Ok(()));
+        assert_eq!(
+            assign_fingering(&mut sequence, &FingeringOptions::default()),
+            Ok(())
+        );
+        assert_eq!(sequence.tracks[0].notes[0].fret, 36);
+
+        sequence.tracks[0].is_percussion = false;
+        sequence.tracks[0].tuning.string_base_pitches.clear();
+        assert_eq!(validate_sequence(&sequence), Ok(()));
+    }
+
+    #[test]
+    fn test_open_chord() {
+        let chord = [40, 47, 52, 56, 59, 64]
+            .iter()
+            .map(|pitch| note(0.0, 4.0, *pitch))
+            .collect();
+        assert_eq!(
+            assign(chord, &FingeringOptions::default()),
+            vec![(5, 0), (4, 2), (3, 2), (2, 1), (1, 0), (0, 0)]
+        );
+    }
+
+    #[test]
+    fn test_hand_position_looks_ahead() {
+        // A4 in the fifth position would be cheaper on its own, but E5 needs the twelfth.
+        let notes = vec![note(0.0, 1.0, 69), note(1.0, 1.0, 76)];
+        assert_eq!(
+            assign(notes, &FingeringOptions::default()),
+            vec![(1, 10), (0, 12)]
+        );
+        let notes = vec![note(0.0, 1.0, 76), note(1.0, 1.0, 69)];
+        assert_eq!(
+            assign(notes, &FingeringOptions::default()),
+            vec![(0, 12), (1, 10)]
+        );
+        let notes = vec![note(0.0, 1.0, 69)];
+        assert_eq!(assign(notes, &FingeringOptions::default()), vec![(0, 5)]);
+    }
+
+    #[test]
+    fn test_ringing_strings() {
+        let notes = vec![note(0.0, 1.0, 64), note(1.0, 1.0, 69)];
+        assert_eq!(
+            assign(notes, &FingeringOptions::default()),
+            vec![(0, 0), (0, 5)]
+        );
+        let notes = vec![note(0.0, 4.0, 64), note(1.0, 1.0, 69)];
+        assert_eq!(
+            assign(notes, &FingeringOptions::default()),
+            vec![(0, 0), (1, 10)]
+        );
+    }
+
+    #[test]
+    fn test_options() {
+        let notes = vec![note(0.0, 1.0, 64), note(1.0, 1.0, 66)];
+        let capo = FingeringOptions {
+            capo: 2,
+            ..FingeringOptions::default()
+        };
+        assert_eq!(assign(notes.clone(), &capo), vec![(1, 5), (0, 2)]);
+
+        let notes = vec![note(0.0, 1.0, 64)];
+        let avoid = FingeringOptions {
+            open_strings: OpenStrings::Avoid,
+            ..FingeringOptions::default()
+        };
+        assert_eq!(assign(notes.clone(), &avoid), vec![(1, 5)]);
+        let neutral = FingeringOptions {
+            open_strings: OpenStrings::Neutral,
+            ..FingeringOptions::default()
+        };
+        assert_eq!(assign(notes, &neutral), vec![(0, 0)]);
+
+        let notes = vec![note(0.0, 1.0, 88)];
+        assert_eq!(
+            assign(notes.clone(), &FingeringOptions::default()),
+            vec![(0, 24)]
+        );
+        let max_fret = FingeringOptions {
+            max_fret: 20,
+            ..FingeringOptions::default()
+        };
+        assert_eq!(
+            assign_err(notes, &max_fret),
+            FingeringError {
+                track: 0,
+                note: 0,
+                kind: FingeringErrorKind::Unplayable { pitch: 88 },
+            }
+        );
+    }
+
+    #[test]
+    fn test_unplayable_chord() {
+        let mut notes: Vec<Note> = (0..7).map(|i| note(1.0, 1.0, 60 + i)).collect();
+        notes.insert(0, note(0.0, 1.0, 60));
+        let err = assign_err(notes, &FingeringOptions::default());
+        assert_eq!(
+            err.to_string(),
+            "Track 0, note 1: The 7 notes of the chord are not playable on different strings"
+        );
+        let notes = vec![note(0.0, 1.0, 60), note(1.0, 1.0, 39)];
+        assert_eq!(
+            assign_err(notes, &FingeringOptions::default()).kind,
+            FingeringErrorKind::Unplayable { pitch: 39 }
+        );
+    }
+}
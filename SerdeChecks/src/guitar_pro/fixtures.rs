@@ -0,0 +1,397 @@
+//! Writes the synthetic Guitar Pro files in `testdata/guitar_pro`.
+//!
+//! The song has a guitar and a drum track, and two measures in 4/4 and 3/4. It covers the
+//! elements the reader extracts, plus a few of those it skips (marker, text, chord diagram,
+//! mix table change, dynamics, empty beat, second voice).
+
+use super::GpVersion;
+
+#[derive(Default)]
+struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    fn i8(&mut self, value: i8) {
+        self.data.push(value as u8);
+    }
+
+    fn i16(&mut self, value: i16) {
+        self.data.extend(value.to_le_bytes());
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.data.extend(value.to_le_bytes());
+    }
+
+    fn zeros(&mut self, num_bytes: usize) {
+        self.data.extend(vec![0; num_bytes]);
+    }
+
+    fn byte_size_string(&mut self, value: &str, size: usize) {
+        self.u8(value.len() as u8);
+        self.data.extend(value.as_bytes());
+        self.zeros(size - value.len());
+    }
+
+    fn int_byte_size_string(&mut self, value: &str) {
+        self.i32(value.len() as i32 + 1);
+        self.u8(value.len() as u8);
+        self.data.extend(value.as_bytes());
+    }
+
+    fn int_size_string(&mut self, value: &str) {
+        self.i32(value.len() as i32);
+        self.data.extend(value.as_bytes());
+    }
+}
+
+#[derive(Default)]
+struct GpNote {
+    string: usize,
+    fret: i8,
+    tied: bool,
+    dead: bool,
+    vibrato: bool,
+    bend: Vec<(i32, i32)>,
+}
+
+#[derive(Default)]
+struct GpBeat {
+    duration: i8,
+    dotted: bool,
+    tuplet: Option<i32>,
+    /// 0: empty, 2: rest
+    status: Option<u8>,
+    text: Option<&'static str>,
+    chord: bool,
+    tempo: Option<i32>,
+    notes: Vec<GpNote>,
+}
+
+fn note(string: usize, fret: i8) -> GpNote {
+    GpNote {
+        string,
+        fret,
+        ..GpNote::default()
+    }
+}
+
+fn beat(duration: i8, notes: Vec<GpNote>) -> GpBeat {
+    GpBeat {
+        duration,
+        notes,
+        ..GpBeat::default()
+    }
+}
+
+/// The voices of both measures of the guitar and the drum track.
+fn measures(version: GpVersion) -> [[Vec<Vec<GpBeat>>; 2]; 2] {
+    let has_second_voice = version >= GpVersion::V5_00;
+    let guitar_1 = vec![
+        GpBeat {
+            text: Some("Intro"),
+            chord: true,
+            ..beat(
+                0,
+                vec![
+                    GpNote {
+                        vibrato: true,
+                        ..note(0, 0)
+                    },
+                    GpNote {
+                        vibrato: true,
+                        ..note(2, 2)
+                    },
+                ],
+            )
+        },
+        GpBeat {
+            dotted: true,
+            ..beat(
+                1,
+                vec![GpNote {
+                    dead: true,
+                    ..note(1, 0)
+                }],
+            )
+        },
+        GpBeat {
+            status: Some(2),
+            ..beat(2, vec![])
+        },
+        beat(
+            -1,
+            vec![GpNote {
+                bend: vec![(0, 0), (30, 100), (60, 100)],
+                ..note(1, 10)
+            }],
+        ),
+    ];
+    let guitar_2 = vec![
+        GpBeat {
+            tuplet: Some(3),
+            tempo: Some(90),
+            ..beat(1, vec![note(0, 3)])
+        },
+        GpBeat {
+            tuplet: Some(3),
+            ..beat(1, vec![note(0, 5)])
+        },
+        GpBeat {
+            tuplet: Some(3),
+            ..beat(1, vec![note(0, 7)])
+        },
+        beat(0, vec![note(0, 5)]),
+        beat(
+            0,
+            vec![GpNote {
+                tied: true,
+                ..note(0, 5)
+            }],
+        ),
+        GpBeat {
+            status: Some(0),
+            ..beat(0, vec![])
+        },
+    ];
+    let drums_1 = (0..4).map(|_| beat(0, vec![note(0, 36)])).collect();
+    let mut measures = [
+        [vec![guitar_1], vec![drums_1]],
+        [vec![guitar_2], vec![vec![]]],
+    ];
+    if has_second_voice {
+        measures[0][0].push(vec![beat(-2, vec![note(5, 0)])]);
+        for voices in measures.iter_mut().flatten() {
+            voices.resize_with(2, Vec::new);
+        }
+    }
+    measures
+}
+
+pub(super) fn write_fixture(version: GpVersion) -> Vec<u8> {
+    let v5 = version >= GpVersion::V5_00;
+    let mut wr = Writer::default();
+    wr.byte_size_string(&format!("FICHIER GUITAR PRO v{}", version), 30);
+    let num_infos = if v5 { 9 } else { 8 };
+    for i in 0..num_infos {
+        wr.int_byte_size_string(if i == 0 { "Fixture" } else { "" });
+    }
+    wr.i32(1);
+    wr.int_byte_size_string("Synthetic");
+    if !v5 {
+        wr.u8(0);
+    }
+    if version >= GpVersion::V4_00 {
+        wr.i32(0);
+        for _ in 0..5 {
+            wr.i32(1);
+            wr.int_size_string("");
+        }
+    }
+    if version >= GpVersion::V5_10 {
+        wr.zeros(19);
+    }
+    if v5 {
+        wr.zeros(30);
+        for _ in 0..10 {
+            wr.int_byte_size_string("%TITLE%");
+        }
+        wr.int_byte_size_string("Moderate");
+    }
+    wr.i32(120);
+    if version >= GpVersion::V5_10 {
+        wr.u8(0);
+    }
+    wr.zeros(if version >= GpVersion::V4_00 { 5 } else { 4 });
+    for _ in 0..64 {
+        wr.i32(25);
+        wr.zeros(8);
+    }
+    if v5 {
+        for _ in 0..19 {
+            wr.i16(-1);
+        }
+        wr.i32(0);
+    }
+    wr.i32(2);
+    wr.i32(2);
+
+    // measure headers: 4/4 with a marker, then 3/4
+    wr.u8(0x01 | 0x02 | 0x20);
+    wr.u8(4);
+    wr.u8(4);
+    wr.int_byte_size_string("A");
+    wr.zeros(4);
+    if v5 {
+        wr.zeros(4 + 2);
+        wr.u8(0);
+    }
+    wr.u8(0x01);
+    wr.u8(3);
+    if v5 {
+        wr.zeros(4 + 2);
+    }
+
+    for (index, (name, is_percussion)) in [("Guitar", false), ("Drums", true)].iter().enumerate() {
+        if v5 && (index == 0 || version == GpVersion::V5_00) {
+            wr.u8(0);
+        }
+        wr.u8(*is_percussion as u8);
+        wr.byte_size_string(name, 40);
+        wr.i32(6);
+        let tuning = if *is_percussion {
+            [0; 7]
+        } else {
+            [64, 59, 55, 50, 45, 40, -1]
+        };
+        for pitch in tuning {
+            wr.i32(pitch);
+        }
+        let channel = if *is_percussion { 10 } else { 1 };
+        wr.i32(1);
+        wr.i32(channel);
+        wr.i32(channel);
+        wr.i32(24);
+        wr.i32(0);
+        wr.zeros(4);
+        if v5 {
+            wr.zeros(if version == GpVersion::V5_00 { 44 } else { 49 });
+            if version > GpVersion::V5_00 {
+                wr.int_byte_size_string("");
+                wr.int_byte_size_string("");
+            }
+        }
+    }
+    if v5 {
+        wr.zeros(if version == GpVersion::V5_00 { 2 } else { 1 });
+    }
+
+    for tracks in measures(version) {
+        for voices in tracks {
+            for beats in voices {
+                wr.i32(beats.len() as i32);
+                for beat in beats {
+                    write_beat(&mut wr, version, beat);
+                }
+            }
+            if v5 {
+                wr.u8(0);
+            }
+        }
+    }
+    wr.data
+}
+
+fn write_beat(wr: &mut Writer, version: GpVersion, beat: GpBeat) {
+    let v5 = version >= GpVersion::V5_00;
+    // v3 has vibrato as beat effect only
+    let beat_vibrato = version < GpVersion::V4_00 && beat.notes.iter().any(|note| note.vibrato);
+    let flags = beat.dotted as u8
+        | (beat.chord as u8) << 1
+        | (beat.text.is_some() as u8) << 2
+        | (beat_vibrato as u8) << 3
+        | (beat.tempo.is_some() as u8) << 4
+        | (beat.tuplet.is_some() as u8) << 5
+        | (beat.status.is_some() as u8) << 6;
+    wr.u8(flags);
+    if let Some(status) = beat.status {
+        wr.u8(status);
+    }
+    wr.i8(beat.duration);
+    if let Some(tuplet) = beat.tuplet {
+        wr.i32(tuplet);
+    }
+    if beat.chord {
+        // old format in v3, new format from v4 on
+        if version < GpVersion::V4_00 {
+            wr.u8(0);
+            wr.int_byte_size_string("Em");
+            wr.i32(1);
+            wr.zeros(6 * 4);
+        } else {
+            wr.u8(1);
+            wr.zeros(106);
+        }
+    }
+    if let Some(text) = beat.text {
+        wr.int_byte_size_string(text);
+    }
+    if beat_vibrato {
+        wr.u8(0x01);
+    }
+    if let Some(tempo) = beat.tempo {
+        wr.i8(-1);
+        if v5 {
+            wr.zeros(16);
+        }
+        for _ in 0..6 {
+            wr.i8(-1);
+        }
+        if v5 {
+            wr.int_byte_size_string("");
+        }
+        wr.i32(tempo);
+        wr.u8(0);
+        if version >= GpVersion::V5_10 {
+            wr.u8(0);
+        }
+        if version >= GpVersion::V4_00 {
+            wr.u8(0);
+        }
+        if v5 {
+            wr.i8(-1);
+        }
+        if version >= GpVersion::V5_10 {
+            wr.int_byte_size_string("");
+            wr.int_byte_size_string("");
+        }
+    }
+    let string_flags = beat
+        .notes
+        .iter()
+        .fold(0, |flags, note| flags | 0x40 >> note.string);
+    wr.u8(string_flags);
+    let mut notes = beat.notes;
+    notes.sort_by_key(|note| note.string);
+    for note in notes {
+        let has_effects = !note.bend.is_empty() || (version >= GpVersion::V4_00 && note.vibrato);
+        wr.u8(0x20 | 0x10 | (has_effects as u8) << 3);
+        wr.u8(if note.tied {
+            2
+        } else if note.dead {
+            3
+        } else {
+            1
+        });
+        // dynamics
+        wr.u8(6);
+        wr.i8(note.fret);
+        if v5 {
+            wr.u8(0);
+        }
+        if has_effects {
+            wr.u8(!note.bend.is_empty() as u8);
+            if version >= GpVersion::V4_00 {
+                wr.u8((note.vibrato as u8) << 6);
+            }
+            if !note.bend.is_empty() {
+                wr.u8(1);
+                wr.i32(100);
+                wr.i32(note.bend.len() as i32);
+                for (position, value) in &note.bend {
+                    wr.i32(*position);
+                    wr.i32(*value);
+                    wr.u8(0);
+                }
+            }
+        }
+    }
+    if v5 {
+        wr.i16(0);
+    }
+}
@@ -0,0 +1,89 @@
+use super::GpError;
+
+/// Reads the little-endian primitives and the string encodings of Guitar Pro files.
+pub(super) struct Input<'a> {
+    data: &'a [u8],
+    /// Offset relative to the start of the file.
+    pub pos: usize,
+}
+
+impl<'a> Input<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Input { data, pos: 0 }
+    }
+
+    pub fn take(&mut self, num_bytes: usize) -> Result<&'a [u8], GpError> {
+        let remaining = self.data.len() - self.pos;
+        if num_bytes > remaining {
+            return Err(GpError::Truncated {
+                expected: num_bytes - remaining,
+                offset: self.pos,
+            });
+        }
+        self.pos += num_bytes;
+        Ok(&self.data[self.pos - num_bytes..self.pos])
+    }
+
+    pub fn skip(&mut self, num_bytes: usize) -> Result<(), GpError> {
+        self.take(num_bytes).map(|_| ())
+    }
+
+    pub fn u8(&mut self) -> Result<u8, GpError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn i8(&mut self) -> Result<i8, GpError> {
+        Ok(self.u8()? as i8)
+    }
+
+    pub fn i16(&mut self) -> Result<i16, GpError> {
+        let bytes = self.take(2)?;
+        Ok(i16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn i32(&mut self) -> Result<i32, GpError> {
+        let bytes = self.take(4)?;
+        Ok(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// A non-negative `i32`, e.g., the number of elements that follow.
+    pub fn count(&mut self, what: &'static str) -> Result<usize, GpError> {
+        let offset = self.pos;
+        let value = self.i32()?;
+        usize::try_from(value).map_err(|_| GpError::InvalidValue {
+            what,
+            value: value as i64,
+            offset,
+        })
+    }
+
+    /// A length byte followed by `size` bytes, of which the first `length` are the string.
+    pub fn byte_size_string(&mut self, size: usize) -> Result<String, GpError> {
+        let len = self.u8()? as usize;
+        let bytes = self.take(size)?;
+        Ok(decode(&bytes[..len.min(size)]))
+    }
+
+    /// The size of a `byte_size_string` (including its length byte) as `i32`, followed by
+    /// that string.
+    pub fn int_byte_size_string(&mut self) -> Result<String, GpError> {
+        let size = self.count("string size")?;
+        let bytes = self.take(size)?;
+        match bytes.split_first() {
+            Some((len, bytes)) => Ok(decode(&bytes[..(*len as usize).min(bytes.len())])),
+            None => Ok(String::new()),
+        }
+    }
+
+    /// A length as `i32` followed by the string.
+    pub fn int_size_string(&mut self) -> Result<String, GpError> {
+        let len = self.count("string length")?;
+        Ok(decode(self.take(len)?))
+    }
+}
+
+/// Guitar Pro strings are Latin-1 (or rather Windows-1252, but the differences are rare
+/// punctuation characters).
+fn decode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| *byte as char).collect()
+}
@@ -0,0 +1,385 @@
+//! Import of Guitar Pro 3, 4 and 5 files (`.gp3`, `.gp4`, `.gp5`).
+//!
+//! The mapping to a `Sequence`:
+//! - The tempo of the song is `bpm_base`, the tempos of mix table changes are tempo changes,
+//!   and the time signatures of the measure headers are time signatures.
+//! - A `Track` per Guitar Pro track with its name, `is_percussion` (also set for MIDI
+//!   channel 10) and its tuning, i.e., `string_base_pitches` from the highest string to the
+//!   lowest.
+//! - A `Note` per note of the beats of all voices, with the onset and the duration of the
+//!   beat in quarter notes. `string` counts from the highest string, starting at zero, and
+//!   `pitch` is the tuning of the string plus the fret. A tied note extends the note it is
+//!   tied to instead of starting a new one.
+//! - Dead notes and vibrato (of the note or its beat) are `NoteEffects`. The positions of
+//!   bend points are relative to the duration of the note in 1/60, their values are in 1/50
+//!   semitones (1/100 tones), i.e., both are converted to beats and semitones.
+//!
+//! Everything else (lyrics, page setup, chord diagrams, grace notes, RSE settings, ...) is
+//! skipped.
+
+mod input;
+mod read;
+
+use std::fmt;
+
+pub use read::read_guitar_pro;
+
+/// The version in the file header, e.g., `FICHIER GUITAR PRO v5.10` is 5.10.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GpVersion {
+    pub major: u8,
+    pub minor: u8,
+}
+
+impl GpVersion {
+    pub const V3_00: GpVersion = GpVersion { major: 3, minor: 0 };
+    pub const V4_00: GpVersion = GpVersion { major: 4, minor: 0 };
+    pub const V4_06: GpVersion = GpVersion { major: 4, minor: 6 };
+    pub const V5_00: GpVersion = GpVersion { major: 5, minor: 0 };
+    pub const V5_10: GpVersion = GpVersion {
+        major: 5,
+        minor: 10,
+    };
+}
+
+impl fmt::Display for GpVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{:02}", self.major, self.minor)
+    }
+}
+
+// ----------------------------------------------------------------------------
+// GpError
+// ----------------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GpError {
+    /// The header is not the one of a Guitar Pro 3 to 5 file.
+    UnsupportedVersion { version: String, offset: usize },
+    /// The input ended, although at least `expected` more bytes were required.
+    Truncated { expected: usize, offset: usize },
+    /// A value that is out of its range, e.g., a negative count.
+    InvalidValue {
+        what: &'static str,
+        value: i64,
+        offset: usize,
+    },
+}
+
+impl GpError {
+    /// Byte offset relative to the start of the file.
+    pub fn offset(&self) -> usize {
+        match self {
+            GpError::UnsupportedVersion { offset, .. }
+            | GpError::Truncated { offset, .. }
+            | GpError::InvalidValue { offset, .. } => *offset,
+        }
+    }
+}
+
+impl fmt::Display for GpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GpError::UnsupportedVersion { version, .. } => {
+                write!(f, "Unsupported Guitar Pro version {:?}", version)?
+            }
+            GpError::Truncated { expected, .. } => write!(
+                f,
+                "Unexpected end of input, expected {} more byte(s)",
+                expected
+            )?,
+            GpError::InvalidValue { what, value, .. } => write!(f, "Invalid {} {}", what, value)?,
+        }
+        write!(f, " at offset {}", self.offset())
+    }
+}
+
+impl std::error::Error for GpError {}
+
+#[cfg(test)]
+mod fixtures;
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use crate::types::{
+        BendData, BendPoint, Note, NoteEffects, Sequence, TempoChange, TempoMap, TimeSignature,
+        Track, Tuning,
+    };
+
+    use super::fixtures::write_fixture;
+    use super::*;
+
+    const FIXTURE_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/guitar_pro");
+
+    const FIXTURES: [(GpVersion, &str); 4] = [
+        (GpVersion::V3_00, "fixture_v3_00.gp3"),
+        (GpVersion::V4_06, "fixture_v4_06.gp4"),
+        (GpVersion::V5_00, "fixture_v5_00.gp5"),
+        (GpVersion::V5_10, "fixture_v5_10.gp5"),
+    ];
+
+    fn read_fixture(name: &str) -> Vec<u8> {
+        let path = format!("{}/{}", FIXTURE_DIR, name);
+        std::fs::read(&path).unwrap_or_else(|e| panic!("Failed to read {}: {}", path, e))
+    }
+
+    fn note(s: f64, d: f64, pitch: u8, string: u8, fret: u8) -> Note {
+        Note {
+            s,
+            d,
+            pitch,
+            string,
+            fret,
+            ..Note::default()
+        }
+    }
+
+    fn vibrato(note: Note) -> Note {
+        Note {
+            effects: NoteEffects {
+                vibrato: true,
+                ..note.effects
+            },
+            ..note
+        }
+    }
+
+    /// The sequence of the fixture of `version`, see `fixtures`.
+    fn expected_sequence(version: GpVersion) -> Sequence {
+        let bend = |pos: f64, bend: f32| BendPoint { pos, bend };
+        let mut guitar = vec![
+            vibrato(note(0.0, 1.0, 64, 0, 0)),
+            vibrato(note(0.0, 1.0, 57, 2, 2)),
+            Note {
+                effects: NoteEffects {
+                    dead_note: true,
+                    ..NoteEffects::default()
+                },
+                ..note(1.0, 0.75, 59, 1, 0)
+            },
+            Note {
+                effects: NoteEffects {
+                    bend_data: Some(BendData {
+                        points: vec![bend(0.0, 0.0), bend(1.0, 2.0), bend(2.0, 2.0)],
+                    }),
+                    ..NoteEffects::default()
+                },
+                ..note(2.0, 2.0, 69, 1, 10)
+            },
+            note(4.0, 1.0 / 3.0, 67, 0, 3),
+            note(13.0 / 3.0, 1.0 / 3.0, 69, 0, 5),
+            note(14.0 / 3.0, 1.0 / 3.0, 71, 0, 7),
+            note(5.0, 2.0, 69, 0, 5),
+        ];
+        if version >= GpVersion::V5_00 {
+            guitar.insert(2, note(0.0, 4.0, 40, 5, 0));
+        }
+        let drums = (0..4).map(|i| note(i as f64, 1.0, 36, 0, 36)).collect();
+        Sequence {
+            tempo_map: TempoMap {
+                bpm_base: 120.0,
+                tempo_changes: vec![TempoChange {
+                    beat: 4.0,
+                    bpm: 90.0,
+                }],
+                time_signatures: vec![TimeSignature {
+                    beat: 4.0,
+                    numerator: 3,
+                    denominator: 4,
+                }],
+            },
+            tracks: vec![
+                Track {
+                    name: "Guitar".to_string(),
+                    is_percussion: false,
+                    tuning: Tuning {
+                        string_base_pitches: vec![64, 59, 55, 50, 45, 40],
+                    },
+                    notes: guitar,
+                },
+                Track {
+                    name: "Drums".to_string(),
+                    is_percussion: true,
+                    tuning: Tuning {
+                        string_base_pitches: vec![0; 6],
+                    },
+                    notes: drums,
+                },
+            ],
+        }
+    }
+
+    /// Run with `cargo test -- --ignored` after changing the fixtures.
+    #[test]
+    #[ignore]
+    fn regenerate_fixtures() {
+        std::fs::create_dir_all(FIXTURE_DIR).unwrap();
+        for (version, name) in FIXTURES {
+            std::fs::write(format!("{}/{}", FIXTURE_DIR, name), write_fixture(version)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_fixtures_are_up_to_date() {
+        for (version, name) in FIXTURES {
+            assert_eq!(write_fixture(version), read_fixture(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_read_fixtures() {
+        for (version, name) in FIXTURES {
+            let sequence = read_guitar_pro(&read_fixture(name)).unwrap();
+            assert_eq!(sequence, expected_sequence(version), "{}", name);
+        }
+    }
+
+    /// A Guitar Pro 3 file assembled field by field from the published description of the
+    /// format (as implemented by PyGuitarPro), independently of `fixtures`, so that a reader
+    /// and a writer sharing the same misreading can't agree with each other.
+    fn spec_gp3() -> Vec<u8> {
+        let mut data = vec![];
+        let i32 = |value: i32| value.to_le_bytes();
+        // version: a byte-size string padded to 30 bytes
+        data.push(24);
+        data.extend(b"FICHIER GUITAR PRO v3.00");
+        data.extend([0; 6]);
+        // title: an int-byte-size string, i.e., its size including the length byte
+        data.extend(i32(5));
+        data.extend(b"\x04Spec");
+        // subtitle, artist, album, words, copyright, tab, instructions: empty
+        for _ in 0..7 {
+            data.extend(i32(1));
+            data.push(0);
+        }
+        // number of notice lines
+        data.extend(i32(0));
+        // triplet feel
+        data.push(0);
+        // tempo, key
+        data.extend(i32(120));
+        data.extend(i32(0));
+        // 4 ports with 16 MIDI channels: instrument, volume, balance, chorus, reverb,
+        // phaser, tremolo and two blank bytes
+        for _ in 0..64 {
+            data.extend(i32(25));
+            data.extend([13, 8, 0, 0, 0, 0, 0, 0]);
+        }
+        // number of measures and tracks
+        data.extend(i32(2));
+        data.extend(i32(1));
+        // measure 1: numerator (0x01) and denominator (0x02) of 3/4
+        data.extend([0x03, 3, 4]);
+        // measure 2: no changes
+        data.push(0x00);
+        // track: flags, name as a byte-size string padded to 40 bytes
+        data.push(0x00);
+        data.push(4);
+        data.extend(b"Bass");
+        data.extend([0; 36]);
+        // number of strings, and 7 tunings from the highest string
+        data.extend(i32(4));
+        for pitch in [43, 38, 33, 28, 0, 0, 0] {
+            data.extend(i32(pitch));
+        }
+        // port, channel, effect channel, number of frets, capo, color
+        for value in [1, 1, 2, 24, 0] {
+            data.extend(i32(value));
+        }
+        data.extend([255, 0, 0, 0]);
+        // measure 1, track 1: one beat
+        data.extend(i32(1));
+        // beat flags: dotted (0x01); duration: half (-1); strings: 0x40 >> 1 for string 2
+        data.extend([0x01, 0xff, 0x20]);
+        // note flags: type and fret (0x20); type: normal (1); fret 3
+        data.extend([0x20, 1, 3]);
+        // measure 2, track 1: two beats
+        data.extend(i32(2));
+        // beat flags: status (0x40); status: rest (2); duration: quarter (0); no strings
+        data.extend([0x40, 2, 0, 0x00]);
+        // beat flags: mix table change (0x10); duration: half (-1)
+        data.extend([0x10, 0xff]);
+        // mix table change: instrument, and volume to tremolo unchanged (-1)
+        data.extend([0xff; 7]);
+        // tempo, and its transition duration
+        data.extend(i32(100));
+        data.push(0);
+        // strings: 0x40 >> 3 for string 4; note: normal, fret 0
+        data.extend([0x08, 0x20, 1, 0]);
+        data
+    }
+
+    #[test]
+    fn test_read_spec_gp3() {
+        assert_eq!(
+            read_guitar_pro(&spec_gp3()).unwrap(),
+            Sequence {
+                tempo_map: TempoMap {
+                    bpm_base: 120.0,
+                    tempo_changes: vec![TempoChange {
+                        beat: 4.0,
+                        bpm: 100.0,
+                    }],
+                    time_signatures: vec![TimeSignature {
+                        beat: 0.0,
+                        numerator: 3,
+                        denominator: 4,
+                    }],
+                },
+                tracks: vec![Track {
+                    name: "Bass".to_string(),
+                    is_percussion: false,
+                    tuning: Tuning {
+                        string_base_pitches: vec![43, 38, 33, 28],
+                    },
+                    notes: vec![note(0.0, 3.0, 41, 1, 3), note(4.0, 2.0, 28, 3, 0)],
+                }],
+            }
+        );
+    }
+
+    #[test]
+    fn test_truncated_fixtures() {
+        for (_, name) in FIXTURES {
+            let data = read_fixture(name);
+            for len in 0..data.len() {
+                let err = read_guitar_pro(&data[..len]).unwrap_err();
+                assert!(err.offset() <= len, "{}: {}", name, err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let mut data = read_fixture("fixture_v4_06.gp4");
+        data[21] = b'6';
+        assert_eq!(
+            read_guitar_pro(&data).unwrap_err().to_string(),
+            "Unsupported Guitar Pro version \"FICHIER GUITAR PRO v6.06\" at offset 0"
+        );
+        data[..12].copy_from_slice(b"\x0bNOT A GP FI");
+        assert!(matches!(
+            read_guitar_pro(&data),
+            Err(GpError::UnsupportedVersion { .. })
+        ));
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        // the number of notice lines follows the header and 8 strings with 1 to 8 bytes
+        let data = read_fixture("fixture_v3_00.gp3");
+        let offset = 31 + 8 * 4 + "Fixture".len() + 8;
+        let mut invalid = data.clone();
+        invalid[offset..offset + 4].copy_from_slice(&(-1i32).to_le_bytes());
+        assert_eq!(
+            read_guitar_pro(&invalid).unwrap_err(),
+            GpError::InvalidValue {
+                what: "number of notice lines",
+                value: -1,
+                offset,
+            }
+        );
+    }
+}
@@ -0,0 +1,656 @@
+use crate::types::{
+    BendData, BendPoint, Note, NoteEffects, Sequence, TempoChange, TempoMap, TimeSignature, Track,
+    Tuning,
+};
+
+use super::input::Input;
+use super::{GpError, GpVersion};
+
+/// Beats are measured in ticks first, so that tuplets add up exactly. The resolution is
+/// divisible by all tuplets (up to 13) and by dotted 64th notes.
+const TICKS_PER_QUARTER: u64 = 9 * 5 * 7 * 11 * 13 * 4 * 32;
+
+/// The resolution of bend point positions (relative to the duration of the note) and values.
+const BEND_POSITIONS: i64 = 60;
+const BEND_VALUES_PER_SEMITONE: f32 = 50.0;
+
+const HEADER_PREFIX: &str = "FICHIER GUITAR PRO ";
+
+/// The MIDI channel of percussion tracks, counting from one.
+const PERCUSSION_CHANNEL: i32 = 10;
+
+struct MeasureHeader {
+    start: u64,
+    numerator: u8,
+    denominator: u8,
+}
+
+struct TrackState {
+    track: Track,
+    /// The notes with their start and end in ticks.
+    notes: Vec<(u64, u64, Note)>,
+}
+
+struct SongReader<'a> {
+    input: Input<'a>,
+    version: GpVersion,
+    /// The tempos of mix table changes.
+    tempo_changes: Vec<(u64, f64)>,
+}
+
+fn ticks_to_beats(ticks: u64) -> f64 {
+    ticks as f64 / TICKS_PER_QUARTER as f64
+}
+
+/// Reads a Guitar Pro file of version 3.00 to 5.10, see `guitar_pro` for the mapping.
+pub fn read_guitar_pro(data: &[u8]) -> Result<Sequence, GpError> {
+    let mut input = Input::new(data);
+    let version = read_version(&mut input)?;
+    SongReader {
+        input,
+        version,
+        tempo_changes: vec![],
+    }
+    .read_song()
+}
+
+fn read_version(input: &mut Input) -> Result<GpVersion, GpError> {
+    let header = input.byte_size_string(30)?;
+    let version = header
+        .strip_prefix(HEADER_PREFIX)
+        .and_then(|version| version.strip_prefix(['v', 'L']))
+        .and_then(|version| version.split_once('.'))
+        .and_then(|(major, minor)| Some((major.parse().ok()?, minor.parse().ok()?)));
+    match version {
+        Some((major @ 3..=5, minor)) => Ok(GpVersion { major, minor }),
+        _ => Err(GpError::UnsupportedVersion {
+            version: header,
+            offset: 0,
+        }),
+    }
+}
+
+impl<'a> SongReader<'a> {
+    fn read_song(mut self) -> Result<Sequence, GpError> {
+        let version = self.version;
+        let input = &mut self.input;
+        // title, subtitle, artist, album, words, (music,) copyright, tab, instructions
+        let num_infos = if version >= GpVersion::V5_00 { 9 } else { 8 };
+        for _ in 0..num_infos {
+            input.int_byte_size_string()?;
+        }
+        let num_notice_lines = input.count("number of notice lines")?;
+        for _ in 0..num_notice_lines {
+            input.int_byte_size_string()?;
+        }
+        if version < GpVersion::V5_00 {
+            // triplet feel
+            input.skip(1)?;
+        }
+        if version >= GpVersion::V4_00 {
+            // lyrics: the track, and five lines with their starting measure
+            input.skip(4)?;
+            for _ in 0..5 {
+                input.skip(4)?;
+                input.int_size_string()?;
+            }
+        }
+        if version >= GpVersion::V5_10 {
+            // master effect: volume, unknown, equalizer
+            input.skip(19)?;
+        }
+        if version >= GpVersion::V5_00 {
+            // page setup: size, margins, proportion, header and footer flags, 10 formats
+            input.skip(30)?;
+            for _ in 0..10 {
+                input.int_byte_size_string()?;
+            }
+            // tempo name
+            input.int_byte_size_string()?;
+        }
+        let tempo = input.i32()?;
+        if version >= GpVersion::V5_10 {
+            // hide tempo
+            input.skip(1)?;
+        }
+        // key, and octave from v4 on
+        input.skip(if version >= GpVersion::V4_00 { 5 } else { 4 })?;
+        // MIDI channels: 4 ports with 16 channels of 12 bytes
+        input.skip(64 * 12)?;
+        if version >= GpVersion::V5_00 {
+            // directions (19 measure numbers), and master reverb
+            input.skip(19 * 2 + 4)?;
+        }
+        let num_measures = input.count("number of measures")?;
+        let num_tracks = input.count("number of tracks")?;
+
+        let headers = self.read_measure_headers(num_measures)?;
+        let mut tracks = vec![];
+        for index in 0..num_tracks {
+            tracks.push(self.read_track(index)?);
+        }
+        if version >= GpVersion::V5_00 {
+            self.input
+                .skip(if version == GpVersion::V5_00 { 2 } else { 1 })?;
+        }
+        for header in &headers {
+            for track in &mut tracks {
+                self.read_measure(header, track)?;
+            }
+        }
+
+        Ok(Sequence {
+            tempo_map: self.tempo_map(tempo as f64, &headers),
+            tracks: tracks.into_iter().map(TrackState::into_track).collect(),
+        })
+    }
+
+    fn tempo_map(&mut self, bpm_base: f64, headers: &[MeasureHeader]) -> TempoMap {
+        self.tempo_changes.sort_by_key(|(tick, _)| *tick);
+        self.tempo_changes.dedup_by_key(|(tick, _)| *tick);
+        let mut time_signatures: Vec<TimeSignature> = vec![];
+        let mut previous = (4, 4);
+        for header in headers {
+            let time_signature = (header.numerator, header.denominator);
+            if time_signature != previous {
+                time_signatures.push(TimeSignature {
+                    beat: ticks_to_beats(header.start),
+                    numerator: header.numerator,
+                    denominator: header.denominator,
+                });
+                previous = time_signature;
+            }
+        }
+        TempoMap {
+            bpm_base,
+            tempo_changes: self
+                .tempo_changes
+                .iter()
+                .map(|(tick, bpm)| TempoChange {
+                    beat: ticks_to_beats(*tick),
+                    bpm: *bpm,
+                })
+                .collect(),
+            time_signatures,
+        }
+    }
+
+    fn read_measure_headers(&mut self, num_measures: usize) -> Result<Vec<MeasureHeader>, GpError> {
+        let version = self.version;
+        let input = &mut self.input;
+        let mut headers = vec![];
+        let (mut numerator, mut denominator) = (4, 4);
+        let mut start = 0;
+        for index in 0..num_measures {
+            if version >= GpVersion::V5_00 && index > 0 {
+                input.skip(1)?;
+            }
+            let flags = input.u8()?;
+            if flags & 0x01 != 0 {
+                let offset = input.pos;
+                numerator = input.u8()?;
+                if numerator == 0 {
+                    return Err(GpError::InvalidValue {
+                        what: "time signature numerator",
+                        value: 0,
+                        offset,
+                    });
+                }
+            }
+            if flags & 0x02 != 0 {
+                let offset = input.pos;
+                denominator = input.u8()?;
+                if !denominator.is_power_of_two() || denominator > 64 {
+                    return Err(GpError::InvalidValue {
+                        what: "time signature denominator",
+                        value: denominator as i64,
+                        offset,
+                    });
+                }
+            }
+            if flags & 0x08 != 0 {
+                // repeat count
+                input.skip(1)?;
+            }
+            if flags & 0x10 != 0 && version < GpVersion::V5_00 {
+                // alternate endings
+                input.skip(1)?;
+            }
+            if flags & 0x20 != 0 {
+                // marker name and color
+                input.int_byte_size_string()?;
+                input.skip(4)?;
+            }
+            if flags & 0x40 != 0 {
+                // key signature and type
+                input.skip(2)?;
+            }
+            if version >= GpVersion::V5_00 {
+                if flags & 0x03 != 0 {
+                    // beams
+                    input.skip(4)?;
+                }
+                // alternate endings (or a blank byte), and triplet feel
+                input.skip(2)?;
+            }
+            headers.push(MeasureHeader {
+                start,
+                numerator,
+                denominator,
+            });
+            start += numerator as u64 * 4 * TICKS_PER_QUARTER / denominator as u64;
+        }
+        Ok(headers)
+    }
+
+    fn read_track(&mut self, index: usize) -> Result<TrackState, GpError> {
+        let version = self.version;
+        let input = &mut self.input;
+        if version >= GpVersion::V5_00 && (index == 0 || version == GpVersion::V5_00) {
+            input.skip(1)?;
+        }
+        let flags = input.u8()?;
+        let name = input.byte_size_string(40)?;
+        let offset = input.pos;
+        let num_strings = input.count("number of strings")?;
+        if num_strings > 7 {
+            return Err(GpError::InvalidValue {
+                what: "number of strings",
+                value: num_strings as i64,
+                offset,
+            });
+        }
+        let mut string_base_pitches = vec![];
+        for _ in 0..7 {
+            string_base_pitches.push(input.i32()?);
+        }
+        string_base_pitches.truncate(num_strings);
+        // port
+        input.skip(4)?;
+        let channel = input.i32()?;
+        // effect channel, number of frets, capo, color
+        input.skip(16)?;
+        if version >= GpVersion::V5_00 {
+            // display and MIDI flags, auto accentuation, MIDI bank, RSE settings
+            input.skip(if version == GpVersion::V5_00 { 44 } else { 49 })?;
+            if version > GpVersion::V5_00 {
+                // RSE effect and effect category
+                input.int_byte_size_string()?;
+                input.int_byte_size_string()?;
+            }
+        }
+        Ok(TrackState {
+            track: Track {
+                name,
+                is_percussion: flags & 0x01 != 0 || channel == PERCUSSION_CHANNEL,
+                tuning: Tuning {
+                    string_base_pitches,
+                },
+                notes: vec![],
+            },
+            notes: vec![],
+        })
+    }
+
+    fn read_measure(
+        &mut self,
+        header: &MeasureHeader,
+        track: &mut TrackState,
+    ) -> Result<(), GpError> {
+        let num_voices = if self.version >= GpVersion::V5_00 {
+            2
+        } else {
+            1
+        };
+        for _ in 0..num_voices {
+            let num_beats = self.input.count("number of beats")?;
+            let mut start = header.start;
+            for _ in 0..num_beats {
+                start += self.read_beat(start, track)?;
+            }
+        }
+        if self.version >= GpVersion::V5_00 {
+            // line break
+            self.input.skip(1)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the duration of the beat in ticks, which is zero for empty beats.
+    fn read_beat(&mut self, start: u64, track: &mut TrackState) -> Result<u64, GpError> {
+        let flags = self.input.u8()?;
+        let mut is_empty = false;
+        if flags & 0x40 != 0 {
+            // 0: empty, 2: rest
+            is_empty = self.input.u8()? == 0;
+        }
+        let offset = self.input.pos;
+        let duration = self.input.i8()?;
+        if !(-2..=4).contains(&duration) {
+            return Err(GpError::InvalidValue {
+                what: "duration",
+                value: duration as i64,
+                offset,
+            });
+        }
+        // -2 is a whole note, 0 a quarter note, and 4 a 64th note
+        let mut ticks = (16 * TICKS_PER_QUARTER) >> (duration + 4);
+        if flags & 0x01 != 0 {
+            ticks = ticks * 3 / 2;
+        }
+        if flags & 0x20 != 0 {
+            let offset = self.input.pos;
+            let tuplet = self.input.i32()?;
+            let (enters, times) = match tuplet {
+                3 => (3, 2),
+                5..=7 => (tuplet, 4),
+                9..=13 => (tuplet, 8),
+                _ => {
+                    return Err(GpError::InvalidValue {
+                        what: "tuplet",
+                        value: tuplet as i64,
+                        offset,
+                    })
+                }
+            };
+            ticks = ticks * times as u64 / enters as u64;
+        }
+        if flags & 0x02 != 0 {
+            self.skip_chord()?;
+        }
+        if flags & 0x04 != 0 {
+            // text
+            self.input.int_byte_size_string()?;
+        }
+        let mut vibrato = false;
+        if flags & 0x08 != 0 {
+            vibrato = self.read_beat_effects()?;
+        }
+        if flags & 0x10 != 0 {
+            if let Some(bpm) = self.read_mix_table_change()? {
+                self.tempo_changes.push((start, bpm));
+            }
+        }
+        let string_flags = self.input.u8()?;
+        let num_strings = track.track.tuning.string_base_pitches.len();
+        for string in 0..num_strings {
+            if string_flags & (0x40 >> string) != 0 {
+                self.read_note(track, string, start, ticks, vibrato)?;
+            }
+        }
+        if self.version >= GpVersion::V5_00 {
+            let flags2 = self.input.i16()?;
+            if flags2 & 0x0800 != 0 {
+                // secondary beam breaks
+                self.input.skip(1)?;
+            }
+        }
+        Ok(if is_empty { 0 } else { ticks })
+    }
+
+    fn skip_chord(&mut self) -> Result<(), GpError> {
+        let version = self.version;
+        let input = &mut self.input;
+        let new_format = input.u8()? != 0;
+        if new_format || version >= GpVersion::V5_00 {
+            input.skip(if version >= GpVersion::V4_00 {
+                106
+            } else {
+                124
+            })?;
+        } else {
+            // name, first fret and the frets of the strings
+            input.int_byte_size_string()?;
+            if input.i32()? != 0 {
+                input.skip(if version >= GpVersion::V4_06 {
+                    7 * 4
+                } else {
+                    6 * 4
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns whether the beat has vibrato.
+    fn read_beat_effects(&mut self) -> Result<bool, GpError> {
+        let version = self.version;
+        let input = &mut self.input;
+        let flags = input.u8()?;
+        let flags2 = if version >= GpVersion::V4_00 {
+            input.u8()?
+        } else {
+            0
+        };
+        if flags & 0x20 != 0 {
+            // tapping, slapping or popping, and the tremolo bar value in v3
+            input.skip(if version >= GpVersion::V4_00 { 1 } else { 5 })?;
+        }
+        if flags2 & 0x04 != 0 {
+            // tremolo bar
+            self.read_bend()?;
+        }
+        let input = &mut self.input;
+        if flags & 0x40 != 0 {
+            // stroke up and down
+            input.skip(2)?;
+        }
+        if flags2 & 0x02 != 0 {
+            // pick stroke
+            input.skip(1)?;
+        }
+        Ok(flags & 0x03 != 0)
+    }
+
+    /// Returns the new tempo, if any.
+    fn read_mix_table_change(&mut self) -> Result<Option<f64>, GpError> {
+        let version = self.version;
+        let input = &mut self.input;
+        // instrument, and the RSE instrument
+        input.skip(if version >= GpVersion::V5_00 { 17 } else { 1 })?;
+        // volume, balance, chorus, reverb, phaser, tremolo
+        let mut values = vec![];
+        for _ in 0..6 {
+            values.push(input.i8()?);
+        }
+        if version >= GpVersion::V5_00 {
+            // tempo name
+            input.int_byte_size_string()?;
+        }
+        let tempo = input.i32()?;
+        // the transition durations of the changed values
+        let num_changes = values.iter().filter(|value| **value >= 0).count();
+        input.skip(num_changes)?;
+        if tempo >= 0 {
+            input.skip(if version >= GpVersion::V5_10 { 2 } else { 1 })?;
+        }
+        if version >= GpVersion::V4_00 {
+            // whether changes apply to all tracks
+            input.skip(1)?;
+        }
+        if version >= GpVersion::V5_00 {
+            // wah
+            input.skip(1)?;
+        }
+        if version >= GpVersion::V5_10 {
+            // RSE effect and effect category
+            input.int_byte_size_string()?;
+            input.int_byte_size_string()?;
+        }
+        Ok((tempo > 0).then_some(tempo as f64))
+    }
+
+    fn read_note(
+        &mut self,
+        track: &mut TrackState,
+        string: usize,
+        start: u64,
+        ticks: u64,
+        beat_vibrato: bool,
+    ) -> Result<(), GpError> {
+        let version = self.version;
+        let input = &mut self.input;
+        let flags = input.u8()?;
+        // 1: normal, 2: tied, 3: dead
+        let mut note_type = 1;
+        if flags & 0x20 != 0 {
+            note_type = input.u8()?;
+        }
+        if flags & 0x01 != 0 && version < GpVersion::V5_00 {
+            // time independent duration and tuplet
+            input.skip(2)?;
+        }
+        if flags & 0x10 != 0 {
+            // dynamics
+            input.skip(1)?;
+        }
+        let offset = input.pos;
+        let mut fret = 0;
+        if flags & 0x20 != 0 {
+            fret = input.i8()?;
+        }
+        if flags & 0x80 != 0 {
+            // left and right hand fingering
+            input.skip(2)?;
+        }
+        if version >= GpVersion::V5_00 {
+            if flags & 0x01 != 0 {
+                // duration percent
+                input.skip(8)?;
+            }
+            // accidentals
+            input.skip(1)?;
+        }
+        let (vibrato, bend) = if flags & 0x08 != 0 {
+            self.read_note_effects()?
+        } else {
+            (false, None)
+        };
+
+        if note_type == 2 {
+            let tied = track
+                .notes
+                .iter_mut()
+                .rev()
+                .find(|(_, _, note)| note.string as usize == string);
+            if let Some((_, end, _)) = tied {
+                *end = (*end).max(start + ticks);
+                return Ok(());
+            }
+        }
+        let invalid_fret = || GpError::InvalidValue {
+            what: "fret",
+            value: fret as i64,
+            offset,
+        };
+        let pitch = track.track.tuning.string_base_pitches[string]
+            .checked_add(fret as i32)
+            .and_then(|pitch| u8::try_from(pitch).ok())
+            .ok_or_else(invalid_fret)?;
+        let fret = u8::try_from(fret).map_err(|_| invalid_fret())?;
+        let bend_data = bend.map(|points| BendData {
+            points: points
+                .into_iter()
+                .map(|(position, value)| BendPoint {
+                    pos: (position as i64 * ticks as i64) as f64
+                        / (BEND_POSITIONS * TICKS_PER_QUARTER as i64) as f64,
+                    bend: value as f32 / BEND_VALUES_PER_SEMITONE,
+                })
+                .collect(),
+        });
+        let note = Note {
+            s: ticks_to_beats(start),
+            d: ticks_to_beats(ticks),
+            pitch,
+            string: string as u8,
+            fret,
+            effects: NoteEffects {
+                dead_note: note_type == 3,
+                vibrato: vibrato || beat_vibrato,
+                bend_data,
+            },
+        };
+        track.notes.push((start, start + ticks, note));
+        Ok(())
+    }
+
+    /// Returns whether the note has vibrato, and its bend points.
+    #[allow(clippy::type_complexity)]
+    fn read_note_effects(&mut self) -> Result<(bool, Option<Vec<(i32, i32)>>), GpError> {
+        let version = self.version;
+        let flags = self.input.u8()?;
+        let flags2 = if version >= GpVersion::V4_00 {
+            self.input.u8()?
+        } else {
+            0
+        };
+        let bend = if flags & 0x01 != 0 {
+            Some(self.read_bend()?)
+        } else {
+            None
+        };
+        let input = &mut self.input;
+        if flags & 0x10 != 0 {
+            // grace note: fret, dynamics, transition, duration, and flags in v5
+            input.skip(if version >= GpVersion::V5_00 { 5 } else { 4 })?;
+        }
+        if flags2 & 0x04 != 0 {
+            // tremolo picking
+            input.skip(1)?;
+        }
+        if flags2 & 0x08 != 0 {
+            // slide
+            input.skip(1)?;
+        }
+        if flags2 & 0x10 != 0 {
+            let harmonic = input.u8()?;
+            if version >= GpVersion::V5_00 {
+                match harmonic {
+                    // artificial: tone, key and octave
+                    2 => input.skip(3)?,
+                    // tapped: fret
+                    3 => input.skip(1)?,
+                    _ => {}
+                }
+            }
+        }
+        if flags2 & 0x20 != 0 {
+            // trill: fret and period
+            input.skip(2)?;
+        }
+        Ok((flags2 & 0x40 != 0, bend))
+    }
+
+    /// Returns the points of a bend (or tremolo bar) as positions and values.
+    fn read_bend(&mut self) -> Result<Vec<(i32, i32)>, GpError> {
+        let input = &mut self.input;
+        // type and value
+        input.skip(5)?;
+        let num_points = input.count("number of bend points")?;
+        let mut points = vec![];
+        for _ in 0..num_points {
+            let position = input.i32()?;
+            let value = input.i32()?;
+            // vibrato
+            input.skip(1)?;
+            points.push((position, value));
+        }
+        Ok(points)
+    }
+}
+
+impl TrackState {
+    /// The notes of all voices, ordered by their onset.
+    fn into_track(mut self) -> Track {
+        self.notes.sort_by_key(|(start, _, _)| *start);
+        self.track.notes = self
+            .notes
+            .into_iter()
+            .map(|(start, end, note)| Note {
+                d: ticks_to_beats(end - start),
+                ..note
+            })
+            .collect();
+        self.track
+    }
+}
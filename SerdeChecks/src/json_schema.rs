@@ -0,0 +1,573 @@
+//! A JSON Schema (draft 2020-12) of the JSON representation of `Sequence`, and a loader that
+//! checks JSON input against it before deserializing.
+//!
+//! The schema mirrors the serde attributes of `types`: the properties are in camelCase, and
+//! the fields with `#[serde(default)]` (or in a struct with `#[serde(default)]`) are not
+//! required. Unlike serde, the schema rejects unknown properties, so that misspelled
+//! optional properties, e.g., `deadnote`, are reported instead of being silently dropped.
+//! The derived `Deserialize` also accepts a struct as an array of its field values, e.g.,
+//! `[]` for `NoteEffects`, which has `#[serde(default)]`. Which arrays are accepted depends on
+//! the serde version, the schema only accepts the object form that `serde_json` writes.
+//!
+//! The validator supports the keywords that the schema uses: `$ref` (to `#/$defs/...`),
+//! `anyOf`, `type`, `properties`, `required`, `additionalProperties: false`, `items`, `minimum` and
+//! `maximum`.
+
+use std::fmt;
+use std::io::Read;
+
+use serde_json::{json, Map, Value};
+
+use crate::types::Sequence;
+
+/// The JSON Schema of `Sequence`.
+pub fn sequence_schema() -> Value {
+    let u8_schema = json!({"type": "integer", "minimum": 0, "maximum": u8::MAX});
+    let i32_schema = json!({"type": "integer", "minimum": i32::MIN, "maximum": i32::MAX});
+    let array_of =
+        |name: &str| json!({"type": "array", "items": {"$ref": format!("#/$defs/{}", name)}});
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "Sequence",
+        "$ref": "#/$defs/Sequence",
+        "$defs": {
+            "Sequence": object(
+                json!({
+                    "tempoMap": {"$ref": "#/$defs/TempoMap"},
+                    "tracks": array_of("Track"),
+                }),
+                &["tempoMap", "tracks"],
+            ),
+            "TempoMap": object(
+                json!({
+                    "bpmBase": {"type": "number"},
+                    "tempoChanges": array_of("TempoChange"),
+                    "timeSignatures": array_of("TimeSignature"),
+                }),
+                &["bpmBase"],
+            ),
+            "TempoChange": object(
+                json!({
+                    "beat": {"type": "number"},
+                    "bpm": {"type": "number"},
+                }),
+                &["beat", "bpm"],
+            ),
+            "TimeSignature": object(
+                json!({
+                    "beat": {"type": "number"},
+                    "numerator": u8_schema,
+                    "denominator": u8_schema,
+                }),
+                &["beat", "numerator", "denominator"],
+            ),
+            "Track": object(
+                json!({
+                    "name": {"type": "string"},
+                    "isPercussion": {"type": "boolean"},
+                    "tuning": {"$ref": "#/$defs/Tuning"},
+                    "notes": array_of("Note"),
+                }),
+                &["name", "isPercussion", "tuning", "notes"],
+            ),
+            "Tuning": object(
+                json!({
+                    "stringBasePitches": {"type": "array", "items": i32_schema},
+                }),
+                &["stringBasePitches"],
+            ),
+            "Note": object(
+                json!({
+                    "s": {"type": "number"},
+                    "d": {"type": "number"},
+                    "pitch": u8_schema,
+                    "string": u8_schema,
+                    "fret": u8_schema,
+                    "effects": {"$ref": "#/$defs/NoteEffects"},
+                }),
+                &["s", "d", "pitch", "string", "fret"],
+            ),
+            "NoteEffects": object(
+                json!({
+                    "deadNote": {"type": "boolean"},
+                    "vibrato": {"type": "boolean"},
+                    // `Option`, serde reads null as `None`
+                    "bendData": {"anyOf": [{"$ref": "#/$defs/BendData"}, {"type": "null"}]},
+                }),
+                &[],
+            ),
+            "BendData": object(
+                json!({
+                    "points": array_of("BendPoint"),
+                }),
+                &["points"],
+            ),
+            "BendPoint": object(
+                json!({
+                    "pos": {"type": "number"},
+                    "bend": {"type": "number"},
+                }),
+                &["pos", "bend"],
+            ),
+        },
+    })
+}
+
+fn object(properties: Value, required: &[&str]) -> Value {
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+    })
+}
+
+// ----------------------------------------------------------------------------
+// Validation
+// ----------------------------------------------------------------------------
+
+/// A value that does not match its schema.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SchemaViolation {
+    /// JSON Pointer (RFC 6901) to the value, empty for the whole document.
+    pub pointer: String,
+    pub message: String,
+}
+
+impl fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {}", self.pointer, self.message)
+    }
+}
+
+/// Checks `value` against `schema`, returning all violations depth-first, with the properties
+/// of an object in the order of `serde_json::Map`, i.e., sorted by name.
+pub fn validate(schema: &Value, value: &Value) -> Vec<SchemaViolation> {
+    let mut validator = Validator {
+        root: schema,
+        pointer: String::new(),
+        violations: vec![],
+    };
+    validator.validate(schema, value);
+    validator.violations
+}
+
+struct Validator<'a> {
+    root: &'a Value,
+    pointer: String,
+    violations: Vec<SchemaViolation>,
+}
+
+impl<'a> Validator<'a> {
+    fn violation(&mut self, message: String) {
+        self.violations.push(SchemaViolation {
+            pointer: self.pointer.clone(),
+            message,
+        });
+    }
+
+    /// Validates `value` with the pointer extended by `token`.
+    fn validate_child(&mut self, token: &str, schema: &'a Value, value: &Value) {
+        let len = self.pointer.len();
+        self.pointer.push('/');
+        self.pointer
+            .push_str(&token.replace('~', "~0").replace('/', "~1"));
+        self.validate(schema, value);
+        self.pointer.truncate(len);
+    }
+
+    fn resolve(&self, reference: &str) -> &'a Value {
+        reference
+            .strip_prefix('#')
+            .and_then(|pointer| self.root.pointer(pointer))
+            .unwrap_or_else(|| panic!("Unsupported $ref {:?}", reference))
+    }
+
+    fn validate(&mut self, schema: &'a Value, value: &Value) {
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            let resolved = self.resolve(reference);
+            self.validate(resolved, value);
+        }
+        if let Some(alternatives) = schema.get("anyOf").and_then(Value::as_array) {
+            self.validate_any_of(alternatives, value);
+        }
+        if let Some(expected) = schema.get("type").and_then(Value::as_str) {
+            let matches = match expected {
+                "object" => value.is_object(),
+                "array" => value.is_array(),
+                "string" => value.is_string(),
+                "null" => value.is_null(),
+                "boolean" => value.is_boolean(),
+                "number" => value.is_number(),
+                "integer" => value.is_i64() || value.is_u64(),
+                _ => panic!("Unsupported type {:?}", expected),
+            };
+            if !matches {
+                self.violation(format!("Expected {}, found {}", expected, type_name(value)));
+                // the other keywords only make sense for the expected type
+                return;
+            }
+        }
+        if let Some(minimum) = schema.get("minimum").and_then(Value::as_f64) {
+            if value.as_f64().is_some_and(|v| v < minimum) {
+                self.violation(format!("{} is less than the minimum {}", value, minimum));
+            }
+        }
+        if let Some(maximum) = schema.get("maximum").and_then(Value::as_f64) {
+            if value.as_f64().is_some_and(|v| v > maximum) {
+                self.violation(format!("{} is greater than the maximum {}", value, maximum));
+            }
+        }
+        if let Some(object) = value.as_object() {
+            self.validate_object(schema, object);
+        }
+        if let (Some(items), Some(array)) = (schema.get("items"), value.as_array()) {
+            for (i, item) in array.iter().enumerate() {
+                self.validate_child(&i.to_string(), items, item);
+            }
+        }
+    }
+
+    /// Reports the violations of the first alternative if none of them matches.
+    fn validate_any_of(&mut self, alternatives: &'a [Value], value: &Value) {
+        let outer = std::mem::take(&mut self.violations);
+        let mut first_violations = None;
+        for alternative in alternatives {
+            self.validate(alternative, value);
+            let violations = std::mem::take(&mut self.violations);
+            if violations.is_empty() {
+                self.violations = outer;
+                return;
+            }
+            first_violations.get_or_insert(violations);
+        }
+        self.violations = outer;
+        self.violations.extend(first_violations.unwrap_or_default());
+    }
+
+    fn validate_object(&mut self, schema: &'a Value, object: &Map<String, Value>) {
+        if let Some(required) = schema.get("required").and_then(Value::as_array) {
+            for name in required.iter().filter_map(Value::as_str) {
+                if !object.contains_key(name) {
+                    self.violation(format!("Missing property {:?}", name));
+                }
+            }
+        }
+        let closed = schema.get("additionalProperties") == Some(&Value::Bool(false));
+        for (name, value) in object {
+            match schema.get("properties").and_then(|p| p.get(name)) {
+                Some(property) => self.validate_child(name, property, value),
+                None if closed => self.violation(format!("Unknown property {:?}", name)),
+                None => {}
+            }
+        }
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Loading
+// ----------------------------------------------------------------------------
+
+#[derive(Debug)]
+pub enum JsonLoadError {
+    /// The input is not JSON, or (despite matching the schema) not a `Sequence`.
+    Json(serde_json::Error),
+    /// The input is JSON, but does not match the schema.
+    Schema(Vec<SchemaViolation>),
+}
+
+impl fmt::Display for JsonLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const MAX_LISTED: usize = 10;
+        match self {
+            JsonLoadError::Json(err) => write!(f, "Invalid JSON: {}", err),
+            JsonLoadError::Schema(violations) => {
+                write!(f, "{} schema violation(s)", violations.len())?;
+                for violation in violations.iter().take(MAX_LISTED) {
+                    write!(f, "\n  {}", violation)?;
+                }
+                if violations.len() > MAX_LISTED {
+                    write!(f, "\n  ... and {} more", violations.len() - MAX_LISTED)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for JsonLoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            JsonLoadError::Json(err) => Some(err),
+            JsonLoadError::Schema(_) => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for JsonLoadError {
+    fn from(err: serde_json::Error) -> Self {
+        JsonLoadError::Json(err)
+    }
+}
+
+/// Reads a `Sequence` from JSON, reporting all schema violations at once.
+pub fn load_sequence_json<R: Read>(rd: R) -> Result<Sequence, JsonLoadError> {
+    let value: Value = serde_json::from_reader(rd)?;
+    let violations = validate(&sequence_schema(), &value);
+    if !violations.is_empty() {
+        return Err(JsonLoadError::Schema(violations));
+    }
+    Ok(serde_json::from_value(value)?)
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::synthetic::gen_sequence_with_tempo_events;
+    use crate::types::{
+        BendData, BendPoint, Note, NoteEffects, TempoChange, TempoMap, TimeSignature, Track, Tuning,
+    };
+
+    use super::*;
+
+    const SCHEMA_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/sequence.schema.json");
+
+    fn schema_file_contents() -> String {
+        serde_json::to_string_pretty(&sequence_schema()).unwrap() + "\n"
+    }
+
+    /// A sequence that uses all optional properties.
+    fn sequence() -> Sequence {
+        Sequence {
+            tempo_map: TempoMap {
+                bpm_base: 120.0,
+                tempo_changes: vec![TempoChange {
+                    beat: 4.0,
+                    bpm: 90.0,
+                }],
+                time_signatures: vec![TimeSignature {
+                    beat: 0.0,
+                    numerator: 3,
+                    denominator: 4,
+                }],
+            },
+            tracks: vec![Track {
+                name: "Guitar".to_string(),
+                is_percussion: false,
+                tuning: Tuning {
+                    string_base_pitches: vec![64, 59, 55, 50, 45, 40],
+                },
+                notes: vec![
+                    Note {
+                        s: 0.0,
+                        d: 1.0,
+                        pitch: 64,
+                        string: 0,
+                        fret: 0,
+                        effects: NoteEffects::default(),
+                    },
+                    Note {
+                        s: 1.0,
+                        d: 0.5,
+                        pitch: 62,
+                        string: 1,
+                        fret: 3,
+                        effects: NoteEffects {
+                            dead_note: true,
+                            vibrato: true,
+                            bend_data: Some(BendData {
+                                points: vec![BendPoint {
+                                    pos: 0.25,
+                                    bend: 1.0,
+                                }],
+                            }),
+                        },
+                    },
+                ],
+            }],
+        }
+    }
+
+    fn violations(value: &Value) -> Vec<(String, String)> {
+        validate(&sequence_schema(), value)
+            .into_iter()
+            .map(|v| (v.pointer, v.message))
+            .collect()
+    }
+
+    /// Run with `cargo test -- --ignored` after changing the schema.
+    #[test]
+    #[ignore]
+    fn regenerate_schema_file() {
+        std::fs::write(SCHEMA_PATH, schema_file_contents()).unwrap();
+    }
+
+    #[test]
+    fn test_schema_file_is_up_to_date() {
+        let contents = std::fs::read_to_string(SCHEMA_PATH)
+            .unwrap_or_else(|e| panic!("Failed to read {}: {}", SCHEMA_PATH, e));
+        assert_eq!(contents, schema_file_contents());
+    }
+
+    #[test]
+    fn test_valid_inputs() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for sequence in [
+            sequence(),
+            Sequence::default(),
+            gen_sequence_with_tempo_events(&mut rng),
+        ] {
+            let value = serde_json::to_value(&sequence).unwrap();
+            assert_eq!(violations(&value), []);
+        }
+        let test_json = concat!(env!("CARGO_MANIFEST_DIR"), "/test.json");
+        let file = std::fs::File::open(test_json).unwrap();
+        assert!(load_sequence_json(std::io::BufReader::new(file)).is_ok());
+    }
+
+    #[test]
+    fn test_violations() {
+        let mut value = serde_json::to_value(sequence()).unwrap();
+        let note = &mut value["tracks"][0]["notes"][1];
+        note["pitch"] = json!(300);
+        note["fret"] = json!(-1);
+        note["string"] = json!(1.5);
+        note["effects"]["deadnote"] = json!(true);
+        note["effects"]["bendData"]["points"][0]["bend"] = json!("up");
+        let track = value["tracks"][0].as_object_mut().unwrap();
+        track.remove("tuning");
+        track.insert("a/b~c".to_string(), json!(null));
+        value["tempoMap"]["timeSignatures"] = json!({});
+        assert_eq!(
+            violations(&value),
+            [
+                ("/tempoMap/timeSignatures", "Expected array, found object"),
+                ("/tracks/0", "Missing property \"tuning\""),
+                ("/tracks/0", "Unknown property \"a/b~c\""),
+                (
+                    "/tracks/0/notes/1/effects/bendData/points/0/bend",
+                    "Expected number, found string"
+                ),
+                ("/tracks/0/notes/1/effects", "Unknown property \"deadnote\""),
+                ("/tracks/0/notes/1/fret", "-1 is less than the minimum 0"),
+                (
+                    "/tracks/0/notes/1/pitch",
+                    "300 is greater than the maximum 255"
+                ),
+                ("/tracks/0/notes/1/string", "Expected integer, found number"),
+            ]
+            .map(|(pointer, message)| (pointer.to_string(), message.to_string()))
+        );
+
+        let schema = json!({"properties": {"a/b~c": {"type": "string"}}});
+        assert_eq!(
+            validate(&schema, &json!({"a/b~c": 1})),
+            [SchemaViolation {
+                pointer: "/a~1b~0c".to_string(),
+                message: "Expected string, found integer".to_string(),
+            }]
+        );
+    }
+
+    /// Collects the pointers of all values in the document.
+    fn pointers(value: &Value, pointer: String, result: &mut Vec<String>) {
+        match value {
+            Value::Object(object) => {
+                for (name, value) in object {
+                    pointers(value, format!("{}/{}", pointer, name), result);
+                }
+            }
+            Value::Array(array) => {
+                for (i, value) in array.iter().enumerate() {
+                    pointers(value, format!("{}/{}", pointer, i), result);
+                }
+            }
+            _ => {}
+        }
+        result.push(pointer);
+    }
+
+    /// The schema accepts a modified document if and only if serde does, except for unknown
+    /// properties and structs in array form (see the module documentation).
+    #[test]
+    fn test_schema_matches_serde() {
+        let original = serde_json::to_value(sequence()).unwrap();
+        let mut all_pointers = vec![];
+        pointers(&original, String::new(), &mut all_pointers);
+        let replacements = [
+            json!(null),
+            json!(true),
+            json!(-1),
+            json!(256),
+            json!(i64::from(i32::MAX) + 1),
+            json!(1.5),
+            json!("x"),
+            json!([]),
+            json!({}),
+        ];
+        let check = |value: &Value, description: &str| {
+            let schema_accepts = validate(&sequence_schema(), value).is_empty();
+            let serde_accepts = serde_json::from_value::<Sequence>(value.clone()).is_ok();
+            assert_eq!(schema_accepts, serde_accepts, "{}", description);
+        };
+        for pointer in &all_pointers {
+            let is_struct = original.pointer(pointer).unwrap().is_object();
+            for replacement in &replacements {
+                if is_struct && replacement.is_array() {
+                    continue;
+                }
+                let mut value = original.clone();
+                *value.pointer_mut(pointer).unwrap() = replacement.clone();
+                check(&value, &format!("{} = {}", pointer, replacement));
+            }
+            let (parent, name) = pointer.rsplit_once('/').unwrap_or_default();
+            if let Some(object) = original.pointer(parent).filter(|v| v.is_object()) {
+                let mut object = object.clone();
+                object.as_object_mut().unwrap().remove(name);
+                let mut value = original.clone();
+                *value.pointer_mut(parent).unwrap() = object;
+                check(&value, &format!("without {}", pointer));
+            }
+        }
+    }
+
+    #[test]
+    fn test_load_sequence_json() {
+        let json = serde_json::to_vec(&sequence()).unwrap();
+        assert_eq!(load_sequence_json(&json[..]).unwrap(), sequence());
+
+        let err = load_sequence_json(&b"{\"tracks\": "[..]).unwrap_err();
+        assert!(matches!(err, JsonLoadError::Json(_)), "{:?}", err);
+
+        let notes: Vec<Value> = (0..12).map(|_| json!({"s": 0})).collect();
+        let json = json!({
+            "tempoMap": {"bpmBase": 120},
+            "tracks": [{"name": "", "isPercussion": false, "tuning": {"stringBasePitches": []}, "notes": notes}],
+        });
+        let err = load_sequence_json(json.to_string().as_bytes()).unwrap_err();
+        let JsonLoadError::Schema(violations) = &err else {
+            panic!("Expected schema violations, got {:?}", err);
+        };
+        assert_eq!(violations.len(), 48);
+        let message = err.to_string();
+        assert!(message.starts_with(
+            "48 schema violation(s)\n  \"/tracks/0/notes/0\": Missing property \"d\"\n"
+        ));
+        assert!(message.ends_with("\n  ... and 38 more"), "{}", message);
+    }
+}
@@ -1,7 +1,23 @@
-mod cereal_like;
+pub mod archived;
+pub mod benchmark;
+pub mod cereal_like;
+pub mod container;
 mod custom_file_format;
+pub mod diff;
+pub mod editing;
+pub mod fingering;
+pub mod guitar_pro;
+pub mod json_schema;
+pub mod midi;
+pub mod musicxml;
 mod semantics;
+pub mod synthetic;
+pub mod tempo;
 pub mod types;
 
-pub use custom_file_format::parse_sequence;
-pub use custom_file_format::{serialize_sequence, serialize_sequence_to_vec, Params};
+pub use custom_file_format::{parse_sequence, parse_sequence_with_limits};
+pub use custom_file_format::{
+    quantization_report, serialize_sequence, serialize_sequence_to_vec, ErrorStats, FormatError,
+    FormatVersion, Location, NoteLayout, Params, ParseLimits, Path, PathSegment,
+    QuantizationReport, ReadError, Rounding, SequenceReader, SequenceWriter, TrackHeader,
+};
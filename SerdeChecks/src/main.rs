@@ -1,179 +1,200 @@
 use std::fs::File;
-use std::io::copy;
-use std::io::BufReader;
-use std::io::Write;
+use std::io::{stderr, stdout, BufReader, Write};
 use std::path::Path;
-use std::path::PathBuf;
-
-use flate2::write::GzEncoder;
-use flate2::Compression;
-
-use serde_checks::serialize_sequence;
+use std::process::exit;
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use serde_checks::benchmark::{
+    all_formats, all_loaders, run_benchmark, run_load_benchmark, write_load_table, write_table,
+    BenchmarkOptions, BoxError, Input, TableFormat,
+};
+use serde_checks::json_schema::load_sequence_json;
+use serde_checks::quantization_report;
+use serde_checks::synthetic::gen_sized_sequence;
 use serde_checks::types::Sequence;
 use serde_checks::Params;
 
-fn load_sequence_from_file(path: &Path) -> Sequence {
-    let file = File::open(path).unwrap();
-    serde_json::from_reader(file).unwrap()
+const USAGE: &str = "\
+Compares the serialization formats on the given sequences (JSON files, see
+sequence.schema.json).
+
+Usage: serde_checks [OPTIONS] [INPUT.json ...]
+
+Options:
+  --table <markdown|csv>     Format of the result table [default: markdown]
+  --output <PATH>            Writes the table to a file instead of stdout
+  --load-output <PATH>       Writes the table of --load-benchmark to a file instead of
+                             after the result table, required with --table csv
+  --synthetic <N>            Adds N synthetic sequences [default: 0]
+  --seed <SEED>              Seed of the first synthetic sequence [default: 0]
+  --tracks <N>               Tracks per synthetic sequence [default: 8]
+  --notes <N>                Notes per synthetic track [default: 1000]
+  --min-duration-ms <MS>     Minimum duration of each throughput measurement [default: 200]
+  --load-benchmark           Adds a table of the time to load and read all notes of the
+                             archived representation, the custom format (also in a
+                             container) and bincode
+  --quantization-report      Prints the quantization errors of the custom format per input,
+                             to stderr with --table csv
+  -h, --help                 Prints this help
+";
+
+struct Args {
+    inputs: Vec<String>,
+    table_format: TableFormat,
+    output: Option<String>,
+    load_output: Option<String>,
+    num_synthetic: usize,
+    seed: u64,
+    num_tracks: usize,
+    num_notes: usize,
+    min_duration: Duration,
+    load_benchmark: bool,
+    quantization_report: bool,
 }
 
-fn add_extension(path: &Path, extension: impl AsRef<std::path::Path>) -> PathBuf {
-    // https://users.rust-lang.org/t/append-an-additional-extension/23586/12
-    let mut path = path.to_owned();
-    match path.extension() {
-        Some(ext) => {
-            let mut ext = ext.to_os_string();
-            ext.push(".");
-            ext.push(extension.as_ref());
-            path.set_extension(ext)
-        }
-        None => path.set_extension(extension.as_ref()),
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Args>, String> {
+    let mut parsed = Args {
+        inputs: Vec::new(),
+        table_format: TableFormat::Markdown,
+        output: None,
+        load_output: None,
+        num_synthetic: 0,
+        seed: 0,
+        num_tracks: 8,
+        num_notes: 1000,
+        min_duration: BenchmarkOptions::default().min_duration,
+        load_benchmark: false,
+        quantization_report: false,
     };
-    path
-}
-
-struct FileSize {
-    raw: u64,
-    zip: u64,
-}
-
-fn get_raw_file_size(path: &Path) -> u64 {
-    let file = File::open(path).unwrap();
-    file.metadata().unwrap().len()
-}
-
-fn get_zip_file_size(path: &Path) -> u64 {
-    let file = File::open(path).unwrap();
-    let mut input = BufReader::new(file);
-
-    let path_out = add_extension(path, "gz");
-    {
-        // https://github.com/rust-lang/flate2-rs/blob/master/examples/compress_file.rs
-        // Note that default compression corresponds to level 6:
-        // https://docs.rs/flate2/1.0.22/src/flate2/lib.rs.html#223-225
-        let compression = Compression::default();
-        let output = File::create(&path_out).unwrap();
-        let mut encoder = GzEncoder::new(output, compression);
-        copy(&mut input, &mut encoder).unwrap();
-        encoder.finish().unwrap();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("Missing value for {}", arg));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--table" => parsed.table_format = value()?.parse()?,
+            "--output" => parsed.output = Some(value()?),
+            "--load-output" => parsed.load_output = Some(value()?),
+            "--synthetic" => parsed.num_synthetic = parse_number(&value()?)?,
+            "--seed" => parsed.seed = parse_number(&value()?)?,
+            "--tracks" => parsed.num_tracks = parse_number(&value()?)?,
+            "--notes" => parsed.num_notes = parse_number(&value()?)?,
+            "--min-duration-ms" => {
+                parsed.min_duration = Duration::from_millis(parse_number(&value()?)?)
+            }
+            "--load-benchmark" => parsed.load_benchmark = true,
+            "--quantization-report" => parsed.quantization_report = true,
+            _ if arg.starts_with('-') => return Err(format!("Unknown option {}", arg)),
+            _ => parsed.inputs.push(arg),
+        }
     }
-    get_raw_file_size(&path_out)
-}
-
-fn get_file_size(path: &Path) -> FileSize {
-    FileSize {
-        raw: get_raw_file_size(path),
-        zip: get_zip_file_size(path),
+    if parsed.inputs.is_empty() && parsed.num_synthetic == 0 {
+        return Err("No inputs given, pass JSON files and/or --synthetic <N>".to_string());
     }
-}
-
-fn write_as_json(seq: &Sequence) -> FileSize {
-    let path = Path::new("/tmp/test.json");
+    // a CSV file holds a single table
+    if parsed.table_format == TableFormat::Csv
+        && parsed.load_benchmark
+        && parsed.load_output.is_none()
     {
-        serde_json::to_writer(File::create(path).unwrap(), seq).unwrap();
+        return Err("--load-benchmark with --table csv needs --load-output <PATH>".to_string());
     }
-    get_file_size(path)
+    Ok(Some(parsed))
 }
 
-fn write_as_pretty_json(seq: &Sequence) -> FileSize {
-    let path = Path::new("/tmp/test.pretty.json");
-    {
-        serde_json::to_writer_pretty(File::create(path).unwrap(), seq).unwrap();
-    }
-    get_file_size(path)
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid number '{}'", value))
 }
 
-fn write_as_msgpack_compact(seq: &Sequence) -> FileSize {
-    let path = Path::new("/tmp/test.compact.msgpack");
-    let data = rmp_serde::encode::to_vec(seq).unwrap();
-    {
-        File::create(path).unwrap().write_all(&data).unwrap();
-    }
-    get_file_size(path)
+fn load_sequence_from_file(path: &Path) -> Result<Sequence, BoxError> {
+    let file = File::open(path)?;
+    Ok(load_sequence_json(BufReader::new(file))?)
 }
 
-fn write_as_msgpack_named(seq: &Sequence) -> FileSize {
-    let path = Path::new("/tmp/test.named.msgpack");
-    let data = rmp_serde::encode::to_vec_named(seq).unwrap();
-    {
-        File::create(path).unwrap().write_all(&data).unwrap();
+fn load_inputs(args: &Args) -> Result<Vec<Input>, BoxError> {
+    let mut inputs = Vec::new();
+    for path in &args.inputs {
+        let sequence = load_sequence_from_file(Path::new(path))
+            .map_err(|err| format!("Failed to load {}: {}", path, err))?;
+        inputs.push(Input {
+            name: path.clone(),
+            sequence,
+        });
     }
-    get_file_size(path)
-}
-
-fn write_as_bincode(seq: &Sequence) -> FileSize {
-    let path = Path::new("/tmp/test.bincode");
-    let data = bincode::serialize(seq).unwrap();
-    {
-        File::create(path).unwrap().write_all(&data).unwrap();
+    for i in 0..args.num_synthetic as u64 {
+        let seed = args.seed + i;
+        let mut rng = StdRng::seed_from_u64(seed);
+        inputs.push(Input {
+            name: format!("synthetic (seed {})", seed),
+            sequence: gen_sized_sequence(&mut rng, args.num_tracks, args.num_notes),
+        });
     }
-    get_file_size(path)
+    Ok(inputs)
 }
 
-fn write_as_cbor(seq: &Sequence) -> FileSize {
-    let path = Path::new("/tmp/test.cbor");
-    {
-        ciborium::ser::into_writer(seq, File::create(path).unwrap()).unwrap();
-    }
-    get_file_size(path)
+/// A file at `path`, or stdout.
+fn create_output(path: Option<&String>) -> Result<Box<dyn Write>, BoxError> {
+    Ok(match path {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(stdout().lock()),
+    })
 }
 
-fn write_as_bare(seq: &Sequence) -> FileSize {
-    let path = Path::new("/tmp/test.bare");
-    {
-        serde_bare::to_writer(File::create(path).unwrap(), seq).unwrap();
+fn run(args: &Args) -> Result<(), BoxError> {
+    let inputs = load_inputs(args)?;
+    let options = BenchmarkOptions {
+        min_duration: args.min_duration,
+    };
+    let measurements = run_benchmark(&inputs, &all_formats(), &options)?;
+
+    let mut wr = create_output(args.output.as_ref())?;
+    write_table(&mut wr, &measurements, args.table_format)?;
+
+    if args.load_benchmark {
+        let load_measurements = run_load_benchmark(&inputs, &all_loaders(), &options)?;
+        if args.load_output.is_some() {
+            let mut load_wr = create_output(args.load_output.as_ref())?;
+            write_load_table(&mut load_wr, &load_measurements, args.table_format)?;
+        } else {
+            writeln!(wr)?;
+            write_load_table(&mut wr, &load_measurements, args.table_format)?;
+        }
     }
-    get_file_size(path)
-}
 
-fn write_as_custom(seq: &Sequence) -> FileSize {
-    let path = Path::new("/tmp/test.custom");
-    {
-        serialize_sequence(seq, File::create(path).unwrap(), &Params::default()).unwrap();
+    if args.quantization_report {
+        // free text, which would break a CSV file
+        let mut report_wr: Box<dyn Write> = match args.table_format {
+            TableFormat::Csv => Box::new(stderr().lock()),
+            TableFormat::Markdown => wr,
+        };
+        for input in &inputs {
+            let report = quantization_report(&input.sequence, &Params::default());
+            writeln!(
+                report_wr,
+                "\nQuantization errors of {}:\n{}",
+                input.name, report
+            )?;
+        }
     }
-    get_file_size(path)
+    Ok(())
 }
 
 fn main() {
-    let path = Path::new("test.json");
-    let seq = load_sequence_from_file(path);
-
-    let size_orig = get_file_size(path);
-    let size_json = write_as_json(&seq);
-    let size_pretty_json = write_as_pretty_json(&seq);
-    let size_msgpack_compact = write_as_msgpack_compact(&seq);
-    let size_msgpack_named = write_as_msgpack_named(&seq);
-    let size_bincode = write_as_bincode(&seq);
-    let size_cbor = write_as_cbor(&seq);
-    let size_bare = write_as_bare(&seq);
-    let size_custom = write_as_custom(&seq);
-
-    let entries = [
-        ("orig", size_orig),
-        ("json", size_json),
-        ("pretty.json", size_pretty_json),
-        ("msgpack (compact)", size_msgpack_compact),
-        ("msgpack (named)", size_msgpack_named),
-        ("bincode", size_bincode),
-        ("cbor", size_cbor),
-        ("bare", size_bare),
-        ("custom", size_custom),
-    ];
-
-    let size_reference = 21749;
-    let size_reference_zip = 4038;
-    let get_ratio_to_ref = |len: u64| len as f64 / size_reference as f64;
-    let get_ratio_to_ref_zip = |len: u64| len as f64 / size_reference_zip as f64;
-
-    for (name, len) in entries {
-        println!(
-            "{:<20} {:10} {:8.3} {:10} {:8.3} {:8.3}",
-            name.to_owned() + ":",
-            len.raw,
-            get_ratio_to_ref(len.raw),
-            len.zip,
-            get_ratio_to_ref(len.zip),
-            get_ratio_to_ref_zip(len.zip),
-        );
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return;
+        }
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            exit(2);
+        }
+    };
+    if let Err(err) = run(&args) {
+        eprintln!("Error: {}", err);
+        exit(1);
     }
 }
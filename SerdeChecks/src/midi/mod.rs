@@ -0,0 +1,338 @@
+//! Import and export of Standard MIDI Files (SMF) of type 0 and 1.
+//!
+//! The mapping between a `Sequence` and MIDI:
+//! - Times are ticks. Exporting uses `Params::time_quantization` as the ticks per quarter note
+//!   (PPQ) and rounds the times according to `Params::rounding`, `max_error` and `strict`.
+//!   Importing divides by the PPQ of the file.
+//! - A note is a note-on/note-off pair of `pitch` with the velocity `VELOCITY`.
+//! - The `TempoMap` is a set-tempo meta event per tempo (rounded to whole microseconds per
+//!   quarter note) and a time-signature meta event per time signature. When importing, the
+//!   first set-tempo at tick 0 is the base tempo, 120 bpm if there is none.
+//! - `Track::name` is a track-name meta event, and `is_percussion` selects channel 10. Other
+//!   tracks get the remaining channels in order, starting over after 16 channels.
+//! - The points of a `BendData` are pitch-bend messages at `s + pos`, with a range of
+//!   `PITCH_BEND_RANGE` semitones set by RPN 0. A pitch bend is reset at the end of the note.
+//!   Points at or after the end of the note are dropped, and a bend applies to all notes that
+//!   sound on the channel.
+//!
+//! The string, fret, tuning and the other effects have no MIDI equivalent, they are dropped
+//! on export and zero (or empty) on import.
+//!
+//! Type 1 files start with a conductor track, which holds the tempo map, followed by one
+//! MIDI track per `Track`. Every MIDI track starts with a channel-prefix meta event, so that
+//! importing restores empty tracks as well. Type 0 files hold all events in a single MIDI
+//! track, i.e., tracks are identified by channel only and keep their name only if there is
+//! a single track. Importing creates one `Track` per MIDI track and channel.
+
+mod read;
+mod write;
+
+use std::fmt;
+
+pub use read::read_smf;
+pub use write::{write_smf, write_smf_to_vec};
+
+/// The velocity of all exported notes.
+pub const VELOCITY: u8 = 100;
+
+/// The pitch-bend range of exported tracks in semitones. The 14 bit pitch-bend values then
+/// have a resolution of 1/512 semitones, i.e., bends on the default pitch quantization grid
+/// survive a round trip exactly.
+pub const PITCH_BEND_RANGE: u8 = 16;
+
+/// The pitch-bend range of imported tracks without RPN 0.
+pub const DEFAULT_PITCH_BEND_RANGE: u8 = 2;
+
+/// The channel of percussion tracks (channel 10 when counting from one).
+pub const PERCUSSION_CHANNEL: u8 = 9;
+
+/// The tempo of imported files without a set-tempo event at tick 0.
+pub const DEFAULT_BPM: f64 = 120.0;
+
+/// The SMF file type.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SmfFormat {
+    /// Type 0: a single MIDI track.
+    SingleTrack,
+    /// Type 1: a conductor track followed by one MIDI track per `Track`.
+    MultiTrack,
+}
+
+const HEADER_CHUNK: [u8; 4] = *b"MThd";
+const TRACK_CHUNK: [u8; 4] = *b"MTrk";
+
+const NOTE_OFF: u8 = 0x80;
+const NOTE_ON: u8 = 0x90;
+const CONTROL_CHANGE: u8 = 0xB0;
+const PITCH_BEND: u8 = 0xE0;
+const SYSEX: u8 = 0xF0;
+const SYSEX_ESCAPE: u8 = 0xF7;
+const META: u8 = 0xFF;
+
+const META_TRACK_NAME: u8 = 0x03;
+const META_CHANNEL_PREFIX: u8 = 0x20;
+const META_END_OF_TRACK: u8 = 0x2F;
+const META_SET_TEMPO: u8 = 0x51;
+const META_TIME_SIGNATURE: u8 = 0x58;
+
+const CC_DATA_ENTRY_MSB: u8 = 6;
+const CC_DATA_ENTRY_LSB: u8 = 38;
+const CC_RPN_LSB: u8 = 100;
+const CC_RPN_MSB: u8 = 101;
+
+/// The value of an unbent pitch-bend message.
+const PITCH_BEND_CENTER: i32 = 0x2000;
+
+const MICROSECONDS_PER_MINUTE: f64 = 60_000_000.0;
+
+// ----------------------------------------------------------------------------
+// MidiError
+// ----------------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MidiError {
+    /// The file does not start with an `MThd` chunk.
+    InvalidHeader { offset: usize },
+    /// Type 2 files (independent sequences) and unknown types.
+    UnsupportedFormat { format: u16, offset: usize },
+    /// SMPTE time divisions, i.e., ticks that are not relative to quarter notes.
+    UnsupportedDivision { division: u16, offset: usize },
+    /// The input ended, although at least `expected` more bytes were required.
+    Truncated { expected: usize, offset: usize },
+    /// A variable-length quantity has more than four bytes.
+    InvalidVarLen { offset: usize },
+    /// A data byte without a running status, or a status byte that is not allowed in a track.
+    InvalidStatus { status: u8, offset: usize },
+    /// A meta event whose data does not match its type.
+    InvalidMetaEvent { meta_type: u8, offset: usize },
+}
+
+impl MidiError {
+    /// Byte offset relative to the start of the file.
+    pub fn offset(&self) -> usize {
+        match self {
+            MidiError::InvalidHeader { offset }
+            | MidiError::UnsupportedFormat { offset, .. }
+            | MidiError::UnsupportedDivision { offset, .. }
+            | MidiError::Truncated { offset, .. }
+            | MidiError::InvalidVarLen { offset }
+            | MidiError::InvalidStatus { offset, .. }
+            | MidiError::InvalidMetaEvent { offset, .. } => *offset,
+        }
+    }
+}
+
+impl fmt::Display for MidiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MidiError::InvalidHeader { .. } => write!(f, "Missing MThd header chunk")?,
+            MidiError::UnsupportedFormat { format, .. } => {
+                write!(f, "Unsupported SMF type {}", format)?
+            }
+            MidiError::UnsupportedDivision { division, .. } => {
+                write!(f, "Unsupported time division {:#06x}", division)?
+            }
+            MidiError::Truncated { expected, .. } => write!(
+                f,
+                "Unexpected end of input, expected {} more byte(s)",
+                expected
+            )?,
+            MidiError::InvalidVarLen { .. } => {
+                write!(f, "Variable-length quantity exceeds four bytes")?
+            }
+            MidiError::InvalidStatus { status, .. } => {
+                write!(f, "Invalid status byte {:#04x}", status)?
+            }
+            MidiError::InvalidMetaEvent { meta_type, .. } => {
+                write!(f, "Invalid meta event of type {:#04x}", meta_type)?
+            }
+        }
+        write!(f, " at offset {}", self.offset())
+    }
+}
+
+impl std::error::Error for MidiError {}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use crate::custom_file_format::Params;
+    use crate::types::{
+        BendData, BendPoint, Note, NoteEffects, Sequence, TempoChange, TempoMap, TimeSignature,
+        Track, Tuning,
+    };
+
+    use super::*;
+
+    fn note(s: f64, d: f64, pitch: u8) -> Note {
+        Note {
+            s,
+            d,
+            pitch,
+            ..Note::default()
+        }
+    }
+
+    fn track(name: &str, is_percussion: bool, notes: Vec<Note>) -> Track {
+        Track {
+            name: name.to_string(),
+            is_percussion,
+            tuning: Tuning::default(),
+            notes,
+        }
+    }
+
+    /// PPQ 4, i.e., sixteenth notes are one tick.
+    fn params() -> Params {
+        Params {
+            time_quantization: 4,
+            ..Params::default()
+        }
+    }
+
+    fn sequence() -> Sequence {
+        let bent_note = Note {
+            effects: NoteEffects {
+                bend_data: Some(BendData {
+                    points: vec![
+                        BendPoint {
+                            pos: 0.0,
+                            bend: 0.0,
+                        },
+                        BendPoint {
+                            pos: 0.5,
+                            bend: 1.5,
+                        },
+                    ],
+                }),
+                ..NoteEffects::default()
+            },
+            ..note(1.0, 1.0, 62)
+        };
+        Sequence {
+            tempo_map: TempoMap {
+                bpm_base: 120.0,
+                tempo_changes: vec![TempoChange {
+                    beat: 2.0,
+                    bpm: 60.0,
+                }],
+                time_signatures: vec![TimeSignature {
+                    beat: 0.0,
+                    numerator: 3,
+                    denominator: 4,
+                }],
+            },
+            tracks: vec![
+                track("Gtr", false, vec![note(0.0, 1.0, 60), bent_note]),
+                track("Dr", true, vec![note(0.0, 0.25, 36)]),
+            ],
+        }
+    }
+
+    /// The conductor track of `sequence()`.
+    #[rustfmt::skip]
+    const CONDUCTOR_EVENTS: &[u8] = &[
+        0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20, // 500000 us per quarter
+        0x00, 0xFF, 0x58, 0x04, 0x03, 0x02, 0x18, 0x08, // 3/4
+        0x08, 0xFF, 0x51, 0x03, 0x0F, 0x42, 0x40, // 1000000 us per quarter at beat 2
+    ];
+
+    #[rustfmt::skip]
+    const GUITAR_EVENTS: &[u8] = &[
+        // RPN 0 (pitch-bend range) = 16 semitones
+        0x00, 0xB0, 0x65, 0x00,
+        0x00, 0xB0, 0x64, 0x00,
+        0x00, 0xB0, 0x06, 0x10,
+        0x00, 0xB0, 0x26, 0x00,
+        0x00, 0x90, 0x3C, 0x64,
+        0x04, 0x80, 0x3C, 0x00,
+        0x00, 0x90, 0x3E, 0x64,
+        0x00, 0xE0, 0x00, 0x40, // center
+        0x02, 0xE0, 0x00, 0x46, // 0x2000 + 1.5 * 512
+        0x02, 0x80, 0x3E, 0x00,
+        0x00, 0xE0, 0x00, 0x40, // reset
+    ];
+
+    #[rustfmt::skip]
+    const DRUM_EVENTS: &[u8] = &[
+        0x00, 0x99, 0x24, 0x64,
+        0x01, 0x89, 0x24, 0x00,
+    ];
+
+    const END_OF_TRACK: &[u8] = &[0x00, 0xFF, 0x2F, 0x00];
+
+    fn chunk(kind: &[u8; 4], parts: &[&[u8]]) -> Vec<u8> {
+        let data = parts.concat();
+        let mut chunk = kind.to_vec();
+        chunk.extend((data.len() as u32).to_be_bytes());
+        chunk.extend(data);
+        chunk
+    }
+
+    fn type_1_fixture() -> Vec<u8> {
+        [
+            chunk(b"MThd", &[&[0x00, 0x01, 0x00, 0x03, 0x00, 0x04]]),
+            chunk(b"MTrk", &[CONDUCTOR_EVENTS, END_OF_TRACK]),
+            chunk(
+                b"MTrk",
+                &[
+                    &[0x00, 0xFF, 0x20, 0x01, 0x00],
+                    &[0x00, 0xFF, 0x03, 0x03],
+                    b"Gtr",
+                    GUITAR_EVENTS,
+                    END_OF_TRACK,
+                ],
+            ),
+            chunk(
+                b"MTrk",
+                &[
+                    &[0x00, 0xFF, 0x20, 0x01, 0x09],
+                    &[0x00, 0xFF, 0x03, 0x02],
+                    b"Dr",
+                    DRUM_EVENTS,
+                    END_OF_TRACK,
+                ],
+            ),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn test_write_type_1() {
+        let data = write_smf_to_vec(&sequence(), &params(), SmfFormat::MultiTrack).unwrap();
+        assert_eq!(data, type_1_fixture());
+    }
+
+    #[test]
+    fn test_read_type_1() {
+        assert_eq!(read_smf(&type_1_fixture()).unwrap(), sequence());
+    }
+
+    #[test]
+    fn test_type_0_roundtrip() {
+        let sequence = Sequence {
+            tracks: vec![track("Gtr", false, vec![note(0.0, 1.0, 60)])],
+            ..sequence()
+        };
+        let fixture = [
+            chunk(b"MThd", &[&[0x00, 0x00, 0x00, 0x01, 0x00, 0x04]]),
+            chunk(
+                b"MTrk",
+                &[
+                    &CONDUCTOR_EVENTS[..15],
+                    &[0x00, 0xFF, 0x03, 0x03],
+                    b"Gtr",
+                    &[0x00, 0x90, 0x3C, 0x64],
+                    &[0x04, 0x80, 0x3C, 0x00],
+                    &[0x04, 0xFF, 0x51, 0x03, 0x0F, 0x42, 0x40],
+                    END_OF_TRACK,
+                ],
+            ),
+        ]
+        .concat();
+        let data = write_smf_to_vec(&sequence, &params(), SmfFormat::SingleTrack).unwrap();
+        assert_eq!(data, fixture);
+        assert_eq!(read_smf(&fixture).unwrap(), sequence);
+    }
+}
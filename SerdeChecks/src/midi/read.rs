@@ -0,0 +1,523 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::types::{
+    BendData, BendPoint, Note, NoteEffects, Sequence, TempoChange, TempoMap, TimeSignature, Track,
+    Tuning,
+};
+
+use super::*;
+
+/// A window of the file, `pos` and `end` are offsets relative to the start of the file.
+struct Input<'a> {
+    data: &'a [u8],
+    pos: usize,
+    end: usize,
+}
+
+impl<'a> Input<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Input {
+            data,
+            pos: 0,
+            end: data.len(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.end
+    }
+
+    fn take(&mut self, num_bytes: usize) -> Result<&'a [u8], MidiError> {
+        let remaining = self.end - self.pos;
+        if num_bytes > remaining {
+            return Err(MidiError::Truncated {
+                expected: num_bytes - remaining,
+                offset: self.pos,
+            });
+        }
+        self.pos += num_bytes;
+        Ok(&self.data[self.pos - num_bytes..self.pos])
+    }
+
+    fn peek_u8(&self) -> Result<u8, MidiError> {
+        if self.is_empty() {
+            return Err(MidiError::Truncated {
+                expected: 1,
+                offset: self.pos,
+            });
+        }
+        Ok(self.data[self.pos])
+    }
+
+    fn u8(&mut self) -> Result<u8, MidiError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, MidiError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, MidiError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn var_len(&mut self) -> Result<u32, MidiError> {
+        let offset = self.pos;
+        let mut value = 0;
+        for _ in 0..4 {
+            let byte = self.u8()?;
+            value = (value << 7) | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(MidiError::InvalidVarLen { offset })
+    }
+
+    /// Splits off the next `len` bytes as a window of their own.
+    fn sub_input(&mut self, len: usize) -> Result<Input<'a>, MidiError> {
+        let pos = self.pos;
+        self.take(len)?;
+        Ok(Input {
+            data: self.data,
+            pos,
+            end: self.pos,
+        })
+    }
+}
+
+/// The tempo map events of all MIDI tracks, in file order.
+#[derive(Default)]
+struct TempoEvents {
+    tempos: Vec<(u64, f64)>,
+    time_signatures: Vec<(u64, u8, u8)>,
+}
+
+/// The parsing state of a single MIDI track.
+struct TrackReader<'t> {
+    ppq: u64,
+    tick: u64,
+    running_status: Option<u8>,
+    name: Option<String>,
+    channel_prefix: Option<u8>,
+    /// The notes with their channel and the tick of their note-on.
+    notes: Vec<(u8, u64, Note)>,
+    /// The indices of the sounding notes by channel and pitch, in the order of their note-ons.
+    sounding: BTreeMap<(u8, u8), VecDeque<usize>>,
+    /// The selected RPN (MSB, LSB) of every channel.
+    rpn: [(u8, u8); 16],
+    bend_range: [f32; 16],
+    bend: [f32; 16],
+    tempo_events: &'t mut TempoEvents,
+}
+
+/// Reads an SMF of type 0 or 1, see `midi` for the mapping. Chunks other than `MTrk` are
+/// skipped.
+pub fn read_smf(data: &[u8]) -> Result<Sequence, MidiError> {
+    let mut input = Input::new(data);
+    if input.take(4).ok() != Some(&HEADER_CHUNK[..]) {
+        return Err(MidiError::InvalidHeader { offset: 0 });
+    }
+    let header_len = input.u32()? as usize;
+    let mut header = input.sub_input(header_len)?;
+    let format_offset = header.pos;
+    let format = header.u16()?;
+    if format > 1 {
+        return Err(MidiError::UnsupportedFormat {
+            format,
+            offset: format_offset,
+        });
+    }
+    let _num_tracks = header.u16()?;
+    let division_offset = header.pos;
+    let division = header.u16()?;
+    if division & 0x8000 != 0 || division == 0 {
+        return Err(MidiError::UnsupportedDivision {
+            division,
+            offset: division_offset,
+        });
+    }
+
+    let mut tempo_events = TempoEvents::default();
+    let mut tracks = vec![];
+    while !input.is_empty() {
+        let kind = input.take(4)?;
+        let len = input.u32()? as usize;
+        let mut chunk = input.sub_input(len)?;
+        if kind == TRACK_CHUNK {
+            let reader = TrackReader::new(division as u64, &mut tempo_events);
+            tracks.extend(reader.read(&mut chunk)?);
+        }
+    }
+    Ok(Sequence {
+        tempo_map: tempo_events.into_tempo_map(division as u64),
+        tracks,
+    })
+}
+
+impl TempoEvents {
+    /// The first tempo at tick 0 is the base tempo, all others are changes.
+    fn into_tempo_map(mut self, ppq: u64) -> TempoMap {
+        let beat = |tick: u64| tick as f64 / ppq as f64;
+        self.tempos.sort_by_key(|(tick, _)| *tick);
+        self.time_signatures.sort_by_key(|(tick, _, _)| *tick);
+        let mut tempos = self.tempos.as_slice();
+        let mut bpm_base = DEFAULT_BPM;
+        if let [(0, bpm), rest @ ..] = tempos {
+            bpm_base = *bpm;
+            tempos = rest;
+        }
+        TempoMap {
+            bpm_base,
+            tempo_changes: tempos
+                .iter()
+                .map(|(tick, bpm)| TempoChange {
+                    beat: beat(*tick),
+                    bpm: *bpm,
+                })
+                .collect(),
+            time_signatures: self
+                .time_signatures
+                .iter()
+                .map(|(tick, numerator, denominator)| TimeSignature {
+                    beat: beat(*tick),
+                    numerator: *numerator,
+                    denominator: *denominator,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl<'t> TrackReader<'t> {
+    fn new(ppq: u64, tempo_events: &'t mut TempoEvents) -> Self {
+        TrackReader {
+            ppq,
+            tick: 0,
+            running_status: None,
+            name: None,
+            channel_prefix: None,
+            notes: vec![],
+            sounding: BTreeMap::new(),
+            rpn: [(0x7F, 0x7F); 16],
+            bend_range: [DEFAULT_PITCH_BEND_RANGE as f32; 16],
+            bend: [0.0; 16],
+            tempo_events,
+        }
+    }
+
+    fn beats(&self, ticks: u64) -> f64 {
+        ticks as f64 / self.ppq as f64
+    }
+
+    /// Reads the events up to the end-of-track event or the end of the chunk.
+    fn read(mut self, input: &mut Input) -> Result<Vec<Track>, MidiError> {
+        while !input.is_empty() {
+            self.tick += input.var_len()? as u64;
+            let offset = input.pos;
+            let mut status = input.peek_u8()?;
+            if status & 0x80 != 0 {
+                input.u8()?;
+            } else {
+                status = self
+                    .running_status
+                    .ok_or(MidiError::InvalidStatus { status, offset })?;
+            }
+            match status {
+                META => {
+                    let meta_type = input.u8()?;
+                    let len = input.var_len()? as usize;
+                    let data = input.take(len)?;
+                    if meta_type == META_END_OF_TRACK {
+                        break;
+                    }
+                    self.meta_event(meta_type, data, offset)?;
+                }
+                SYSEX | SYSEX_ESCAPE => {
+                    let len = input.var_len()? as usize;
+                    input.take(len)?;
+                }
+                0x80..=0xEF => {
+                    self.running_status = Some(status);
+                    let num_data_bytes = match status & 0xF0 {
+                        0xC0 | 0xD0 => 1,
+                        _ => 2,
+                    };
+                    let data = input.take(num_data_bytes)?;
+                    let data = [data[0] & 0x7F, data.get(1).map_or(0, |byte| byte & 0x7F)];
+                    self.channel_event(status & 0xF0, status & 0x0F, data);
+                }
+                _ => return Err(MidiError::InvalidStatus { status, offset }),
+            }
+        }
+        Ok(self.into_tracks())
+    }
+
+    fn meta_event(&mut self, meta_type: u8, data: &[u8], offset: usize) -> Result<(), MidiError> {
+        let invalid = MidiError::InvalidMetaEvent { meta_type, offset };
+        match meta_type {
+            META_TRACK_NAME => self.name = Some(String::from_utf8_lossy(data).into_owned()),
+            META_CHANNEL_PREFIX => match data {
+                [channel] if *channel < 16 => self.channel_prefix = Some(*channel),
+                _ => return Err(invalid),
+            },
+            META_SET_TEMPO => match data {
+                [a, b, c] => {
+                    let microseconds = u32::from_be_bytes([0, *a, *b, *c]);
+                    if microseconds == 0 {
+                        return Err(invalid);
+                    }
+                    let bpm = MICROSECONDS_PER_MINUTE / microseconds as f64;
+                    self.tempo_events.tempos.push((self.tick, bpm));
+                }
+                _ => return Err(invalid),
+            },
+            META_TIME_SIGNATURE => match data {
+                [numerator, log_denominator, ..] if *log_denominator < 8 => {
+                    let denominator = 1 << log_denominator;
+                    let time_signatures = &mut self.tempo_events.time_signatures;
+                    time_signatures.push((self.tick, *numerator, denominator));
+                }
+                _ => return Err(invalid),
+            },
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn channel_event(&mut self, kind: u8, channel: u8, data: [u8; 2]) {
+        let ch = channel as usize;
+        match (kind, data) {
+            (NOTE_ON, [pitch, velocity]) if velocity > 0 => {
+                let mut effects = NoteEffects::default();
+                if self.bend[ch] != 0.0 {
+                    effects.bend_data = Some(BendData {
+                        points: vec![BendPoint {
+                            pos: 0.0,
+                            bend: self.bend[ch],
+                        }],
+                    });
+                }
+                let note = Note {
+                    s: self.beats(self.tick),
+                    pitch,
+                    effects,
+                    ..Note::default()
+                };
+                self.notes.push((channel, self.tick, note));
+                let sounding = self.sounding.entry((channel, pitch)).or_default();
+                sounding.push_back(self.notes.len() - 1);
+            }
+            (NOTE_OFF | NOTE_ON, [pitch, _]) => {
+                if let Some(sounding) = self.sounding.get_mut(&(channel, pitch)) {
+                    if let Some(index) = sounding.pop_front() {
+                        self.end_note(index);
+                    }
+                }
+            }
+            (CONTROL_CHANGE, [controller, value]) => match controller {
+                CC_RPN_MSB => self.rpn[ch].0 = value,
+                CC_RPN_LSB => self.rpn[ch].1 = value,
+                CC_DATA_ENTRY_MSB if self.rpn[ch] == (0, 0) => {
+                    self.bend_range[ch] = value as f32;
+                }
+                CC_DATA_ENTRY_LSB if self.rpn[ch] == (0, 0) => {
+                    self.bend_range[ch] = self.bend_range[ch].floor() + value as f32 / 100.0;
+                }
+                _ => {}
+            },
+            (PITCH_BEND, [lsb, msb]) => {
+                let value = (lsb as i32 | (msb as i32) << 7) - PITCH_BEND_CENTER;
+                let bend = value as f32 * self.bend_range[ch] / PITCH_BEND_CENTER as f32;
+                self.bend[ch] = bend;
+                for ((sounding_channel, _), indices) in &self.sounding {
+                    if *sounding_channel != channel {
+                        continue;
+                    }
+                    for index in indices {
+                        let (_, on_tick, note) = &mut self.notes[*index];
+                        let pos = (self.tick - *on_tick) as f64 / self.ppq as f64;
+                        let bend_data =
+                            note.effects.bend_data.get_or_insert_with(BendData::default);
+                        bend_data.points.push(BendPoint { pos, bend });
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn end_note(&mut self, index: usize) {
+        let (_, on_tick, note) = &mut self.notes[index];
+        note.d = (self.tick - *on_tick) as f64 / self.ppq as f64;
+    }
+
+    /// One track per channel with notes, in the order of their first note. Notes without
+    /// note-off end at the end of the track.
+    fn into_tracks(mut self) -> Vec<Track> {
+        let sounding: Vec<usize> = self.sounding.values().flatten().copied().collect();
+        for index in sounding {
+            self.end_note(index);
+        }
+        let name = self.name.unwrap_or_default();
+        let mut tracks: Vec<(u8, Track)> = vec![];
+        for (channel, _, note) in self.notes {
+            let position = tracks.iter().position(|(c, _)| *c == channel);
+            let index = position.unwrap_or_else(|| {
+                let track = Track {
+                    name: name.clone(),
+                    is_percussion: channel == PERCUSSION_CHANNEL,
+                    tuning: Tuning::default(),
+                    notes: vec![],
+                };
+                tracks.push((channel, track));
+                tracks.len() - 1
+            });
+            tracks[index].1.notes.push(note);
+        }
+        if tracks.is_empty() {
+            if let Some(channel) = self.channel_prefix {
+                let track = Track {
+                    name,
+                    is_percussion: channel == PERCUSSION_CHANNEL,
+                    tuning: Tuning::default(),
+                    notes: vec![],
+                };
+                tracks.push((channel, track));
+            }
+        }
+        tracks.into_iter().map(|(_, track)| track).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn smf(format: u8, ppq: u8, track_events: &[u8]) -> Vec<u8> {
+        let mut data = b"MThd\x00\x00\x00\x06\x00".to_vec();
+        data.extend([format, 0x00, 0x01, 0x00, ppq]);
+        data.extend(b"MTrk");
+        data.extend((track_events.len() as u32).to_be_bytes());
+        data.extend(track_events);
+        data
+    }
+
+    fn note(s: f64, d: f64, pitch: u8) -> Note {
+        Note {
+            s,
+            d,
+            pitch,
+            ..Note::default()
+        }
+    }
+
+    fn read_error(data: &[u8]) -> String {
+        read_smf(data).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_foreign_events() {
+        #[rustfmt::skip]
+        let events = [
+            0x00, 0xF0, 0x03, 0x7E, 0x7F, 0xF7, // sysex
+            0x00, 0xFF, 0x01, 0x02, b'h', b'i', // text
+            0x00, 0xC0, 0x19, // program change
+            0x00, 0x90, 0x40, 0x50,
+            0x00, 0x43, 0x50, // running status
+            0x81, 0x00, 0x40, 0x00, // running status note-on with velocity 0
+            0x00, 0x80, 0x43, 0x40,
+            0x00, 0x91, 0x30, 0x50, // never ends
+            0x20, 0xFF, 0x2F, 0x00,
+            0x00, 0x90, 0x30, 0x50, // after the end of the track
+        ];
+        let sequence = read_smf(&smf(0, 0x40, &events)).unwrap();
+        assert_eq!(sequence.tempo_map, TempoMap::new(DEFAULT_BPM));
+        let tracks: Vec<_> = sequence
+            .tracks
+            .iter()
+            .map(|track| (track.name.as_str(), track.notes.clone()))
+            .collect();
+        assert_eq!(
+            tracks,
+            [
+                ("", vec![note(0.0, 2.0, 0x40), note(0.0, 2.0, 0x43)]),
+                ("", vec![note(2.0, 0.5, 0x30)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pitch_bend() {
+        #[rustfmt::skip]
+        let events = [
+            0x00, 0xE0, 0x00, 0x60, // +1 semitone before the note
+            0x00, 0x90, 0x40, 0x50,
+            0x02, 0xE0, 0x00, 0x40,
+            // RPN 0 = 12 semitones and 50 cents
+            0x00, 0xB0, 0x65, 0x00,
+            0x00, 0xB0, 0x64, 0x00,
+            0x00, 0xB0, 0x06, 0x0C,
+            0x00, 0xB0, 0x26, 0x32,
+            0x02, 0xE0, 0x00, 0x20,
+            0x00, 0x80, 0x40, 0x00,
+        ];
+        let sequence = read_smf(&smf(0, 4, &events)).unwrap();
+        let bend_point = |pos, bend| BendPoint { pos, bend };
+        assert_eq!(
+            sequence.tracks[0].notes[0].effects.bend_data,
+            Some(BendData {
+                points: vec![
+                    bend_point(0.0, 1.0),
+                    bend_point(0.5, 0.0),
+                    bend_point(1.0, -6.25)
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        assert_eq!(read_error(b"RIFF"), "Missing MThd header chunk at offset 0");
+        assert_eq!(
+            read_error(&smf(2, 4, &[])),
+            "Unsupported SMF type 2 at offset 8"
+        );
+        let mut data = smf(1, 4, &[]);
+        data[12] = 0xE8;
+        assert_eq!(
+            read_error(&data),
+            "Unsupported time division 0xe804 at offset 12"
+        );
+        assert_eq!(
+            read_error(&smf(1, 4, &[0x00, 0x40, 0x00])),
+            "Invalid status byte 0x40 at offset 23"
+        );
+        assert_eq!(
+            read_error(&smf(1, 4, &[0x00, 0xF2, 0x00])),
+            "Invalid status byte 0xf2 at offset 23"
+        );
+        assert_eq!(
+            read_error(&smf(1, 4, &[0xFF, 0xFF, 0xFF, 0xFF, 0x00])),
+            "Variable-length quantity exceeds four bytes at offset 22"
+        );
+        assert_eq!(
+            read_error(&smf(1, 4, &[0x00, 0xFF, 0x51, 0x02, 0x00, 0x01])),
+            "Invalid meta event of type 0x51 at offset 23"
+        );
+        assert_eq!(
+            read_error(&smf(1, 4, &[0x00, 0x90, 0x40])),
+            "Unexpected end of input, expected 1 more byte(s) at offset 24"
+        );
+        let mut data = smf(1, 4, &[0x00, 0x90, 0x40, 0x50]);
+        data[21] = 0x05;
+        assert_eq!(
+            read_error(&data),
+            "Unexpected end of input, expected 1 more byte(s) at offset 22"
+        );
+    }
+}
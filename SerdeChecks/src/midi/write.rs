@@ -0,0 +1,453 @@
+use std::io::{Error, ErrorKind, Result, Write};
+
+use crate::custom_file_format::quantization::{
+    check_quantization_error, quantize_int, quantize_uint,
+};
+use crate::custom_file_format::Params;
+use crate::types::{Sequence, TempoMap, Track};
+
+use super::*;
+
+/// The largest tick that keeps the delta times within a four byte variable-length quantity.
+const MAX_TICK: u64 = 0x0FFF_FFFF;
+
+/// Pitch-bend steps per semitone with `PITCH_BEND_RANGE`.
+const PITCH_BEND_STEPS: u64 = PITCH_BEND_CENTER as u64 / PITCH_BEND_RANGE as u64;
+
+/// Orders the events of the same tick, e.g., note-offs precede note-ons so that repeated
+/// notes do not overlap, and a note sounds before it is bent.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Priority {
+    Meta,
+    NoteOff,
+    BendReset,
+    NoteOn,
+    Bend,
+    /// The note-off of a note without duration has to follow its note-on.
+    EmptyNoteOff,
+}
+
+struct Event {
+    tick: u64,
+    priority: Priority,
+    /// The complete event without the delta time.
+    bytes: Vec<u8>,
+}
+
+fn invalid_input(message: String) -> Error {
+    Error::new(ErrorKind::InvalidInput, message)
+}
+
+pub fn write_smf<W>(
+    sequence: &Sequence,
+    mut wr: W,
+    params: &Params,
+    format: SmfFormat,
+) -> Result<()>
+where
+    W: Write,
+{
+    let ppq = params.time_quantization;
+    if !(1..=0x7FFF).contains(&ppq) {
+        return Err(invalid_input(format!(
+            "Time quantization {} is not a valid number of ticks per quarter note",
+            ppq
+        )));
+    }
+    let channels = assign_channels(&sequence.tracks);
+    let mut midi_tracks = vec![tempo_map_events(&sequence.tempo_map, params)?];
+    match format {
+        SmfFormat::SingleTrack => {
+            let events = &mut midi_tracks[0];
+            if let [track] = sequence.tracks.as_slice() {
+                events.push(meta_event(0, META_TRACK_NAME, track.name.as_bytes()));
+            }
+            for (track, channel) in sequence.tracks.iter().zip(channels) {
+                events.extend(note_events(track, channel, params)?);
+            }
+        }
+        SmfFormat::MultiTrack => {
+            for (track, channel) in sequence.tracks.iter().zip(channels) {
+                let mut events = vec![
+                    meta_event(0, META_CHANNEL_PREFIX, &[channel]),
+                    meta_event(0, META_TRACK_NAME, track.name.as_bytes()),
+                ];
+                events.extend(note_events(track, channel, params)?);
+                midi_tracks.push(events);
+            }
+        }
+    }
+    let num_tracks = u16::try_from(midi_tracks.len())
+        .map_err(|_| invalid_input(format!("Too many tracks ({})", midi_tracks.len())))?;
+    let format_type: u16 = match format {
+        SmfFormat::SingleTrack => 0,
+        SmfFormat::MultiTrack => 1,
+    };
+
+    wr.write_all(&HEADER_CHUNK)?;
+    wr.write_all(&6u32.to_be_bytes())?;
+    wr.write_all(&format_type.to_be_bytes())?;
+    wr.write_all(&num_tracks.to_be_bytes())?;
+    wr.write_all(&(ppq as u16).to_be_bytes())?;
+    for events in midi_tracks {
+        write_track_chunk(&mut wr, events)?;
+    }
+    Ok(())
+}
+
+pub fn write_smf_to_vec(
+    sequence: &Sequence,
+    params: &Params,
+    format: SmfFormat,
+) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    write_smf(sequence, &mut buf, params, format)?;
+    Ok(buf)
+}
+
+/// Percussion tracks share `PERCUSSION_CHANNEL`, the other tracks cycle through the rest.
+fn assign_channels(tracks: &[Track]) -> Vec<u8> {
+    let mut melodic_channels = (0..16u8)
+        .filter(|channel| *channel != PERCUSSION_CHANNEL)
+        .cycle();
+    tracks
+        .iter()
+        .map(|track| {
+            if track.is_percussion {
+                PERCUSSION_CHANNEL
+            } else {
+                melodic_channels.next().unwrap()
+            }
+        })
+        .collect()
+}
+
+fn beat_to_tick(beat: f64, params: &Params) -> Result<u64> {
+    let tick = quantize_uint(beat, params.time_quantization, params.rounding).0;
+    check_quantization_error(beat, tick as f64 / params.time_quantization as f64, params)?;
+    if tick > MAX_TICK {
+        return Err(invalid_input(format!(
+            "Time {} exceeds the maximum MIDI tick {}",
+            beat, MAX_TICK
+        )));
+    }
+    Ok(tick)
+}
+
+fn meta_event(tick: u64, meta_type: u8, data: &[u8]) -> Event {
+    let mut bytes = vec![META, meta_type];
+    write_var_len(&mut bytes, data.len() as u32);
+    bytes.extend(data);
+    Event {
+        tick,
+        priority: Priority::Meta,
+        bytes,
+    }
+}
+
+fn set_tempo_event(tick: u64, bpm: f64) -> Result<Event> {
+    let microseconds = (MICROSECONDS_PER_MINUTE / bpm).round();
+    if !(1.0..=0xFF_FFFF as f64).contains(&microseconds) {
+        return Err(invalid_input(format!(
+            "Tempo {} bpm is out of the MIDI range",
+            bpm
+        )));
+    }
+    let bytes = (microseconds as u32).to_be_bytes();
+    Ok(meta_event(tick, META_SET_TEMPO, &bytes[1..]))
+}
+
+fn tempo_map_events(tempo_map: &TempoMap, params: &Params) -> Result<Vec<Event>> {
+    let mut events = vec![set_tempo_event(0, tempo_map.bpm_base)?];
+    for time_signature in &tempo_map.time_signatures {
+        let denominator = time_signature.denominator;
+        if !denominator.is_power_of_two() {
+            return Err(invalid_input(format!(
+                "Time signature denominator {} is not a power of two",
+                denominator
+            )));
+        }
+        let tick = beat_to_tick(time_signature.beat, params)?;
+        let data = [
+            time_signature.numerator,
+            denominator.trailing_zeros() as u8,
+            // MIDI clocks per metronome click, and 32nd notes per quarter note
+            24,
+            8,
+        ];
+        events.push(meta_event(tick, META_TIME_SIGNATURE, &data));
+    }
+    for tempo_change in &tempo_map.tempo_changes {
+        let tick = beat_to_tick(tempo_change.beat, params)?;
+        events.push(set_tempo_event(tick, tempo_change.bpm)?);
+    }
+    Ok(events)
+}
+
+fn channel_event(tick: u64, priority: Priority, status: u8, data: [u8; 2]) -> Event {
+    Event {
+        tick,
+        priority,
+        bytes: vec![status, data[0], data[1]],
+    }
+}
+
+fn pitch_bend_event(tick: u64, priority: Priority, channel: u8, value: i32) -> Event {
+    let value = value.clamp(0, 2 * PITCH_BEND_CENTER - 1) as u16;
+    let data = [(value & 0x7F) as u8, (value >> 7) as u8];
+    channel_event(tick, priority, PITCH_BEND | channel, data)
+}
+
+fn note_events(track: &Track, channel: u8, params: &Params) -> Result<Vec<Event>> {
+    let mut events = vec![];
+    let has_bends = track
+        .notes
+        .iter()
+        .any(|note| note.effects.bend_data.is_some());
+    if has_bends {
+        let control_change = CONTROL_CHANGE | channel;
+        for data in [
+            [CC_RPN_MSB, 0],
+            [CC_RPN_LSB, 0],
+            [CC_DATA_ENTRY_MSB, PITCH_BEND_RANGE],
+            [CC_DATA_ENTRY_LSB, 0],
+        ] {
+            events.push(channel_event(0, Priority::Meta, control_change, data));
+        }
+    }
+    for note in &track.notes {
+        if note.pitch > 0x7F {
+            return Err(invalid_input(format!(
+                "Pitch {} is out of the MIDI range",
+                note.pitch
+            )));
+        }
+        let on_tick = beat_to_tick(note.s, params)?;
+        let off_tick = beat_to_tick(note.s + note.d.max(0.0), params)?;
+        let off_priority = if off_tick == on_tick {
+            Priority::EmptyNoteOff
+        } else {
+            Priority::NoteOff
+        };
+        events.push(channel_event(
+            on_tick,
+            Priority::NoteOn,
+            NOTE_ON | channel,
+            [note.pitch, VELOCITY],
+        ));
+        events.push(channel_event(
+            off_tick,
+            off_priority,
+            NOTE_OFF | channel,
+            [note.pitch, 0],
+        ));
+        if let Some(bend_data) = &note.effects.bend_data {
+            for point in &bend_data.points {
+                let tick = beat_to_tick(note.s + point.pos, params)?;
+                if tick >= off_tick {
+                    continue;
+                }
+                let steps = quantize_int(point.bend as f64, PITCH_BEND_STEPS, params.rounding).0;
+                check_quantization_error(
+                    point.bend,
+                    steps as f32 / PITCH_BEND_STEPS as f32,
+                    params,
+                )?;
+                let value = PITCH_BEND_CENTER.saturating_add(steps as i32);
+                events.push(pitch_bend_event(tick, Priority::Bend, channel, value));
+            }
+            events.push(pitch_bend_event(
+                off_tick,
+                Priority::BendReset,
+                channel,
+                PITCH_BEND_CENTER,
+            ));
+        }
+    }
+    Ok(events)
+}
+
+fn write_var_len(buf: &mut Vec<u8>, value: u32) {
+    let mut groups = vec![(value & 0x7F) as u8];
+    let mut rest = value >> 7;
+    while rest > 0 {
+        groups.push((rest & 0x7F) as u8 | 0x80);
+        rest >>= 7;
+    }
+    buf.extend(groups.iter().rev());
+}
+
+/// Sorts the events and appends the end-of-track event. Running status is not used.
+fn write_track_chunk<W: Write>(wr: &mut W, mut events: Vec<Event>) -> Result<()> {
+    events.sort_by_key(|event| (event.tick, event.priority));
+    let mut data = vec![];
+    let mut tick = 0;
+    for event in &events {
+        write_var_len(&mut data, (event.tick - tick) as u32);
+        data.extend(&event.bytes);
+        tick = event.tick;
+    }
+    write_var_len(&mut data, 0);
+    data.extend([META, META_END_OF_TRACK, 0]);
+
+    wr.write_all(&TRACK_CHUNK)?;
+    wr.write_all(&(data.len() as u32).to_be_bytes())?;
+    wr.write_all(&data)
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use crate::types::{BendData, BendPoint, Note, NoteEffects, TempoMap, TimeSignature};
+
+    use super::*;
+
+    fn write_error(sequence: &Sequence, params: &Params) -> String {
+        write_smf_to_vec(sequence, params, SmfFormat::MultiTrack)
+            .unwrap_err()
+            .to_string()
+    }
+
+    fn sequence_with_note(note: Note) -> Sequence {
+        Sequence {
+            tempo_map: TempoMap::new(120.0),
+            tracks: vec![Track {
+                notes: vec![note],
+                ..Track::default()
+            }],
+        }
+    }
+
+    #[test]
+    fn test_var_len() {
+        for (value, expected) in [
+            (0, vec![0x00]),
+            (0x7F, vec![0x7F]),
+            (0x80, vec![0x81, 0x00]),
+            (0x2000, vec![0xC0, 0x00]),
+            (0x0FFF_FFFF, vec![0xFF, 0xFF, 0xFF, 0x7F]),
+        ] {
+            let mut buf = vec![];
+            write_var_len(&mut buf, value);
+            assert_eq!(buf, expected, "{:#x}", value);
+        }
+    }
+
+    #[test]
+    fn test_assign_channels() {
+        let melodic = Track::default();
+        let percussion = Track {
+            is_percussion: true,
+            ..Track::default()
+        };
+        let mut tracks = vec![melodic.clone(); 10];
+        tracks.insert(2, percussion);
+        assert_eq!(assign_channels(&tracks), [0, 1, 9, 2, 3, 4, 5, 6, 7, 8, 10]);
+        let channels = assign_channels(&vec![melodic; 17]);
+        assert_eq!(channels[14..], [15, 0, 1]);
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        let params = Params::default();
+        assert_eq!(
+            write_error(
+                &sequence_with_note(Note {
+                    pitch: 128,
+                    ..Note::default()
+                }),
+                &params
+            ),
+            "Pitch 128 is out of the MIDI range"
+        );
+        assert_eq!(
+            write_error(
+                &sequence_with_note(Note {
+                    s: 1e6,
+                    ..Note::default()
+                }),
+                &params
+            ),
+            "Time 1000000 exceeds the maximum MIDI tick 268435455"
+        );
+        assert_eq!(
+            write_error(&Sequence::default(), &params),
+            "Tempo 0 bpm is out of the MIDI range"
+        );
+        let sequence = Sequence {
+            tempo_map: TempoMap {
+                time_signatures: vec![TimeSignature {
+                    beat: 0.0,
+                    numerator: 5,
+                    denominator: 6,
+                }],
+                ..TempoMap::new(120.0)
+            },
+            ..Sequence::default()
+        };
+        assert_eq!(
+            write_error(&sequence, &params),
+            "Time signature denominator 6 is not a power of two"
+        );
+        let params = Params {
+            time_quantization: 0x8000,
+            ..Params::default()
+        };
+        assert_eq!(
+            write_error(&Sequence::default(), &params),
+            "Time quantization 32768 is not a valid number of ticks per quarter note"
+        );
+    }
+
+    #[test]
+    fn test_quantization_params() {
+        let note = Note {
+            s: 0.1,
+            d: 1.0,
+            ..Note::default()
+        };
+        let params = Params {
+            time_quantization: 4,
+            ..Params::default()
+        };
+        write_smf_to_vec(
+            &sequence_with_note(note.clone()),
+            &params,
+            SmfFormat::MultiTrack,
+        )
+        .unwrap();
+        let params = Params {
+            strict: true,
+            ..params
+        };
+        assert_eq!(
+            write_error(&sequence_with_note(note), &params),
+            "Value 0.1 is not representable, the closest value is 0"
+        );
+    }
+
+    #[test]
+    fn test_bend_points_after_note_end_are_dropped() {
+        let bend_point = |pos: f64| BendPoint { pos, bend: 1.0 };
+        let note = Note {
+            s: 0.0,
+            d: 1.0,
+            effects: NoteEffects {
+                bend_data: Some(BendData {
+                    points: vec![bend_point(0.5), bend_point(1.0), bend_point(2.0)],
+                }),
+                ..NoteEffects::default()
+            },
+            ..Note::default()
+        };
+        let events =
+            note_events(&sequence_with_note(note).tracks[0], 0, &Params::default()).unwrap();
+        let bends: Vec<_> = events
+            .iter()
+            .filter(|event| event.bytes[0] == PITCH_BEND)
+            .map(|event| (event.tick, event.priority))
+            .collect();
+        assert_eq!(bends, [(480, Priority::Bend), (960, Priority::BendReset)]);
+    }
+}
@@ -0,0 +1,346 @@
+//! Divides the notes of a track into voices and the voices into measures and note values.
+
+use std::io::Result;
+
+use crate::types::{Note, TempoMap};
+
+use super::invalid_input;
+
+/// Divisions per quarter note. With 48, the note values from whole notes down to 64th notes,
+/// their dotted variants and triplets down to 64th triplets are whole numbers.
+pub const DIVISIONS: u64 = 48;
+
+const WHOLE: u64 = 4 * DIVISIONS;
+
+/// Over 13 hours at 120 bpm, bounds the number of measures that are written.
+pub const MAX_BEATS: f64 = 100_000.0;
+
+const NOTE_TYPES: [(&str, u64); 7] = [
+    ("whole", WHOLE),
+    ("half", WHOLE / 2),
+    ("quarter", WHOLE / 4),
+    ("eighth", WHOLE / 8),
+    ("16th", WHOLE / 16),
+    ("32nd", WHOLE / 32),
+    ("64th", WHOLE / 64),
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(super) struct NoteValue {
+    pub duration: u64,
+    /// None for a remainder that is shorter than all note values.
+    pub note_type: Option<&'static str>,
+    pub dotted: bool,
+    /// Three in the time of two.
+    pub triplet: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) struct Measure {
+    pub start: u64,
+    pub end: u64,
+    pub numerator: u8,
+    pub denominator: u8,
+    /// Whether the time signature is new, always true for the first measure.
+    pub new_time_signature: bool,
+}
+
+/// Notes of a track with the same onset and end, i.e., a chord or a single note.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) struct Chord {
+    pub start: u64,
+    pub end: u64,
+    /// Indices into `Track::notes`, ordered by pitch.
+    pub notes: Vec<usize>,
+}
+
+/// Rounds a time in beats to divisions.
+pub(super) fn to_divisions(beat: f64) -> Result<u64> {
+    if !(beat >= 0.0 && beat.is_finite()) {
+        return Err(invalid_input(format!(
+            "Time {} is not a non-negative number of beats",
+            beat
+        )));
+    }
+    if beat > MAX_BEATS {
+        return Err(invalid_input(format!(
+            "Time {} exceeds the maximum of {} beats",
+            beat, MAX_BEATS
+        )));
+    }
+    Ok((beat * DIVISIONS as f64).round() as u64)
+}
+
+/// The measures up to (and including) the one containing `end - 1`, at least one.
+pub(super) fn measures(tempo_map: &TempoMap, end: u64) -> Result<Vec<Measure>> {
+    let mut time_signatures = vec![];
+    let mut previous_start = 0;
+    for time_signature in &tempo_map.time_signatures {
+        let (numerator, denominator) = (time_signature.numerator, time_signature.denominator);
+        if numerator == 0 || !denominator.is_power_of_two() || denominator > 64 {
+            return Err(invalid_input(format!(
+                "Time signature {}/{} is not supported",
+                numerator, denominator
+            )));
+        }
+        // time signatures that are out of order start at the previous one
+        let start = to_divisions(time_signature.beat)?.max(previous_start);
+        time_signatures.push((start, numerator, denominator));
+        previous_start = start;
+    }
+
+    let mut measures: Vec<Measure> = vec![];
+    let (mut numerator, mut denominator) = (4, 4);
+    let mut new_time_signature = true;
+    let mut next = time_signatures.iter().peekable();
+    let mut start = 0;
+    while start < end || measures.is_empty() {
+        while let Some((_, n, d)) = next.next_if(|(ts_start, _, _)| *ts_start <= start) {
+            new_time_signature |= (*n, *d) != (numerator, denominator);
+            (numerator, denominator) = (*n, *d);
+        }
+        let mut measure_end = start + numerator as u64 * WHOLE / denominator as u64;
+        if let Some((ts_start, _, _)) = next.peek() {
+            // a bar that is cut short by the next time signature
+            measure_end = measure_end.min(*ts_start);
+        }
+        measures.push(Measure {
+            start,
+            end: measure_end,
+            numerator,
+            denominator,
+            new_time_signature,
+        });
+        new_time_signature = false;
+        start = measure_end;
+    }
+    Ok(measures)
+}
+
+/// Distributes the notes into voices of chords that do not overlap. A note joins the last
+/// chord of the first voice with the same onset and end, or starts a new chord in the first
+/// voice that is free at its onset.
+pub(super) fn voices(notes: &[Note]) -> Result<Vec<Vec<Chord>>> {
+    let mut times = vec![];
+    for note in notes {
+        let start = to_divisions(note.s)?;
+        // notes without duration get the shortest duration
+        let end = to_divisions(note.s + note.d)?.max(start.saturating_add(1));
+        times.push((start, end));
+    }
+    let mut order: Vec<usize> = (0..notes.len()).collect();
+    order.sort_by_key(|i| (times[*i], notes[*i].pitch));
+
+    let mut voices: Vec<Vec<Chord>> = vec![];
+    for i in order {
+        let (start, end) = times[i];
+        let same_chord = voices.iter_mut().find_map(|voice| {
+            voice
+                .last_mut()
+                .filter(|chord| (chord.start, chord.end) == (start, end))
+        });
+        if let Some(chord) = same_chord {
+            chord.notes.push(i);
+            continue;
+        }
+        let chord = Chord {
+            start,
+            end,
+            notes: vec![i],
+        };
+        let free_voice = voices
+            .iter_mut()
+            .find(|voice| voice.last().is_none_or(|last| last.end <= start));
+        match free_voice {
+            Some(voice) => voice.push(chord),
+            None => voices.push(vec![chord]),
+        }
+    }
+    Ok(voices)
+}
+
+fn all_note_values() -> Vec<NoteValue> {
+    let mut values = vec![];
+    for (note_type, duration) in NOTE_TYPES {
+        let note_value = NoteValue {
+            duration,
+            note_type: Some(note_type),
+            dotted: false,
+            triplet: false,
+        };
+        values.push(note_value);
+        if duration % 2 == 0 {
+            values.push(NoteValue {
+                duration: duration * 3 / 2,
+                dotted: true,
+                ..note_value
+            });
+        }
+        if duration % 3 == 0 {
+            values.push(NoteValue {
+                duration: duration * 2 / 3,
+                triplet: true,
+                ..note_value
+            });
+        }
+    }
+    values
+}
+
+/// Splits a duration into as few note values as possible, preferring values without
+/// triplets, longest first. A remainder that no note value fits gets no type.
+pub(super) fn note_values(duration: u64) -> Vec<NoteValue> {
+    const UNTYPED_COST: u64 = 1_000_000;
+    const NOTE_COST: u64 = 1_000;
+    const TRIPLET_COST: u64 = 1;
+    let values = all_note_values();
+    let len = duration as usize;
+    // costs[n]: the cost of splitting n, choices[n]: a value of that split
+    let mut costs = vec![0; len + 1];
+    let mut choices: Vec<Option<NoteValue>> = vec![None; len + 1];
+    for n in 1..=len {
+        costs[n] = costs[n - 1] + UNTYPED_COST;
+        for value in &values {
+            let Some(rest) = n.checked_sub(value.duration as usize) else {
+                continue;
+            };
+            let cost = costs[rest] + NOTE_COST + if value.triplet { TRIPLET_COST } else { 0 };
+            if cost < costs[n] {
+                costs[n] = cost;
+                choices[n] = Some(*value);
+            }
+        }
+    }
+    let mut result = vec![];
+    let mut n = len;
+    while n > 0 {
+        let value = choices[n].unwrap_or(NoteValue {
+            duration: 1,
+            note_type: None,
+            dotted: false,
+            triplet: false,
+        });
+        result.push(value);
+        n -= value.duration as usize;
+    }
+    result.sort_by_key(|value| std::cmp::Reverse(value.duration));
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use crate::types::TimeSignature;
+
+    use super::*;
+
+    fn describe(duration: u64) -> Vec<String> {
+        note_values(duration)
+            .iter()
+            .map(|value| {
+                let mut description = value.note_type.unwrap_or("untyped").to_string();
+                if value.dotted {
+                    description.push('.');
+                }
+                if value.triplet {
+                    description.push_str("/3");
+                }
+                description
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_note_values() {
+        assert_eq!(describe(WHOLE), ["whole"]);
+        assert_eq!(describe(72), ["quarter."]);
+        assert_eq!(describe(32), ["quarter/3"]);
+        assert_eq!(describe(40), ["eighth", "eighth/3"]);
+        assert_eq!(describe(WHOLE + 72), ["whole", "quarter."]);
+        assert_eq!(describe(5), ["64th", "64th/3"]);
+        assert_eq!(describe(1), ["untyped"]);
+        assert_eq!(describe(0), [] as [&str; 0]);
+        for duration in 0..1000 {
+            let total: u64 = note_values(duration).iter().map(|v| v.duration).sum();
+            assert_eq!(total, duration);
+        }
+    }
+
+    #[test]
+    fn test_measures() {
+        let tempo_map = TempoMap {
+            time_signatures: vec![
+                TimeSignature {
+                    beat: 0.0,
+                    numerator: 4,
+                    denominator: 4,
+                },
+                // cuts the second bar short
+                TimeSignature {
+                    beat: 6.0,
+                    numerator: 6,
+                    denominator: 8,
+                },
+            ],
+            ..TempoMap::new(120.0)
+        };
+        let bounds: Vec<(u64, u64, u8, u8, bool)> = measures(&tempo_map, 10 * DIVISIONS)
+            .unwrap()
+            .iter()
+            .map(|m| {
+                (
+                    m.start,
+                    m.end,
+                    m.numerator,
+                    m.denominator,
+                    m.new_time_signature,
+                )
+            })
+            .collect();
+        assert_eq!(
+            bounds,
+            [
+                (0, 192, 4, 4, true),
+                (192, 288, 4, 4, false),
+                (288, 432, 6, 8, true),
+                (432, 576, 6, 8, false),
+            ]
+        );
+        assert_eq!(measures(&TempoMap::new(120.0), 0).unwrap().len(), 1);
+
+        let mut invalid = tempo_map;
+        invalid.time_signatures[1].denominator = 3;
+        assert_eq!(
+            measures(&invalid, 1).unwrap_err().to_string(),
+            "Time signature 6/3 is not supported"
+        );
+    }
+
+    #[test]
+    fn test_voices() {
+        let note = |s: f64, d: f64, pitch: u8| Note {
+            s,
+            d,
+            pitch,
+            ..Note::default()
+        };
+        let notes = [
+            note(1.0, 1.0, 60),
+            note(0.0, 1.0, 64),
+            note(0.0, 2.0, 40),
+            note(0.0, 1.0, 55),
+            note(1.5, 0.0, 62),
+        ];
+        let chord = |start: u64, end: u64, notes: Vec<usize>| Chord { start, end, notes };
+        assert_eq!(
+            voices(&notes).unwrap(),
+            [
+                vec![chord(0, 48, vec![3, 1]), chord(48, 96, vec![0])],
+                vec![chord(0, 96, vec![2])],
+                vec![chord(72, 73, vec![4])],
+            ]
+        );
+        assert!(voices(&[note(-1.0, 1.0, 60)]).is_err());
+    }
+}
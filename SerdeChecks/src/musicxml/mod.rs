@@ -0,0 +1,970 @@
+//! Export of sequences as MusicXML 4.0 (partwise) for printing notation and tablature.
+//!
+//! The mapping from a `Sequence`:
+//! - A part per track. Tracks with a tuning (that are not percussion) get two staves with the
+//!   same notes: standard notation in a treble clef an octave below the sounding pitch, and a
+//!   TAB staff with a line per string of `Tuning::string_base_pitches`. Percussion tracks get
+//!   a percussion staff with unpitched notes, other tracks a treble staff.
+//! - The measures follow the time signatures of the `TempoMap`, where a bar that is cut short
+//!   by a time signature is a shorter measure. The tempos are metronome marks (with a sound
+//!   tempo) in the first part.
+//! - Times are rounded to `DIVISIONS` per quarter note. The notes of a track are distributed
+//!   into voices, and notes that cross a bar line or do not fit a single note value are split
+//!   into tied notes, see `layout`. The gaps between the notes of a voice are rests.
+//! - `string` and `fret` are technical notations, with strings counting from one. Dead notes
+//!   have x noteheads, and vibrato is a wavy line. If the first bend point is bent, it is a
+//!   pre-bend, and every change between two bend points is a bend (a release if it goes
+//!   down) with the positions of the points as first-beat and last-beat. A bend belongs to the
+//!   tied note that contains its start, i.e., points at or after the end of the note are
+//!   dropped.
+
+mod layout;
+mod xml;
+
+use std::io::{Error, ErrorKind, Result, Write};
+
+use crate::types::{Note, Sequence, TempoMap, Track};
+
+use layout::{Chord, Measure, NoteValue};
+use xml::XmlWriter;
+
+pub use layout::{DIVISIONS, MAX_BEATS};
+
+const DOCTYPE: &str = "<!DOCTYPE score-partwise PUBLIC \"-//Recordare//DTD MusicXML 4.0 \
+    Partwise//EN\" \"http://www.musicxml.org/dtds/partwise.dtd\">";
+
+/// The note names of the pitch classes, with sharps.
+const STEPS: [(&str, i8); 12] = [
+    ("C", 0),
+    ("C", 1),
+    ("D", 0),
+    ("D", 1),
+    ("E", 0),
+    ("F", 0),
+    ("F", 1),
+    ("G", 0),
+    ("G", 1),
+    ("A", 0),
+    ("A", 1),
+    ("B", 0),
+];
+
+fn invalid_input(message: String) -> Error {
+    Error::new(ErrorKind::InvalidInput, message)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum PartKind {
+    /// Standard notation and TAB.
+    Fretted,
+    Percussion,
+    Pitched,
+}
+
+impl PartKind {
+    fn of(track: &Track) -> PartKind {
+        if track.is_percussion {
+            PartKind::Percussion
+        } else if track.tuning.string_base_pitches.is_empty() {
+            PartKind::Pitched
+        } else {
+            PartKind::Fretted
+        }
+    }
+
+    fn num_staves(self) -> usize {
+        match self {
+            PartKind::Fretted => 2,
+            PartKind::Percussion | PartKind::Pitched => 1,
+        }
+    }
+}
+
+struct Part<'a> {
+    track: &'a Track,
+    kind: PartKind,
+    voices: Vec<Vec<Chord>>,
+}
+
+/// A note value of a note, i.e., the note or one of the tied notes it is split into.
+struct NotePart<'a> {
+    note: &'a Note,
+    chord: &'a Chord,
+    start: u64,
+    value: NoteValue,
+    is_chord: bool,
+    staff: usize,
+    voice: usize,
+}
+
+impl NotePart<'_> {
+    fn end(&self) -> u64 {
+        self.start + self.value.duration
+    }
+
+    fn tie_stop(&self) -> bool {
+        self.start > self.chord.start
+    }
+
+    fn tie_start(&self) -> bool {
+        self.end() < self.chord.end
+    }
+}
+
+struct Bend {
+    alter: f32,
+    pre_bend: bool,
+    /// Percent of the duration of the note part.
+    first_beat: f64,
+    last_beat: f64,
+}
+
+pub fn write_musicxml<W>(sequence: &Sequence, wr: W) -> Result<()>
+where
+    W: Write,
+{
+    if sequence.tracks.is_empty() {
+        return Err(invalid_input(
+            "A score needs at least one track".to_string(),
+        ));
+    }
+    let mut parts = vec![];
+    let mut end = 0;
+    for track in &sequence.tracks {
+        let voices = layout::voices(&track.notes)?;
+        let track_end = voices.iter().flatten().map(|chord| chord.end).max();
+        end = end.max(track_end.unwrap_or(0));
+        parts.push(Part {
+            track,
+            kind: PartKind::of(track),
+            voices,
+        });
+    }
+    let measures = layout::measures(&sequence.tempo_map, end)?;
+    let tempos = tempos(&sequence.tempo_map)?;
+
+    let mut xml = XmlWriter::new(wr);
+    xml.raw("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>")?;
+    xml.raw(DOCTYPE)?;
+    xml.open("score-partwise", &[("version", "4.0")])?;
+    xml.open("part-list", &[])?;
+    for (index, part) in parts.iter().enumerate() {
+        xml.open("score-part", &[("id", &part_id(index))])?;
+        xml.text("part-name", &part.track.name)?;
+        xml.close("score-part")?;
+    }
+    xml.close("part-list")?;
+    for (index, part) in parts.iter().enumerate() {
+        xml.open("part", &[("id", &part_id(index))])?;
+        for (number, measure) in measures.iter().enumerate() {
+            let tempos: &[(u64, f64)] = if index == 0 { &tempos } else { &[] };
+            write_measure(&mut xml, part, number, measure, tempos)?;
+        }
+        xml.close("part")?;
+    }
+    xml.close("score-partwise")
+}
+
+pub fn write_musicxml_to_string(sequence: &Sequence) -> Result<String> {
+    let mut buf = Vec::new();
+    write_musicxml(sequence, &mut buf)?;
+    Ok(String::from_utf8(buf).expect("the XML writer only writes strings"))
+}
+
+fn part_id(index: usize) -> String {
+    format!("P{}", index + 1)
+}
+
+/// The base tempo and the tempo changes in divisions.
+fn tempos(tempo_map: &TempoMap) -> Result<Vec<(u64, f64)>> {
+    let mut tempos = vec![(0, tempo_map.bpm_base)];
+    for change in &tempo_map.tempo_changes {
+        // changes that are out of order take effect at the previous one
+        let previous = tempos[tempos.len() - 1].0;
+        tempos.push((layout::to_divisions(change.beat)?.max(previous), change.bpm));
+    }
+    for (_, bpm) in &tempos {
+        if !(*bpm > 0.0 && bpm.is_finite()) {
+            return Err(invalid_input(format!("Tempo {} bpm is not positive", bpm)));
+        }
+    }
+    Ok(tempos)
+}
+
+/// The step, alter and octave of a MIDI pitch.
+fn spell(pitch: i32) -> Result<(&'static str, i8, i32)> {
+    // MusicXML octaves range from 0 to 9
+    if !(12..=127).contains(&pitch) {
+        return Err(invalid_input(format!(
+            "Pitch {} is out of the range of MusicXML",
+            pitch
+        )));
+    }
+    let (step, alter) = STEPS[pitch as usize % 12];
+    Ok((step, alter, pitch / 12 - 1))
+}
+
+fn write_measure<W: Write>(
+    xml: &mut XmlWriter<W>,
+    part: &Part,
+    number: usize,
+    measure: &Measure,
+    tempos: &[(u64, f64)],
+) -> Result<()> {
+    xml.open("measure", &[("number", &(number + 1).to_string())])?;
+    if measure.new_time_signature {
+        write_attributes(xml, part, number == 0, measure)?;
+    }
+    for (time, bpm) in tempos {
+        if (measure.start..measure.end).contains(time) {
+            write_tempo(xml, time - measure.start, *bpm)?;
+        }
+    }
+    let mut is_first_voice = true;
+    for staff in 1..=part.kind.num_staves() {
+        let num_voices = part.voices.len().max(1);
+        for index in 0..num_voices {
+            let voice = part.voices.get(index).map_or(&[][..], |voice| &voice[..]);
+            // the chords of a voice do not overlap, i.e., they are sorted by their end as well
+            let first = voice.partition_point(|chord| chord.end <= measure.start);
+            let num_chords = voice[first..]
+                .iter()
+                .take_while(|chord| chord.start < measure.end)
+                .count();
+            let chords = &voice[first..first + num_chords];
+            // every staff shows the first voice, if only as a measure rest
+            if chords.is_empty() && index > 0 {
+                continue;
+            }
+            if !is_first_voice {
+                xml.open("backup", &[])?;
+                xml.text("duration", measure.end - measure.start)?;
+                xml.close("backup")?;
+            }
+            let voice_number = (staff - 1) * num_voices + index + 1;
+            write_voice(xml, part, staff, voice_number, chords, measure)?;
+            is_first_voice = false;
+        }
+    }
+    xml.close("measure")
+}
+
+fn write_attributes<W: Write>(
+    xml: &mut XmlWriter<W>,
+    part: &Part,
+    is_first_measure: bool,
+    measure: &Measure,
+) -> Result<()> {
+    xml.open("attributes", &[])?;
+    if is_first_measure {
+        xml.text("divisions", DIVISIONS)?;
+        xml.open("key", &[])?;
+        xml.text("fifths", 0)?;
+        xml.close("key")?;
+    }
+    xml.open("time", &[])?;
+    xml.text("beats", measure.numerator)?;
+    xml.text("beat-type", measure.denominator)?;
+    xml.close("time")?;
+    if is_first_measure {
+        match part.kind {
+            PartKind::Fretted => {
+                xml.text("staves", 2)?;
+                xml.open("clef", &[("number", "1")])?;
+                xml.text("sign", "G")?;
+                xml.text("line", 2)?;
+                xml.text("clef-octave-change", -1)?;
+                xml.close("clef")?;
+                xml.open("clef", &[("number", "2")])?;
+                xml.text("sign", "TAB")?;
+                xml.text("line", 5)?;
+                xml.close("clef")?;
+                write_staff_details(xml, part.track)?;
+            }
+            PartKind::Percussion => {
+                xml.open("clef", &[])?;
+                xml.text("sign", "percussion")?;
+                xml.close("clef")?;
+            }
+            PartKind::Pitched => {
+                xml.open("clef", &[])?;
+                xml.text("sign", "G")?;
+                xml.text("line", 2)?;
+                xml.close("clef")?;
+            }
+        }
+    }
+    xml.close("attributes")
+}
+
+fn write_staff_details<W: Write>(xml: &mut XmlWriter<W>, track: &Track) -> Result<()> {
+    let base_pitches = &track.tuning.string_base_pitches;
+    xml.open("staff-details", &[("number", "2")])?;
+    xml.text("staff-lines", base_pitches.len())?;
+    for (string, base_pitch) in base_pitches.iter().enumerate() {
+        // line 1 is the bottom line, i.e., the lowest string
+        let line = (base_pitches.len() - string).to_string();
+        let (step, alter, octave) = spell(*base_pitch)?;
+        xml.open("staff-tuning", &[("line", &line)])?;
+        xml.text("tuning-step", step)?;
+        if alter != 0 {
+            xml.text("tuning-alter", alter)?;
+        }
+        xml.text("tuning-octave", octave)?;
+        xml.close("staff-tuning")?;
+    }
+    xml.close("staff-details")
+}
+
+fn write_tempo<W: Write>(xml: &mut XmlWriter<W>, offset: u64, bpm: f64) -> Result<()> {
+    xml.open("direction", &[("placement", "above")])?;
+    xml.open("direction-type", &[])?;
+    xml.open("metronome", &[])?;
+    xml.text("beat-unit", "quarter")?;
+    xml.text("per-minute", bpm)?;
+    xml.close("metronome")?;
+    xml.close("direction-type")?;
+    if offset > 0 {
+        xml.text("offset", offset)?;
+    }
+    xml.empty("sound", &[("tempo", &bpm.to_string())])?;
+    xml.close("direction")
+}
+
+fn write_voice<W: Write>(
+    xml: &mut XmlWriter<W>,
+    part: &Part,
+    staff: usize,
+    voice: usize,
+    chords: &[Chord],
+    measure: &Measure,
+) -> Result<()> {
+    let has_staff = part.kind.num_staves() > 1;
+    if chords.is_empty() {
+        xml.open("note", &[])?;
+        xml.empty("rest", &[("measure", "yes")])?;
+        xml.text("duration", measure.end - measure.start)?;
+        xml.text("voice", voice)?;
+        if has_staff {
+            xml.text("staff", staff)?;
+        }
+        return xml.close("note");
+    }
+    let mut time = measure.start;
+    for chord in chords {
+        let start = chord.start.max(measure.start);
+        let end = chord.end.min(measure.end);
+        write_rests(xml, has_staff, staff, voice, start - time)?;
+        time = start;
+        for value in layout::note_values(end - start) {
+            for (index, note) in chord.notes.iter().enumerate() {
+                let note_part = NotePart {
+                    note: &part.track.notes[*note],
+                    chord,
+                    start: time,
+                    value,
+                    is_chord: index > 0,
+                    staff,
+                    voice,
+                };
+                write_note(xml, part.kind, &note_part)?;
+            }
+            time += value.duration;
+        }
+    }
+    write_rests(xml, has_staff, staff, voice, measure.end - time)
+}
+
+fn write_rests<W: Write>(
+    xml: &mut XmlWriter<W>,
+    has_staff: bool,
+    staff: usize,
+    voice: usize,
+    duration: u64,
+) -> Result<()> {
+    for value in layout::note_values(duration) {
+        xml.open("note", &[])?;
+        xml.empty("rest", &[])?;
+        xml.text("duration", value.duration)?;
+        xml.text("voice", voice)?;
+        write_note_value(xml, &value)?;
+        if has_staff {
+            xml.text("staff", staff)?;
+        }
+        xml.close("note")?;
+    }
+    Ok(())
+}
+
+fn write_note_value<W: Write>(xml: &mut XmlWriter<W>, value: &NoteValue) -> Result<()> {
+    if let Some(note_type) = value.note_type {
+        xml.text("type", note_type)?;
+    }
+    if value.dotted {
+        xml.empty("dot", &[])?;
+    }
+    if value.triplet {
+        xml.open("time-modification", &[])?;
+        xml.text("actual-notes", 3)?;
+        xml.text("normal-notes", 2)?;
+        xml.close("time-modification")?;
+    }
+    Ok(())
+}
+
+fn write_note<W: Write>(xml: &mut XmlWriter<W>, kind: PartKind, part: &NotePart) -> Result<()> {
+    let note = part.note;
+    let (step, alter, octave) = spell(note.pitch as i32)?;
+    xml.open("note", &[])?;
+    if part.is_chord {
+        xml.empty("chord", &[])?;
+    }
+    if kind == PartKind::Percussion {
+        xml.open("unpitched", &[])?;
+        xml.text("display-step", step)?;
+        xml.text("display-octave", octave)?;
+        xml.close("unpitched")?;
+    } else {
+        xml.open("pitch", &[])?;
+        xml.text("step", step)?;
+        if alter != 0 {
+            xml.text("alter", alter)?;
+        }
+        xml.text("octave", octave)?;
+        xml.close("pitch")?;
+    }
+    xml.text("duration", part.value.duration)?;
+    if part.tie_stop() {
+        xml.empty("tie", &[("type", "stop")])?;
+    }
+    if part.tie_start() {
+        xml.empty("tie", &[("type", "start")])?;
+    }
+    xml.text("voice", part.voice)?;
+    write_note_value(xml, &part.value)?;
+    if note.effects.dead_note {
+        xml.text("notehead", "x")?;
+    }
+    if kind.num_staves() > 1 {
+        xml.text("staff", part.staff)?;
+    }
+
+    let vibrato_start = note.effects.vibrato && !part.tie_stop();
+    let vibrato_stop = note.effects.vibrato && !part.tie_start();
+    let bends = bends(part);
+    let has_technical = kind == PartKind::Fretted || !bends.is_empty();
+    if part.tie_stop() || part.tie_start() || vibrato_start || vibrato_stop || has_technical {
+        xml.open("notations", &[])?;
+        if part.tie_stop() {
+            xml.empty("tied", &[("type", "stop")])?;
+        }
+        if part.tie_start() {
+            xml.empty("tied", &[("type", "start")])?;
+        }
+        if vibrato_start || vibrato_stop {
+            xml.open("ornaments", &[])?;
+            if vibrato_start {
+                xml.empty("wavy-line", &[("type", "start")])?;
+            }
+            if vibrato_stop {
+                xml.empty("wavy-line", &[("type", "stop")])?;
+            }
+            xml.close("ornaments")?;
+        }
+        if has_technical {
+            xml.open("technical", &[])?;
+            if kind == PartKind::Fretted {
+                xml.text("string", note.string as u32 + 1)?;
+                xml.text("fret", note.fret)?;
+            }
+            for bend in bends {
+                write_bend(xml, &bend)?;
+            }
+            xml.close("technical")?;
+        }
+        xml.close("notations")?;
+    }
+    xml.close("note")
+}
+
+/// The bends that start in the note part.
+fn bends(part: &NotePart) -> Vec<Bend> {
+    let Some(bend_data) = &part.note.effects.bend_data else {
+        return vec![];
+    };
+    let points = &bend_data.points;
+    let (start, end) = (part.start as f64, part.end() as f64);
+    let time = |pos: f64| part.chord.start as f64 + pos * DIVISIONS as f64;
+    let contains = |pos: f64| (start..end).contains(&time(pos));
+    let percent = |pos: f64| ((time(pos) - start) / (end - start) * 100.0).clamp(0.0, 100.0);
+    let mut bends = vec![];
+    if let Some(first) = points.first() {
+        if first.bend != 0.0 && contains(first.pos) {
+            bends.push(Bend {
+                alter: first.bend,
+                pre_bend: true,
+                first_beat: percent(first.pos),
+                last_beat: percent(first.pos),
+            });
+        }
+    }
+    for pair in points.windows(2) {
+        let alter = pair[1].bend - pair[0].bend;
+        if alter != 0.0 && contains(pair[0].pos) {
+            bends.push(Bend {
+                alter,
+                pre_bend: false,
+                first_beat: percent(pair[0].pos),
+                last_beat: percent(pair[1].pos),
+            });
+        }
+    }
+    bends
+}
+
+fn write_bend<W: Write>(xml: &mut XmlWriter<W>, bend: &Bend) -> Result<()> {
+    let first_beat = bend.first_beat.round().to_string();
+    let last_beat = bend.last_beat.round().to_string();
+    xml.open(
+        "bend",
+        &[("first-beat", &first_beat), ("last-beat", &last_beat)],
+    )?;
+    xml.text("bend-alter", bend.alter)?;
+    if bend.pre_bend {
+        xml.empty("pre-bend", &[])?;
+    } else if bend.alter < 0.0 {
+        xml.empty("release", &[])?;
+    }
+    xml.close("bend")
+}
+
+#[cfg(test)]
+mod schema;
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use pretty_assertions::assert_eq;
+    use roxmltree::{Document, Node, ParsingOptions};
+
+    use crate::types::{BendData, BendPoint, NoteEffects, TempoChange, TimeSignature, Tuning};
+
+    use super::*;
+
+    fn parse(xml: &str) -> Document<'_> {
+        let options = ParsingOptions {
+            allow_dtd: true,
+            ..ParsingOptions::default()
+        };
+        Document::parse_with_options(xml, options).unwrap()
+    }
+
+    fn child<'a>(node: Node<'a, 'a>, name: &str) -> Option<Node<'a, 'a>> {
+        node.children().find(|child| child.has_tag_name(name))
+    }
+
+    fn child_text<'a>(node: Node<'a, 'a>, name: &str) -> Option<&'a str> {
+        child(node, name).and_then(|child| child.text())
+    }
+
+    fn note(s: f64, d: f64, pitch: u8, string: u8, fret: u8) -> Note {
+        Note {
+            s,
+            d,
+            pitch,
+            string,
+            fret,
+            ..Note::default()
+        }
+    }
+
+    fn sequence() -> Sequence {
+        let bent_note = Note {
+            effects: NoteEffects {
+                bend_data: Some(BendData {
+                    points: vec![
+                        BendPoint {
+                            pos: 0.0,
+                            bend: 0.0,
+                        },
+                        BendPoint {
+                            pos: 1.0,
+                            bend: 2.0,
+                        },
+                        BendPoint {
+                            pos: 2.5,
+                            bend: 0.0,
+                        },
+                    ],
+                }),
+                ..NoteEffects::default()
+            },
+            ..note(2.0, 3.0, 69, 1, 10)
+        };
+        let guitar = vec![
+            Note {
+                effects: NoteEffects {
+                    vibrato: true,
+                    ..NoteEffects::default()
+                },
+                ..note(0.0, 1.0, 64, 0, 0)
+            },
+            note(0.0, 1.0, 55, 2, 0),
+            note(0.0, 4.0, 40, 5, 0),
+            Note {
+                effects: NoteEffects {
+                    dead_note: true,
+                    ..NoteEffects::default()
+                },
+                ..note(1.0, 0.5, 59, 1, 0)
+            },
+            bent_note,
+            note(5.0, 1.0 / 3.0, 67, 0, 3),
+            note(5.0 + 1.0 / 3.0, 1.0 / 3.0, 69, 0, 5),
+            note(5.0 + 2.0 / 3.0, 1.0 / 3.0, 71, 0, 7),
+        ];
+        Sequence {
+            tempo_map: TempoMap {
+                bpm_base: 120.0,
+                tempo_changes: vec![TempoChange {
+                    beat: 5.5,
+                    bpm: 90.5,
+                }],
+                time_signatures: vec![TimeSignature {
+                    beat: 4.0,
+                    numerator: 3,
+                    denominator: 4,
+                }],
+            },
+            tracks: vec![
+                Track {
+                    name: "Guitar & Co".to_string(),
+                    is_percussion: false,
+                    tuning: Tuning {
+                        string_base_pitches: vec![64, 59, 55, 50, 45, 40],
+                    },
+                    notes: guitar,
+                },
+                Track {
+                    name: "Drums".to_string(),
+                    is_percussion: true,
+                    tuning: Tuning::default(),
+                    notes: (0..4).map(|i| note(i as f64, 1.0, 36, 0, 0)).collect(),
+                },
+                Track {
+                    name: "Lead".to_string(),
+                    is_percussion: false,
+                    tuning: Tuning::default(),
+                    notes: vec![note(0.0, 6.0, 73, 0, 0)],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_schema() {
+        let xml = write_musicxml_to_string(&sequence()).unwrap();
+        let document = parse(&xml);
+        let root = document.root_element();
+        assert_eq!(root.tag_name().name(), "score-partwise");
+        schema::validate(root);
+    }
+
+    #[test]
+    #[should_panic(expected = "unexpected pitch in note")]
+    fn test_schema_rejects_wrong_order() {
+        let xml = write_musicxml_to_string(&sequence()).unwrap();
+        let xml = xml.replacen("<pitch>", "<duration>1</duration>\n<pitch>", 1);
+        schema::validate(parse(&xml).root_element());
+    }
+
+    /// Every voice of every measure fills the measure, in all parts.
+    #[test]
+    fn test_measure_durations() {
+        let xml = write_musicxml_to_string(&sequence()).unwrap();
+        let document = parse(&xml);
+        let parts: Vec<Node> = document
+            .root_element()
+            .children()
+            .filter(|n| n.has_tag_name("part"))
+            .collect();
+        assert_eq!(parts.len(), 3);
+        for part in parts {
+            let measures: Vec<Node> = part
+                .children()
+                .filter(|n| n.has_tag_name("measure"))
+                .collect();
+            assert_eq!(measures.len(), 2);
+            for (measure, expected) in measures.iter().zip([4 * DIVISIONS, 3 * DIVISIONS]) {
+                let mut time = 0;
+                let mut voice_ends = HashMap::new();
+                for element in measure.children().filter(|n| n.is_element()) {
+                    let duration =
+                        || -> u64 { child_text(element, "duration").unwrap().parse().unwrap() };
+                    match element.tag_name().name() {
+                        "backup" => time -= duration(),
+                        "note" if child(element, "chord").is_none() => {
+                            time += duration();
+                            let voice = child_text(element, "voice").unwrap();
+                            voice_ends.insert(voice, time);
+                        }
+                        _ => {}
+                    }
+                }
+                assert!(!voice_ends.is_empty());
+                for (voice, end) in voice_ends {
+                    assert_eq!(end, expected, "voice {}", voice);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_guitar_part() {
+        let xml = write_musicxml_to_string(&sequence()).unwrap();
+        let document = parse(&xml);
+        let score_part = document
+            .descendants()
+            .find(|n| n.has_tag_name("score-part"))
+            .unwrap();
+        assert_eq!(child_text(score_part, "part-name"), Some("Guitar & Co"));
+        assert!(xml.contains("<part-name>Guitar &amp; Co</part-name>"));
+
+        let guitar = document
+            .descendants()
+            .find(|n| n.has_tag_name("part"))
+            .unwrap();
+        let staff_details = guitar
+            .descendants()
+            .find(|n| n.has_tag_name("staff-details"))
+            .unwrap();
+        assert_eq!(child_text(staff_details, "staff-lines"), Some("6"));
+        let tunings: Vec<(&str, &str, &str)> = staff_details
+            .children()
+            .filter(|n| n.has_tag_name("staff-tuning"))
+            .map(|n| {
+                (
+                    n.attribute("line").unwrap(),
+                    child_text(n, "tuning-step").unwrap(),
+                    child_text(n, "tuning-octave").unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            tunings,
+            [
+                ("6", "E", "4"),
+                ("5", "B", "3"),
+                ("4", "G", "3"),
+                ("3", "D", "3"),
+                ("2", "A", "2"),
+                ("1", "E", "2"),
+            ]
+        );
+
+        // the notes of the TAB staff
+        let tab_notes: Vec<Node> = guitar
+            .descendants()
+            .filter(|n| n.has_tag_name("note") && child_text(*n, "staff") == Some("2"))
+            .filter(|n| child(*n, "rest").is_none())
+            .collect();
+        let summary: Vec<String> = tab_notes
+            .iter()
+            .map(|n| {
+                let pitch = child(*n, "pitch").unwrap();
+                let technical = n
+                    .descendants()
+                    .find(|n| n.has_tag_name("technical"))
+                    .unwrap();
+                let mut summary = format!(
+                    "{}{}{} {}/{} {}",
+                    child_text(pitch, "step").unwrap(),
+                    if child(pitch, "alter").is_some() {
+                        "#"
+                    } else {
+                        ""
+                    },
+                    child_text(pitch, "octave").unwrap(),
+                    child_text(technical, "string").unwrap(),
+                    child_text(technical, "fret").unwrap(),
+                    child_text(*n, "type").unwrap(),
+                );
+                if child(*n, "dot").is_some() {
+                    summary.push('.');
+                }
+                if child(*n, "time-modification").is_some() {
+                    summary.push_str("/3");
+                }
+                for tie in n.children().filter(|n| n.has_tag_name("tie")) {
+                    summary.push_str(&format!(" tie-{}", tie.attribute("type").unwrap()));
+                }
+                summary
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                // measure 1, voice 3
+                "G3 3/0 quarter",
+                "E4 1/0 quarter",
+                "B3 2/0 eighth",
+                "A4 2/10 half tie-start",
+                // voice 4
+                "E2 6/0 whole",
+                // measure 2
+                "A4 2/10 quarter tie-stop",
+                "G4 1/3 eighth/3",
+                "A4 1/5 eighth/3",
+                "B4 1/7 eighth/3",
+            ]
+        );
+
+        let notations = |i: usize| {
+            tab_notes[i]
+                .descendants()
+                .filter(|n| n.is_element())
+                .map(|n| {
+                    let mut description = n.tag_name().name().to_string();
+                    for attribute in n.attributes() {
+                        description += &format!(" {}={}", attribute.name(), attribute.value());
+                    }
+                    if let Some(text) = n.text().filter(|text| !text.trim().is_empty()) {
+                        description += &format!(" {}", text);
+                    }
+                    description
+                })
+                .skip_while(|description| description != "notations")
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            notations(1),
+            [
+                "notations",
+                "ornaments",
+                "wavy-line type=start",
+                "wavy-line type=stop",
+                "technical",
+                "string 1",
+                "fret 0",
+            ]
+        );
+        assert_eq!(child_text(tab_notes[2], "notehead"), Some("x"));
+        assert_eq!(
+            notations(3),
+            [
+                "notations",
+                "tied type=start",
+                "technical",
+                "string 2",
+                "fret 10",
+                "bend first-beat=0 last-beat=50",
+                "bend-alter 2",
+                "bend first-beat=50 last-beat=100",
+                "bend-alter -2",
+                "release",
+            ]
+        );
+        assert_eq!(
+            notations(5),
+            [
+                "notations",
+                "tied type=stop",
+                "technical",
+                "string 2",
+                "fret 10",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_other_parts_and_tempo() {
+        let xml = write_musicxml_to_string(&sequence()).unwrap();
+        let document = parse(&xml);
+        let parts: Vec<Node> = document
+            .descendants()
+            .filter(|n| n.has_tag_name("part"))
+            .collect();
+
+        let tempos: Vec<(Option<&str>, &str)> = parts[0]
+            .descendants()
+            .filter(|n| n.has_tag_name("direction"))
+            .map(|n| {
+                let sound = child(n, "sound").unwrap();
+                (child_text(n, "offset"), sound.attribute("tempo").unwrap())
+            })
+            .collect();
+        assert_eq!(tempos, [(None, "120"), (Some("72"), "90.5")]);
+        assert!(!parts[1].descendants().any(|n| n.has_tag_name("direction")));
+
+        let drum_notes: Vec<Node> = parts[1]
+            .descendants()
+            .filter(|n| n.has_tag_name("unpitched"))
+            .collect();
+        assert_eq!(drum_notes.len(), 4);
+        assert_eq!(child_text(drum_notes[0], "display-step"), Some("C"));
+        assert_eq!(child_text(drum_notes[0], "display-octave"), Some("2"));
+        let measure_rests = parts[1]
+            .descendants()
+            .filter(|n| n.has_tag_name("rest") && n.attribute("measure") == Some("yes"))
+            .count();
+        assert_eq!(measure_rests, 1);
+
+        // C#5 tied over the bar line, followed by a quarter rest
+        let lead: Vec<String> = parts[2]
+            .descendants()
+            .filter(|n| n.has_tag_name("note"))
+            .map(|n| {
+                let pitch = match child(n, "pitch") {
+                    Some(pitch) => format!(
+                        "{}{}{}",
+                        child_text(pitch, "step").unwrap(),
+                        if child(pitch, "alter").is_some() {
+                            "#"
+                        } else {
+                            ""
+                        },
+                        child_text(pitch, "octave").unwrap()
+                    ),
+                    None => "rest".to_string(),
+                };
+                format!("{} {}", pitch, child_text(n, "type").unwrap())
+            })
+            .collect();
+        assert_eq!(lead, ["C#5 whole", "C#5 half", "rest quarter"]);
+        assert!(!parts[2].descendants().any(|n| n.has_tag_name("staff")));
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        let error =
+            |sequence: &Sequence| write_musicxml_to_string(sequence).unwrap_err().to_string();
+        assert_eq!(
+            error(&Sequence::default()),
+            "A score needs at least one track"
+        );
+        let mut sequence = sequence();
+        sequence.tracks[0].notes[0].s = -1.0;
+        assert_eq!(
+            error(&sequence),
+            "Time -1 is not a non-negative number of beats"
+        );
+        sequence.tracks[0].notes[0].s = 1e6;
+        assert_eq!(
+            error(&sequence),
+            "Time 1000000 exceeds the maximum of 100000 beats"
+        );
+        sequence.tracks[0].notes[0].s = 0.0;
+        sequence.tracks[0].notes[0].d = 1e12;
+        assert_eq!(
+            error(&sequence),
+            "Time 1000000000000 exceeds the maximum of 100000 beats"
+        );
+        sequence.tracks[0].notes[0].d = 1.0;
+        sequence.tracks[2].notes[0].pitch = 11;
+        assert_eq!(error(&sequence), "Pitch 11 is out of the range of MusicXML");
+        sequence.tracks[2].notes[0].pitch = 60;
+        sequence.tempo_map.tempo_changes[0].bpm = 0.0;
+        assert_eq!(error(&sequence), "Tempo 0 bpm is not positive");
+    }
+}
@@ -0,0 +1,325 @@
+//! A transcription of the parts of the MusicXML 4.0 XSD that the writer uses, for validating
+//! its output in tests without an XML schema library.
+
+use std::collections::HashMap;
+
+use roxmltree::Node;
+
+const UNBOUNDED: usize = usize::MAX;
+
+/// The names of the children, and their minimum and maximum occurrences.
+type ContentModels = HashMap<&'static str, Vec<(Vec<&'static str>, usize, usize)>>;
+
+/// The content models of the elements that are written, transcribed from the MusicXML
+/// 4.0 XSD: the children in the order of the schema with their minimum and maximum
+/// occurrences. Alternatives (`xs:choice`) share an entry. Elements that are missing
+/// have no element children.
+fn content_models() -> ContentModels {
+    let one = |name| (vec![name], 1, 1);
+    let optional = |name| (vec![name], 0, 1);
+    let any = |names: Vec<&'static str>| vec![(names, 0, UNBOUNDED)];
+    HashMap::from([
+        (
+            "score-partwise",
+            vec![
+                optional("work"),
+                optional("movement-number"),
+                optional("movement-title"),
+                optional("identification"),
+                optional("defaults"),
+                (vec!["credit"], 0, UNBOUNDED),
+                one("part-list"),
+                (vec!["part"], 1, UNBOUNDED),
+            ],
+        ),
+        (
+            "part-list",
+            vec![(vec!["part-group", "score-part"], 1, UNBOUNDED)],
+        ),
+        (
+            "score-part",
+            vec![
+                optional("identification"),
+                (vec!["part-link"], 0, UNBOUNDED),
+                one("part-name"),
+                optional("part-name-display"),
+                optional("part-abbreviation"),
+                optional("part-abbreviation-display"),
+                (vec!["group"], 0, UNBOUNDED),
+                (vec!["score-instrument"], 0, UNBOUNDED),
+                (vec!["player"], 0, UNBOUNDED),
+                (vec!["midi-device", "midi-instrument"], 0, UNBOUNDED),
+            ],
+        ),
+        ("part", vec![(vec!["measure"], 1, UNBOUNDED)]),
+        (
+            "measure",
+            any(vec![
+                "note",
+                "backup",
+                "forward",
+                "direction",
+                "attributes",
+                "harmony",
+                "figured-bass",
+                "print",
+                "sound",
+                "listening",
+                "barline",
+                "grouping",
+                "link",
+                "bookmark",
+            ]),
+        ),
+        (
+            "attributes",
+            vec![
+                optional("footnote"),
+                optional("level"),
+                optional("divisions"),
+                (vec!["key"], 0, UNBOUNDED),
+                (vec!["time"], 0, UNBOUNDED),
+                optional("staves"),
+                optional("part-symbol"),
+                optional("instruments"),
+                (vec!["clef"], 0, UNBOUNDED),
+                (vec!["staff-details"], 0, UNBOUNDED),
+                (vec!["transpose", "for-part"], 0, UNBOUNDED),
+                (vec!["directive"], 0, UNBOUNDED),
+                (vec!["measure-style"], 0, UNBOUNDED),
+            ],
+        ),
+        (
+            "key",
+            vec![optional("cancel"), one("fifths"), optional("mode")],
+        ),
+        ("time", vec![one("beats"), one("beat-type")]),
+        (
+            "clef",
+            vec![
+                one("sign"),
+                optional("line"),
+                optional("clef-octave-change"),
+            ],
+        ),
+        (
+            "staff-details",
+            vec![
+                optional("staff-type"),
+                optional("staff-lines"),
+                (vec!["line-detail"], 0, UNBOUNDED),
+                (vec!["staff-tuning"], 0, UNBOUNDED),
+                optional("capo"),
+                optional("staff-size"),
+            ],
+        ),
+        (
+            "staff-tuning",
+            vec![
+                one("tuning-step"),
+                optional("tuning-alter"),
+                one("tuning-octave"),
+            ],
+        ),
+        (
+            "direction",
+            vec![
+                (vec!["direction-type"], 1, UNBOUNDED),
+                optional("offset"),
+                optional("footnote"),
+                optional("level"),
+                optional("voice"),
+                optional("staff"),
+                optional("sound"),
+                optional("listening"),
+            ],
+        ),
+        (
+            "direction-type",
+            vec![(vec!["metronome", "words", "dynamics"], 1, UNBOUNDED)],
+        ),
+        (
+            "metronome",
+            vec![
+                one("beat-unit"),
+                (vec!["beat-unit-dot"], 0, UNBOUNDED),
+                one("per-minute"),
+            ],
+        ),
+        ("backup", vec![one("duration"), optional("footnote")]),
+        (
+            "note",
+            vec![
+                optional("chord"),
+                (vec!["pitch", "unpitched", "rest"], 1, 1),
+                one("duration"),
+                (vec!["tie"], 0, 2),
+                (vec!["instrument"], 0, UNBOUNDED),
+                optional("footnote"),
+                optional("level"),
+                optional("voice"),
+                optional("type"),
+                (vec!["dot"], 0, UNBOUNDED),
+                optional("accidental"),
+                optional("time-modification"),
+                optional("stem"),
+                optional("notehead"),
+                optional("notehead-text"),
+                optional("staff"),
+                (vec!["beam"], 0, 8),
+                (vec!["notations"], 0, UNBOUNDED),
+                (vec!["lyric"], 0, UNBOUNDED),
+                optional("play"),
+                optional("listen"),
+            ],
+        ),
+        ("pitch", vec![one("step"), optional("alter"), one("octave")]),
+        (
+            "unpitched",
+            vec![one("display-step"), one("display-octave")],
+        ),
+        (
+            "rest",
+            vec![optional("display-step"), optional("display-octave")],
+        ),
+        (
+            "time-modification",
+            vec![
+                one("actual-notes"),
+                one("normal-notes"),
+                optional("normal-type"),
+                (vec!["normal-dot"], 0, UNBOUNDED),
+            ],
+        ),
+        (
+            "notations",
+            vec![
+                optional("footnote"),
+                optional("level"),
+                (
+                    vec![
+                        "tied",
+                        "slur",
+                        "tuplet",
+                        "glissando",
+                        "slide",
+                        "ornaments",
+                        "technical",
+                        "articulations",
+                        "dynamics",
+                        "fermata",
+                        "arpeggiate",
+                        "non-arpeggiate",
+                        "accidental-mark",
+                        "other-notation",
+                    ],
+                    0,
+                    UNBOUNDED,
+                ),
+            ],
+        ),
+        (
+            "ornaments",
+            vec![
+                (
+                    vec![
+                        "trill-mark",
+                        "turn",
+                        "shake",
+                        "wavy-line",
+                        "mordent",
+                        "tremolo",
+                        "haydn",
+                        "other-ornament",
+                    ],
+                    0,
+                    UNBOUNDED,
+                ),
+                (vec!["accidental-mark"], 0, UNBOUNDED),
+            ],
+        ),
+        (
+            "technical",
+            any(vec![
+                "up-bow",
+                "down-bow",
+                "harmonic",
+                "open-string",
+                "string",
+                "fret",
+                "hammer-on",
+                "pull-off",
+                "bend",
+                "tap",
+                "other-technical",
+            ]),
+        ),
+        (
+            "bend",
+            vec![
+                one("bend-alter"),
+                (vec!["pre-bend", "release"], 0, 1),
+                optional("with-bar"),
+            ],
+        ),
+    ])
+}
+
+/// The attributes that the XSD requires.
+const REQUIRED_ATTRIBUTES: [(&str, &str); 8] = [
+    ("score-part", "id"),
+    ("part", "id"),
+    ("measure", "number"),
+    ("tie", "type"),
+    ("tied", "type"),
+    ("wavy-line", "type"),
+    ("staff-tuning", "line"),
+    ("score-partwise", "version"),
+];
+
+/// Checks the children of every element against its content model.
+fn validate_with(node: Node, models: &ContentModels) {
+    let name = node.tag_name().name();
+    for (element, attribute) in REQUIRED_ATTRIBUTES {
+        if name == element {
+            assert!(
+                node.has_attribute(attribute),
+                "{} needs {}",
+                name,
+                attribute
+            );
+        }
+    }
+    let children: Vec<Node> = node.children().filter(|n| n.is_element()).collect();
+    let Some(model) = models.get(name) else {
+        assert_eq!(children.len(), 0, "{} has no element children", name);
+        return;
+    };
+    let mut counts = vec![0; model.len()];
+    let mut position = 0;
+    for child in &children {
+        let child_name = child.tag_name().name();
+        let offset = model[position..]
+            .iter()
+            .position(|(names, _, _)| names.contains(&child_name))
+            .unwrap_or_else(|| panic!("unexpected {} in {}", child_name, name));
+        position += offset;
+        counts[position] += 1;
+        assert!(
+            counts[position] <= model[position].2,
+            "too many {} in {}",
+            child_name,
+            name
+        );
+        validate_with(*child, models);
+    }
+    for ((names, min, _), count) in model.iter().zip(counts) {
+        assert!(count >= *min, "{} needs {:?}", name, names);
+    }
+}
+
+/// Checks the element and its descendants against the content models and required
+/// attributes of the XSD.
+pub(super) fn validate(node: Node) {
+    validate_with(node, &content_models());
+}
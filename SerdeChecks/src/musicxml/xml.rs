@@ -0,0 +1,63 @@
+use std::io::{Result, Write};
+
+/// Writes indented XML elements. The caller is responsible for matching `open` and `close`.
+pub(super) struct XmlWriter<W: Write> {
+    wr: W,
+    depth: usize,
+}
+
+impl<W: Write> XmlWriter<W> {
+    pub fn new(wr: W) -> Self {
+        XmlWriter { wr, depth: 0 }
+    }
+
+    pub fn raw(&mut self, line: &str) -> Result<()> {
+        writeln!(self.wr, "{}", line)
+    }
+
+    fn start_tag(&mut self, name: &str, attributes: &[(&str, &str)]) -> Result<()> {
+        write!(self.wr, "{:1$}<{2}", "", 2 * self.depth, name)?;
+        for (key, value) in attributes {
+            write!(self.wr, " {}=\"{}\"", key, escape(value))?;
+        }
+        Ok(())
+    }
+
+    pub fn open(&mut self, name: &str, attributes: &[(&str, &str)]) -> Result<()> {
+        self.start_tag(name, attributes)?;
+        writeln!(self.wr, ">")?;
+        self.depth += 1;
+        Ok(())
+    }
+
+    pub fn close(&mut self, name: &str) -> Result<()> {
+        self.depth -= 1;
+        writeln!(self.wr, "{:1$}</{2}>", "", 2 * self.depth, name)
+    }
+
+    pub fn empty(&mut self, name: &str, attributes: &[(&str, &str)]) -> Result<()> {
+        self.start_tag(name, attributes)?;
+        writeln!(self.wr, "/>")
+    }
+
+    /// An element that only contains `text`.
+    pub fn text(&mut self, name: &str, text: impl ToString) -> Result<()> {
+        self.start_tag(name, &[])?;
+        writeln!(self.wr, ">{}</{}>", escape(&text.to_string()), name)
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -28,75 +28,169 @@ impl Default for Props {
 
 #[cfg(test)]
 mod test {
+    use pretty_assertions::assert_eq;
     use serde::de::DeserializeOwned;
 
+    use crate::benchmark::BoxError;
+
     use super::*;
 
-    fn roundtrip_serde_json<T: Clone + Serialize + DeserializeOwned>(value: T) -> T {
-        let serialized = serde_json::to_vec(&value).unwrap();
-        serde_json::from_slice(&serialized).unwrap()
+    trait Format {
+        const NAME: &'static str;
+        fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, BoxError>;
+        fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T, BoxError>;
     }
 
-    fn roundtrip_rmp_serde<T: Clone + Serialize + DeserializeOwned>(value: T) -> T {
-        // fails with compact/unnamed representation:
-        // let serialized = rmp_serde::encode::to_vec(&value).unwrap();
-        let serialized = rmp_serde::encode::to_vec_named(&value).unwrap();
-        rmp_serde::decode::from_slice(&serialized).unwrap()
+    struct Json;
+    struct MsgpackNamed;
+    struct MsgpackCompact;
+    struct Cbor;
+    struct Bare;
+    struct Bincode;
+
+    impl Format for Json {
+        const NAME: &'static str = "serde_json";
+        fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, BoxError> {
+            Ok(serde_json::to_vec(value)?)
+        }
+        fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T, BoxError> {
+            Ok(serde_json::from_slice(data)?)
+        }
     }
 
-    fn roundtrip_serde_bare<T: Clone + Serialize + DeserializeOwned>(value: T) -> T {
-        let serialized = serde_bare::to_vec(&value).unwrap();
-        serde_bare::from_slice(&serialized).unwrap()
+    impl Format for MsgpackNamed {
+        const NAME: &'static str = "rmp_serde (named)";
+        fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, BoxError> {
+            Ok(rmp_serde::encode::to_vec_named(value)?)
+        }
+        fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T, BoxError> {
+            Ok(rmp_serde::decode::from_slice(data)?)
+        }
     }
 
-    fn roundtrip_bincode<T: Clone + Serialize + DeserializeOwned>(value: T) -> T {
-        let serialized = bincode::serialize(&value).unwrap();
-        bincode::deserialize(&serialized).unwrap()
+    impl Format for MsgpackCompact {
+        const NAME: &'static str = "rmp_serde (compact)";
+        fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, BoxError> {
+            Ok(rmp_serde::encode::to_vec(value)?)
+        }
+        fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T, BoxError> {
+            Ok(rmp_serde::decode::from_slice(data)?)
+        }
     }
 
-    #[test]
-    fn test_serde_json() {
-        let data_orig = Props {
-            has_foo: false,
-            has_bar: true,
-            has_baz: false,
-        };
-        let data_reconstructed = roundtrip_serde_json(data_orig.clone());
-        assert_eq!(data_reconstructed, data_orig);
+    impl Format for Cbor {
+        const NAME: &'static str = "ciborium";
+        fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, BoxError> {
+            let mut data = vec![];
+            ciborium::ser::into_writer(value, &mut data)?;
+            Ok(data)
+        }
+        fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T, BoxError> {
+            Ok(ciborium::de::from_reader(data)?)
+        }
     }
 
-    #[test]
-    fn test_rmp_serde() {
-        let data_orig = Props {
-            has_foo: false,
-            has_bar: true,
-            has_baz: false,
-        };
-        let data_reconstructed = roundtrip_rmp_serde(data_orig.clone());
-        assert_eq!(data_reconstructed, data_orig);
+    impl Format for Bare {
+        const NAME: &'static str = "serde_bare";
+        fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, BoxError> {
+            Ok(serde_bare::to_vec(value)?)
+        }
+        fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T, BoxError> {
+            Ok(serde_bare::from_slice(data)?)
+        }
     }
 
-    #[test]
-    #[ignore]
-    fn test_serde_bare() {
-        let data_orig = Props {
+    impl Format for Bincode {
+        const NAME: &'static str = "bincode";
+        fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, BoxError> {
+            Ok(bincode::serialize(value)?)
+        }
+        fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T, BoxError> {
+            Ok(bincode::deserialize(data)?)
+        }
+    }
+
+    /// The old version of `PropsWithDefault`, without `has_bar`.
+    #[derive(Serialize)]
+    struct PropsV1 {
+        has_foo: bool,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct PropsWithDefault {
+        has_foo: bool,
+        #[serde(default)]
+        has_bar: bool,
+    }
+
+    #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+    struct Flattened {
+        id: u32,
+        #[serde(flatten)]
+        props: Inner,
+    }
+
+    #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+    struct Inner {
+        has_foo: bool,
+    }
+
+    fn roundtrips<F: Format, T: Serialize + DeserializeOwned + PartialEq>(value: &T) -> bool {
+        F::encode(value)
+            .and_then(|data| F::decode::<T>(&data))
+            .is_ok_and(|decoded| decoded == *value)
+    }
+
+    /// Whether the format supports the serde features:
+    /// - `skip_serializing_if` (with `default`): fields that are skipped when writing are
+    ///   taken from the default when reading, like in `NoteEffects`.
+    /// - `default`: data that was written without a field, e.g., by an older version of the
+    ///   struct, is read with the default of the field.
+    /// - `flatten`: the fields of a nested struct are written into the outer struct.
+    fn supported_features<F: Format>() -> (&'static str, [bool; 3]) {
+        let skip_serializing_if = roundtrips::<F, _>(&Props {
             has_foo: false,
             has_bar: true,
             has_baz: false,
-        };
-        let data_reconstructed = roundtrip_serde_bare(data_orig.clone());
-        assert_eq!(data_reconstructed, data_orig);
+        });
+        let default = F::encode(&PropsV1 { has_foo: true })
+            .and_then(|data| F::decode::<PropsWithDefault>(&data))
+            .is_ok_and(|decoded| {
+                decoded
+                    == PropsWithDefault {
+                        has_foo: true,
+                        has_bar: false,
+                    }
+            });
+        let flatten = roundtrips::<F, _>(&Flattened {
+            id: 7,
+            props: Inner { has_foo: true },
+        });
+        (F::NAME, [skip_serializing_if, default, flatten])
     }
 
+    /// Self-describing formats (with field names) support all features. Formats that write
+    /// structs as sequences of values cannot tell which fields are missing, except at the
+    /// end, and the binary ones without lengths cannot write the maps of `flatten`.
     #[test]
-    #[ignore]
-    fn test_bincode() {
-        let data_orig = Props {
-            has_foo: false,
-            has_bar: true,
-            has_baz: false,
-        };
-        let data_reconstructed = roundtrip_bincode(data_orig.clone());
-        assert_eq!(data_reconstructed, data_orig);
+    fn test_compatibility_matrix() {
+        let matrix = [
+            supported_features::<Json>(),
+            supported_features::<MsgpackNamed>(),
+            supported_features::<MsgpackCompact>(),
+            supported_features::<Cbor>(),
+            supported_features::<Bare>(),
+            supported_features::<Bincode>(),
+        ];
+        //   skip_serializing_if, default, flatten
+        let expected = [
+            ("serde_json", [true, true, true]),
+            ("rmp_serde (named)", [true, true, true]),
+            ("rmp_serde (compact)", [false, true, true]),
+            ("ciborium", [true, true, true]),
+            ("serde_bare", [false, false, false]),
+            ("bincode", [false, false, false]),
+        ];
+        assert_eq!(matrix, expected);
     }
 }
@@ -0,0 +1,177 @@
+//! Generators for random sequences, so that tests and benchmarks do not depend on private
+//! data. The times and bends are on the grid of `TIME_QUANTIZATION` and
+//! `PITCH_QUANTIZATION`, i.e., they survive a round trip through the custom file format
+//! with these quantizations.
+//!
+//! With the `arbitrary` feature, the `types` also implement `arbitrary::Arbitrary` for
+//! structure-aware fuzzing. The values of these are deliberately off the grid and only
+//! bounded so that they fit into the quantized representation.
+
+use rand::Rng;
+
+use crate::types::{
+    BendData, BendPoint, Note, NoteEffects, Sequence, TempoChange, TempoMap, TimeSignature, Track,
+    Tuning,
+};
+
+pub const TIME_QUANTIZATION: i32 = 960;
+pub const PITCH_QUANTIZATION: i32 = 256;
+
+pub fn gen_random_string<R: Rng>(rng: &mut R) -> String {
+    let len = rng.gen_range(0..=10);
+    rng.sample_iter::<char, _>(rand::distributions::Standard)
+        .take(len)
+        .collect()
+}
+
+pub fn gen_sequence<R: Rng>(rng: &mut R) -> Sequence {
+    Sequence {
+        tempo_map: gen_tempo_map(rng),
+        tracks: (0..rng.gen_range(0..=10)).map(|_| gen_track(rng)).collect(),
+    }
+}
+
+/// Like `gen_sequence`, but with tempo changes and time signatures, which require format v3
+/// or newer.
+pub fn gen_sequence_with_tempo_events<R: Rng>(rng: &mut R) -> Sequence {
+    Sequence {
+        tempo_map: gen_tempo_map_with_events(rng),
+        ..gen_sequence(rng)
+    }
+}
+
+pub fn gen_tempo_map<R: Rng>(rng: &mut R) -> TempoMap {
+    TempoMap::new(rng.gen_range(20.0..=300.0))
+}
+
+/// Tempo changes and time signatures require format v3 or newer.
+pub fn gen_tempo_map_with_events<R: Rng>(rng: &mut R) -> TempoMap {
+    TempoMap {
+        tempo_changes: (0..rng.gen_range(0..=10))
+            .map(|_| TempoChange {
+                beat: gen_time(rng),
+                bpm: rng.gen_range(20.0..=300.0),
+            })
+            .collect(),
+        time_signatures: (0..rng.gen_range(0..=10))
+            .map(|_| TimeSignature {
+                beat: gen_time(rng),
+                numerator: rng.gen_range(1..=16),
+                denominator: 1 << rng.gen_range(0..=4),
+            })
+            .collect(),
+        ..gen_tempo_map(rng)
+    }
+}
+
+fn gen_time<R: Rng>(rng: &mut R) -> f64 {
+    rng.gen::<u16>() as f64
+        + (rng.gen_range(0..TIME_QUANTIZATION) as f64 / TIME_QUANTIZATION as f64)
+}
+
+pub fn gen_track<R: Rng>(rng: &mut R) -> Track {
+    Track {
+        name: gen_random_string(rng),
+        is_percussion: rng.gen_bool(0.5),
+        tuning: gen_tuning(rng),
+        notes: (0..rng.gen_range(0..=10)).map(|_| gen_note(rng)).collect(),
+    }
+}
+
+pub fn gen_tuning<R: Rng>(rng: &mut R) -> Tuning {
+    Tuning {
+        string_base_pitches: (0..rng.gen_range(0..=10))
+            .map(|_| rng.gen::<i32>())
+            .collect(),
+    }
+}
+
+pub fn gen_note<R: Rng>(rng: &mut R) -> Note {
+    Note {
+        s: gen_time(rng),
+        d: rng.gen_range(0..TIME_QUANTIZATION * 10) as f64 / TIME_QUANTIZATION as f64,
+        pitch: rng.gen::<u8>(),
+        string: rng.gen::<u8>(),
+        fret: rng.gen::<u8>(),
+        effects: gen_note_effects(rng),
+    }
+}
+
+pub fn gen_note_effects<R: Rng>(rng: &mut R) -> NoteEffects {
+    NoteEffects {
+        dead_note: rng.gen_bool(0.5),
+        vibrato: rng.gen_bool(0.5),
+        bend_data: if rng.gen_bool(0.5) {
+            Some(gen_bend_data(rng))
+        } else {
+            None
+        },
+    }
+}
+
+pub fn gen_bend_data<R: Rng>(rng: &mut R) -> BendData {
+    BendData {
+        points: (0..rng.gen_range(0..=10))
+            .map(|_| gen_bend_point(rng))
+            .collect(),
+    }
+}
+
+pub fn gen_bend_point<R: Rng>(rng: &mut R) -> BendPoint {
+    BendPoint {
+        pos: rng.gen_range(0..TIME_QUANTIZATION * 10) as f64 / TIME_QUANTIZATION as f64,
+        bend: rng.gen_range(-PITCH_QUANTIZATION * 5..PITCH_QUANTIZATION * 5) as f32
+            / PITCH_QUANTIZATION as f32,
+    }
+}
+
+/// A sequence of `num_tracks` tracks with `num_notes` notes each. In contrast to
+/// `gen_sequence` the notes are sorted by onset, like in real data.
+pub fn gen_sized_sequence<R: Rng>(rng: &mut R, num_tracks: usize, num_notes: usize) -> Sequence {
+    Sequence {
+        tempo_map: gen_tempo_map(rng),
+        tracks: (0..num_tracks)
+            .map(|_| {
+                let mut notes: Vec<_> = (0..num_notes).map(|_| gen_note(rng)).collect();
+                notes.sort_by(|a, b| a.s.total_cmp(&b.s));
+                Track {
+                    notes,
+                    ..gen_track(rng)
+                }
+            })
+            .collect(),
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Arbitrary
+// ----------------------------------------------------------------------------
+
+/// Upper bound of the arbitrary times (onsets, durations, bend positions) in beats.
+pub const MAX_ARBITRARY_TIME: f64 = 1e6;
+/// Upper bound of the magnitude of arbitrary bends in semitones.
+pub const MAX_ARBITRARY_BEND: f32 = 24.0;
+
+/// A value in `[0, 1]`.
+#[cfg(feature = "arbitrary")]
+fn arbitrary_fraction(u: &mut arbitrary::Unstructured) -> arbitrary::Result<f64> {
+    Ok(u.arbitrary::<u32>()? as f64 / u32::MAX as f64)
+}
+
+#[cfg(feature = "arbitrary")]
+pub fn arbitrary_time(u: &mut arbitrary::Unstructured) -> arbitrary::Result<f64> {
+    Ok(arbitrary_fraction(u)? * MAX_ARBITRARY_TIME)
+}
+
+#[cfg(feature = "arbitrary")]
+pub fn arbitrary_bend(u: &mut arbitrary::Unstructured) -> arbitrary::Result<f32> {
+    Ok((arbitrary_fraction(u)? * 2.0 - 1.0) as f32 * MAX_ARBITRARY_BEND)
+}
+
+/// The tempo is stored as is, so any finite value works. Non-finite values are not supported
+/// by JSON.
+#[cfg(feature = "arbitrary")]
+pub fn arbitrary_bpm(u: &mut arbitrary::Unstructured) -> arbitrary::Result<f64> {
+    let bpm: f64 = u.arbitrary()?;
+    Ok(if bpm.is_finite() { bpm } else { 0.0 })
+}
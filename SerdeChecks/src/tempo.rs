@@ -0,0 +1,453 @@
+//! Conversions between beats, seconds and bars based on a `TempoMap`.
+//!
+//! The tempo is piecewise constant: `bpm_base` up to the first tempo change, and the `bpm`
+//! of a change from its `beat` on. Likewise, the bars have the length of the last time
+//! signature, 4/4 before the first one. Times before beat 0 are extrapolated with the
+//! initial tempo and meter, i.e., changes are expected at beats >= 0. Changes must be
+//! sorted by beat.
+//!
+//! `TempoMap::timeline` validates the tempo map once and precomputes the segments of
+//! constant tempo and meter for the conversions.
+
+use std::fmt;
+
+use crate::types::{TempoMap, TimeSignature};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TempoMapError {
+    /// A tempo that is not a positive number, `change` is `None` for `bpm_base`.
+    InvalidTempo { change: Option<usize>, bpm: f64 },
+    /// A beat of a tempo change or time signature that is not finite.
+    InvalidBeat {
+        what: &'static str,
+        index: usize,
+        beat: f64,
+    },
+    /// A tempo change or time signature before the previous one.
+    Unsorted {
+        what: &'static str,
+        index: usize,
+        beat: f64,
+        previous: f64,
+    },
+    /// A time signature with a zero numerator or denominator.
+    InvalidTimeSignature {
+        index: usize,
+        numerator: u8,
+        denominator: u8,
+    },
+}
+
+impl fmt::Display for TempoMapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TempoMapError::InvalidTempo { change: None, bpm } => {
+                write!(f, "Base tempo {} bpm is not positive", bpm)
+            }
+            TempoMapError::InvalidTempo {
+                change: Some(index),
+                bpm,
+            } => write!(f, "Tempo change {}: {} bpm is not positive", index, bpm),
+            TempoMapError::InvalidBeat { what, index, beat } => {
+                write!(f, "{} {}: Beat {} is not finite", what, index, beat)
+            }
+            TempoMapError::Unsorted {
+                what,
+                index,
+                beat,
+                previous,
+            } => write!(
+                f,
+                "{} {}: Beat {} is before the previous one at beat {}",
+                what, index, beat, previous
+            ),
+            TempoMapError::InvalidTimeSignature {
+                index,
+                numerator,
+                denominator,
+            } => write!(
+                f,
+                "Time signature {}: {}/{} is not a valid meter",
+                index, numerator, denominator
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TempoMapError {}
+
+/// A position in the bars of a `TempoMap`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BarBeat {
+    /// Counted from zero, negative before beat 0.
+    pub bar: i64,
+    /// Beats since the start of the bar.
+    pub beat: f64,
+}
+
+/// A range of constant tempo, starting at `beat` which is `seconds` into the sequence.
+#[derive(Clone, Copy, Debug)]
+struct TempoSegment {
+    beat: f64,
+    seconds: f64,
+    bpm: f64,
+}
+
+/// A range of constant meter, starting with `bar` at `beat`.
+#[derive(Clone, Copy, Debug)]
+struct MeterSegment {
+    beat: f64,
+    bar: i64,
+    beats_per_bar: f64,
+}
+
+impl TimeSignature {
+    /// The length of a bar in beats (quarter notes). Numerator and denominator must not be
+    /// zero.
+    pub fn beats_per_bar(&self) -> f64 {
+        4.0 * self.numerator as f64 / self.denominator as f64
+    }
+}
+
+impl TempoMap {
+    /// A constant tempo in 4/4.
+    pub fn new(bpm_base: f64) -> Self {
+        TempoMap {
+            bpm_base,
+            ..TempoMap::default()
+        }
+    }
+
+    /// Validates the tempo map and precomputes the conversions.
+    pub fn timeline(&self) -> Result<Timeline, TempoMapError> {
+        let check_bpm = |change, bpm: f64| {
+            if bpm > 0.0 && bpm.is_finite() {
+                Ok(())
+            } else {
+                Err(TempoMapError::InvalidTempo { change, bpm })
+            }
+        };
+        let check_beat = |what, index, beat: f64, previous: Option<f64>| {
+            if !beat.is_finite() {
+                return Err(TempoMapError::InvalidBeat { what, index, beat });
+            }
+            match previous {
+                Some(previous) if beat < previous => Err(TempoMapError::Unsorted {
+                    what,
+                    index,
+                    beat,
+                    previous,
+                }),
+                _ => Ok(()),
+            }
+        };
+        check_bpm(None, self.bpm_base)?;
+        let mut previous = None;
+        for (index, change) in self.tempo_changes.iter().enumerate() {
+            check_beat("Tempo change", index, change.beat, previous)?;
+            previous = Some(change.beat);
+            check_bpm(Some(index), change.bpm)?;
+        }
+        let mut previous = None;
+        for (index, time_signature) in self.time_signatures.iter().enumerate() {
+            check_beat("Time signature", index, time_signature.beat, previous)?;
+            previous = Some(time_signature.beat);
+            if time_signature.numerator == 0 || time_signature.denominator == 0 {
+                return Err(TempoMapError::InvalidTimeSignature {
+                    index,
+                    numerator: time_signature.numerator,
+                    denominator: time_signature.denominator,
+                });
+            }
+        }
+        Ok(Timeline {
+            tempo_segments: self.tempo_segments(),
+            meter_segments: self.meter_segments(),
+        })
+    }
+
+    fn tempo_segments(&self) -> Vec<TempoSegment> {
+        let mut segments = vec![TempoSegment {
+            beat: 0.0,
+            seconds: 0.0,
+            bpm: self.bpm_base,
+        }];
+        for change in &self.tempo_changes {
+            let last = segments[segments.len() - 1];
+            let beat = change.beat.max(last.beat);
+            segments.push(TempoSegment {
+                beat,
+                seconds: last.seconds + (beat - last.beat) * 60.0 / last.bpm,
+                bpm: change.bpm,
+            });
+        }
+        segments
+    }
+
+    fn meter_segments(&self) -> Vec<MeterSegment> {
+        let mut segments = vec![MeterSegment {
+            beat: 0.0,
+            bar: 0,
+            beats_per_bar: 4.0,
+        }];
+        for time_signature in &self.time_signatures {
+            let last = segments[segments.len() - 1];
+            let beat = time_signature.beat.max(last.beat);
+            // A bar that is cut short by the time signature still counts.
+            let num_bars = ((beat - last.beat) / last.beats_per_bar).ceil() as i64;
+            segments.push(MeterSegment {
+                beat,
+                bar: last.bar + num_bars,
+                beats_per_bar: time_signature.beats_per_bar(),
+            });
+        }
+        segments
+    }
+}
+
+/// The conversions of a validated `TempoMap`, see `TempoMap::timeline`. Each conversion is
+/// a binary search over the segments.
+#[derive(Clone, Debug)]
+pub struct Timeline {
+    tempo_segments: Vec<TempoSegment>,
+    meter_segments: Vec<MeterSegment>,
+}
+
+/// The last segment that starts at or before `key`, or the first one before all segments.
+fn segment_at<T>(segments: &[T], key: impl Fn(&T) -> f64, at: f64) -> &T {
+    let index = segments.partition_point(|segment| key(segment) <= at);
+    &segments[index.saturating_sub(1)]
+}
+
+impl Timeline {
+    pub fn beats_to_seconds(&self, beat: f64) -> f64 {
+        let segment = segment_at(&self.tempo_segments, |segment| segment.beat, beat);
+        segment.seconds + (beat - segment.beat) * 60.0 / segment.bpm
+    }
+
+    pub fn seconds_to_beats(&self, seconds: f64) -> f64 {
+        let segment = segment_at(&self.tempo_segments, |segment| segment.seconds, seconds);
+        segment.beat + (seconds - segment.seconds) * segment.bpm / 60.0
+    }
+
+    pub fn bar_beat_of(&self, beat: f64) -> BarBeat {
+        let segment = segment_at(&self.meter_segments, |segment| segment.beat, beat);
+        let num_bars = ((beat - segment.beat) / segment.beats_per_bar).floor();
+        BarBeat {
+            bar: segment.bar + num_bars as i64,
+            beat: beat - segment.beat - num_bars * segment.beats_per_bar,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use crate::types::TempoChange;
+
+    use super::*;
+
+    fn tempo_change(beat: f64, bpm: f64) -> TempoChange {
+        TempoChange { beat, bpm }
+    }
+
+    fn time_signature(beat: f64, numerator: u8, denominator: u8) -> TimeSignature {
+        TimeSignature {
+            beat,
+            numerator,
+            denominator,
+        }
+    }
+
+    fn bar_beat(bar: i64, beat: f64) -> BarBeat {
+        BarBeat { bar, beat }
+    }
+
+    #[test]
+    fn test_constant_tempo() {
+        let tempo_map = TempoMap::new(120.0).timeline().unwrap();
+        assert_eq!(tempo_map.beats_to_seconds(0.0), 0.0);
+        assert_eq!(tempo_map.beats_to_seconds(3.0), 1.5);
+        assert_eq!(tempo_map.beats_to_seconds(-1.0), -0.5);
+        assert_eq!(tempo_map.seconds_to_beats(1.5), 3.0);
+        assert_eq!(tempo_map.bar_beat_of(9.5), bar_beat(2, 1.5));
+        assert_eq!(tempo_map.bar_beat_of(-1.0), bar_beat(-1, 3.0));
+    }
+
+    #[test]
+    fn test_piecewise_constant_tempo() {
+        // 0.5 s per beat up to beat 4, then 1 s per beat up to beat 8, then 0.25 s per beat.
+        let tempo_map = TempoMap {
+            bpm_base: 120.0,
+            tempo_changes: vec![tempo_change(4.0, 60.0), tempo_change(8.0, 240.0)],
+            ..TempoMap::default()
+        }
+        .timeline()
+        .unwrap();
+        let expected = [
+            (-2.0, -1.0),
+            (0.0, 0.0),
+            (2.0, 1.0),
+            (4.0, 2.0),
+            (6.0, 4.0),
+            (8.0, 6.0),
+            (10.0, 6.5),
+        ];
+        for (beat, seconds) in expected {
+            assert_eq!(tempo_map.beats_to_seconds(beat), seconds, "beat {}", beat);
+            assert_eq!(
+                tempo_map.seconds_to_beats(seconds),
+                beat,
+                "seconds {}",
+                seconds
+            );
+        }
+        for i in 0..100 {
+            let beat = i as f64 * 0.37 - 5.0;
+            let seconds = tempo_map.beats_to_seconds(beat);
+            assert!((tempo_map.seconds_to_beats(seconds) - beat).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_tempo_change_at_zero_replaces_base() {
+        let tempo_map = TempoMap {
+            bpm_base: 120.0,
+            tempo_changes: vec![tempo_change(0.0, 60.0)],
+            ..TempoMap::default()
+        }
+        .timeline()
+        .unwrap();
+        assert_eq!(tempo_map.beats_to_seconds(2.0), 2.0);
+        assert_eq!(tempo_map.seconds_to_beats(2.0), 2.0);
+        // Before beat 0 the base tempo applies.
+        assert_eq!(tempo_map.beats_to_seconds(-2.0), -1.0);
+    }
+
+    #[test]
+    fn test_unordered_tempo_changes() {
+        let tempo_map = TempoMap {
+            bpm_base: 120.0,
+            tempo_changes: vec![tempo_change(4.0, 60.0), tempo_change(2.0, 240.0)],
+            ..TempoMap::default()
+        };
+        assert_eq!(
+            tempo_map.timeline().unwrap_err(),
+            TempoMapError::Unsorted {
+                what: "Tempo change",
+                index: 1,
+                beat: 2.0,
+                previous: 4.0,
+            }
+        );
+        // changes at the same beat are sorted, the last one takes effect
+        let tempo_map = TempoMap {
+            bpm_base: 120.0,
+            tempo_changes: vec![tempo_change(4.0, 60.0), tempo_change(4.0, 240.0)],
+            ..TempoMap::default()
+        }
+        .timeline()
+        .unwrap();
+        assert_eq!(tempo_map.beats_to_seconds(4.0), 2.0);
+        assert_eq!(tempo_map.beats_to_seconds(8.0), 3.0);
+    }
+
+    #[test]
+    fn test_bar_beat_of() {
+        // 4/4 for two bars, 3/4 for two bars, then 6/8.
+        let tempo_map = TempoMap {
+            bpm_base: 120.0,
+            time_signatures: vec![time_signature(8.0, 3, 4), time_signature(14.0, 6, 8)],
+            ..TempoMap::default()
+        }
+        .timeline()
+        .unwrap();
+        let expected = [
+            (0.0, bar_beat(0, 0.0)),
+            (7.5, bar_beat(1, 3.5)),
+            (8.0, bar_beat(2, 0.0)),
+            (11.0, bar_beat(3, 0.0)),
+            (13.5, bar_beat(3, 2.5)),
+            (14.0, bar_beat(4, 0.0)),
+            (17.5, bar_beat(5, 0.5)),
+        ];
+        for (beat, expected) in expected {
+            assert_eq!(tempo_map.bar_beat_of(beat), expected, "beat {}", beat);
+        }
+    }
+
+    #[test]
+    fn test_time_signature_cuts_bar_short() {
+        let tempo_map = TempoMap {
+            bpm_base: 120.0,
+            time_signatures: vec![time_signature(0.0, 2, 4), time_signature(5.0, 4, 4)],
+            ..TempoMap::default()
+        }
+        .timeline()
+        .unwrap();
+        // Bars of 2 beats start at 0, 2 and 4, the bar at 4 is cut short by the 4/4 at 5.
+        assert_eq!(tempo_map.bar_beat_of(4.5), bar_beat(2, 0.5));
+        assert_eq!(tempo_map.bar_beat_of(5.0), bar_beat(3, 0.0));
+        assert_eq!(tempo_map.bar_beat_of(9.0), bar_beat(4, 0.0));
+    }
+
+    #[test]
+    fn test_invalid_tempo_maps() {
+        let error = |tempo_map: TempoMap| tempo_map.timeline().unwrap_err().to_string();
+        assert_eq!(
+            error(TempoMap::new(0.0)),
+            "Base tempo 0 bpm is not positive"
+        );
+        assert_eq!(
+            error(TempoMap::new(f64::INFINITY)),
+            "Base tempo inf bpm is not positive"
+        );
+        let tempo_map = TempoMap {
+            tempo_changes: vec![tempo_change(4.0, 60.0), tempo_change(8.0, -60.0)],
+            ..TempoMap::new(120.0)
+        };
+        assert_eq!(error(tempo_map), "Tempo change 1: -60 bpm is not positive");
+        let tempo_map = TempoMap {
+            tempo_changes: vec![tempo_change(f64::NAN, 60.0)],
+            ..TempoMap::new(120.0)
+        };
+        assert_eq!(error(tempo_map), "Tempo change 0: Beat NaN is not finite");
+        let tempo_map = TempoMap {
+            time_signatures: vec![time_signature(0.0, 0, 4)],
+            ..TempoMap::new(120.0)
+        };
+        assert_eq!(
+            error(tempo_map),
+            "Time signature 0: 0/4 is not a valid meter"
+        );
+        let tempo_map = TempoMap {
+            time_signatures: vec![time_signature(4.0, 3, 4), time_signature(0.0, 6, 8)],
+            ..TempoMap::new(120.0)
+        };
+        assert_eq!(
+            error(tempo_map),
+            "Time signature 1: Beat 0 is before the previous one at beat 4"
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let tempo_map = TempoMap::new(120.0);
+        let json = serde_json::to_string(&tempo_map).unwrap();
+        assert_eq!(json, r#"{"bpmBase":120.0}"#);
+
+        let tempo_map = TempoMap {
+            bpm_base: 120.0,
+            tempo_changes: vec![tempo_change(4.0, 60.0)],
+            time_signatures: vec![time_signature(8.0, 3, 4)],
+        };
+        let json = serde_json::to_string(&tempo_map).unwrap();
+        assert_eq!(
+            json,
+            r#"{"bpmBase":120.0,"tempoChanges":[{"beat":4.0,"bpm":60.0}],"#.to_string()
+                + r#""timeSignatures":[{"beat":8.0,"numerator":3,"denominator":4}]}"#
+        );
+        assert_eq!(serde_json::from_str::<TempoMap>(&json).unwrap(), tempo_map);
+    }
+}
@@ -1,20 +1,64 @@
 use serde::Deserialize;
 use serde::Serialize;
+use serde_checks_derive::{Parse as CustomParse, Serialize as CustomSerialize};
 
-#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[serde(rename_all = "camelCase")]
 pub struct Sequence {
     pub tempo_map: TempoMap,
     pub tracks: Vec<Track>,
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+/// The tempo and the meter over time, see `tempo` for the conversions between beats, seconds
+/// and bars. Beats are quarter notes.
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[serde(rename_all = "camelCase")]
 pub struct TempoMap {
+    /// The tempo before the first tempo change.
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::synthetic::arbitrary_bpm))]
     pub bpm_base: f64,
+    /// Sorted by `beat`, `TempoMap::timeline` rejects other orders. Requires format v3 or
+    /// newer.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tempo_changes: Vec<TempoChange>,
+    /// Sorted by `beat`, `TempoMap::timeline` rejects other orders. Bars before the first time
+    /// signature are in 4/4. Requires format v3 or newer.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub time_signatures: Vec<TimeSignature>,
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[derive(
+    Clone, Serialize, Deserialize, Debug, Default, PartialEq, CustomSerialize, CustomParse,
+)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[serde(rename_all = "camelCase")]
+pub struct TempoChange {
+    #[quantize(time, split)]
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::synthetic::arbitrary_time))]
+    pub beat: f64,
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::synthetic::arbitrary_bpm))]
+    pub bpm: f64,
+}
+
+/// Starts a new bar at `beat`, i.e., if `beat` is not at the end of a bar of the previous
+/// time signature, that bar is cut short.
+#[derive(
+    Clone, Serialize, Deserialize, Debug, Default, PartialEq, CustomSerialize, CustomParse,
+)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[serde(rename_all = "camelCase")]
+pub struct TimeSignature {
+    #[quantize(time, split)]
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::synthetic::arbitrary_time))]
+    pub beat: f64,
+    pub numerator: u8,
+    pub denominator: u8,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[serde(rename_all = "camelCase")]
 pub struct Track {
     pub name: String,
@@ -23,16 +67,26 @@ pub struct Track {
     pub notes: Vec<Note>,
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[derive(
+    Clone, Serialize, Deserialize, Debug, Default, PartialEq, CustomSerialize, CustomParse,
+)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[serde(rename_all = "camelCase")]
 pub struct Tuning {
     pub string_base_pitches: Vec<i32>,
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[derive(
+    Clone, Serialize, Deserialize, Debug, Default, PartialEq, CustomSerialize, CustomParse,
+)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[serde(rename_all = "camelCase")]
 pub struct Note {
+    #[quantize(time, split)]
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::synthetic::arbitrary_time))]
     pub s: f64,
+    #[quantize(time)]
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::synthetic::arbitrary_time))]
     pub d: f64,
     pub pitch: u8,
     pub string: u8,
@@ -43,7 +97,8 @@ pub struct Note {
     pub effects: NoteEffects,
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, CustomSerialize, CustomParse)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[serde(rename_all = "camelCase")]
 #[serde(default)] // as a container attribute, this means that missing fields are taken from the struct's Default::default().
 pub struct NoteEffects {
@@ -65,16 +120,27 @@ impl Default for NoteEffects {
     }
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[derive(
+    Clone, Serialize, Deserialize, Debug, Default, PartialEq, CustomSerialize, CustomParse,
+)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[serde(rename_all = "camelCase")]
 pub struct BendData {
     pub points: Vec<BendPoint>,
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[derive(
+    Clone, Serialize, Deserialize, Debug, Default, PartialEq, CustomSerialize, CustomParse,
+)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[serde(rename_all = "camelCase")]
 pub struct BendPoint {
+    #[quantize(time)]
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::synthetic::arbitrary_time))]
     pub pos: f64,
+    #[quantize(pitch)]
+    #[zigzag]
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::synthetic::arbitrary_bend))]
     pub bend: f32,
 }
 